@@ -69,12 +69,20 @@ func main() {
 	}
 	log.Println("Connected to MinIO successfully")
 
-	// Initialize execution engine
+	// Initialize execution engine. VORTEX_RUNNER=stdio shares one resident
+	// runtime between concurrent requests instead of spawning one per request.
 	log.Printf("Initializing runner with max %d concurrent workers", cfg.Runner.MaxConcurrent)
-	processRunner := runner.NewProcessRunner(cfg.Runner)
+	var execRunner runner.Runner
+	if getEnv("VORTEX_RUNNER", "process") == "stdio" {
+		stdioRunner := runner.NewStdioRunner(cfg.Runner)
+		defer stdioRunner.Close()
+		execRunner = stdioRunner
+	} else {
+		execRunner = runner.NewProcessRunner(cfg.Runner)
+	}
 
 	// Initialize API handlers
-	handler := api.NewHandler(blobStore, processRunner)
+	handler := api.NewHandler(blobStore, execRunner)
 
 	// Initialize Redis client for WebSocket log streaming
 	log.Printf("Connecting to Redis at %s...", cfg.RedisAddr)
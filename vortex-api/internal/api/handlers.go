@@ -17,11 +17,11 @@ import (
 // Handler holds dependencies for the API handlers.
 type Handler struct {
 	Store  *store.BlobStore
-	Runner *runner.ProcessRunner
+	Runner runner.Runner
 }
 
 // NewHandler creates a new Handler with the given dependencies.
-func NewHandler(s *store.BlobStore, r *runner.ProcessRunner) *Handler {
+func NewHandler(s *store.BlobStore, r runner.Runner) *Handler {
 	return &Handler{
 		Store:  s,
 		Runner: r,
@@ -83,9 +83,28 @@ func (h *Handler) HandleDeploy(w http.ResponseWriter, r *http.Request) {
 
 // ExecuteResponse is the response body for POST /execute/{functionID}.
 type ExecuteResponse struct {
-	Output          interface{}       `json:"output"`
-	Logs            []runner.LogEntry `json:"logs"`
-	ExecutionTimeMs uint64            `json:"execution_time_ms"`
+	Output          interface{}             `json:"output"`
+	Logs            []runner.LogEntry       `json:"logs"`
+	ExecutionTimeMs uint64                  `json:"execution_time_ms"`
+	InvocationID    string                  `json:"invocation_id"`
+	Chunks          []string                `json:"chunks,omitempty"`
+	OutputTruncated *runner.OutputTruncated `json:"output_truncated,omitempty"`
+	OutputRef       *runner.OutputRef       `json:"output_ref,omitempty"`
+	RuntimeLogs     []runner.RuntimeLog     `json:"runtime_logs,omitempty"`
+}
+
+// newExecuteResponse copies an execution result into the response body.
+func newExecuteResponse(result *runner.ExecutionResult) ExecuteResponse {
+	return ExecuteResponse{
+		Output:          result.Output,
+		Logs:            result.Logs,
+		ExecutionTimeMs: result.ExecutionTimeMs,
+		InvocationID:    result.InvocationID,
+		Chunks:          result.Chunks,
+		OutputTruncated: result.OutputTruncated,
+		OutputRef:       result.OutputRef,
+		RuntimeLogs:     result.RuntimeLogs,
+	}
 }
 
 // HandleExecute handles POST /execute/{functionID}
@@ -136,13 +155,9 @@ func (h *Handler) HandleExecute(w http.ResponseWriter, r *http.Request) {
 	}
 
 	// Return execution result
-	WriteJSON(w, http.StatusOK, ExecuteResponse{
-		Output:          result.Output,
-		Logs:            result.Logs,
-		ExecutionTimeMs: result.ExecutionTimeMs,
-	})
+	WriteJSON(w, http.StatusOK, newExecuteResponse(result))
 
-	log.Printf("Executed function %s in %dms", functionID, result.ExecutionTimeMs)
+	log.Printf("Executed function %s in %dms (invocation %s)", functionID, result.ExecutionTimeMs, result.InvocationID)
 }
 
 // HealthResponse is the response for GET /health.
@@ -0,0 +1,39 @@
+package api
+
+import (
+	"encoding/json"
+	"net/http"
+	"net/http/httptest"
+	"testing"
+
+	"github.com/vortex/vortex-api/internal/runner"
+)
+
+func TestExecuteResponseIncludesRuntimeLogs(t *testing.T) {
+	result := &runner.ExecutionResult{
+		Output:       "ok",
+		InvocationID: "inv-1",
+		RuntimeLogs: []runner.RuntimeLog{{
+			Timestamp: "2024-01-01T00:00:00Z",
+			Level:     "warn",
+			Message:   "Redis publish error (non-fatal)",
+		}},
+	}
+
+	rec := httptest.NewRecorder()
+	WriteJSON(rec, http.StatusOK, newExecuteResponse(result))
+
+	var body struct {
+		InvocationID string              `json:"invocation_id"`
+		RuntimeLogs  []runner.RuntimeLog `json:"runtime_logs"`
+	}
+	if err := json.NewDecoder(rec.Body).Decode(&body); err != nil {
+		t.Fatalf("decode response: %v", err)
+	}
+	if body.InvocationID != "inv-1" {
+		t.Errorf("invocation_id = %q, want %q", body.InvocationID, "inv-1")
+	}
+	if len(body.RuntimeLogs) != 1 || body.RuntimeLogs[0] != result.RuntimeLogs[0] {
+		t.Errorf("runtime_logs = %+v, want %+v", body.RuntimeLogs, result.RuntimeLogs)
+	}
+}
@@ -27,14 +27,58 @@ type ExecutionResult struct {
 	Output          interface{} `json:"output"`
 	Logs            []LogEntry  `json:"logs"`
 	ExecutionTimeMs uint64      `json:"execution_time_ms"`
+	InvocationID    string      `json:"invocation_id"`
+	Chunks          []string    `json:"chunks,omitempty"`
+	// OutputTruncated is set when the return value exceeded the runtime's
+	// --max-output-bytes limit and Output was cut down or dropped.
+	OutputTruncated *OutputTruncated `json:"output_truncated,omitempty"`
+	// OutputRef is set instead of Output when an oversized return value was
+	// uploaded to the runtime's --output-storage bucket.
+	OutputRef *OutputRef `json:"output_ref,omitempty"`
+	// RuntimeLogs holds warnings of the runtime itself, e.g. that it
+	// couldn't reach Redis, kept apart from the function's own Logs.
+	RuntimeLogs []RuntimeLog `json:"runtime_logs,omitempty"`
 }
 
-// LogEntry represents a single log message captured from the runtime.
-type LogEntry struct {
+// RuntimeLog is a warning raised by the runtime rather than user code.
+type RuntimeLog struct {
 	Timestamp string `json:"timestamp"`
+	Level     string `json:"level"`
 	Message   string `json:"message"`
 }
 
+// OutputRef points at an output stored in object storage.
+type OutputRef struct {
+	URL  string `json:"url"`
+	Size uint64 `json:"size"`
+}
+
+// OutputTruncated describes an oversized return value.
+type OutputTruncated struct {
+	OriginalBytes uint64 `json:"original_bytes"`
+	Limit         uint64 `json:"limit"`
+}
+
+// LogEntry represents a single log message captured from the runtime.
+type LogEntry struct {
+	InvocationID string                 `json:"invocation_id"`
+	Seq          uint64                 `json:"seq"`
+	Timestamp    string                 `json:"timestamp"`
+	ElapsedMs    uint64                 `json:"elapsed_ms"`
+	Level        string                 `json:"level"`
+	Message      string                 `json:"message"`
+	Fields       map[string]interface{} `json:"fields,omitempty"`
+	Repeated     uint64                 `json:"repeated,omitempty"`
+}
+
+// Runner executes functions. ProcessRunner spawns a runtime per request;
+// StdioRunner multiplexes requests over one resident runtime.
+type Runner interface {
+	Execute(ctx context.Context, functionID, code string) (*ExecutionResult, error)
+	CurrentWorkers() int
+	MaxWorkers() int
+}
+
 // Common errors returned by the runner.
 var (
 	// ErrCapacityExceeded is returned when the worker pool is full.
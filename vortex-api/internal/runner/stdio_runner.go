@@ -0,0 +1,287 @@
+package runner
+
+import (
+	"bufio"
+	"context"
+	"encoding/json"
+	"errors"
+	"fmt"
+	"io"
+	"log"
+	"os"
+	"os/exec"
+	"sync"
+	"sync/atomic"
+	"time"
+
+	"github.com/google/uuid"
+)
+
+// ErrRuntimeExited is returned for invocations that were in flight when the
+// resident runtime process exited.
+var ErrRuntimeExited = errors.New("runtime process exited")
+
+// StdioRunner executes functions on a single resident vortex-runtime process
+// started with `--listen stdio`.
+//
+// # Multiplexing
+//
+// Unlike ProcessRunner, which spawns one process per request, StdioRunner
+// keeps one warm runtime and writes each invocation to its stdin as a
+// control-protocol frame with a unique id and invocation_id. The runtime
+// executes overlapping invocations concurrently and writes each response as
+// soon as it finishes; a reader goroutine routes responses back to their
+// callers by id. Requests are never serialized behind one another.
+//
+// If the process exits, in-flight invocations fail with ErrRuntimeExited and
+// the next Execute starts a new one.
+type StdioRunner struct {
+	binaryPath     string
+	semaphore      chan struct{}
+	defaultTimeout time.Duration
+
+	mu     sync.Mutex
+	proc   *stdioProcess
+	nextID atomic.Uint64
+}
+
+// stdioProcess is one running `vortex-runtime --listen stdio`.
+type stdioProcess struct {
+	cmd *exec.Cmd
+
+	writeMu sync.Mutex // one frame at a time on stdin
+	stdin   io.WriteCloser
+
+	mu      sync.Mutex
+	pending map[uint64]chan controlResponse
+	done    chan struct{} // closed when stdout reaches EOF
+}
+
+// controlRequest is an `invoke` frame of the runtime's control protocol.
+type controlRequest struct {
+	V            int    `json:"v"`
+	ID           uint64 `json:"id"`
+	Type         string `json:"type"`
+	FunctionID   string `json:"function_id,omitempty"`
+	Code         string `json:"code"`
+	InvocationID string `json:"invocation_id"`
+}
+
+// controlResponse is a response frame of the runtime's control protocol.
+type controlResponse struct {
+	ID           uint64          `json:"id"`
+	InvocationID string          `json:"invocation_id"`
+	Result       json.RawMessage `json:"result"`
+	Error        string          `json:"error"`
+}
+
+// NewStdioRunner creates a runner backed by one resident runtime process.
+// The process is started lazily on the first Execute.
+//
+// MaxConcurrent bounds the invocations in flight on the shared process.
+// RedisURL is not used: in resident mode the Redis channel is not known
+// until an invocation arrives.
+func NewStdioRunner(cfg ProcessRunnerConfig) *StdioRunner {
+	if cfg.MaxConcurrent <= 0 {
+		cfg.MaxConcurrent = 10
+	}
+	if cfg.DefaultTimeout <= 0 {
+		cfg.DefaultTimeout = 5 * time.Second
+	}
+
+	return &StdioRunner{
+		binaryPath:     cfg.BinaryPath,
+		semaphore:      make(chan struct{}, cfg.MaxConcurrent),
+		defaultTimeout: cfg.DefaultTimeout,
+	}
+}
+
+// Execute runs JavaScript code on the resident runtime.
+//
+// A timed-out invocation returns ErrTimeout to the caller; its eventual
+// response is discarded.
+func (r *StdioRunner) Execute(ctx context.Context, functionID, code string) (*ExecutionResult, error) {
+	select {
+	case r.semaphore <- struct{}{}:
+		defer func() { <-r.semaphore }()
+	default:
+		log.Printf("Worker pool full, rejecting execution for function %s", functionID)
+		return nil, ErrCapacityExceeded
+	}
+
+	proc, err := r.process()
+	if err != nil {
+		return nil, err
+	}
+
+	execCtx, cancel := context.WithTimeout(ctx, r.defaultTimeout)
+	defer cancel()
+
+	request := controlRequest{
+		V:            1,
+		ID:           r.nextID.Add(1),
+		Type:         "invoke",
+		FunctionID:   functionID,
+		Code:         code,
+		InvocationID: uuid.NewString(),
+	}
+	reply := proc.register(request.ID)
+	defer proc.unregister(request.ID)
+
+	if err := proc.send(request); err != nil {
+		return nil, err
+	}
+
+	select {
+	case response := <-reply:
+		if response.Error != "" {
+			return nil, fmt.Errorf("execution failed: %s", response.Error)
+		}
+		var result ExecutionResult
+		if err := json.Unmarshal(response.Result, &result); err != nil {
+			return nil, fmt.Errorf("failed to parse runtime output: %w", err)
+		}
+		return &result, nil
+	case <-proc.done:
+		return nil, ErrRuntimeExited
+	case <-execCtx.Done():
+		if errors.Is(ctx.Err(), context.Canceled) {
+			return nil, ctx.Err()
+		}
+		log.Printf("Function %s timed out (invocation %s)", functionID, request.InvocationID)
+		return nil, ErrTimeout
+	}
+}
+
+// CurrentWorkers returns the number of invocations in flight.
+func (r *StdioRunner) CurrentWorkers() int {
+	return len(r.semaphore)
+}
+
+// MaxWorkers returns the maximum number of concurrent invocations.
+func (r *StdioRunner) MaxWorkers() int {
+	return cap(r.semaphore)
+}
+
+// Close shuts down the resident runtime. Closing its stdin ends the session,
+// after which it exits.
+func (r *StdioRunner) Close() error {
+	r.mu.Lock()
+	defer r.mu.Unlock()
+	if r.proc == nil {
+		return nil
+	}
+	err := r.proc.stdin.Close()
+	r.proc = nil
+	return err
+}
+
+// process returns the running runtime, starting a new one if it has exited.
+func (r *StdioRunner) process() (*stdioProcess, error) {
+	r.mu.Lock()
+	defer r.mu.Unlock()
+
+	if r.proc != nil {
+		select {
+		case <-r.proc.done:
+		default:
+			return r.proc, nil
+		}
+	}
+
+	proc, err := startStdioProcess(r.binaryPath)
+	if err != nil {
+		return nil, err
+	}
+	r.proc = proc
+	return proc, nil
+}
+
+func startStdioProcess(binaryPath string) (*stdioProcess, error) {
+	cmd := exec.Command(binaryPath, "--listen", "stdio")
+	cmd.Stderr = os.Stderr
+	stdin, err := cmd.StdinPipe()
+	if err != nil {
+		return nil, fmt.Errorf("failed to open runtime stdin: %w", err)
+	}
+	stdout, err := cmd.StdoutPipe()
+	if err != nil {
+		return nil, fmt.Errorf("failed to open runtime stdout: %w", err)
+	}
+	if err := cmd.Start(); err != nil {
+		return nil, fmt.Errorf("failed to start runtime: %w", err)
+	}
+	log.Printf("Started resident runtime (pid %d) via %s", cmd.Process.Pid, binaryPath)
+
+	proc := &stdioProcess{
+		cmd:     cmd,
+		stdin:   stdin,
+		pending: make(map[uint64]chan controlResponse),
+		done:    make(chan struct{}),
+	}
+	go proc.readResponses(stdout)
+	return proc, nil
+}
+
+// readResponses routes response frames to their callers until stdout closes.
+func (p *stdioProcess) readResponses(stdout io.Reader) {
+	reader := bufio.NewReader(stdout)
+	for {
+		line, err := reader.ReadBytes('\n')
+		if len(line) > 0 {
+			var response controlResponse
+			if jsonErr := json.Unmarshal(line, &response); jsonErr != nil {
+				log.Printf("Ignoring malformed runtime output: %v", jsonErr)
+			} else {
+				p.deliver(response)
+			}
+		}
+		if err != nil {
+			break
+		}
+	}
+
+	close(p.done)
+	if err := p.cmd.Wait(); err != nil {
+		log.Printf("Resident runtime exited: %v", err)
+	}
+}
+
+func (p *stdioProcess) register(id uint64) <-chan controlResponse {
+	reply := make(chan controlResponse, 1)
+	p.mu.Lock()
+	p.pending[id] = reply
+	p.mu.Unlock()
+	return reply
+}
+
+func (p *stdioProcess) unregister(id uint64) {
+	p.mu.Lock()
+	delete(p.pending, id)
+	p.mu.Unlock()
+}
+
+func (p *stdioProcess) deliver(response controlResponse) {
+	p.mu.Lock()
+	reply, ok := p.pending[response.ID]
+	delete(p.pending, response.ID)
+	p.mu.Unlock()
+	if ok {
+		reply <- response
+	}
+}
+
+func (p *stdioProcess) send(request controlRequest) error {
+	line, err := json.Marshal(request)
+	if err != nil {
+		return fmt.Errorf("failed to encode request: %w", err)
+	}
+	line = append(line, '\n')
+
+	p.writeMu.Lock()
+	defer p.writeMu.Unlock()
+	if _, err := p.stdin.Write(line); err != nil {
+		return fmt.Errorf("failed to send request to runtime: %w", err)
+	}
+	return nil
+}
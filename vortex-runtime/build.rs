@@ -1,56 +1,67 @@
 //! Build script for Vortex Runtime
 //!
 //! This script runs at compile time and creates a V8 snapshot containing
-//! the pre-initialized JavaScript environment (console polyfills, timers, etc.)
+//! deno_core's own JavaScript (primordials, `Deno.core`, the op bindings'
+//! infrastructure), already evaluated.
 //!
 //! The snapshot is embedded into the final binary, enabling sub-millisecond cold starts.
+//!
+//! `bootstrap.js` is not part of it: it reads the worker's configuration
+//! (`op_bootstrap_config`) as it runs and removes `Deno` when done, so each
+//! worker evaluates it on top of the snapshot. For the same reason the
+//! snapshot has no extension of ours, and none of the runtime's modules (or
+//! their database and HTTP clients) are compiled into this script; the
+//! runtime's ops are registered after the ones in the snapshot.
+//!
+//! Before the snapshot is taken, a warmup script is executed so that the
+//! deno_core functions every worker calls are already compiled in it. It
+//! defaults to `src/warmup.js` and can be overridden with the
+//! `VORTEX_SNAPSHOT_WARMUP` environment variable (an empty value disables it).
+//! `vortex-runtime snapshot` takes the same snapshot on demand.
 
 use std::env;
 use std::path::PathBuf;
 
-// Import the actual ops module to ensure Single Source of Truth
-#[path = "src/ops.rs"]
-mod ops;
+#[path = "src/snapshot_builder.rs"]
+mod snapshot_builder;
 
 fn main() {
-    use deno_core::extension;
-
-    // Define the extension with ops AND the bootstrap JavaScript
-    // The esm parameter points to the actual JS file
-    // NOTE: Extension name MUST match the one in worker.rs
-    extension!(
-        vortex_runtime,
-        ops = [
-            ops::op_log,
-            ops::op_get_time_ms,
-            ops::op_sleep,
-        ],
-        esm_entry_point = "ext:vortex_runtime/bootstrap.js",
-        esm = [dir "src", "bootstrap.js"],
-    );
+    let warmup_path = match env::var("VORTEX_SNAPSHOT_WARMUP") {
+        Ok(path) if path.is_empty() => None,
+        Ok(path) => Some(PathBuf::from(path)),
+        Err(_) => Some(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/warmup.js")),
+    };
+    let warmup_source = warmup_path.as_ref().map(|path| {
+        std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Failed to read warmup script {:?}: {}", path, e))
+    });
 
     let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
     let snapshot_path = out_dir.join("VORTEX_SNAPSHOT.bin");
 
-    // Create the snapshot using deno_core's snapshot module
-    let snapshot = deno_core::snapshot::create_snapshot(
-        deno_core::snapshot::CreateSnapshotOptions {
-            cargo_manifest_dir: env!("CARGO_MANIFEST_DIR"),
-            startup_snapshot: None,
-            skip_op_registration: false,
-            extensions: vec![vortex_runtime::init_ops_and_esm()],
-            with_runtime_cb: None,
-            extension_transpiler: None,
-        },
-        None,
-    )
-    .expect("Failed to create snapshot");
-
-    std::fs::write(&snapshot_path, snapshot.output).expect("Failed to write snapshot");
-
-    // Tell Cargo to rerun this script if these files change
-    println!("cargo:rerun-if-changed=src/bootstrap.js");
-    println!("cargo:rerun-if-changed=src/ops.rs");
+    // The same baseline flags workers run with (TEMPORAL_V8_FLAG in
+    // src/worker.rs): V8 expects a snapshot to be loaded with the flags it
+    // was created with
+    deno_core::v8_set_flags(vec!["build".to_string(), "--harmony-temporal".to_string()]);
+
+    let snapshot =
+        snapshot_builder::create_snapshot(warmup_source).expect("Failed to create snapshot");
+
+    // Record a content hash next to the snapshot so the runtime can detect a
+    // corrupted or mismatched snapshot at startup (see src/snapshot.rs)
+    let hash = snapshot_builder::snapshot_sha256(&snapshot);
+    std::fs::write(out_dir.join("VORTEX_SNAPSHOT.sha256"), hash)
+        .expect("Failed to write snapshot hash");
+
+    std::fs::write(&snapshot_path, snapshot).expect("Failed to write snapshot");
+
+    // Nothing else from src/ goes into the snapshot, so only these and the
+    // warmup script matter
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/snapshot_builder.rs");
+    println!("cargo:rerun-if-env-changed=VORTEX_SNAPSHOT_WARMUP");
+    if let Some(path) = &warmup_path {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
     println!("cargo:warning=V8 Snapshot written to {:?}", snapshot_path);
 }
@@ -0,0 +1,580 @@
+//! Actors - single-writer isolates addressed by object ID.
+//!
+//! Modelled on Durable Objects: every invocation for the same
+//! `(function_id, object_id)` pair is routed to one pinned [`VortexWorker`]
+//! and runs to completion before the next one starts, so an object's state
+//! never has two concurrent writers. The function's entrypoint default-exports
+//! a class, which is constructed once per isolate with the actor's `state`;
+//! each invocation calls its `fetch(input)` method:
+//!
+//! ```js
+//! export default class Counter {
+//!     constructor(state) {
+//!         this.state = state;
+//!     }
+//!     async fetch(input) {
+//!         const count = ((await this.state.storage.get('count')) ?? 0) + 1;
+//!         await this.state.storage.put('count', count);
+//!         return count;
+//!     }
+//! }
+//! ```
+//!
+//! `state.storage` is loaded from an [`ActorStore`] when the isolate is
+//! created and saved back after every invocation that wrote to it and
+//! succeeded. A failed invocation discards the isolate together with its
+//! unsaved writes, so the next one starts from the last saved state.
+//!
+//! ```text
+//! invoke() ──► thread chosen by hash(function_id, object_id) ──► actor mailbox ──► pinned VortexWorker
+//!                          (mpsc channel)                         (one at a time)         │
+//!          ◄───────────────────────────── oneshot reply ◄──────────────────────────────────┘
+//! ```
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use object_store::memory::InMemory;
+use object_store::path::Path;
+use object_store::{ObjectStore, PutPayload};
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::LocalSet;
+
+use crate::modules::module_url;
+use crate::worker::{ExecutionResult, VortexWorker, WorkerOptions};
+
+/// Bundle path of the host-generated module that owns the actor instance.
+const ACTOR_MODULE: &str = ".vortex/actor.js";
+
+/// Where actors persist their `state.storage`, one JSON object per actor.
+#[derive(Debug, Clone)]
+pub struct ActorStore {
+    store: Arc<dyn ObjectStore>,
+    /// Key prefix for actor objects
+    prefix: Path,
+}
+
+impl ActorStore {
+    /// Keep actor state at the root of `store`.
+    pub fn new(store: Arc<dyn ObjectStore>) -> Self {
+        Self {
+            store,
+            prefix: Path::default(),
+        }
+    }
+
+    /// Keep actor state in process memory. It is lost when the process exits.
+    pub fn in_memory() -> Self {
+        Self::new(Arc::new(InMemory::new()))
+    }
+
+    /// Store objects under `prefix`, e.g. `actors/`.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Path::from(prefix);
+        self
+    }
+
+    /// `<prefix>/<function_id>/<object_id>.json`, with both IDs escaped.
+    fn location(&self, function_id: &str, object_id: &str) -> Path {
+        self.prefix
+            .child(function_id)
+            .child(format!("{}.json", object_id))
+    }
+
+    /// Saved storage of an actor; empty if it has never saved anything.
+    pub async fn load(
+        &self,
+        function_id: &str,
+        object_id: &str,
+    ) -> Result<BTreeMap<String, Value>> {
+        let location = self.location(function_id, object_id);
+        let bytes = match self.store.get(&location).await {
+            Ok(object) => object.bytes().await,
+            Err(object_store::Error::NotFound { .. }) => return Ok(BTreeMap::new()),
+            Err(e) => Err(e),
+        }
+        .map_err(|e| anyhow!("Failed to load actor state from {}: {}", location, e))?;
+        serde_json::from_slice(&bytes)
+            .map_err(|e| anyhow!("Actor state at {} is corrupt: {}", location, e))
+    }
+
+    /// Replace the saved storage of an actor.
+    pub async fn save(
+        &self,
+        function_id: &str,
+        object_id: &str,
+        entries: &BTreeMap<String, Value>,
+    ) -> Result<()> {
+        let location = self.location(function_id, object_id);
+        let json = serde_json::to_vec(entries)?;
+        self.store
+            .put(&location, PutPayload::from(json))
+            .await
+            .map_err(|e| anyhow!("Failed to save actor state to {}: {}", location, e))?;
+        Ok(())
+    }
+}
+
+/// Configuration for an [`ActorRegistry`].
+#[derive(Debug, Clone)]
+pub struct ActorRegistryOptions {
+    /// Number of isolate threads actors are spread over (defaults to the
+    /// number of available cores)
+    pub threads: usize,
+    /// Where actor storage is persisted
+    pub store: ActorStore,
+    /// How long an actor's isolate is kept after its last invocation. The
+    /// next invocation recreates it from the saved state.
+    pub idle_timeout: Duration,
+}
+
+impl Default for ActorRegistryOptions {
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            store: ActorStore::in_memory(),
+            idle_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A single invocation of an actor, submitted to the [`ActorRegistry`].
+#[derive(Debug, Clone, Default)]
+pub struct ActorRequest {
+    /// Function that implements the actor
+    pub function_id: String,
+    /// Object the invocation is addressed to; invocations with the same
+    /// function and object ID run one at a time in the same isolate
+    pub object_id: String,
+    /// Module in `options.modules` whose default export is the actor class
+    pub entrypoint: String,
+    /// Invocation payload, passed to the actor's `fetch()`
+    pub input: Value,
+    /// Correlation ID for logs and result; generated when `None`
+    pub invocation_id: Option<String>,
+    /// Options used if the actor's isolate has to be created
+    pub options: WorkerOptions,
+}
+
+struct Job {
+    request: ActorRequest,
+    reply: oneshot::Sender<Result<ExecutionResult>>,
+}
+
+struct ActorThread {
+    sender: mpsc::UnboundedSender<Job>,
+    handle: JoinHandle<()>,
+}
+
+/// Mailboxes of the actors live on one thread, by `(function_id, object_id)`.
+type Mailboxes = Rc<RefCell<HashMap<(String, String), mpsc::UnboundedSender<Job>>>>;
+
+/// Routes actor invocations to their pinned isolates.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vortex_runtime::{ActorRegistry, ActorRegistryOptions, ActorRequest};
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let registry = ActorRegistry::new(ActorRegistryOptions::default())?;
+///     let mut request = ActorRequest {
+///         function_id: "counter".to_string(),
+///         object_id: "user-42".to_string(),
+///         entrypoint: "index.js".to_string(),
+///         ..Default::default()
+///     };
+///     request.options.modules.insert("index.js".to_string(), std::fs::read_to_string("counter.js")?);
+///     let result = registry.invoke(request).await?;
+///     println!("Output: {:?}", result.output);
+///     Ok(())
+/// }
+/// ```
+pub struct ActorRegistry {
+    threads: Vec<ActorThread>,
+}
+
+impl ActorRegistry {
+    /// Spawn the isolate threads.
+    pub fn new(options: ActorRegistryOptions) -> Result<Self> {
+        let threads = (0..options.threads.max(1))
+            .map(|index| spawn_actor_thread(index, options.store.clone(), options.idle_timeout))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { threads })
+    }
+
+    /// Run an invocation on its actor and wait for the result.
+    ///
+    /// Invocations of one actor run in the order they were submitted.
+    pub async fn invoke(&self, request: ActorRequest) -> Result<ExecutionResult> {
+        if request.function_id.is_empty() || request.object_id.is_empty() {
+            return Err(anyhow!(
+                "Actor invocations need a function ID and an object ID"
+            ));
+        }
+        // Every invocation of an actor must land on the thread that owns it
+        let mut hasher = DefaultHasher::new();
+        (&request.function_id, &request.object_id).hash(&mut hasher);
+        let thread = &self.threads[(hasher.finish() % self.threads.len() as u64) as usize];
+
+        let (reply, response) = oneshot::channel();
+        thread
+            .sender
+            .send(Job { request, reply })
+            .map_err(|_| anyhow!("Actor thread has stopped"))?;
+        response
+            .await
+            .map_err(|_| anyhow!("Actor thread dropped the invocation"))?
+    }
+
+    /// Stop accepting work and wait for queued invocations to finish.
+    pub fn shutdown(self) {
+        let handles: Vec<_> = self
+            .threads
+            .into_iter()
+            .map(|thread| {
+                drop(thread.sender);
+                thread.handle
+            })
+            .collect();
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn spawn_actor_thread(
+    index: usize,
+    store: ActorStore,
+    idle_timeout: Duration,
+) -> Result<ActorThread> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Job>();
+
+    let handle = thread::Builder::new()
+        .name(format!("vortex-actors-{}", index))
+        .spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to build actor thread runtime");
+            let local = LocalSet::new();
+            let mailboxes: Mailboxes = Rc::default();
+            local.block_on(&runtime, async {
+                while let Some(job) = receiver.recv().await {
+                    let key = (
+                        job.request.function_id.clone(),
+                        job.request.object_id.clone(),
+                    );
+                    let mut actors = mailboxes.borrow_mut();
+                    let mailbox = actors.entry(key.clone()).or_insert_with(|| {
+                        let (mailbox, jobs) = mpsc::unbounded_channel();
+                        tokio::task::spawn_local(run_actor(
+                            key,
+                            jobs,
+                            store.clone(),
+                            idle_timeout,
+                            Rc::clone(&mailboxes),
+                        ));
+                        mailbox
+                    });
+                    // Actors only stop after removing their mailbox, so it is open
+                    let _ = mailbox.send(job);
+                }
+            });
+            // Closing the mailboxes lets every actor drain its queue and stop
+            mailboxes.borrow_mut().clear();
+            runtime.block_on(local);
+        })
+        .map_err(|e| anyhow!("Failed to spawn actor thread: {}", e))?;
+
+    Ok(ActorThread { sender, handle })
+}
+
+/// Process one actor's invocations, one at a time, until it has been idle
+/// for `idle_timeout` or its mailbox is closed.
+async fn run_actor(
+    key: (String, String),
+    mut jobs: mpsc::UnboundedReceiver<Job>,
+    store: ActorStore,
+    idle_timeout: Duration,
+    mailboxes: Mailboxes,
+) {
+    let (function_id, object_id) = &key;
+    let mut worker: Option<VortexWorker> = None;
+    loop {
+        let job = match tokio::time::timeout(idle_timeout, jobs.recv()).await {
+            Ok(Some(job)) => job,
+            Ok(None) => break,
+            Err(_) => {
+                // Nothing can be sent between the timeout and the removal,
+                // since both happen on this thread without yielding
+                mailboxes.borrow_mut().remove(&key);
+                break;
+            }
+        };
+
+        let result = invoke_actor(&mut worker, &store, function_id, object_id, job.request).await;
+        if result.is_err() {
+            // Drop unsaved writes along with a possibly broken isolate
+            worker = None;
+        }
+        let _ = job.reply.send(result);
+    }
+}
+
+/// Run one invocation on the actor's isolate, creating it if necessary, and
+/// save its storage if the invocation changed it.
+async fn invoke_actor(
+    worker: &mut Option<VortexWorker>,
+    store: &ActorStore,
+    function_id: &str,
+    object_id: &str,
+    request: ActorRequest,
+) -> Result<ExecutionResult> {
+    let worker = match worker {
+        Some(worker) => worker,
+        None => {
+            let mut options = request.options;
+            options.actor_id = Some(object_id.to_string());
+            options
+                .modules
+                .insert(ACTOR_MODULE.to_string(), actor_module(&request.entrypoint)?);
+            let mut created = VortexWorker::with_options(options)?;
+            created.load_actor_storage(store.load(function_id, object_id).await?);
+            worker.insert(created)
+        }
+    };
+
+    let result = worker
+        .run_module(
+            ACTOR_MODULE,
+            &request.input,
+            request.invocation_id.as_deref(),
+        )
+        .await?;
+    if let Some(entries) = worker.take_actor_changes() {
+        store.save(function_id, object_id, &entries).await?;
+    }
+    Ok(result)
+}
+
+/// Source of the module that constructs the actor class once and forwards
+/// each invocation to its `fetch()`. Module state outlives invocations, so
+/// the instance does too.
+fn actor_module(entrypoint: &str) -> Result<String> {
+    let specifier = serde_json::to_string(&module_url(entrypoint))?;
+    Ok(format!(
+        r#"
+            import Actor from {specifier};
+            let instance;
+            export default async function (input) {{
+                if (instance === undefined) {{
+                    if (typeof Actor !== "function") {{
+                        throw new TypeError("An actor's default export must be a class");
+                    }}
+                    instance = new Actor(vortex.actor);
+                }}
+                if (typeof instance.fetch !== "function") {{
+                    throw new TypeError("Actor classes must define a fetch(input) method");
+                }}
+                return await instance.fetch(input);
+            }}
+        "#
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNTER: &str = r#"
+        export default class Counter {
+            constructor(state) {
+                this.state = state;
+            }
+            async fetch(input) {
+                const count = ((await this.state.storage.get("count")) ?? 0) + 1;
+                // Yield between the read and the write to expose any interleaving
+                await new Promise((resolve) => setTimeout(resolve, 5));
+                await this.state.storage.put("count", count);
+                if (input?.fail) throw new Error("rolled back");
+                return { id: this.state.id, count };
+            }
+        }
+    "#;
+
+    fn counter_request(object_id: &str, input: Value) -> ActorRequest {
+        let mut request = ActorRequest {
+            function_id: "counter".to_string(),
+            object_id: object_id.to_string(),
+            entrypoint: "counter.js".to_string(),
+            input,
+            ..Default::default()
+        };
+        request
+            .options
+            .modules
+            .insert("counter.js".to_string(), COUNTER.to_string());
+        request
+    }
+
+    #[tokio::test]
+    async fn test_state_persists_across_isolates() {
+        let store = ActorStore::in_memory();
+        let registry = ActorRegistry::new(ActorRegistryOptions {
+            threads: 2,
+            store: store.clone(),
+            ..Default::default()
+        })
+        .unwrap();
+        for expected in 1..=3 {
+            let result = registry
+                .invoke(counter_request("a", Value::Null))
+                .await
+                .unwrap();
+            assert_eq!(
+                result.output,
+                Some(serde_json::json!({ "id": "a", "count": expected }))
+            );
+        }
+        let other = registry
+            .invoke(counter_request("b", Value::Null))
+            .await
+            .unwrap();
+        assert_eq!(other.output.unwrap()["count"], serde_json::json!(1));
+        registry.shutdown();
+
+        // A new registry starts new isolates from the saved state
+        let registry = ActorRegistry::new(ActorRegistryOptions {
+            threads: 1,
+            store,
+            ..Default::default()
+        })
+        .unwrap();
+        let result = registry
+            .invoke(counter_request("a", Value::Null))
+            .await
+            .unwrap();
+        assert_eq!(result.output.unwrap()["count"], serde_json::json!(4));
+        registry.shutdown();
+    }
+
+    #[tokio::test]
+    async fn test_invocations_of_one_actor_do_not_interleave() {
+        let registry = ActorRegistry::new(ActorRegistryOptions {
+            threads: 2,
+            ..Default::default()
+        })
+        .unwrap();
+        let runs = (0..10).map(|_| registry.invoke(counter_request("a", Value::Null)));
+        let mut counts: Vec<_> = deno_core::futures::future::join_all(runs)
+            .await
+            .into_iter()
+            .map(|result| result.unwrap().output.unwrap()["count"].as_u64().unwrap())
+            .collect();
+        counts.sort();
+        assert_eq!(counts, (1..=10).collect::<Vec<_>>());
+        registry.shutdown();
+    }
+
+    #[tokio::test]
+    async fn test_failed_invocation_discards_writes() {
+        let store = ActorStore::in_memory();
+        let registry = ActorRegistry::new(ActorRegistryOptions {
+            threads: 1,
+            store: store.clone(),
+            ..Default::default()
+        })
+        .unwrap();
+        registry
+            .invoke(counter_request("a", Value::Null))
+            .await
+            .unwrap();
+        let failed = registry
+            .invoke(counter_request("a", serde_json::json!({ "fail": true })))
+            .await;
+        assert!(failed.is_err());
+        let result = registry
+            .invoke(counter_request("a", Value::Null))
+            .await
+            .unwrap();
+        assert_eq!(result.output.unwrap()["count"], serde_json::json!(2));
+        registry.shutdown();
+
+        let saved = store.load("counter", "a").await.unwrap();
+        assert_eq!(saved.get("count"), Some(&serde_json::json!(2)));
+    }
+
+    #[tokio::test]
+    async fn test_storage_api() {
+        let registry = ActorRegistry::new(ActorRegistryOptions {
+            threads: 1,
+            ..Default::default()
+        })
+        .unwrap();
+        let mut request = ActorRequest {
+            function_id: "kv".to_string(),
+            object_id: "a".to_string(),
+            entrypoint: "kv.js".to_string(),
+            ..Default::default()
+        };
+        request.options.modules.insert(
+            "kv.js".to_string(),
+            r#"
+                export default class {
+                    constructor(state) {
+                        this.storage = state.storage;
+                    }
+                    async fetch() {
+                        await this.storage.put("user:1", { name: "ada" });
+                        await this.storage.put("user:2", null);
+                        await this.storage.put("user:3", 3);
+                        await this.storage.put("other", true);
+                        const deleted = await this.storage.delete("user:3");
+                        return {
+                            deleted,
+                            missing: (await this.storage.get("missing")) === undefined,
+                            stored: (await this.storage.get("user:2")) === null,
+                            users: [...(await this.storage.list({ prefix: "user:" }))],
+                            first: [...(await this.storage.list({ limit: 1 })).keys()],
+                        };
+                    }
+                }
+            "#
+            .to_string(),
+        );
+        let result = registry.invoke(request).await.unwrap();
+        assert_eq!(
+            result.output,
+            Some(serde_json::json!({
+                "deleted": true,
+                "missing": true,
+                "stored": true,
+                "users": [["user:1", { "name": "ada" }], ["user:2", null]],
+                "first": ["other"],
+            }))
+        );
+        registry.shutdown();
+    }
+
+    #[tokio::test]
+    async fn test_store_escapes_ids() {
+        let store = ActorStore::in_memory().with_prefix("actors");
+        assert!(store.load("fn", "../x").await.unwrap().is_empty());
+        let entries = BTreeMap::from([("k".to_string(), serde_json::json!([1, null]))]);
+        store.save("fn", "../x", &entries).await.unwrap();
+        assert_eq!(store.load("fn", "../x").await.unwrap(), entries);
+        assert!(store.load("fn", "x").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,197 @@
+//! Multi-file function bundles packaged as `.tar.gz` / `.tgz` / `.tar` / `.zip`.
+//!
+//! The archive is unpacked entirely into memory; nothing is written to disk.
+//! It contains the module graph plus an optional `vortex.json` manifest whose
+//! `entrypoint` names the module to execute (default `index.js`).
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::{Component, Path};
+
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
+
+use crate::manifest::{Manifest, MANIFEST_FILE_NAME};
+
+/// Upper bound on the total unpacked size, to defuse compression bombs.
+pub const MAX_UNPACKED_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Entrypoint used when the manifest does not name one.
+pub const DEFAULT_ENTRYPOINT: &str = "index.js";
+
+/// An unpacked function bundle.
+#[derive(Debug, Clone, Default)]
+pub struct FunctionBundle {
+    /// Bundle-relative path (`lib/util.js`) to file contents
+    pub files: HashMap<String, String>,
+    /// Parsed `vortex.json`, if the archive has one
+    pub manifest: Option<Manifest>,
+}
+
+impl FunctionBundle {
+    /// Bundle-relative path of the module to execute.
+    pub fn entrypoint(&self) -> &str {
+        self.manifest
+            .as_ref()
+            .and_then(|manifest| manifest.entrypoint.as_deref())
+            .map(|entry| entry.trim_start_matches("./"))
+            .unwrap_or(DEFAULT_ENTRYPOINT)
+    }
+}
+
+/// Whether `path` looks like a supported archive.
+pub fn is_archive(path: &str) -> bool {
+    [".tar.gz", ".tgz", ".tar", ".zip"]
+        .iter()
+        .any(|ext| path.ends_with(ext))
+}
+
+/// Read and unpack an archive file.
+pub fn load_archive(path: &str) -> Result<FunctionBundle> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| anyhow!("Failed to read archive '{}': {}", path, e))?;
+    if path.ends_with(".zip") {
+        unpack_zip(&bytes)
+    } else if path.ends_with(".tar") {
+        unpack_tar(Cursor::new(bytes))
+    } else {
+        unpack_tar(GzDecoder::new(Cursor::new(bytes)))
+    }
+}
+
+/// Normalize an archive member path, rejecting anything that escapes the bundle.
+fn normalize_path(path: &Path) -> Result<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            _ => return Err(anyhow!("Archive entry '{}' escapes the bundle", path.display())),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+/// Collects files while enforcing the unpacked size limit.
+#[derive(Default)]
+struct BundleBuilder {
+    files: HashMap<String, String>,
+    total_bytes: u64,
+}
+
+impl BundleBuilder {
+    fn add(&mut self, path: &Path, reader: impl Read) -> Result<()> {
+        let name = normalize_path(path)?;
+        if name.is_empty() {
+            return Ok(());
+        }
+
+        // Read at most one byte past the remaining budget to detect overflow
+        let budget = MAX_UNPACKED_BYTES - self.total_bytes;
+        let mut contents = Vec::new();
+        reader.take(budget + 1).read_to_end(&mut contents)?;
+        if contents.len() as u64 > budget {
+            return Err(anyhow!(
+                "Archive unpacks to more than {} bytes",
+                MAX_UNPACKED_BYTES
+            ));
+        }
+        self.total_bytes += contents.len() as u64;
+
+        let contents = String::from_utf8(contents)
+            .map_err(|_| anyhow!("Archive entry '{}' is not valid UTF-8", name))?;
+        self.files.insert(name, contents);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<FunctionBundle> {
+        let manifest = self
+            .files
+            .get(MANIFEST_FILE_NAME)
+            .map(|json| Manifest::from_json(json))
+            .transpose()?;
+        let bundle = FunctionBundle {
+            files: self.files,
+            manifest,
+        };
+        if !bundle.files.contains_key(bundle.entrypoint()) {
+            return Err(anyhow!(
+                "Archive does not contain the entrypoint '{}'",
+                bundle.entrypoint()
+            ));
+        }
+        Ok(bundle)
+    }
+}
+
+fn unpack_tar(reader: impl Read) -> Result<FunctionBundle> {
+    let mut archive = tar::Archive::new(reader);
+    let mut builder = BundleBuilder::default();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        builder.add(&path, entry)?;
+    }
+    builder.finish()
+}
+
+fn unpack_zip(bytes: &[u8]) -> Result<FunctionBundle> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut builder = BundleBuilder::default();
+    for index in 0..archive.len() {
+        let file = archive.by_index(index)?;
+        if file.is_dir() {
+            continue;
+        }
+        let path = file
+            .enclosed_name()
+            .ok_or_else(|| anyhow!("Archive entry '{}' escapes the bundle", file.name()))?;
+        builder.add(&path, file)?;
+    }
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tar_gz(files: &[(&str, &str)]) -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, contents.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_unpack_tar_gz_with_manifest() {
+        let bytes = tar_gz(&[
+            ("vortex.json", r#"{ "entrypoint": "src/main.js" }"#),
+            ("src/main.js", "export default () => 1"),
+        ]);
+        let bundle = unpack_tar(GzDecoder::new(Cursor::new(bytes))).unwrap();
+        assert_eq!(bundle.entrypoint(), "src/main.js");
+        assert!(bundle.files.contains_key("src/main.js"));
+    }
+
+    #[test]
+    fn test_missing_entrypoint_is_rejected() {
+        let bytes = tar_gz(&[("lib.js", "export const x = 1")]);
+        let error = unpack_tar(GzDecoder::new(Cursor::new(bytes))).unwrap_err();
+        assert!(error.to_string().contains("index.js"));
+    }
+
+    #[test]
+    fn test_path_traversal_is_rejected() {
+        assert!(normalize_path(Path::new("../etc/passwd")).is_err());
+        assert_eq!(normalize_path(Path::new("./a/b.js")).unwrap(), "a/b.js");
+    }
+}
@@ -0,0 +1,317 @@
+//! Append-only audit log for compliance events.
+//!
+//! Console output is for debugging and may be truncated, sampled or dropped
+//! on its way to the log pipeline. Regulated customers also need a record of
+//! what their functions did that can't be quietly rewritten afterwards:
+//!
+//! ```js
+//! await vortex.audit.record({ action: 'refund.issued', orderId, amount });
+//! ```
+//!
+//! Events go to an [`AuditSink`] chosen by the host, never to the function's
+//! logs. Every [`AuditEntry`] carries the hash of the entry before it, and
+//! its own hash covers all of its fields, so removing, reordering or editing
+//! an entry breaks the chain from that point on; [`verify`] finds where.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Largest event `vortex.audit.record()` accepts, as JSON, in bytes.
+pub const MAX_AUDIT_EVENT_BYTES: usize = 64 * 1024;
+
+/// `prev_hash` of the first entry of a chain.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One recorded event, linked to the entry before it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// Position in the chain, starting at 1
+    pub seq: u64,
+    /// When the event was recorded, RFC 3339 with milliseconds
+    pub timestamp: String,
+    pub function_id: Option<String>,
+    pub invocation_id: String,
+    /// The event as JSON text, kept verbatim so its hash can be recomputed
+    /// from the stored entry
+    pub event: String,
+    /// `hash` of the previous entry, or [`GENESIS_HASH`]
+    pub prev_hash: String,
+    /// SHA-256 of all the other fields, hex-encoded
+    pub hash: String,
+}
+
+/// The fields an entry's hash covers, in the order they are hashed.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Unsealed<'a> {
+    seq: u64,
+    timestamp: &'a str,
+    function_id: Option<&'a str>,
+    invocation_id: &'a str,
+    event: &'a str,
+    prev_hash: &'a str,
+}
+
+impl AuditEntry {
+    /// The hash this entry should have, given its other fields.
+    pub fn compute_hash(&self) -> String {
+        let unsealed = Unsealed {
+            seq: self.seq,
+            timestamp: &self.timestamp,
+            function_id: self.function_id.as_deref(),
+            invocation_id: &self.invocation_id,
+            event: &self.event,
+            prev_hash: &self.prev_hash,
+        };
+        let json = serde_json::to_vec(&unsealed).expect("audit entry serializes");
+        Sha256::digest(&json)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// Check that `entries` form an unbroken chain, returning the `seq` of the
+/// first entry that doesn't. The first entry is trusted to follow whatever
+/// came before it, so check from `seq` 1 to prove nothing was removed at
+/// the start.
+pub fn verify(entries: &[AuditEntry]) -> Result<(), u64> {
+    let mut previous: Option<&AuditEntry> = None;
+    for entry in entries {
+        let linked = match previous {
+            Some(previous) => entry.seq == previous.seq + 1 && entry.prev_hash == previous.hash,
+            None => entry.seq != 1 || entry.prev_hash == GENESIS_HASH,
+        };
+        if !linked || entry.hash != entry.compute_hash() {
+            return Err(entry.seq);
+        }
+        previous = Some(entry);
+    }
+    Ok(())
+}
+
+/// Where audit entries are kept. Implementations must not drop or reorder
+/// entries: an entry is only acknowledged to the function once `append`
+/// has returned.
+pub trait AuditSink: fmt::Debug + Send {
+    /// Durably append `entry`.
+    fn append(&mut self, entry: &AuditEntry) -> io::Result<()>;
+
+    /// The last entry already in the sink, which new entries continue from.
+    fn last(&mut self) -> io::Result<Option<AuditEntry>>;
+}
+
+/// Entries as JSON lines in a local file, synced to disk on every append.
+#[derive(Debug)]
+pub struct FileAuditSink {
+    file: File,
+}
+
+impl FileAuditSink {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        Ok(Self { file })
+    }
+}
+
+impl AuditSink for FileAuditSink {
+    fn append(&mut self, entry: &AuditEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()
+    }
+
+    fn last(&mut self) -> io::Result<Option<AuditEntry>> {
+        let mut last = None;
+        for line in BufReader::new(&self.file).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                last = Some(line);
+            }
+        }
+        // A torn or edited last line must not be silently chained over
+        last.map(|line| serde_json::from_str(&line).map_err(io::Error::from))
+            .transpose()
+    }
+}
+
+/// Entries kept in memory, for tests and hosts that forward them elsewhere.
+/// Clones share their entries.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryAuditSink {
+    entries: Arc<Mutex<Vec<AuditEntry>>>,
+}
+
+impl MemoryAuditSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl AuditSink for MemoryAuditSink {
+    fn append(&mut self, entry: &AuditEntry) -> io::Result<()> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(entry.clone());
+        Ok(())
+    }
+
+    fn last(&mut self) -> io::Result<Option<AuditEntry>> {
+        Ok(self.entries().pop())
+    }
+}
+
+/// The chain entries are appended to. Clones share the sink and the chain,
+/// so give every worker of the process a clone of the same log.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    chain: Arc<Mutex<Chain>>,
+}
+
+#[derive(Debug)]
+struct Chain {
+    sink: Box<dyn AuditSink>,
+    seq: u64,
+    head: String,
+}
+
+impl AuditLog {
+    /// Continue the chain already in `sink`, or start one if it is empty.
+    pub fn new(mut sink: impl AuditSink + 'static) -> io::Result<Self> {
+        let (seq, head) = match sink.last()? {
+            Some(last) => (last.seq, last.hash),
+            None => (0, GENESIS_HASH.to_string()),
+        };
+        Ok(Self {
+            chain: Arc::new(Mutex::new(Chain {
+                sink: Box::new(sink),
+                seq,
+                head,
+            })),
+        })
+    }
+
+    /// Append `event` (JSON text) to the chain. Blocks until the sink has
+    /// stored it.
+    pub fn record(
+        &self,
+        function_id: Option<&str>,
+        invocation_id: &str,
+        event: String,
+        now: DateTime<Utc>,
+    ) -> io::Result<AuditEntry> {
+        let mut chain = self.chain.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entry = AuditEntry {
+            seq: chain.seq + 1,
+            timestamp: now.to_rfc3339_opts(SecondsFormat::Millis, true),
+            function_id: function_id.map(str::to_string),
+            invocation_id: invocation_id.to_string(),
+            event,
+            prev_hash: chain.head.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+        // The chain only moves on once the entry is stored
+        chain.sink.append(&entry)?;
+        chain.seq = entry.seq;
+        chain.head = entry.hash.clone();
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(log: &AuditLog, event: &str) -> AuditEntry {
+        log.record(Some("fn-1"), "inv-1", event.to_string(), Utc::now())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_chain_and_verify() {
+        let sink = MemoryAuditSink::new();
+        let log = AuditLog::new(sink.clone()).unwrap();
+        record(&log, r#"{"action":"login"}"#);
+        record(&log, r#"{"action":"refund","amount":42}"#);
+        record(&log, r#"{"action":"logout"}"#);
+
+        let entries = sink.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].prev_hash, GENESIS_HASH);
+        assert_eq!(entries[1].prev_hash, entries[0].hash);
+        assert_eq!(verify(&entries), Ok(()));
+        assert_eq!(verify(&entries[1..]), Ok(()));
+
+        let mut edited = entries.clone();
+        edited[1].event = r#"{"action":"refund","amount":4200}"#.to_string();
+        assert_eq!(verify(&edited), Err(2));
+
+        let mut removed = entries.clone();
+        removed.remove(1);
+        assert_eq!(verify(&removed), Err(3));
+
+        // Rehashing an edited entry doesn't help: the next one no longer links
+        edited[1].hash = edited[1].compute_hash();
+        assert_eq!(verify(&edited), Err(3));
+
+        let mut forged = entries.clone();
+        forged[0].prev_hash = "f".repeat(64);
+        forged[0].hash = forged[0].compute_hash();
+        assert_eq!(verify(&forged), Err(1));
+    }
+
+    #[test]
+    fn test_file_sink_resumes_chain() {
+        let path = std::env::temp_dir().join(format!("vortex-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let first = AuditLog::new(FileAuditSink::open(&path).unwrap()).unwrap();
+        record(&first, r#"{"n":1}"#);
+        record(&first, r#"{"n":2}"#);
+        drop(first);
+
+        // Reopening continues where the file left off
+        let second = AuditLog::new(FileAuditSink::open(&path).unwrap()).unwrap();
+        assert_eq!(record(&second, r#"{"n":3}"#).seq, 3);
+
+        let entries: Vec<AuditEntry> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(verify(&entries), Ok(()));
+
+        // A damaged tail is reported instead of being chained over
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"seq\":4,\"trunc")
+            .unwrap();
+        assert!(AuditLog::new(FileAuditSink::open(&path).unwrap()).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,13 +1,52 @@
 // Vortex Runtime Bootstrap JavaScript
-// This file is embedded into the V8 snapshot at compile time.
+// Executed as a classic script by every VortexWorker, on top of the V8
+// snapshot (see bootstrap.rs).
+//
+// Everything lives inside an IIFE so that internal helpers (including the
+// `ops` reference) never leak into the global scope seen by user code.
 
-// Store the core ops reference for faster access
-const ops = Deno.core.ops;
+((globalThis, core) => {
+    // Store the core ops reference for faster access
+    const ops = core.ops;
 
-// Polyfill console object to capture logs via our custom op
-globalThis.console = {
-    log: (...args) => {
-        const message = args.map(arg => {
+    // Worker configuration provided by the host (see BootstrapConfig in ops.rs)
+    const config = ops.op_bootstrap_config();
+
+    // Thrown by privileged ops when the worker's Permissions deny a capability
+    class PermissionDenied extends Error {
+        constructor(message) {
+            super(message);
+            this.name = 'PermissionDenied';
+        }
+    }
+    core.registerErrorClass('PermissionDenied', PermissionDenied);
+
+    // Thrown by vortex.jwt.verify() for a bad signature, disallowed algorithm
+    // or failed claim check (expired, wrong audience, ...)
+    class JwtError extends Error {
+        constructor(message) {
+            super(message);
+            this.name = 'JwtError';
+        }
+    }
+    core.registerErrorClass('JwtError', JwtError);
+
+    // Thrown when code touches a vortex.* namespace that isn't enabled for
+    // the function (see Permissions::apis)
+    class VortexCapabilityError extends Error {
+        constructor(capability) {
+            super(`vortex.${capability} is not available to this function`);
+            this.name = 'VortexCapabilityError';
+            this.capability = capability;
+        }
+    }
+
+    // =========================================================================
+    // Console
+    // =========================================================================
+
+    function formatLogArgs(args) {
+        return args.map(arg => {
             if (arg === null) return 'null';
             if (arg === undefined) return 'undefined';
             if (typeof arg === 'object') {
@@ -19,82 +58,4352 @@ globalThis.console = {
             }
             return String(arg);
         }).join(' ');
-        ops.op_log(message);
-    },
-    error: (...args) => {
-        globalThis.console.log('[ERROR]', ...args);
-    },
-    warn: (...args) => {
-        globalThis.console.log('[WARN]', ...args);
-    },
-    info: (...args) => {
-        globalThis.console.log('[INFO]', ...args);
-    },
-    debug: (...args) => {
-        globalThis.console.log('[DEBUG]', ...args);
-    }
-};
-
-// Global vortex object for future API extensions
-globalThis.vortex = {
-    version: '0.1.0',
-    platform: 'vortex-runtime',
-};
-
-// Timer tracking
-let __timerId = 0;
-const __activeTimers = new Map();
-const __timerCallbacks = new Map();
-
-// setTimeout using PROPER async sleep (backed by tokio via op_sleep)
-globalThis.setTimeout = (callback, delay = 0) => {
-    const id = ++__timerId;
-
-    const timerPromise = (async () => {
-        await Deno.core.ops.op_sleep(BigInt(Math.max(0, delay)));
-        __activeTimers.delete(id);
-        __timerCallbacks.delete(id);
-        if (typeof callback === 'function') {
-            callback();
-        }
-    })();
-
-    __activeTimers.set(id, timerPromise);
-    __timerCallbacks.set(id, callback);
-    return id;
-};
-
-globalThis.clearTimeout = (id) => {
-    __activeTimers.delete(id);
-    __timerCallbacks.delete(id);
-};
-
-// setInterval using proper async sleep
-globalThis.setInterval = (callback, delay = 0) => {
-    const id = ++__timerId;
-    let running = true;
-
-    const intervalLoop = async () => {
-        while (running && __activeTimers.has(id)) {
-            await Deno.core.ops.op_sleep(BigInt(Math.max(0, delay)));
-            if (running && __activeTimers.has(id) && typeof callback === 'function') {
-                callback();
-            }
-        }
-    };
-
-    __activeTimers.set(id, { running: true });
-    intervalLoop();
-    return id;
-};
-
-globalThis.clearInterval = (id) => {
-    const timer = __activeTimers.get(id);
-    if (timer) {
-        timer.running = false;
-        __activeTimers.delete(id);
-    }
-};
-
-// Prevent access to potentially dangerous globals
-delete globalThis.Deno;
+    }
+
+    function isPlainObject(value) {
+        if (value === null || typeof value !== 'object') return false;
+        const proto = Object.getPrototypeOf(value);
+        return proto === Object.prototype || proto === null;
+    }
+
+    const LOG_LEVELS = ['debug', 'info', 'warn', 'error'];
+
+    // Emit a log entry at `level`, with structured fields when given. Fields
+    // that can't be serialized are folded into the message instead of being
+    // lost.
+    function writeLog(level, message, fields) {
+        if (fields !== undefined) {
+            let json;
+            try {
+                json = JSON.stringify(fields);
+            } catch (e) {
+                json = undefined;
+            }
+            if (json !== undefined) {
+                ops.op_log_structured(level, message, json);
+                return;
+            }
+            message = `${message} ${formatLogArgs([fields])}`;
+        }
+        ops.op_log(level, message);
+    }
+
+    // `console.log('user signed in', { userId })`: a trailing plain object
+    // after at least one message argument becomes the entry's fields
+    function logWithPrefix(level, prefix, args) {
+        const messageArgs = prefix === undefined ? args : [prefix, ...args];
+        if (args.length > 1 && isPlainObject(args[args.length - 1])) {
+            writeLog(level, formatLogArgs(messageArgs.slice(0, -1)), args[args.length - 1]);
+        } else {
+            writeLog(level, formatLogArgs(messageArgs));
+        }
+    }
+
+    // Polyfill console object to capture logs via our custom op. The prefixes
+    // predate the entry's `level` and are kept for readers of plain messages
+    const console = {
+        log: (...args) => logWithPrefix('info', undefined, args),
+        error: (...args) => logWithPrefix('error', '[ERROR]', args),
+        warn: (...args) => logWithPrefix('warn', '[WARN]', args),
+        info: (...args) => logWithPrefix('info', '[INFO]', args),
+        debug: (...args) => logWithPrefix('debug', '[DEBUG]', args),
+    };
+
+    // Global vortex object for future API extensions
+    const vortex = {
+        version: '0.1.0',
+        platform: 'vortex-runtime',
+        errors: Object.freeze({ PermissionDenied, JwtError, VortexCapabilityError }),
+        // Structured logging: vortex.log('charged card', { fields: { amount: 42 } }),
+        // optionally with a level: vortex.log('card declined', { level: 'warn' })
+        log: (message, { fields, level = 'info' } = {}) => {
+            if (!LOG_LEVELS.includes(level)) {
+                throw new TypeError(
+                    `vortex.log(): level must be one of ${LOG_LEVELS.join(', ')}, got '${level}'`
+                );
+            }
+            writeLog(level, String(message), fields);
+        },
+        // Incremental output: vortex.write(chunk) ... vortex.end(). Chunks go
+        // to the host's output sink as they are written instead of being
+        // held in the isolate until the function returns
+        write: (chunk) => ops.op_write(outputChunk(chunk)),
+        end: (chunk) => {
+            if (chunk !== undefined) ops.op_write(outputChunk(chunk));
+            ops.op_end();
+        },
+        // Explicit output: vortex.setResult(value) designates the function's
+        // result from anywhere, e.g. an event listener, instead of a
+        // top-level `return`. The value is serialized when it is set, and the
+        // last call wins over any return value
+        setResult: (value) => {
+            const json = JSON.stringify(value);
+            if (json === undefined && value !== undefined) {
+                throw new TypeError(
+                    `vortex.setResult(): a ${typeof value} has no JSON representation`,
+                );
+            }
+            ops.op_set_result(json === undefined ? 'null' : json);
+        },
+    };
+
+    // =========================================================================
+    // vortex.fs: in-memory scratch filesystem
+    // =========================================================================
+
+    // Node-style error for a missing path, so `e.code === 'ENOENT'` checks work
+    function fsError(code, syscall, path) {
+        const messages = {
+            ENOENT: 'no such file or directory',
+            EISDIR: 'illegal operation on a directory',
+            ENOTDIR: 'not a directory',
+        };
+        const error = new Error(`${code}: ${messages[code]}, ${syscall} '${path}'`);
+        error.code = code;
+        error.syscall = syscall;
+        error.path = path;
+        return error;
+    }
+
+    function fsData(data) {
+        if (typeof data === 'string') return new TextEncoder().encode(data);
+        if (data instanceof ArrayBuffer) return new Uint8Array(data);
+        if (ArrayBuffer.isView(data)) {
+            return new Uint8Array(data.buffer, data.byteOffset, data.byteLength);
+        }
+        return new TextEncoder().encode(String(data));
+    }
+
+    function fsEncoding(options) {
+        return typeof options === 'string' ? options : options?.encoding;
+    }
+
+    // Synchronous because there is no I/O: files live in host memory, are
+    // capped in total size, and are discarded when the invocation ends
+    const fs = Object.freeze({
+        readFile(path, options) {
+            path = String(path);
+            const stat = ops.op_fs_stat(path);
+            if (stat === null) throw fsError('ENOENT', 'open', path);
+            if (stat.isDirectory) throw fsError('EISDIR', 'read', path);
+            const bytes = ops.op_fs_read(path);
+            const encoding = fsEncoding(options);
+            return encoding ? new TextDecoder(encoding).decode(bytes) : bytes;
+        },
+        writeFile(path, data) {
+            ops.op_fs_write(String(path), fsData(data), false);
+        },
+        appendFile(path, data) {
+            ops.op_fs_write(String(path), fsData(data), true);
+        },
+        readdir(path = '/') {
+            path = String(path);
+            const stat = ops.op_fs_stat(path);
+            if (stat === null) throw fsError('ENOENT', 'scandir', path);
+            if (!stat.isDirectory) throw fsError('ENOTDIR', 'scandir', path);
+            return ops.op_fs_list(path);
+        },
+        stat(path) {
+            path = String(path);
+            const stat = ops.op_fs_stat(path);
+            if (stat === null) throw fsError('ENOENT', 'stat', path);
+            return {
+                size: stat.size,
+                isFile: () => !stat.isDirectory,
+                isDirectory: () => stat.isDirectory,
+            };
+        },
+        exists(path) {
+            return ops.op_fs_stat(String(path)) !== null;
+        },
+        unlink(path) {
+            path = String(path);
+            if (!ops.op_fs_remove(path)) throw fsError('ENOENT', 'unlink', path);
+        },
+    });
+    vortex.fs = fs;
+
+    // Bytes are decoded as UTF-8; anything else is stringified
+    function outputChunk(chunk) {
+        if (typeof chunk === 'string') return chunk;
+        if (chunk instanceof ArrayBuffer || ArrayBuffer.isView(chunk)) {
+            return new TextDecoder().decode(chunk);
+        }
+        return String(chunk);
+    }
+
+    // =========================================================================
+    // Timers
+    // =========================================================================
+
+    let timerId = 0;
+    const activeTimers = new Map();
+
+    // Mirror the Rust-side pending op limit so runaway timer creation fails
+    // synchronously at the call site rather than as an unhandled rejection.
+    // Each timer maps to the cancel handle its op_sleep calls wait on.
+    function reserveTimer() {
+        const limit = config.maxPendingOps;
+        if (limit !== null && limit !== undefined && activeTimers.size >= limit) {
+            throw new RangeError(`Too many pending timers (limit: ${limit})`);
+        }
+        const id = ++timerId;
+        activeTimers.set(id, core.createCancelHandle());
+        return id;
+    }
+
+    // Sleep for a timer; false once the timer is cleared. Other failures
+    // (e.g. the pending op limit, shared with fetch and friends) are
+    // reported like an uncaught error instead of rejecting nowhere.
+    async function sleepTimer(id, delay) {
+        try {
+            return await ops.op_sleep(BigInt(Math.max(0, Number(delay) || 0)), activeTimers.get(id));
+        } catch (e) {
+            console.error('Uncaught (in timer)', e && e.stack ? e.stack : e);
+            return false;
+        }
+    }
+
+    // A sleep for the runtime's own retries, which user code can't clear
+    async function sleep(delay) {
+        const rid = core.createCancelHandle();
+        try {
+            return await ops.op_sleep(BigInt(delay), rid);
+        } finally {
+            core.tryClose(rid);
+        }
+    }
+
+    function releaseTimer(id) {
+        const rid = activeTimers.get(id);
+        if (rid !== undefined) {
+            activeTimers.delete(id);
+            core.tryClose(rid);
+        }
+    }
+
+    // setTimeout using PROPER async sleep (backed by tokio via op_sleep)
+    globalThis.setTimeout = (callback, delay = 0, ...args) => {
+        const id = reserveTimer();
+
+        (async () => {
+            const fired = await sleepTimer(id, delay);
+            // Only call callback if timer wasn't cleared
+            const active = activeTimers.has(id);
+            releaseTimer(id);
+            if (fired && active && typeof callback === 'function') {
+                callback(...args);
+            }
+        })();
+
+        return id;
+    };
+
+    globalThis.clearTimeout = (id) => {
+        releaseTimer(id);
+    };
+
+    // setInterval using proper async sleep
+    globalThis.setInterval = (callback, delay = 0, ...args) => {
+        const id = reserveTimer();
+
+        (async () => {
+            try {
+                while (activeTimers.has(id)) {
+                    if (!(await sleepTimer(id, delay))) break;
+                    if (activeTimers.has(id) && typeof callback === 'function') {
+                        callback(...args);
+                    }
+                }
+            } finally {
+                releaseTimer(id);
+            }
+        })();
+
+        return id;
+    };
+
+    globalThis.clearInterval = (id) => {
+        releaseTimer(id);
+    };
+
+    globalThis.queueMicrotask = (callback) => {
+        if (typeof callback !== 'function') {
+            throw new TypeError('queueMicrotask: callback must be a function');
+        }
+        Promise.resolve().then(callback).catch((e) => {
+            console.error('Uncaught (in microtask)', e && e.stack ? e.stack : e);
+        });
+    };
+
+    // =========================================================================
+    // DOMException, Event, EventTarget, AbortController
+    // =========================================================================
+
+    const DOM_EXCEPTION_CODES = {
+        IndexSizeError: 1,
+        NotFoundError: 8,
+        NotSupportedError: 9,
+        InvalidStateError: 11,
+        SyntaxError: 12,
+        InvalidAccessError: 15,
+        TypeMismatchError: 17,
+        SecurityError: 18,
+        NetworkError: 19,
+        AbortError: 20,
+        TimeoutError: 23,
+        DataCloneError: 25,
+    };
+
+    class DOMException extends Error {
+        constructor(message = '', name = 'Error') {
+            super(message);
+            Object.defineProperty(this, 'name', { value: name, configurable: true, writable: true });
+        }
+
+        get code() {
+            return DOM_EXCEPTION_CODES[this.name] || 0;
+        }
+    }
+
+    class Event {
+        #stopped = false;
+
+        constructor(type, init = {}) {
+            if (arguments.length === 0) {
+                throw new TypeError("Event: 'type' argument is required");
+            }
+            this.type = String(type);
+            this.bubbles = Boolean(init.bubbles);
+            this.cancelable = Boolean(init.cancelable);
+            this.composed = Boolean(init.composed);
+            this.defaultPrevented = false;
+            this.target = null;
+            this.currentTarget = null;
+            this.timeStamp = performance.now();
+        }
+
+        preventDefault() {
+            if (this.cancelable) {
+                this.defaultPrevented = true;
+            }
+        }
+
+        stopPropagation() {}
+
+        stopImmediatePropagation() {
+            this.#stopped = true;
+        }
+
+        static isImmediatePropagationStopped(event) {
+            return #stopped in event && event.#stopped;
+        }
+    }
+
+    const isStopped = Event.isImmediatePropagationStopped;
+    delete Event.isImmediatePropagationStopped;
+
+    class EventTarget {
+        #listeners = new Map();
+
+        addEventListener(type, listener, options = {}) {
+            if (listener === null || listener === undefined) return;
+            const once = typeof options === 'object' && Boolean(options.once);
+            const signal = typeof options === 'object' ? options.signal : undefined;
+            if (signal && signal.aborted) return;
+
+            let list = this.#listeners.get(type);
+            if (!list) {
+                list = [];
+                this.#listeners.set(type, list);
+            }
+            if (list.some((entry) => entry.listener === listener)) return;
+            list.push({ listener, once });
+
+            if (signal) {
+                signal.addEventListener('abort', () => this.removeEventListener(type, listener));
+            }
+        }
+
+        removeEventListener(type, listener) {
+            const list = this.#listeners.get(type);
+            if (!list) return;
+            const index = list.findIndex((entry) => entry.listener === listener);
+            if (index !== -1) list.splice(index, 1);
+        }
+
+        dispatchEvent(event) {
+            if (!(event instanceof Event)) {
+                throw new TypeError('dispatchEvent: argument must be an Event');
+            }
+            event.target = this;
+            event.currentTarget = this;
+            const list = (this.#listeners.get(event.type) || []).slice();
+            for (const entry of list) {
+                if (entry.once) this.removeEventListener(event.type, entry.listener);
+                const handler = typeof entry.listener === 'function'
+                    ? entry.listener
+                    : entry.listener.handleEvent.bind(entry.listener);
+                try {
+                    handler.call(this, event);
+                } catch (e) {
+                    console.error('Uncaught (in event listener)', e && e.stack ? e.stack : e);
+                }
+                if (isStopped(event)) break;
+            }
+            event.currentTarget = null;
+            return !event.defaultPrevented;
+        }
+    }
+
+    const ABORT_SIGNAL_INTERNAL = Symbol('AbortSignal.internal');
+
+    class AbortSignal extends EventTarget {
+        #aborted = false;
+        #reason = undefined;
+
+        constructor(key) {
+            if (key !== ABORT_SIGNAL_INTERNAL) {
+                throw new TypeError('Illegal constructor');
+            }
+            super();
+            this.onabort = null;
+        }
+
+        get aborted() {
+            return this.#aborted;
+        }
+
+        get reason() {
+            return this.#reason;
+        }
+
+        throwIfAborted() {
+            if (this.#aborted) throw this.#reason;
+        }
+
+        static abort(reason) {
+            const signal = new AbortSignal(ABORT_SIGNAL_INTERNAL);
+            signal.#abort(reason);
+            return signal;
+        }
+
+        static timeout(ms) {
+            const signal = new AbortSignal(ABORT_SIGNAL_INTERNAL);
+            globalThis.setTimeout(() => {
+                signal.#abort(new DOMException('The operation timed out.', 'TimeoutError'));
+            }, ms);
+            return signal;
+        }
+
+        static any(signals) {
+            const signal = new AbortSignal(ABORT_SIGNAL_INTERNAL);
+            for (const source of signals) {
+                if (source.aborted) {
+                    signal.#abort(source.reason);
+                    return signal;
+                }
+            }
+            for (const source of signals) {
+                source.addEventListener('abort', () => signal.#abort(source.reason), { once: true });
+            }
+            return signal;
+        }
+
+        static signalAbort(signal, reason) {
+            signal.#abort(reason);
+        }
+
+        #abort(reason) {
+            if (this.#aborted) return;
+            this.#aborted = true;
+            this.#reason = reason === undefined
+                ? new DOMException('This operation was aborted', 'AbortError')
+                : reason;
+            const event = new Event('abort');
+            if (typeof this.onabort === 'function') {
+                this.onabort(event);
+            }
+            this.dispatchEvent(event);
+        }
+    }
+
+    const signalAbort = AbortSignal.signalAbort;
+    delete AbortSignal.signalAbort;
+
+    class AbortController {
+        #signal = new AbortSignal(ABORT_SIGNAL_INTERNAL);
+
+        get signal() {
+            return this.#signal;
+        }
+
+        abort(reason) {
+            signalAbort(this.#signal, reason);
+        }
+    }
+
+    // =========================================================================
+    // Performance and navigator
+    // =========================================================================
+
+    const timeOrigin = ops.op_get_time_ms();
+
+    const performance = {
+        timeOrigin,
+        now: () => ops.op_get_time_ms() - timeOrigin,
+        toJSON: () => ({ timeOrigin }),
+    };
+
+    const navigator = {
+        userAgent: `Vortex/${vortex.version}`,
+    };
+
+    // =========================================================================
+    // Encoding: TextEncoder, TextDecoder, atob, btoa
+    // =========================================================================
+
+    function utf8Encode(input) {
+        const str = String(input);
+        const out = [];
+        for (let i = 0; i < str.length; i++) {
+            let code = str.charCodeAt(i);
+            if (code >= 0xd800 && code <= 0xdbff && i + 1 < str.length) {
+                const next = str.charCodeAt(i + 1);
+                if (next >= 0xdc00 && next <= 0xdfff) {
+                    code = 0x10000 + ((code - 0xd800) << 10) + (next - 0xdc00);
+                    i++;
+                }
+            }
+            // Lone surrogates are replaced with U+FFFD per the Encoding spec
+            if (code >= 0xd800 && code <= 0xdfff) code = 0xfffd;
+
+            if (code < 0x80) {
+                out.push(code);
+            } else if (code < 0x800) {
+                out.push(0xc0 | (code >> 6), 0x80 | (code & 0x3f));
+            } else if (code < 0x10000) {
+                out.push(0xe0 | (code >> 12), 0x80 | ((code >> 6) & 0x3f), 0x80 | (code & 0x3f));
+            } else {
+                out.push(
+                    0xf0 | (code >> 18),
+                    0x80 | ((code >> 12) & 0x3f),
+                    0x80 | ((code >> 6) & 0x3f),
+                    0x80 | (code & 0x3f),
+                );
+            }
+        }
+        return new Uint8Array(out);
+    }
+
+    function utf8Decode(bytes, fatal) {
+        let out = '';
+        let i = 0;
+        const fail = () => {
+            if (fatal) throw new TypeError('The encoded data was not valid utf-8');
+            out += '�';
+        };
+        while (i < bytes.length) {
+            const byte = bytes[i];
+            let needed = 0;
+            let code = 0;
+            let lower = 0x80;
+            let upper = 0xbf;
+            if (byte < 0x80) {
+                out += String.fromCharCode(byte);
+                i++;
+                continue;
+            } else if (byte >= 0xc2 && byte <= 0xdf) {
+                needed = 1;
+                code = byte & 0x1f;
+            } else if (byte >= 0xe0 && byte <= 0xef) {
+                needed = 2;
+                code = byte & 0x0f;
+                if (byte === 0xe0) lower = 0xa0;
+                if (byte === 0xed) upper = 0x9f;
+            } else if (byte >= 0xf0 && byte <= 0xf4) {
+                needed = 3;
+                code = byte & 0x07;
+                if (byte === 0xf0) lower = 0x90;
+                if (byte === 0xf4) upper = 0x8f;
+            } else {
+                fail();
+                i++;
+                continue;
+            }
+
+            let j = 1;
+            for (; j <= needed; j++) {
+                const next = bytes[i + j];
+                if (next === undefined || next < lower || next > upper) break;
+                lower = 0x80;
+                upper = 0xbf;
+                code = (code << 6) | (next & 0x3f);
+            }
+            if (j <= needed) {
+                fail();
+                i += j;
+                continue;
+            }
+            out += String.fromCodePoint(code);
+            i += needed + 1;
+        }
+        return out;
+    }
+
+    function toUint8Array(input) {
+        if (input === undefined) return new Uint8Array(0);
+        if (input instanceof Uint8Array) return input;
+        if (input instanceof ArrayBuffer) return new Uint8Array(input);
+        if (ArrayBuffer.isView(input)) {
+            return new Uint8Array(input.buffer, input.byteOffset, input.byteLength);
+        }
+        throw new TypeError('Expected an ArrayBuffer or ArrayBufferView');
+    }
+
+    class TextEncoder {
+        get encoding() {
+            return 'utf-8';
+        }
+
+        encode(input = '') {
+            return utf8Encode(input);
+        }
+
+        encodeInto(input, dest) {
+            const str = String(input);
+            let read = 0;
+            let written = 0;
+            for (const char of str) {
+                const bytes = utf8Encode(char);
+                if (written + bytes.length > dest.length) break;
+                dest.set(bytes, written);
+                written += bytes.length;
+                read += char.length;
+            }
+            return { read, written };
+        }
+    }
+
+    class TextDecoder {
+        #fatal;
+        #ignoreBOM;
+
+        constructor(label = 'utf-8', options = {}) {
+            const normalized = String(label).trim().toLowerCase();
+            if (normalized !== 'utf-8' && normalized !== 'utf8' && normalized !== 'unicode-1-1-utf-8') {
+                throw new RangeError(`TextDecoder: unsupported encoding '${label}'`);
+            }
+            this.#fatal = Boolean(options.fatal);
+            this.#ignoreBOM = Boolean(options.ignoreBOM);
+        }
+
+        get encoding() {
+            return 'utf-8';
+        }
+
+        get fatal() {
+            return this.#fatal;
+        }
+
+        get ignoreBOM() {
+            return this.#ignoreBOM;
+        }
+
+        decode(input, options = {}) {
+            if (options.stream) {
+                throw new TypeError('TextDecoder: streaming decode is not supported');
+            }
+            let bytes = toUint8Array(input);
+            if (!this.#ignoreBOM && bytes[0] === 0xef && bytes[1] === 0xbb && bytes[2] === 0xbf) {
+                bytes = bytes.subarray(3);
+            }
+            return utf8Decode(bytes, this.#fatal);
+        }
+    }
+
+    const BASE64_ALPHABET = 'ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/';
+
+    function btoa(data) {
+        const str = String(data);
+        let out = '';
+        for (let i = 0; i < str.length; i += 3) {
+            const codes = [str.charCodeAt(i), str.charCodeAt(i + 1), str.charCodeAt(i + 2)];
+            for (let j = 0; j < 3; j++) {
+                if (codes[j] > 0xff) {
+                    throw new DOMException(
+                        "Failed to execute 'btoa': The string to be encoded contains characters outside of the Latin1 range.",
+                        'InvalidCharacterError',
+                    );
+                }
+            }
+            const [a, b, c] = codes;
+            out += BASE64_ALPHABET[a >> 2];
+            out += BASE64_ALPHABET[((a & 0x03) << 4) | (isNaN(b) ? 0 : b >> 4)];
+            out += isNaN(b) ? '=' : BASE64_ALPHABET[((b & 0x0f) << 2) | (isNaN(c) ? 0 : c >> 6)];
+            out += isNaN(c) ? '=' : BASE64_ALPHABET[c & 0x3f];
+        }
+        return out;
+    }
+
+    function atob(data) {
+        let str = String(data).replace(/[\t\n\f\r ]/g, '');
+        if (str.length % 4 === 0) str = str.replace(/==?$/, '');
+        if (str.length % 4 === 1 || /[^A-Za-z0-9+/]/.test(str)) {
+            throw new DOMException(
+                "Failed to execute 'atob': The string to be decoded is not correctly encoded.",
+                'InvalidCharacterError',
+            );
+        }
+        let out = '';
+        let buffer = 0;
+        let bits = 0;
+        for (const char of str) {
+            buffer = (buffer << 6) | BASE64_ALPHABET.indexOf(char);
+            bits += 6;
+            if (bits >= 8) {
+                bits -= 8;
+                out += String.fromCharCode((buffer >> bits) & 0xff);
+            }
+        }
+        return out;
+    }
+
+    // =========================================================================
+    // structuredClone
+    // =========================================================================
+
+    const TYPED_ARRAYS = [
+        Int8Array, Uint8Array, Uint8ClampedArray, Int16Array, Uint16Array,
+        Int32Array, Uint32Array, Float32Array, Float64Array, BigInt64Array, BigUint64Array,
+    ];
+
+    function structuredClone(value) {
+        const seen = new Map();
+
+        const clone = (input) => {
+            if (input === null || (typeof input !== 'object' && typeof input !== 'function' && typeof input !== 'symbol')) {
+                return input;
+            }
+            if (typeof input === 'function' || typeof input === 'symbol') {
+                throw new DOMException(`${String(input)} could not be cloned.`, 'DataCloneError');
+            }
+            if (seen.has(input)) return seen.get(input);
+
+            let output;
+            if (Array.isArray(input)) {
+                output = new Array(input.length);
+                seen.set(input, output);
+                for (let i = 0; i < input.length; i++) {
+                    if (i in input) output[i] = clone(input[i]);
+                }
+                return output;
+            }
+            if (input instanceof Date) {
+                output = new Date(input.getTime());
+            } else if (input instanceof RegExp) {
+                output = new RegExp(input.source, input.flags);
+            } else if (input instanceof ArrayBuffer) {
+                output = input.slice(0);
+            } else if (ArrayBuffer.isView(input)) {
+                const ctor = TYPED_ARRAYS.find((T) => input instanceof T) || DataView;
+                const buffer = clone(input.buffer);
+                output = ctor === DataView
+                    ? new DataView(buffer, input.byteOffset, input.byteLength)
+                    : new ctor(buffer, input.byteOffset, input.length);
+            } else if (input instanceof Map) {
+                output = new Map();
+                seen.set(input, output);
+                for (const [k, v] of input) output.set(clone(k), clone(v));
+                return output;
+            } else if (input instanceof Set) {
+                output = new Set();
+                seen.set(input, output);
+                for (const v of input) output.add(clone(v));
+                return output;
+            } else if (input instanceof Error) {
+                const ctor = [EvalError, RangeError, ReferenceError, SyntaxError, TypeError, URIError]
+                    .find((E) => input instanceof E) || Error;
+                output = new ctor(input.message);
+                seen.set(input, output);
+                if (input.stack !== undefined) output.stack = String(input.stack);
+                if ('cause' in input) output.cause = clone(input.cause);
+                return output;
+            } else if (input instanceof Boolean || input instanceof Number || input instanceof String) {
+                output = Object(input.valueOf());
+            } else if (Object.prototype.toString.call(input) === '[object Object]') {
+                output = {};
+                seen.set(input, output);
+                for (const key of Object.keys(input)) output[key] = clone(input[key]);
+                return output;
+            } else {
+                throw new DOMException(
+                    `${Object.prototype.toString.call(input)} could not be cloned.`,
+                    'DataCloneError',
+                );
+            }
+            seen.set(input, output);
+            return output;
+        };
+
+        return clone(value);
+    }
+
+    // =========================================================================
+    // URL and URLSearchParams
+    // =========================================================================
+
+    function formEncode(str) {
+        return encodeURIComponent(str)
+            .replace(/%20/g, '+')
+            .replace(/[!'()~]/g, (c) => '%' + c.charCodeAt(0).toString(16).toUpperCase());
+    }
+
+    function formDecode(str) {
+        try {
+            return decodeURIComponent(str.replace(/\+/g, ' '));
+        } catch (e) {
+            return str;
+        }
+    }
+
+    class URLSearchParams {
+        #list = [];
+        #onUpdate = null;
+
+        constructor(init = '') {
+            if (typeof init === 'object' && init !== null) {
+                if (typeof init[Symbol.iterator] === 'function') {
+                    for (const pair of init) {
+                        const [name, value] = Array.from(pair);
+                        this.#list.push([String(name), String(value)]);
+                    }
+                } else {
+                    for (const key of Object.keys(init)) {
+                        this.#list.push([key, String(init[key])]);
+                    }
+                }
+            } else {
+                this.#parse(String(init));
+            }
+        }
+
+        #parse(query) {
+            this.#list = [];
+            if (query.startsWith('?')) query = query.slice(1);
+            for (const part of query.split('&')) {
+                if (part === '') continue;
+                const index = part.indexOf('=');
+                const name = index === -1 ? part : part.slice(0, index);
+                const value = index === -1 ? '' : part.slice(index + 1);
+                this.#list.push([formDecode(name), formDecode(value)]);
+            }
+        }
+
+        #update() {
+            if (this.#onUpdate) this.#onUpdate(this.toString());
+        }
+
+        get size() {
+            return this.#list.length;
+        }
+
+        append(name, value) {
+            this.#list.push([String(name), String(value)]);
+            this.#update();
+        }
+
+        delete(name, value) {
+            this.#list = this.#list.filter(([n, v]) => n !== String(name) || (value !== undefined && v !== String(value)));
+            this.#update();
+        }
+
+        get(name) {
+            const entry = this.#list.find(([n]) => n === String(name));
+            return entry ? entry[1] : null;
+        }
+
+        getAll(name) {
+            return this.#list.filter(([n]) => n === String(name)).map(([, v]) => v);
+        }
+
+        has(name, value) {
+            return this.#list.some(([n, v]) => n === String(name) && (value === undefined || v === String(value)));
+        }
+
+        set(name, value) {
+            name = String(name);
+            value = String(value);
+            const index = this.#list.findIndex(([n]) => n === name);
+            if (index === -1) {
+                this.#list.push([name, value]);
+            } else {
+                this.#list[index][1] = value;
+                this.#list = this.#list.filter(([n], i) => n !== name || i <= index);
+            }
+            this.#update();
+        }
+
+        sort() {
+            this.#list.sort(([a], [b]) => (a < b ? -1 : a > b ? 1 : 0));
+            this.#update();
+        }
+
+        forEach(callback, thisArg) {
+            for (const [name, value] of this.#list) {
+                callback.call(thisArg, value, name, this);
+            }
+        }
+
+        keys() {
+            return this.#list.map(([n]) => n)[Symbol.iterator]();
+        }
+
+        values() {
+            return this.#list.map(([, v]) => v)[Symbol.iterator]();
+        }
+
+        entries() {
+            return this.#list.map(([n, v]) => [n, v])[Symbol.iterator]();
+        }
+
+        [Symbol.iterator]() {
+            return this.entries();
+        }
+
+        toString() {
+            return this.#list.map(([n, v]) => `${formEncode(n)}=${formEncode(v)}`).join('&');
+        }
+
+        static attach(params, query, onUpdate) {
+            params.#parse(query);
+            params.#onUpdate = onUpdate;
+        }
+    }
+
+    const attachSearchParams = URLSearchParams.attach;
+    delete URLSearchParams.attach;
+
+    // URL parsing is delegated to the WHATWG-compliant `url` crate via op_url_parse
+    class URL {
+        #parts;
+        #searchParams = new URLSearchParams();
+
+        constructor(url, base) {
+            this.#set(ops.op_url_parse(
+                String(url),
+                base === undefined ? null : String(base),
+            ));
+        }
+
+        static canParse(url, base) {
+            try {
+                new URL(url, base);
+                return true;
+            } catch (e) {
+                return false;
+            }
+        }
+
+        #set(parts) {
+            this.#parts = parts;
+            attachSearchParams(this.#searchParams, parts.search, (query) => {
+                this.#parts = ops.op_url_set(this.#parts.href, 'search', query);
+            });
+        }
+
+        #update(component, value) {
+            this.#set(ops.op_url_set(this.#parts.href, component, String(value)));
+        }
+
+        get href() { return this.#parts.href; }
+        set href(value) { this.#set(ops.op_url_parse(String(value), null)); }
+        get origin() { return this.#parts.origin; }
+        get protocol() { return this.#parts.protocol; }
+        set protocol(value) { this.#update('protocol', value); }
+        get username() { return this.#parts.username; }
+        set username(value) { this.#update('username', value); }
+        get password() { return this.#parts.password; }
+        set password(value) { this.#update('password', value); }
+        get host() { return this.#parts.host; }
+        set host(value) { this.#update('host', value); }
+        get hostname() { return this.#parts.hostname; }
+        set hostname(value) { this.#update('hostname', value); }
+        get port() { return this.#parts.port; }
+        set port(value) { this.#update('port', value); }
+        get pathname() { return this.#parts.pathname; }
+        set pathname(value) { this.#update('pathname', value); }
+        get search() { return this.#parts.search; }
+        set search(value) { this.#update('search', value); }
+        get hash() { return this.#parts.hash; }
+        set hash(value) { this.#update('hash', value); }
+        get searchParams() { return this.#searchParams; }
+
+        toString() {
+            return this.href;
+        }
+
+        toJSON() {
+            return this.href;
+        }
+    }
+
+    // =========================================================================
+    // Crypto (getRandomValues / randomUUID)
+    // =========================================================================
+
+    const crypto = {
+        getRandomValues(array) {
+            if (!(array instanceof Int8Array || array instanceof Uint8Array ||
+                array instanceof Uint8ClampedArray || array instanceof Int16Array ||
+                array instanceof Uint16Array || array instanceof Int32Array ||
+                array instanceof Uint32Array || array instanceof BigInt64Array ||
+                array instanceof BigUint64Array)) {
+                throw new DOMException('getRandomValues: argument must be an integer TypedArray', 'TypeMismatchError');
+            }
+            if (array.byteLength > 65536) {
+                throw new DOMException(
+                    `getRandomValues: ArrayBufferView byte length (${array.byteLength}) exceeds 65536`,
+                    'QuotaExceededError',
+                );
+            }
+            ops.op_get_random_values(new Uint8Array(array.buffer, array.byteOffset, array.byteLength));
+            return array;
+        },
+
+        randomUUID() {
+            const bytes = crypto.getRandomValues(new Uint8Array(16));
+            bytes[6] = (bytes[6] & 0x0f) | 0x40;
+            bytes[8] = (bytes[8] & 0x3f) | 0x80;
+            const hex = Array.from(bytes, (b) => b.toString(16).padStart(2, '0')).join('');
+            return `${hex.slice(0, 8)}-${hex.slice(8, 12)}-${hex.slice(12, 16)}-${hex.slice(16, 20)}-${hex.slice(20)}`;
+        },
+    };
+
+    // vortex.crypto.verifyHmac(): webhook signatures checked against a secret
+    // the host holds, so user code never handles (or leaks) the secret itself
+    const HMAC_DIGEST_LENGTHS = { sha1: 20, sha256: 32, sha384: 48, sha512: 64 };
+
+    // Signatures arrive as hex or base64, sometimes behind a `sha256=` prefix
+    // (GitHub); an undecodable signature simply never matches
+    function hmacSignature(signature, algorithm) {
+        if (typeof signature !== 'string') return fsData(signature);
+        let text = signature.trim();
+        if (text.toLowerCase().startsWith(`${algorithm}=`)) text = text.slice(algorithm.length + 1);
+        if (text.length === HMAC_DIGEST_LENGTHS[algorithm] * 2 && /^[0-9a-f]+$/i.test(text)) {
+            return Uint8Array.from(text.match(/../g), (hex) => parseInt(hex, 16));
+        }
+        try {
+            const base64 = text.replace(/-/g, '+').replace(/_/g, '/');
+            const binary = atob(base64.padEnd(Math.ceil(base64.length / 4) * 4, '='));
+            return Uint8Array.from(binary, (c) => c.charCodeAt(0));
+        } catch {
+            return new Uint8Array(0);
+        }
+    }
+
+    vortex.crypto = Object.freeze({
+        verifyHmac(secretRef, payload, signature, algorithm = 'sha256') {
+            algorithm = String(algorithm).toLowerCase().replace('-', '');
+            return ops.op_crypto_verify_hmac(
+                String(secretRef),
+                fsData(payload),
+                hmacSignature(signature, algorithm),
+                algorithm,
+            );
+        },
+    });
+
+    // vortex.jwt: HS256/RS256/ES256 tokens signed and verified in Rust. `key`
+    // names a secret in the host's secrets provider (an HMAC key, or a PEM
+    // private key for sign / public key for verify)
+    function jwtKey(method, key) {
+        if (key === undefined) throw new TypeError(`vortex.jwt.${method}: options.key is required`);
+        return String(key);
+    }
+
+    function jwtList(value) {
+        return value === undefined ? [] : [].concat(value).map(String);
+    }
+
+    vortex.jwt = Object.freeze({
+        // Adds `iat`, and `exp` when `expiresIn` (seconds) is given
+        sign(payload, { key, algorithm = 'HS256', expiresIn, keyId } = {}) {
+            if (payload === null || typeof payload !== 'object' || Array.isArray(payload)) {
+                throw new TypeError('vortex.jwt.sign: payload must be an object');
+            }
+            const now = Math.floor(Date.now() / 1000);
+            const claims = { iat: now, ...payload };
+            if (expiresIn !== undefined) claims.exp = now + Math.floor(Number(expiresIn));
+            return ops.op_jwt_sign(claims, {
+                key: jwtKey('sign', key),
+                algorithm: String(algorithm),
+                keyId: keyId === undefined ? undefined : String(keyId),
+            });
+        },
+        // Returns the claims; throws a JwtError unless the token checks out
+        verify(token, { key, algorithms = ['HS256'], audience, issuer, leeway = 0, requireExp = true } = {}) {
+            return ops.op_jwt_verify(String(token), {
+                key: jwtKey('verify', key),
+                algorithms: jwtList(algorithms),
+                audience: jwtList(audience),
+                issuer: jwtList(issuer),
+                leeway: Math.max(0, Math.floor(Number(leeway)) || 0),
+                requireExp: Boolean(requireExp),
+            });
+        },
+    });
+
+    // =========================================================================
+    // vortex.cookies: Cookie header parsing, Set-Cookie serialization, signing
+    // =========================================================================
+
+    // RFC 6265 cookie-name token
+    const COOKIE_NAME = /^[!#$%&'*+\-.^_`|~0-9A-Za-z]+$/;
+    const SAME_SITE = ['Strict', 'Lax', 'None'];
+
+    function cookieDecode(value) {
+        try {
+            return decodeURIComponent(value);
+        } catch {
+            return value;
+        }
+    }
+
+    function base64url(bytes) {
+        let binary = '';
+        for (const byte of bytes) binary += String.fromCharCode(byte);
+        return btoa(binary).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+    }
+
+    vortex.cookies = Object.freeze({
+        // `Cookie` header to `{ name: value }`; the first of duplicate names wins
+        parse(header) {
+            const cookies = {};
+            for (const pair of String(header ?? '').split(';')) {
+                const eq = pair.indexOf('=');
+                if (eq === -1) continue;
+                const name = pair.slice(0, eq).trim();
+                let value = pair.slice(eq + 1).trim();
+                if (value.length >= 2 && value.startsWith('"') && value.endsWith('"')) value = value.slice(1, -1);
+                if (name && !Object.hasOwn(cookies, name)) cookies[name] = cookieDecode(value);
+            }
+            return cookies;
+        },
+        // A `Set-Cookie` header value. The value is percent-encoded
+        serialize(name, value, options = {}) {
+            name = String(name);
+            if (!COOKIE_NAME.test(name)) throw new TypeError(`vortex.cookies.serialize: invalid cookie name '${name}'`);
+            let cookie = `${name}=${encodeURIComponent(String(value))}`;
+            const { maxAge, expires, domain, path, secure, httpOnly, sameSite, partitioned } = options;
+            if (maxAge !== undefined) {
+                if (!Number.isFinite(Number(maxAge))) throw new TypeError('vortex.cookies.serialize: maxAge must be a number');
+                cookie += `; Max-Age=${Math.floor(Number(maxAge))}`;
+            }
+            if (expires !== undefined) {
+                const date = expires instanceof Date ? expires : new Date(expires);
+                if (Number.isNaN(date.getTime())) throw new TypeError('vortex.cookies.serialize: invalid expires date');
+                cookie += `; Expires=${date.toUTCString()}`;
+            }
+            for (const [attribute, text] of [['Domain', domain], ['Path', path]]) {
+                if (text === undefined) continue;
+                if (/[;\x00-\x1f\x7f]/.test(String(text))) {
+                    throw new TypeError(`vortex.cookies.serialize: invalid ${attribute.toLowerCase()}`);
+                }
+                cookie += `; ${attribute}=${text}`;
+            }
+            if (httpOnly) cookie += '; HttpOnly';
+            // SameSite=None and Partitioned are only honored on Secure cookies
+            if (secure || sameSite === 'none' || sameSite === 'None' || partitioned) cookie += '; Secure';
+            if (sameSite !== undefined) {
+                const normalized = SAME_SITE.find((option) => option.toLowerCase() === String(sameSite).toLowerCase());
+                if (!normalized) throw new TypeError(`vortex.cookies.serialize: invalid sameSite '${sameSite}'`);
+                cookie += `; SameSite=${normalized}`;
+            }
+            if (partitioned) cookie += '; Partitioned';
+            return cookie;
+        },
+        // `value.signature`, an HMAC-SHA256 under the host-held secret
+        // `secretRef`, so a client can read the cookie but not forge it
+        sign(value, secretRef) {
+            value = String(value);
+            return `${value}.${base64url(ops.op_crypto_sign_hmac(String(secretRef), fsData(value), 'sha256'))}`;
+        },
+        // The original value, or `null` if the signature doesn't match
+        unsign(signed, secretRef) {
+            signed = String(signed);
+            const dot = signed.lastIndexOf('.');
+            if (dot === -1) return null;
+            const value = signed.slice(0, dot);
+            const signature = hmacSignature(signed.slice(dot + 1), 'sha256');
+            return ops.op_crypto_verify_hmac(String(secretRef), fsData(value), signature, 'sha256') ? value : null;
+        },
+    });
+
+    // =========================================================================
+    // vortex.ratelimit: Redis-backed limits shared by every invocation
+    // =========================================================================
+
+    vortex.ratelimit = Object.freeze({
+        // Sliding window: allow at most `limit` calls per `windowMs` for `key`,
+        // e.g. `await vortex.ratelimit.check(`login:${userId}`, 5, 60_000)`
+        async check(key, limit, windowMs) {
+            limit = Number(limit);
+            windowMs = Number(windowMs);
+            if (!Number.isSafeInteger(limit) || limit < 0) {
+                throw new RangeError('vortex.ratelimit.check: limit must be a non-negative integer');
+            }
+            if (!Number.isSafeInteger(windowMs) || windowMs <= 0) {
+                throw new RangeError('vortex.ratelimit.check: window must be a positive number of milliseconds');
+            }
+            const decision = await ops.op_ratelimit_check(String(key), BigInt(limit), BigInt(windowMs));
+            return { ...decision, limit };
+        },
+    });
+
+    // =========================================================================
+    // vortex.lock: Redis-backed mutual exclusion across invocations
+    // =========================================================================
+
+    // How often acquire() retries while waiting for a held lock
+    const LOCK_RETRY_MS = 50;
+
+    class Lock {
+        #token;
+
+        constructor(name, token) {
+            this.name = name;
+            this.#token = token;
+        }
+
+        // Resolves to false if the TTL ran out first (someone else may hold it now)
+        release() {
+            return ops.op_lock_release(this.name, this.#token);
+        }
+    }
+
+    vortex.lock = Object.freeze({
+        // Resolves to a Lock, or null if `name` is still held after `wait` ms.
+        // The lock expires after `ttlMs` even if never released
+        async acquire(name, ttlMs, { wait = 0 } = {}) {
+            name = String(name);
+            ttlMs = Number(ttlMs);
+            if (!Number.isSafeInteger(ttlMs) || ttlMs <= 0) {
+                throw new RangeError('vortex.lock.acquire: ttl must be a positive number of milliseconds');
+            }
+            const deadline = Date.now() + Math.max(0, Number(wait) || 0);
+            for (;;) {
+                const token = await ops.op_lock_acquire(name, BigInt(ttlMs));
+                if (token !== null) return new Lock(name, token);
+                const remaining = deadline - Date.now();
+                if (remaining <= 0) return null;
+                await sleep(Math.min(LOCK_RETRY_MS, remaining));
+            }
+        },
+        release(lock) {
+            if (!(lock instanceof Lock)) {
+                throw new TypeError('vortex.lock.release: expected a lock returned by vortex.lock.acquire()');
+            }
+            return lock.release();
+        },
+    });
+
+    // =========================================================================
+    // vortex.kv: key-value storage shared by every invocation (kv.rs)
+    // =========================================================================
+
+    // Values are stored as JSON. Needs the kv capability. Every write gets a
+    // new revision, which putIfMatch() compares against for optimistic
+    // concurrency:
+    //
+    //   const entry = await vortex.kv.getWithRevision('cart:42');
+    //   const cart = entry?.value ?? { items: [] };
+    //   cart.items.push(item);
+    //   if (await vortex.kv.putIfMatch('cart:42', cart, entry?.revision ?? null) === null) retry();
+    const KV_LIST_LIMIT = 1000;
+
+    // `ttl` in milliseconds, as BigInt for the op; 0n keeps the value until deleted
+    function kvTtl(method, options) {
+        const ttl = options?.ttl;
+        if (ttl === undefined || ttl === null) return 0n;
+        if (!Number.isSafeInteger(ttl) || ttl <= 0) {
+            throw new RangeError(`vortex.kv.${method}: ttl must be a positive number of milliseconds`);
+        }
+        return BigInt(ttl);
+    }
+
+    function kvValue(method, value) {
+        if (value === undefined) {
+            throw new TypeError(`vortex.kv.${method}: value must not be undefined`);
+        }
+        return JSON.stringify(value);
+    }
+
+    vortex.kv = Object.freeze({
+        // Resolves to null for a missing key
+        async get(key) {
+            const entry = await ops.op_kv_get(String(key));
+            return entry === null ? null : JSON.parse(entry.value);
+        },
+        // Resolves to { value, revision }, or null for a missing key
+        async getWithRevision(key) {
+            const entry = await ops.op_kv_get(String(key));
+            return entry === null ? null : Object.freeze({ value: JSON.parse(entry.value), revision: entry.revision });
+        },
+        // Resolves to the new revision. Options: `ttl` in milliseconds
+        async put(key, value, options = {}) {
+            return ops.op_kv_put(String(key), kvValue('put', value), kvTtl('put', options), null);
+        },
+        // Writes only if the key is still at `revision` (null: doesn't exist).
+        // Resolves to the new revision, or null if someone else wrote first
+        async putIfMatch(key, value, revision, options = {}) {
+            if (revision !== null && (!Number.isSafeInteger(revision) || revision <= 0)) {
+                throw new TypeError('vortex.kv.putIfMatch: revision must be a revision from getWithRevision() or null');
+            }
+            return ops.op_kv_put(String(key), kvValue('putIfMatch', value), kvTtl('putIfMatch', options), revision ?? 0);
+        },
+        // Atomically adds `delta` to an integer value (missing keys count as
+        // 0) and resolves to the result
+        async incr(key, delta = 1) {
+            if (!Number.isSafeInteger(delta)) {
+                throw new TypeError('vortex.kv.incr: delta must be an integer');
+            }
+            return ops.op_kv_incr(String(key), BigInt(delta));
+        },
+        delete(key) {
+            return ops.op_kv_delete(String(key));
+        },
+        // One page of keys in order, as { keys, cursor }. Pass `cursor` back
+        // for the next page; it is null on the last one
+        async list({ prefix = '', limit = KV_LIST_LIMIT, cursor = null } = {}) {
+            limit = Number(limit);
+            if (!Number.isSafeInteger(limit) || limit < 1 || limit > KV_LIST_LIMIT) {
+                throw new RangeError(`vortex.kv.list: limit must be an integer from 1 to ${KV_LIST_LIMIT}`);
+            }
+            cursor = cursor == null ? null : String(cursor);
+            return Object.freeze(await ops.op_kv_list(String(prefix), limit, cursor));
+        },
+    });
+
+    // =========================================================================
+    // vortex.sqlite: the function's own embedded database (sqlite.rs)
+    // =========================================================================
+
+    // Needs the sql capability. Parameters bind to `?` placeholders and may
+    // be null, booleans, numbers or strings; blobs come back as arrays of
+    // bytes:
+    //
+    //   await vortex.sqlite.execute('INSERT INTO visits (path) VALUES (?)', [path]);
+    //   const [{ n }] = await vortex.sqlite.query('SELECT count(*) AS n FROM visits');
+    function sqliteParams(method, params) {
+        if (!Array.isArray(params)) {
+            throw new TypeError(`vortex.sqlite.${method}: params must be an array`);
+        }
+        return params;
+    }
+
+    vortex.sqlite = Object.freeze({
+        // Resolves to the rows as objects keyed by column name
+        async query(sql, params = []) {
+            return ops.op_sqlite_query(String(sql), sqliteParams('query', params));
+        },
+        // For statements that don't return rows. Resolves to
+        // { changes, lastInsertRowid }
+        async execute(sql, params = []) {
+            return Object.freeze(await ops.op_sqlite_execute(String(sql), sqliteParams('execute', params)));
+        },
+    });
+
+    // =========================================================================
+    // vortex.sql: the function's SQL database (sql.rs)
+    // =========================================================================
+
+    // Needs the sql capability. The database is the host's Postgres, through
+    // a connection pool every invocation shares, or else the function's
+    // vortex.sqlite database. Placeholders are the database's own ($1 or ?).
+    // Dates bind as ISO 8601 strings and typed arrays as arrays of bytes:
+    //
+    //   const rows = await vortex.sql.query('SELECT * FROM orders WHERE placed > $1', [since]);
+    function sqlParams(method, params) {
+        if (!Array.isArray(params)) {
+            throw new TypeError(`vortex.sql.${method}: params must be an array`);
+        }
+        return params.map((param) => {
+            if (param instanceof Date) {
+                return param.toISOString();
+            }
+            if (ArrayBuffer.isView(param)) {
+                return Array.from(new Uint8Array(param.buffer, param.byteOffset, param.byteLength));
+            }
+            if (param instanceof ArrayBuffer) {
+                return Array.from(new Uint8Array(param));
+            }
+            return param;
+        });
+    }
+
+    vortex.sql = Object.freeze({
+        // Resolves to the rows as objects keyed by column name
+        async query(sql, params = []) {
+            return ops.op_sql_query(String(sql), sqlParams('query', params));
+        },
+        // Resolves to { changes }, plus lastInsertRowid on SQLite
+        async execute(sql, params = []) {
+            return Object.freeze(await ops.op_sql_execute(String(sql), sqlParams('execute', params)));
+        },
+        // Runs fn(tx) in a transaction that has a connection to itself; tx
+        // has query() and execute() like the above. Commits when fn resolves
+        // and resolves to its result. Rolls back when fn throws, or when the
+        // transaction is still open after timeoutMs (default 5000, at most
+        // 30000), after which its statements and the commit throw. While it
+        // is open, SQLite statements must go through tx.
+        async transaction(fn, { timeoutMs } = {}) {
+            if (typeof fn !== 'function') {
+                throw new TypeError('vortex.sql.transaction: expects a function');
+            }
+            if (timeoutMs !== undefined && !(typeof timeoutMs === 'number' && timeoutMs > 0)) {
+                throw new TypeError('vortex.sql.transaction: timeoutMs must be a positive number');
+            }
+            const id = await ops.op_sql_transaction_begin(
+                timeoutMs === undefined ? null : Math.min(Math.ceil(timeoutMs), 0xffffffff),
+            );
+            const tx = Object.freeze({
+                async query(sql, params = []) {
+                    return ops.op_sql_transaction_query(id, String(sql), sqlParams('query', params));
+                },
+                async execute(sql, params = []) {
+                    return Object.freeze(
+                        await ops.op_sql_transaction_execute(id, String(sql), sqlParams('execute', params)),
+                    );
+                },
+            });
+            let result;
+            try {
+                result = await fn(tx);
+            } catch (e) {
+                // The error that made us roll back is the one worth reporting
+                await ops.op_sql_transaction_end(id, false).catch(() => {});
+                throw e;
+            }
+            await ops.op_sql_transaction_end(id, true);
+            return result;
+        },
+        // Applies the migrations ({ version, name, sql }) the database doesn't
+        // have yet, in version order and all in one transaction, while other
+        // invocations wait. Safe to call on every cold start. Resolves to
+        // { applied: [versions applied now], version }
+        async migrate(migrations) {
+            if (!Array.isArray(migrations)) {
+                throw new TypeError('vortex.sql.migrate: migrations must be an array of { version, name, sql }');
+            }
+            const steps = migrations.map((migration) => {
+                const { version, name = '', sql } = migration ?? {};
+                if (!Number.isSafeInteger(version)) {
+                    throw new TypeError('vortex.sql.migrate: migration versions must be integers');
+                }
+                if (typeof sql !== 'string') {
+                    throw new TypeError(`vortex.sql.migrate: migration ${version} needs its sql as a string`);
+                }
+                return { version, name: String(name), sql };
+            });
+            const report = await ops.op_sql_migrate(steps);
+            return Object.freeze({ applied: Object.freeze(report.applied), version: report.version });
+        },
+    });
+
+    // =========================================================================
+    // vortex.audit: hash-chained compliance records (audit.rs)
+    // =========================================================================
+
+    vortex.audit = Object.freeze({
+        // Resolves to { seq, hash } once the host's audit sink has stored the
+        // event, e.g. `await vortex.audit.record({ action: 'refund.issued', orderId })`
+        async record(event) {
+            if (event === null || typeof event !== 'object' || Array.isArray(event)) {
+                throw new TypeError('vortex.audit.record: event must be an object');
+            }
+            return Object.freeze(await ops.op_audit_record(JSON.stringify(event)));
+        },
+    });
+
+    // =========================================================================
+    // vortex.actor: state of the actor this isolate is pinned to (actors.rs)
+    // =========================================================================
+
+    // Only present when the host runs the function as an actor. The actor
+    // class receives it as `state`. Storage lives in the isolate and is
+    // saved by the host after each invocation that succeeds, so the methods
+    // are synchronous underneath; they return promises to match the Durable
+    // Objects API
+    if (config.actorId != null) {
+        const storage = Object.freeze({
+            async get(key) {
+                const [value] = ops.op_actor_storage_get(String(key));
+                return value;
+            },
+            async put(key, value) {
+                if (value === undefined) {
+                    throw new TypeError('state.storage.put: value must not be undefined');
+                }
+                ops.op_actor_storage_put(String(key), value);
+            },
+            async delete(key) {
+                return ops.op_actor_storage_delete(String(key));
+            },
+            // Entries in key order, as a Map
+            async list({ prefix = '', limit = 0 } = {}) {
+                limit = Number(limit);
+                if (!Number.isSafeInteger(limit) || limit < 0) {
+                    throw new RangeError('state.storage.list: limit must be a non-negative integer');
+                }
+                return new Map(ops.op_actor_storage_list(String(prefix), Math.min(limit, 0xffffffff)));
+            },
+        });
+        vortex.actor = Object.freeze({ id: config.actorId, storage });
+    }
+
+    // =========================================================================
+    // ReadableStream
+    // =========================================================================
+
+    // Response and Request bodies, vortex.request.body and the readable side
+    // of the compression streams are all ReadableStreams. This is the default
+    // (non-byte) stream: an underlying source with start/pull/cancel, a
+    // reader, async iteration, tee(), and pipeTo()/pipeThrough() into
+    // anything with getWriter(), such as a CompressionStream's writable side.
+
+    // The internals of each stream, out of reach of user code
+    const readableStates = new WeakMap();
+
+    function readableState(source, highWaterMark, size) {
+        const state = {
+            status: 'readable', // 'readable' | 'closed' | 'errored'
+            storedError: undefined,
+            queue: [],
+            queueTotal: 0,
+            closeRequested: false,
+            started: false,
+            pulling: false,
+            pullAgain: false,
+            // read() calls waiting for a chunk
+            reads: [],
+            reader: null,
+            // Settles the locked reader's `closed` promise
+            closed: null,
+        };
+
+        const desiredSize = () => highWaterMark - state.queueTotal;
+
+        function finishClose() {
+            state.status = 'closed';
+            for (const read of state.reads.splice(0)) read.resolve({ value: undefined, done: true });
+            state.closed?.resolve();
+        }
+
+        function fail(error) {
+            if (state.status !== 'readable') return;
+            state.status = 'errored';
+            state.storedError = error;
+            state.queue = [];
+            state.queueTotal = 0;
+            for (const read of state.reads.splice(0)) read.reject(error);
+            state.closed?.reject(error);
+        }
+
+        // Ask the source for more while a read is waiting or the queue is
+        // below the high water mark, one pull at a time
+        function pull() {
+            if (!state.started || state.status !== 'readable' || state.closeRequested) return;
+            if (state.reads.length === 0 && desiredSize() <= 0) return;
+            if (typeof source.pull !== 'function') return;
+            if (state.pulling) {
+                state.pullAgain = true;
+                return;
+            }
+            state.pulling = true;
+            new Promise((resolve) => resolve(source.pull(controller))).then(() => {
+                state.pulling = false;
+                if (state.pullAgain) {
+                    state.pullAgain = false;
+                    pull();
+                }
+            }, fail);
+        }
+
+        const controller = {
+            get desiredSize() {
+                if (state.status === 'errored') return null;
+                return state.status === 'closed' ? 0 : desiredSize();
+            },
+            enqueue(chunk) {
+                if (state.closeRequested || state.status !== 'readable') {
+                    throw new TypeError('ReadableStream: cannot enqueue into a closed stream');
+                }
+                if (state.reads.length > 0) {
+                    state.reads.shift().resolve({ value: chunk, done: false });
+                } else {
+                    let chunkSize;
+                    try {
+                        chunkSize = Number(size(chunk));
+                    } catch (error) {
+                        fail(error);
+                        throw error;
+                    }
+                    state.queue.push({ chunk, size: chunkSize });
+                    state.queueTotal += chunkSize;
+                }
+                pull();
+            },
+            close() {
+                if (state.closeRequested || state.status !== 'readable') {
+                    throw new TypeError('ReadableStream: the stream is already closed');
+                }
+                state.closeRequested = true;
+                if (state.queue.length === 0) finishClose();
+            },
+            error(error) {
+                fail(error);
+            },
+        };
+
+        function read() {
+            if (state.status === 'closed') return Promise.resolve({ value: undefined, done: true });
+            if (state.status === 'errored') return Promise.reject(state.storedError);
+            if (state.queue.length > 0) {
+                const { chunk, size: chunkSize } = state.queue.shift();
+                state.queueTotal -= chunkSize;
+                if (state.closeRequested && state.queue.length === 0) finishClose();
+                else pull();
+                return Promise.resolve({ value: chunk, done: false });
+            }
+            return new Promise((resolve, reject) => {
+                state.reads.push({ resolve, reject });
+                pull();
+            });
+        }
+
+        function cancel(reason) {
+            if (state.status === 'closed') return Promise.resolve();
+            if (state.status === 'errored') return Promise.reject(state.storedError);
+            state.queue = [];
+            state.queueTotal = 0;
+            finishClose();
+            return new Promise((resolve) => resolve(source.cancel?.(reason))).then(() => undefined);
+        }
+
+        // Lock the stream to `reader`, returning its `closed` promise
+        function lock(reader) {
+            state.reader = reader;
+            const closed = new Promise((resolve, reject) => {
+                if (state.status === 'closed') resolve();
+                else if (state.status === 'errored') reject(state.storedError);
+                else state.closed = { resolve, reject };
+            });
+            closed.catch(() => {});
+            return closed;
+        }
+
+        function release() {
+            const error = new TypeError('ReadableStream: the reader was released');
+            for (const read of state.reads.splice(0)) read.reject(error);
+            state.closed?.reject(error);
+            state.closed = null;
+            state.reader = null;
+        }
+
+        const started = typeof source.start === 'function' ? source.start(controller) : undefined;
+        Promise.resolve(started).then(() => {
+            state.started = true;
+            pull();
+        }, fail);
+
+        return { state, read, cancel, lock, release };
+    }
+
+    class ReadableStreamDefaultReader {
+        #stream;
+        #closed;
+
+        constructor(stream) {
+            const internals = readableStates.get(stream);
+            if (internals === undefined) throw new TypeError('ReadableStreamDefaultReader: expected a ReadableStream');
+            if (internals.state.reader !== null) throw new TypeError('ReadableStream is locked');
+            this.#stream = internals;
+            this.#closed = internals.lock(this);
+        }
+
+        get closed() {
+            return this.#closed;
+        }
+
+        read() {
+            if (this.#stream === null) return Promise.reject(new TypeError('ReadableStream: the reader was released'));
+            return this.#stream.read();
+        }
+
+        cancel(reason) {
+            if (this.#stream === null) return Promise.reject(new TypeError('ReadableStream: the reader was released'));
+            return this.#stream.cancel(reason);
+        }
+
+        releaseLock() {
+            if (this.#stream === null) return;
+            this.#stream.release();
+            this.#stream = null;
+        }
+    }
+
+    class ReadableStream {
+        constructor(source = {}, strategy = {}) {
+            if (source === null || typeof source !== 'object') {
+                throw new TypeError('ReadableStream: the underlying source must be an object');
+            }
+            if (source.type !== undefined) {
+                throw new RangeError(`ReadableStream: unsupported stream type '${source.type}'`);
+            }
+            const { highWaterMark = 1, size = () => 1 } = strategy ?? {};
+            const mark = Number(highWaterMark);
+            if (Number.isNaN(mark) || mark < 0) throw new RangeError('ReadableStream: invalid highWaterMark');
+            readableStates.set(this, readableState(source, mark, size));
+        }
+
+        // A stream of the values of an (async) iterable
+        static from(iterable) {
+            const iterator = iterable?.[Symbol.asyncIterator]?.() ?? iterable?.[Symbol.iterator]?.();
+            if (iterator === undefined) throw new TypeError('ReadableStream.from: expected an iterable');
+            return iteratorStream(iterator);
+        }
+
+        get locked() {
+            return readableStates.get(this).state.reader !== null;
+        }
+
+        cancel(reason) {
+            if (this.locked) return Promise.reject(new TypeError('ReadableStream is locked'));
+            return readableStates.get(this).cancel(reason);
+        }
+
+        getReader(options = {}) {
+            if (options?.mode !== undefined) {
+                throw new RangeError(`ReadableStream: unsupported reader mode '${options.mode}'`);
+            }
+            return new ReadableStreamDefaultReader(this);
+        }
+
+        // Breaking out of `for await` cancels the stream, unless preventCancel is set
+        values({ preventCancel = false } = {}) {
+            const reader = this.getReader();
+            return (async function* () {
+                let finished = false;
+                try {
+                    for (;;) {
+                        const { value, done } = await reader.read();
+                        if (done) break;
+                        yield value;
+                    }
+                    finished = true;
+                } catch (error) {
+                    finished = true;
+                    throw error;
+                } finally {
+                    if (!finished && !preventCancel) await reader.cancel();
+                    reader.releaseLock();
+                }
+            })();
+        }
+
+        [Symbol.asyncIterator](options) {
+            return this.values(options);
+        }
+
+        async pipeTo(destination, { preventClose = false, preventAbort = false, preventCancel = false, signal } = {}) {
+            if (this.locked) throw new TypeError('ReadableStream is locked');
+            if (typeof destination?.getWriter !== 'function') {
+                throw new TypeError('ReadableStream.pipeTo: expected a WritableStream');
+            }
+            const reader = this.getReader();
+            const writer = destination.getWriter();
+            try {
+                for (;;) {
+                    if (signal?.aborted) {
+                        if (!preventAbort) await writer.abort(signal.reason);
+                        if (!preventCancel) await reader.cancel(signal.reason);
+                        throw signal.reason;
+                    }
+                    let result;
+                    try {
+                        result = await reader.read();
+                    } catch (error) {
+                        if (!preventAbort) await writer.abort(error);
+                        throw error;
+                    }
+                    if (result.done) break;
+                    try {
+                        await writer.ready;
+                        await writer.write(result.value);
+                    } catch (error) {
+                        if (!preventCancel) await reader.cancel(error);
+                        throw error;
+                    }
+                }
+                if (!preventClose) await writer.close();
+            } finally {
+                reader.releaseLock();
+                writer.releaseLock();
+            }
+        }
+
+        pipeThrough(transform, options) {
+            const { writable, readable } = transform ?? {};
+            if (!(readable instanceof ReadableStream)) {
+                throw new TypeError('ReadableStream.pipeThrough: expected { writable, readable }');
+            }
+            if (this.locked) throw new TypeError('ReadableStream is locked');
+            if (writable?.locked) throw new TypeError('WritableStream is locked');
+            this.pipeTo(writable, options).catch(() => {});
+            return readable;
+        }
+
+        // Two streams that each see every chunk; the source is cancelled
+        // once both are
+        tee() {
+            const reader = this.getReader();
+            const branches = teeIterator({ next: () => reader.read() });
+            let cancelled = 0;
+            return branches.map((iterator) => new ReadableStream({
+                async pull(controller) {
+                    const { value, done } = await iterator.next();
+                    if (done) controller.close();
+                    else controller.enqueue(value);
+                },
+                cancel(reason) {
+                    if (++cancelled === 2) return reader.cancel(reason);
+                },
+            }, { highWaterMark: 0 }));
+        }
+
+        get [Symbol.toStringTag]() {
+            return 'ReadableStream';
+        }
+    }
+
+    // A stream that pulls one chunk from `iterator` per read
+    function iteratorStream(iterator) {
+        return new ReadableStream({
+            async pull(controller) {
+                const { value, done } = await iterator.next();
+                if (done) controller.close();
+                else controller.enqueue(value);
+            },
+            async cancel(reason) {
+                await iterator.return?.(reason);
+            },
+        }, { highWaterMark: 0 });
+    }
+
+    // =========================================================================
+    // vortex.request: the HTTP request body in serve mode (http.rs)
+    // =========================================================================
+
+    // The host streams the body in as it is read, one chunk per op call, so
+    // an upload never has to fit in the heap in full. A body can be read
+    // once per invocation, either as a stream or with one of the helpers
+    function requestBodyStream() {
+        let claimed = false;
+        return new ReadableStream({
+            async pull(controller) {
+                if (!claimed) {
+                    ops.op_request_body_claim();
+                    claimed = true;
+                }
+                const chunk = await ops.op_request_body_read();
+                if (chunk.byteLength > 0) controller.enqueue(chunk);
+                else controller.close();
+            },
+            // The host drops the rest of the body when the invocation ends
+            cancel() {},
+        }, { highWaterMark: 0 });
+    }
+
+    // A file field of a multipart body
+    function uploadedFile(part) {
+        const data = part.data;
+        return Object.freeze({
+            name: part.filename,
+            type: part.contentType ?? '',
+            size: data.byteLength,
+            async bytes() {
+                return data.slice();
+            },
+            async arrayBuffer() {
+                return data.slice().buffer;
+            },
+            async text() {
+                return new TextDecoder().decode(data);
+            },
+        });
+    }
+
+    // The read-only subset of FormData that parsed request bodies need
+    class FormData {
+        #entries;
+
+        constructor(entries = []) {
+            this.#entries = entries;
+        }
+
+        get(name) {
+            const entry = this.#entries.find(([key]) => key === String(name));
+            return entry === undefined ? null : entry[1];
+        }
+
+        getAll(name) {
+            return this.#entries.filter(([key]) => key === String(name)).map(([, value]) => value);
+        }
+
+        has(name) {
+            return this.#entries.some(([key]) => key === String(name));
+        }
+
+        *entries() {
+            for (const [key, value] of this.#entries) yield [key, value];
+        }
+
+        *keys() {
+            for (const [key] of this.#entries) yield key;
+        }
+
+        *values() {
+            for (const [, value] of this.#entries) yield value;
+        }
+
+        forEach(callback, thisArg) {
+            for (const [key, value] of this.#entries) callback.call(thisArg, value, key, this);
+        }
+
+        [Symbol.iterator]() {
+            return this.entries();
+        }
+    }
+
+    const request = Object.freeze({
+        // A fresh stream on every access; `null` when there is no body
+        get body() {
+            return ops.op_request_body_present() ? requestBodyStream() : null;
+        },
+        async arrayBuffer() {
+            const body = request.body;
+            if (body === null) return new ArrayBuffer(0);
+            const chunks = [];
+            let length = 0;
+            for await (const chunk of body) {
+                chunks.push(chunk);
+                length += chunk.byteLength;
+            }
+            const bytes = new Uint8Array(length);
+            let offset = 0;
+            for (const chunk of chunks) {
+                bytes.set(chunk, offset);
+                offset += chunk.byteLength;
+            }
+            return bytes.buffer;
+        },
+        async text() {
+            return new TextDecoder().decode(await request.arrayBuffer());
+        },
+        async json() {
+            return JSON.parse(await request.text());
+        },
+        // Parsed by the host; parts over the size limit reject
+        async formData() {
+            const parts = await ops.op_request_body_form_data();
+            return new FormData(
+                parts.map((part) => [
+                    part.name,
+                    part.filename == null ? new TextDecoder().decode(part.data) : uploadedFile(part),
+                ]),
+            );
+        },
+    });
+    vortex.request = request;
+
+    // =========================================================================
+    // fetch: Headers, Request, Response (HTTP client in fetch.rs)
+    // =========================================================================
+
+    // The request itself is sent by the host over a pooled keep-alive client;
+    // the response body is pulled one chunk per op call as it is read.
+    const HTTP_TOKEN = /^[!#$%&'*+\-.^_`|~0-9A-Za-z]+$/;
+    const NULL_BODY_STATUSES = [101, 103, 204, 205, 304];
+    const REDIRECT_STATUSES = [301, 302, 303, 307, 308];
+
+    function headerName(name) {
+        name = String(name);
+        if (!HTTP_TOKEN.test(name)) throw new TypeError(`Invalid header name: '${name}'`);
+        return name.toLowerCase();
+    }
+
+    function headerValue(value) {
+        value = String(value).replace(/^[\t\n\r ]+|[\t\n\r ]+$/g, '');
+        if (/[\0\r\n]/.test(value)) throw new TypeError(`Invalid header value: '${value}'`);
+        return value;
+    }
+
+    // Thrown by fetch() when a request runs past its timeout
+    class FetchTimeoutError extends DOMException {
+        constructor(message) {
+            super(message, 'TimeoutError');
+        }
+    }
+    core.registerErrorClass('TimeoutError', FetchTimeoutError);
+
+    // `init.vortex` overrides of the worker's FetchOptions: timeout and
+    // retryBackoff in milliseconds, maxRedirects and retries as counts
+    const FETCH_POLICY_KEYS = ['timeout', 'maxRedirects', 'retries', 'retryBackoff'];
+
+    function fetchPolicy(value) {
+        const policy = {};
+        for (const key of FETCH_POLICY_KEYS) {
+            if (value?.[key] === undefined) continue;
+            const number = Number(value[key]);
+            if (!Number.isInteger(number) || number < 0 || number > 0xffffffff) {
+                throw new TypeError(`Invalid fetch option vortex.${key}: ${value[key]}`);
+            }
+            policy[key] = number;
+        }
+        return Object.freeze(policy);
+    }
+
+    class Headers {
+        // [name, value] pairs in insertion order, names lowercased
+        #list = [];
+
+        constructor(init = undefined) {
+            if (init == null) return;
+            if (init instanceof Headers) {
+                this.#list = init.#list.map(([name, value]) => [name, value]);
+            } else if (typeof init[Symbol.iterator] === 'function') {
+                for (const pair of init) {
+                    const entry = [...pair];
+                    if (entry.length !== 2) throw new TypeError('Header pairs must have exactly two items');
+                    this.append(entry[0], entry[1]);
+                }
+            } else if (typeof init === 'object') {
+                for (const name of Object.keys(init)) this.append(name, init[name]);
+            } else {
+                throw new TypeError('Headers: init must be an object or an iterable of pairs');
+            }
+        }
+
+        append(name, value) {
+            this.#list.push([headerName(name), headerValue(value)]);
+        }
+
+        delete(name) {
+            name = headerName(name);
+            this.#list = this.#list.filter(([key]) => key !== name);
+        }
+
+        get(name) {
+            name = headerName(name);
+            const values = this.#list.filter(([key]) => key === name).map(([, value]) => value);
+            return values.length > 0 ? values.join(', ') : null;
+        }
+
+        getSetCookie() {
+            return this.#list.filter(([key]) => key === 'set-cookie').map(([, value]) => value);
+        }
+
+        has(name) {
+            name = headerName(name);
+            return this.#list.some(([key]) => key === name);
+        }
+
+        set(name, value) {
+            name = headerName(name);
+            value = headerValue(value);
+            const index = this.#list.findIndex(([key]) => key === name);
+            if (index < 0) {
+                this.#list.push([name, value]);
+            } else {
+                this.#list[index][1] = value;
+                this.#list = this.#list.filter(([key], i) => i <= index || key !== name);
+            }
+        }
+
+        // Sorted and combined, except that each Set-Cookie stays separate
+        *entries() {
+            const names = [...new Set(this.#list.map(([name]) => name))].sort();
+            for (const name of names) {
+                if (name === 'set-cookie') {
+                    for (const value of this.getSetCookie()) yield [name, value];
+                } else {
+                    yield [name, this.get(name)];
+                }
+            }
+        }
+
+        *keys() {
+            for (const [name] of this.entries()) yield name;
+        }
+
+        *values() {
+            for (const [, value] of this.entries()) yield value;
+        }
+
+        forEach(callback, thisArg) {
+            for (const [name, value] of this.entries()) callback.call(thisArg, value, name, this);
+        }
+
+        [Symbol.iterator]() {
+            return this.entries();
+        }
+
+        get [Symbol.toStringTag]() {
+            return 'Headers';
+        }
+    }
+
+    // Split one async iterator into two that each see every chunk
+    function teeIterator(iterator) {
+        const queues = [[], []];
+        let pending = null;
+        const next = (i) => {
+            if (queues[i].length > 0) return Promise.resolve(queues[i].shift());
+            pending ??= iterator.next().then(
+                (result) => {
+                    pending = null;
+                    queues[0].push(result);
+                    queues[1].push(result);
+                },
+                (error) => {
+                    pending = null;
+                    throw error;
+                },
+            );
+            return pending.then(() => queues[i].shift());
+        };
+        return [0, 1].map((i) => ({
+            next: () => next(i),
+            return: async () => ({ value: undefined, done: true }),
+            [Symbol.asyncIterator]() {
+                return this;
+            },
+        }));
+    }
+
+    async function* streamChunks(stream) {
+        if (typeof stream[Symbol.asyncIterator] === 'function') {
+            for await (const chunk of stream) yield chunkBytes(chunk);
+            return;
+        }
+        const reader = stream.getReader();
+        try {
+            for (;;) {
+                const { value, done } = await reader.read();
+                if (done) return;
+                yield chunkBytes(value);
+            }
+        } finally {
+            reader.releaseLock?.();
+        }
+    }
+
+    const kBody = Symbol('body');
+    const kTee = Symbol('tee');
+    const kFetched = Symbol('fetched');
+
+    // The chunk iterator behind each body stream, so that a body passed on to
+    // another Request or Response, or returned, is taken over without going
+    // through the stream
+    const bodyChunks = new WeakMap();
+
+    // A ReadableStream over `chunks`; `onRead` runs once it is first read or
+    // its chunks are taken over
+    function bodyStream(chunks, onRead = () => {}) {
+        const stream = iteratorStream({
+            next() {
+                onRead();
+                return chunks.next();
+            },
+            return: (reason) => chunks.return?.(reason),
+        });
+        bodyChunks.set(stream, () => {
+            onRead();
+            return chunks;
+        });
+        return stream;
+    }
+
+    // Shared by Request and Response: the body is held as bytes, or as an
+    // async iterator of chunks that is pulled as it is read
+    class Body {
+        #bytes = null;
+        #chunks = null;
+        #used = false;
+        #stream = null;
+
+        // Take `body` as this object's body, returning its default content type
+        [kBody](body) {
+            if (body == null) return null;
+            if (typeof body === 'string') {
+                this.#bytes = new TextEncoder().encode(body);
+                return 'text/plain;charset=UTF-8';
+            }
+            if (body instanceof URLSearchParams) {
+                this.#bytes = new TextEncoder().encode(body.toString());
+                return 'application/x-www-form-urlencoded;charset=UTF-8';
+            }
+            if (body instanceof ArrayBuffer || ArrayBuffer.isView(body)) {
+                this.#bytes = compressionChunk(body).slice();
+                return null;
+            }
+            if (typeof body[Symbol.asyncIterator] === 'function' || typeof body.getReader === 'function') {
+                if (body.locked) throw new TypeError('ReadableStream is locked');
+                const takeOver = bodyChunks.get(body);
+                this.#chunks = takeOver?.() ?? streamChunks(body);
+                return null;
+            }
+            this.#bytes = new TextEncoder().encode(String(body));
+            return 'text/plain;charset=UTF-8';
+        }
+
+        get body() {
+            if (this.#bytes === null && this.#chunks === null) return null;
+            if (this.#stream === null) {
+                const chunks = this.#chunks ?? (async function* (bytes) {
+                    if (bytes.byteLength > 0) yield bytes.slice();
+                })(this.#bytes);
+                this.#stream = bodyStream(chunks, () => {
+                    this.#used = true;
+                });
+            }
+            return this.#stream;
+        }
+
+        get bodyUsed() {
+            return this.#used;
+        }
+
+        // A copy of the body for clone(), leaving this one readable
+        [kTee]() {
+            if (this.#used || this.#stream?.locked) throw new TypeError('Body has already been used');
+            if (this.#chunks === null) return this.#bytes;
+            const [mine, theirs] = teeIterator(this.#chunks);
+            this.#chunks = mine;
+            this.#stream = null;
+            return bodyStream(theirs);
+        }
+
+        async bytes() {
+            if (this.#used || this.#stream?.locked) throw new TypeError('Body has already been used');
+            this.#used = true;
+            if (this.#chunks === null) return this.#bytes === null ? new Uint8Array(0) : this.#bytes.slice();
+            const chunks = [];
+            let length = 0;
+            for await (const chunk of this.#chunks) {
+                chunks.push(chunk);
+                length += chunk.byteLength;
+            }
+            const bytes = new Uint8Array(length);
+            let offset = 0;
+            for (const chunk of chunks) {
+                bytes.set(chunk, offset);
+                offset += chunk.byteLength;
+            }
+            return bytes;
+        }
+
+        async arrayBuffer() {
+            return (await this.bytes()).buffer;
+        }
+
+        async text() {
+            return new TextDecoder().decode(await this.bytes());
+        }
+
+        async json() {
+            return JSON.parse(await this.text());
+        }
+    }
+
+    class Request extends Body {
+        #method;
+        #url;
+        #headers;
+        #signal;
+        #redirect;
+        #vortex;
+
+        constructor(input, init = {}) {
+            super();
+            const source = input instanceof Request ? input : null;
+            this.#url = source ? source.url : new URL(String(input)).href;
+
+            let method = init.method ?? source?.method ?? 'GET';
+            method = String(method);
+            if (!HTTP_TOKEN.test(method)) throw new TypeError(`Invalid method: '${method}'`);
+            const upper = method.toUpperCase();
+            if (['CONNECT', 'TRACE', 'TRACK'].includes(upper)) {
+                throw new TypeError(`Method '${method}' is forbidden`);
+            }
+            if (['DELETE', 'GET', 'HEAD', 'OPTIONS', 'POST', 'PUT', 'PATCH'].includes(upper)) method = upper;
+            this.#method = method;
+
+            this.#headers = new Headers(init.headers ?? source?.headers);
+            this.#signal = init.signal ?? source?.signal ?? null;
+            this.#redirect = String(init.redirect ?? source?.redirect ?? 'follow');
+            if (!['follow', 'manual', 'error'].includes(this.#redirect)) {
+                throw new TypeError(`Invalid redirect mode: '${this.#redirect}'`);
+            }
+            this.#vortex = fetchPolicy(init.vortex ?? source?.vortex);
+
+            const body = init.body !== undefined ? init.body : source?.body ?? null;
+            if (body != null && (method === 'GET' || method === 'HEAD')) {
+                throw new TypeError('Request with GET/HEAD method cannot have body');
+            }
+            const type = this[kBody](body);
+            if (type !== null && !this.#headers.has('content-type')) this.#headers.set('content-type', type);
+        }
+
+        get method() {
+            return this.#method;
+        }
+
+        get url() {
+            return this.#url;
+        }
+
+        get headers() {
+            return this.#headers;
+        }
+
+        get signal() {
+            return this.#signal;
+        }
+
+        get redirect() {
+            return this.#redirect;
+        }
+
+        // Vortex extension: per-request timeout, redirect and retry policy
+        get vortex() {
+            return this.#vortex;
+        }
+
+        clone() {
+            return new Request(this.#url, {
+                method: this.#method,
+                headers: this.#headers,
+                signal: this.#signal,
+                redirect: this.#redirect,
+                vortex: this.#vortex,
+                body: this[kTee](),
+            });
+        }
+
+        get [Symbol.toStringTag]() {
+            return 'Request';
+        }
+    }
+
+    class Response extends Body {
+        #status;
+        #statusText;
+        #headers;
+        #type = 'default';
+        #url = '';
+        #redirected = false;
+        #webSocket = null;
+
+        constructor(body = null, init = {}) {
+            super();
+            const status = init.status ?? 200;
+            const webSocket = init.webSocket ?? null;
+            if (webSocket !== null) {
+                if (!webSocketServers.has(webSocket)) {
+                    throw new TypeError('Response: webSocket must be the client side of a WebSocketPair');
+                }
+                if (status !== 101) throw new RangeError('Response: a webSocket requires status 101');
+            } else if (!Number.isInteger(status) || status < 200 || status > 599) {
+                throw new RangeError(`Invalid response status: ${status}`);
+            }
+            if (body != null && NULL_BODY_STATUSES.includes(status)) {
+                throw new TypeError(`Response with status ${status} cannot have a body`);
+            }
+            this.#status = status;
+            this.#statusText = String(init.statusText ?? '');
+            this.#headers = new Headers(init.headers);
+            this.#webSocket = webSocket;
+            const type = this[kBody](body);
+            if (type !== null && !this.#headers.has('content-type')) this.#headers.set('content-type', type);
+        }
+
+        static json(data, init = {}) {
+            const body = JSON.stringify(data);
+            if (body === undefined) throw new TypeError('Response.json: data is not JSON serializable');
+            const headers = new Headers(init.headers);
+            if (!headers.has('content-type')) headers.set('content-type', 'application/json');
+            return new Response(body, { ...init, headers });
+        }
+
+        static redirect(url, status = 302) {
+            if (!REDIRECT_STATUSES.includes(status)) throw new RangeError(`Invalid redirect status: ${status}`);
+            return new Response(null, { status, headers: { location: new URL(String(url)).href } });
+        }
+
+        static error() {
+            const response = new Response(null);
+            response.#status = 0;
+            response.#type = 'error';
+            return response;
+        }
+
+        // A response received by fetch(); its body is read from the host
+        static [kFetched](fetched, chunks) {
+            const response = new Response(chunks === null ? null : bodyStream(chunks));
+            response.#status = fetched.status;
+            response.#statusText = fetched.statusText;
+            response.#headers = new Headers(fetched.headers);
+            response.#type = 'basic';
+            response.#url = fetched.url;
+            response.#redirected = fetched.redirected;
+            return response;
+        }
+
+        get status() {
+            return this.#status;
+        }
+
+        get ok() {
+            return this.#status >= 200 && this.#status <= 299;
+        }
+
+        get statusText() {
+            return this.#statusText;
+        }
+
+        get headers() {
+            return this.#headers;
+        }
+
+        get type() {
+            return this.#type;
+        }
+
+        get url() {
+            return this.#url;
+        }
+
+        get redirected() {
+            return this.#redirected;
+        }
+
+        get webSocket() {
+            return this.#webSocket;
+        }
+
+        clone() {
+            const body = this[kTee]();
+            const response = new Response(null, { headers: this.#headers });
+            response[kBody](body);
+            response.#status = this.#status;
+            response.#statusText = this.#statusText;
+            response.#type = this.#type;
+            response.#url = this.#url;
+            response.#redirected = this.#redirected;
+            response.#webSocket = this.#webSocket;
+            return response;
+        }
+
+        get [Symbol.toStringTag]() {
+            return 'Response';
+        }
+    }
+
+    // Settle with `promise`, or reject as soon as `signal` aborts
+    function abortable(promise, signal) {
+        if (signal === null) return promise;
+        return new Promise((resolve, reject) => {
+            const onAbort = () => reject(signal.reason);
+            signal.addEventListener('abort', onAbort, { once: true });
+            promise.then(resolve, reject).finally(() => signal.removeEventListener('abort', onAbort));
+        });
+    }
+
+    async function* fetchedChunks(rid, signal) {
+        try {
+            for (;;) {
+                signal?.throwIfAborted();
+                const chunk = await abortable(ops.op_fetch_body_read(rid), signal);
+                if (chunk.byteLength === 0) return;
+                yield chunk;
+            }
+        } finally {
+            ops.op_fetch_body_close(rid);
+        }
+    }
+
+    async function fetch(input, init = undefined) {
+        const request = new Request(input, init);
+        const signal = request.signal;
+        signal?.throwIfAborted();
+        const body = request.body === null ? null : await request.bytes();
+        const pending = ops.op_fetch({
+            method: request.method,
+            url: request.url,
+            headers: [...request.headers],
+            body,
+            redirect: request.redirect,
+            timeoutMs: request.vortex.timeout ?? null,
+            maxRedirects: request.vortex.maxRedirects ?? null,
+            retries: request.vortex.retries ?? null,
+            retryBackoffMs: request.vortex.retryBackoff ?? null,
+        });
+        // A response that arrives after an abort is dropped
+        pending.then((fetched) => {
+            if (signal?.aborted) ops.op_fetch_body_close(fetched.rid);
+        }, () => {});
+        const fetched = await abortable(pending, signal);
+
+        let chunks = fetchedChunks(fetched.rid, signal);
+        if (request.method === 'HEAD' || NULL_BODY_STATUSES.includes(fetched.status)) {
+            ops.op_fetch_body_close(fetched.rid);
+            chunks = null;
+        }
+        return Response[kFetched](fetched, chunks);
+    }
+
+    // =========================================================================
+    // vortex.sse: Server-Sent Events (text/event-stream)
+    // =========================================================================
+
+    // vortex.sse(events, init) is a Response whose body is `events`, an
+    // (async) iterable, framed as a `text/event-stream`:
+    //
+    //     return vortex.sse((async function* () {
+    //         for await (const token of completion) yield { event: 'token', data: token };
+    //     })());
+    //
+    // An event is a string (its data) or { data, event, id, retry }; data
+    // that isn't a string is sent as JSON. In serve mode each event reaches
+    // the client as soon as it is produced, and http.rs sends keep-alive
+    // comments while the stream is idle.
+    function sseField(name, value) {
+        value = String(value);
+        if (/[\r\n]/.test(value)) {
+            throw new TypeError(`vortex.sse(): ${name} must not contain a line break`);
+        }
+        return `${name}: ${value}\n`;
+    }
+
+    function sseEvent(event) {
+        const { data, event: type, id, retry } = typeof event === 'object' && event !== null ? event : { data: event };
+        let text = '';
+        if (type !== undefined) text += sseField('event', type);
+        if (id !== undefined) text += sseField('id', id);
+        if (retry !== undefined) {
+            if (!Number.isInteger(retry) || retry < 0) {
+                throw new TypeError('vortex.sse(): retry must be a non-negative integer');
+            }
+            text += `retry: ${retry}\n`;
+        }
+        // JSON.stringify() gives undefined for functions and symbols, which
+        // have no data to send, the same as undefined itself
+        const lines = typeof data === 'string' ? data : JSON.stringify(data);
+        if (lines !== undefined) {
+            for (const line of lines.split(/\r\n|\r|\n/)) text += `data: ${line}\n`;
+        }
+        return `${text}\n`;
+    }
+
+    vortex.sse = (events, init = {}) => {
+        const headers = new Headers(init.headers);
+        if (!headers.has('content-type')) headers.set('content-type', 'text/event-stream');
+        if (!headers.has('cache-control')) headers.set('cache-control', 'no-cache');
+        const body = (async function* () {
+            for await (const event of events) yield sseEvent(event);
+        })();
+        return new Response(body, { ...init, headers });
+    };
+
+    // =========================================================================
+    // WebSocketPair (upgrades relayed by http.rs)
+    // =========================================================================
+
+    // A handler accepts a WebSocket upgrade by accepting the server side of a
+    // pair and returning the client side:
+    //
+    //     const [client, server] = Object.values(new WebSocketPair());
+    //     server.accept();
+    //     server.addEventListener('message', (event) => server.send(event.data));
+    //     return new Response(null, { status: 101, webSocket: client });
+    //
+    // The server side exchanges messages with the remote end through ops; the
+    // client side only stands for that end. The invocation lasts until the
+    // connection is closed.
+    const WEBSOCKET_INTERNAL = Symbol('WebSocket.internal');
+    const MAX_CLOSE_REASON_BYTES = 123;
+
+    // The client side of each pair, mapped to its server side
+    const webSocketServers = new WeakMap();
+
+    class MessageEvent extends Event {
+        constructor(type, init = {}) {
+            super(type, init);
+            this.data = init.data ?? null;
+        }
+    }
+
+    class CloseEvent extends Event {
+        constructor(type, init = {}) {
+            super(type, init);
+            this.code = init.code ?? 0;
+            this.reason = String(init.reason ?? '');
+            this.wasClean = Boolean(init.wasClean);
+        }
+    }
+
+    class WebSocket extends EventTarget {
+        static CONNECTING = 0;
+        static OPEN = 1;
+        static CLOSING = 2;
+        static CLOSED = 3;
+
+        #readyState = WebSocket.CONNECTING;
+        #closed = null;
+
+        constructor(key) {
+            if (key !== WEBSOCKET_INTERNAL) {
+                throw new TypeError('Illegal constructor');
+            }
+            super();
+            this.onmessage = null;
+            this.onclose = null;
+        }
+
+        get readyState() {
+            return this.#readyState;
+        }
+
+        accept() {
+            this.#checkServer();
+            if (this.#closed !== null) throw new TypeError('WebSocket has already been accepted');
+            ops.op_websocket_accept();
+            this.#readyState = WebSocket.OPEN;
+            this.#closed = this.#receive();
+        }
+
+        send(data) {
+            this.#checkServer();
+            if (this.#readyState !== WebSocket.OPEN) {
+                throw new DOMException('WebSocket is not open', 'InvalidStateError');
+            }
+            // Not awaited: sends queue up in order, each waiting while the
+            // client is behind
+            if (data instanceof ArrayBuffer || ArrayBuffer.isView(data)) {
+                ops.op_websocket_send_binary(compressionChunk(data));
+            } else {
+                ops.op_websocket_send(String(data));
+            }
+        }
+
+        close(code = 1000, reason = '') {
+            this.#checkServer();
+            if (code !== 1000 && !(Number.isInteger(code) && code >= 3000 && code <= 4999)) {
+                throw new DOMException(`Invalid close code: ${code}`, 'InvalidAccessError');
+            }
+            reason = String(reason);
+            if (new TextEncoder().encode(reason).byteLength > MAX_CLOSE_REASON_BYTES) {
+                throw new DOMException(`Close reason is longer than ${MAX_CLOSE_REASON_BYTES} bytes`, 'SyntaxError');
+            }
+            if (this.#readyState !== WebSocket.OPEN) return;
+            this.#readyState = WebSocket.CLOSING;
+            ops.op_websocket_close(code, reason);
+        }
+
+        #checkServer() {
+            if (webSocketServers.has(this)) {
+                throw new TypeError('The client side of a WebSocketPair can only be returned in a Response');
+            }
+        }
+
+        // Dispatch messages until the connection is closed, by either end
+        async #receive() {
+            for (;;) {
+                let message;
+                try {
+                    message = await ops.op_websocket_receive();
+                } catch {
+                    message = { type: 'close', code: 1006, reason: '' };
+                }
+                if (message.type === 'close') {
+                    this.#readyState = WebSocket.CLOSED;
+                    const { code, reason } = message;
+                    this.#dispatch('onclose', new CloseEvent('close', { code, reason, wasClean: code !== 1006 }));
+                    return;
+                }
+                const data = message.type === 'text' ? message.data : message.data.slice().buffer;
+                this.#dispatch('onmessage', new MessageEvent('message', { data }));
+            }
+        }
+
+        #dispatch(handler, event) {
+            if (typeof this[handler] === 'function') {
+                try {
+                    this[handler](event);
+                } catch (e) {
+                    console.error('Uncaught (in event listener)', e && e.stack ? e.stack : e);
+                }
+            }
+            this.dispatchEvent(event);
+        }
+
+        static closedPromise(client) {
+            const server = webSocketServers.get(client);
+            if (server.#closed === null) {
+                throw new TypeError('The server side of a WebSocketPair must be accepted before the client side is returned');
+            }
+            return server.#closed;
+        }
+    }
+
+    // Settles once the connection `client` stands for is closed
+    const webSocketClosed = WebSocket.closedPromise;
+    delete WebSocket.closedPromise;
+
+    class WebSocketPair {
+        constructor() {
+            const client = new WebSocket(WEBSOCKET_INTERNAL);
+            const server = new WebSocket(WEBSOCKET_INTERNAL);
+            webSocketServers.set(client, server);
+            this[0] = client;
+            this[1] = server;
+        }
+    }
+
+    // =========================================================================
+    // Cache API (caches.default, caches.open; storage in cache.rs)
+    // =========================================================================
+
+    const kCache = Symbol('cache');
+
+    // Entries are keyed by the request URL without its fragment
+    function cacheRequest(request) {
+        request = request instanceof Request ? request : new Request(request);
+        return { method: request.method, url: request.url.split('#')[0] };
+    }
+
+    class Cache {
+        #name;
+
+        constructor(token, name) {
+            if (token !== kCache) throw new TypeError('Illegal constructor');
+            this.#name = name;
+        }
+
+        async match(request, options = {}) {
+            const { method, url } = cacheRequest(request);
+            if (method !== 'GET' && !options.ignoreMethod) return undefined;
+            const cached = await ops.op_cache_match(this.#name, url);
+            if (cached === null) return undefined;
+            const body = NULL_BODY_STATUSES.includes(cached.status) ? null : cached.body;
+            return new Response(body, { status: cached.status, statusText: cached.statusText, headers: cached.headers });
+        }
+
+        async put(request, response) {
+            const { method, url } = cacheRequest(request);
+            if (method !== 'GET') throw new TypeError('Cache.put: only GET requests can be cached');
+            if (!(response instanceof Response)) throw new TypeError('Cache.put: response must be a Response');
+            if (response.type === 'error' || response.status === 206) {
+                throw new TypeError(`Cache.put: a response with status ${response.status} cannot be cached`);
+            }
+            if ((response.headers.get('vary') ?? '').split(',').some((name) => name.trim() === '*')) {
+                throw new TypeError("Cache.put: a response with 'Vary: *' cannot be cached");
+            }
+            const body = await response.bytes();
+            await ops.op_cache_put(this.#name, url, {
+                status: response.status,
+                statusText: response.statusText,
+                headers: [...response.headers],
+            }, body);
+        }
+
+        async delete(request, options = {}) {
+            const { method, url } = cacheRequest(request);
+            if (method !== 'GET' && !options.ignoreMethod) return false;
+            return ops.op_cache_delete(this.#name, url);
+        }
+
+        get [Symbol.toStringTag]() {
+            return 'Cache';
+        }
+    }
+
+    class CacheStorage {
+        #caches = new Map();
+
+        constructor(token) {
+            if (token !== kCache) throw new TypeError('Illegal constructor');
+        }
+
+        #get(name) {
+            if (!this.#caches.has(name)) this.#caches.set(name, new Cache(kCache, name));
+            return this.#caches.get(name);
+        }
+
+        // The cache Workers code reaches for first; same as open('default')
+        get default() {
+            return this.#get('default');
+        }
+
+        async open(name) {
+            return this.#get(String(name));
+        }
+
+        get [Symbol.toStringTag]() {
+            return 'CacheStorage';
+        }
+    }
+
+    const caches = new CacheStorage(kCache);
+
+    // =========================================================================
+    // CompressionStream / DecompressionStream
+    // =========================================================================
+
+    // The codecs run in Rust (flate2). The readable side is a ReadableStream;
+    // the writable side exposes the subset of the WritableStream API that
+    // pipeTo() and hand-written feeders need: getWriter() with
+    // ready/write/close/abort.
+    const COMPRESSION_FORMATS = ['gzip', 'deflate', 'deflate-raw'];
+
+    function compressionChunk(chunk) {
+        if (chunk instanceof ArrayBuffer) return new Uint8Array(chunk);
+        if (ArrayBuffer.isView(chunk)) {
+            return new Uint8Array(chunk.buffer, chunk.byteOffset, chunk.byteLength);
+        }
+        throw new TypeError('Chunk must be an ArrayBuffer or ArrayBufferView');
+    }
+
+    function codecStreams(name, format, decompress) {
+        format = String(format);
+        if (!COMPRESSION_FORMATS.includes(format)) {
+            throw new TypeError(`${name}: unsupported format '${format}'`);
+        }
+        const id = ops.op_compression_new(format, decompress);
+        const queue = []; // output not yet read
+        const reads = []; // read() calls waiting for output
+        let state = 'open'; // 'open' | 'closed' | 'errored'
+        let storedError;
+
+        function settle() {
+            while (reads.length > 0 && (queue.length > 0 || state !== 'open')) {
+                const { resolve, reject } = reads.shift();
+                if (queue.length > 0) resolve({ value: queue.shift(), done: false });
+                else if (state === 'closed') resolve({ value: undefined, done: true });
+                else reject(storedError);
+            }
+        }
+
+        function enqueue(bytes) {
+            if (bytes.byteLength > 0) queue.push(bytes);
+        }
+
+        function fail(error) {
+            if (state !== 'open') return;
+            ops.op_compression_close(id);
+            state = 'errored';
+            storedError = error;
+            queue.length = 0;
+            settle();
+        }
+
+        function cancel() {
+            if (state === 'open') ops.op_compression_close(id);
+            if (state !== 'errored') state = 'closed';
+            queue.length = 0;
+            settle();
+            return Promise.resolve();
+        }
+
+        // Run a step against the codec; a failing step errors both sides
+        function step(fn) {
+            if (state === 'errored') return Promise.reject(storedError);
+            if (state === 'closed') return Promise.reject(new TypeError(`${name}: the stream is closed`));
+            try {
+                fn();
+            } catch (error) {
+                fail(error);
+                return Promise.reject(error);
+            }
+            settle();
+            return Promise.resolve();
+        }
+
+        function read() {
+            return new Promise((resolve, reject) => {
+                reads.push({ resolve, reject });
+                settle();
+            });
+        }
+
+        let writerLocked = false;
+        const writable = {
+            get locked() {
+                return writerLocked;
+            },
+            getWriter() {
+                if (writerLocked) throw new TypeError('WritableStream is locked');
+                writerLocked = true;
+                return {
+                    get ready() {
+                        return Promise.resolve();
+                    },
+                    write(chunk) {
+                        return step(() => enqueue(ops.op_compression_write(id, compressionChunk(chunk))));
+                    },
+                    close() {
+                        return step(() => {
+                            enqueue(ops.op_compression_finish(id));
+                            state = 'closed';
+                        });
+                    },
+                    abort(reason) {
+                        fail(reason);
+                        return Promise.resolve();
+                    },
+                    releaseLock() {
+                        writerLocked = false;
+                    },
+                };
+            },
+        };
+
+        const readable = iteratorStream({ next: read, return: cancel });
+
+        return { readable, writable };
+    }
+
+    class CompressionStream {
+        #streams;
+
+        constructor(format) {
+            this.#streams = codecStreams('CompressionStream', format, false);
+        }
+
+        get readable() {
+            return this.#streams.readable;
+        }
+
+        get writable() {
+            return this.#streams.writable;
+        }
+    }
+
+    class DecompressionStream {
+        #streams;
+
+        constructor(format) {
+            this.#streams = codecStreams('DecompressionStream', format, true);
+        }
+
+        get readable() {
+            return this.#streams.readable;
+        }
+
+        get writable() {
+            return this.#streams.writable;
+        }
+    }
+
+    // =========================================================================
+    // HTMLRewriter: streaming HTML transforms (tokenizer in ops.rs)
+    // =========================================================================
+
+    // Rust splits the document into tokens as chunks arrive; selector
+    // matching, the open element stack and the handler API live here. Tokens
+    // nobody touches are written back byte for byte, and output is produced
+    // chunk by chunk, so a proxied page is never buffered whole.
+    const VOID_ELEMENTS = new Set([
+        'area', 'base', 'br', 'col', 'embed', 'hr', 'img', 'input',
+        'link', 'meta', 'param', 'source', 'track', 'wbr',
+    ]);
+
+    // Start tags that close an open <p>, as the HTML parser would
+    const CLOSES_P = new Set([
+        'address', 'article', 'aside', 'blockquote', 'div', 'dl', 'fieldset', 'footer', 'form',
+        'h1', 'h2', 'h3', 'h4', 'h5', 'h6', 'header', 'hr', 'main', 'nav', 'ol', 'p', 'pre',
+        'section', 'table', 'ul',
+    ]);
+
+    // Elements closed by a sibling start tag of the same kind, e.g. <li>
+    const CLOSED_BY_SIBLING = new Set(['li', 'dt', 'dd', 'option', 'tr', 'td', 'th']);
+
+    function parseSelector(text) {
+        const source = String(text);
+        let i = 0;
+        const fail = () => {
+            throw new TypeError(`HTMLRewriter: unsupported selector '${source}'`);
+        };
+        const ident = () => {
+            const match = /^[-\w]+/.exec(source.slice(i));
+            if (!match) fail();
+            i += match[0].length;
+            return match[0];
+        };
+        const space = () => {
+            const start = i;
+            while (i < source.length && /\s/.test(source[i])) i++;
+            return i > start;
+        };
+
+        // Each selector is a list of compounds, left to right, with the
+        // combinator that relates each one to the compound before it
+        const selectors = [];
+        let selector = [];
+        let combinator = null;
+        space();
+        for (;;) {
+            const compound = { tag: null, id: null, classes: [], attributes: [] };
+            let empty = true;
+            for (;;) {
+                const c = source[i];
+                if (c === '*' && empty) {
+                    i++;
+                } else if (c === '#') {
+                    i++;
+                    compound.id = ident();
+                } else if (c === '.') {
+                    i++;
+                    compound.classes.push(ident());
+                } else if (c === '[') {
+                    i++;
+                    space();
+                    const name = ident().toLowerCase();
+                    space();
+                    let op = null;
+                    let value = null;
+                    const match = /^[~^$*|]?=/.exec(source.slice(i));
+                    if (match) {
+                        op = match[0];
+                        i += op.length;
+                        space();
+                        const quote = source[i];
+                        if (quote === '"' || quote === "'") {
+                            const end = source.indexOf(quote, i + 1);
+                            if (end < 0) fail();
+                            value = source.slice(i + 1, end);
+                            i = end + 1;
+                        } else {
+                            value = ident();
+                        }
+                        space();
+                    }
+                    if (source[i] !== ']') fail();
+                    i++;
+                    compound.attributes.push({ name, op, value });
+                } else if (c !== undefined && /[-\w]/.test(c) && empty) {
+                    compound.tag = ident().toLowerCase();
+                } else {
+                    break;
+                }
+                empty = false;
+            }
+            if (empty) fail();
+            selector.push({ compound, combinator });
+
+            const spaced = space();
+            const c = source[i];
+            if (c === undefined) {
+                selectors.push(selector);
+                return selectors;
+            }
+            if (c === ',') {
+                i++;
+                space();
+                selectors.push(selector);
+                selector = [];
+                combinator = null;
+            } else if (c === '>') {
+                i++;
+                space();
+                combinator = '>';
+            } else if (spaced) {
+                combinator = ' ';
+            } else {
+                fail();
+            }
+        }
+    }
+
+    function matchesCompound(compound, node) {
+        if (compound.tag !== null && compound.tag !== node.name) return false;
+        const get = (name) => node.attributes.get(name);
+        if (compound.id !== null && get('id') !== compound.id) return false;
+        if (compound.classes.length > 0) {
+            const classes = (get('class') ?? '').split(/\s+/);
+            if (!compound.classes.every((name) => classes.includes(name))) return false;
+        }
+        return compound.attributes.every(({ name, op, value }) => {
+            const actual = get(name);
+            if (actual === undefined) return false;
+            switch (op) {
+                case null: return true;
+                case '=': return actual === value;
+                case '~=': return actual.split(/\s+/).includes(value);
+                case '^=': return value !== '' && actual.startsWith(value);
+                case '$=': return value !== '' && actual.endsWith(value);
+                case '*=': return value !== '' && actual.includes(value);
+                case '|=': return actual === value || actual.startsWith(`${value}-`);
+            }
+            return false;
+        });
+    }
+
+    // Whether `path` (the open elements, innermost last) ends in an element
+    // matching compounds 0..=index of `selector`
+    function matchesSelector(selector, path, end = path.length - 1, index = selector.length - 1) {
+        if (!matchesCompound(selector[index].compound, path[end])) return false;
+        if (index === 0) return true;
+        if (selector[index].combinator === '>') {
+            return end > 0 && matchesSelector(selector, path, end - 1, index - 1);
+        }
+        for (let j = end - 1; j >= 0; j--) {
+            if (matchesSelector(selector, path, j, index - 1)) return true;
+        }
+        return false;
+    }
+
+    function decodeEntities(value) {
+        const named = { amp: '&', lt: '<', gt: '>', quot: '"', apos: "'", nbsp: ' ' };
+        return value.replace(/&(#x[0-9a-f]+|#[0-9]+|[a-z]+);/gi, (entity, name) => {
+            if (name[0] !== '#') return named[name.toLowerCase()] ?? entity;
+            const code = name[1] === 'x' || name[1] === 'X'
+                ? parseInt(name.slice(2), 16)
+                : parseInt(name.slice(1), 10);
+            return code <= 0x10ffff ? String.fromCodePoint(code) : entity;
+        });
+    }
+
+    function escapeHtml(text) {
+        return text.replace(/&/g, '&amp;').replace(/</g, '&lt;').replace(/>/g, '&gt;');
+    }
+
+    // Content passed to before()/after()/... is text unless `{ html: true }`
+    function rewriterContent(content, options) {
+        content = String(content);
+        return options?.html ? content : escapeHtml(content);
+    }
+
+    // before/after/replace/remove, shared by text chunks and comments, on
+    // top of the accessors in `node`
+    function contentNode(target, node) {
+        const mutations = {
+            before(content, options) {
+                target.before.push(rewriterContent(content, options));
+                return this;
+            },
+            after(content, options) {
+                target.after.unshift(rewriterContent(content, options));
+                return this;
+            },
+            replace(content, options) {
+                target.replacement = rewriterContent(content, options);
+                target.removed = true;
+                return this;
+            },
+            remove() {
+                target.replacement = '';
+                target.removed = true;
+                return this;
+            },
+            get removed() {
+                return target.removed;
+            },
+        };
+        return Object.defineProperties(mutations, Object.getOwnPropertyDescriptors(node));
+    }
+
+    function textChunk(token) {
+        const state = { before: [], after: [], replacement: '', removed: false };
+        const chunk = contentNode(state, {
+            get text() {
+                return token.raw;
+            },
+            get lastInTextNode() {
+                return token.last;
+            },
+        });
+        const output = () =>
+            state.before.join('') + (state.removed ? state.replacement : token.raw) + state.after.join('');
+        return { chunk, output };
+    }
+
+    function commentNode(token) {
+        const state = { before: [], after: [], replacement: '', removed: false };
+        let raw = token.raw;
+        const comment = contentNode(state, {
+            get text() {
+                return raw.startsWith('<!--') ? raw.slice(4, -3) : raw.slice(2, -1);
+            },
+            set text(value) {
+                raw = `<!--${value}-->`;
+            },
+        });
+        const output = () =>
+            state.before.join('') + (state.removed ? state.replacement : raw) + state.after.join('');
+        return { comment, output };
+    }
+
+    function doctypeNode(token) {
+        let removed = false;
+        const match = /^<!doctype\s+([^\s>]+)(?:\s+public\s+(["'])(.*?)\2)?(?:\s+(?:system\s+)?(["'])(.*?)\4)?/i
+            .exec(token.raw);
+        const doctype = {
+            name: match ? match[1].toLowerCase() : null,
+            publicId: match?.[3] ?? null,
+            systemId: match?.[5] ?? null,
+            remove() {
+                removed = true;
+                return this;
+            },
+            get removed() {
+                return removed;
+            },
+        };
+        return { doctype, output: () => (removed ? '' : token.raw) };
+    }
+
+    function rewriterElement(token) {
+        const state = {
+            name: token.name,
+            // Values are kept as written in the source, entities and all
+            attributes: token.attributes.map(([name, value]) => [name, value]),
+            changed: false,
+            before: [],
+            after: [],
+            prepend: [],
+            append: [],
+            inner: null,
+            replacement: null,
+            removed: false,
+            keepContent: false,
+            endTagHandlers: [],
+        };
+        const index = (name) => state.attributes.findIndex(([n]) => n === String(name).toLowerCase());
+        const voidElement = VOID_ELEMENTS.has(token.name);
+        const element = {
+            get tagName() {
+                return state.name;
+            },
+            set tagName(name) {
+                state.name = String(name).toLowerCase();
+                state.changed = true;
+            },
+            get attributes() {
+                return state.attributes.map(([name, value]) => [name, decodeEntities(value)])[Symbol.iterator]();
+            },
+            get selfClosing() {
+                return token.selfClosing;
+            },
+            get canHaveContent() {
+                return !voidElement && !token.selfClosing;
+            },
+            get removed() {
+                return state.removed;
+            },
+            getAttribute(name) {
+                const i = index(name);
+                return i < 0 ? null : decodeEntities(state.attributes[i][1]);
+            },
+            hasAttribute(name) {
+                return index(name) >= 0;
+            },
+            setAttribute(name, value) {
+                name = String(name).toLowerCase();
+                if (!/^[^\s"'>/=]+$/.test(name)) {
+                    throw new TypeError(`HTMLRewriter: invalid attribute name '${name}'`);
+                }
+                value = String(value).replace(/&/g, '&amp;');
+                const i = index(name);
+                if (i < 0) state.attributes.push([name, value]);
+                else state.attributes[i][1] = value;
+                state.changed = true;
+                return this;
+            },
+            removeAttribute(name) {
+                const i = index(name);
+                if (i >= 0) {
+                    state.attributes.splice(i, 1);
+                    state.changed = true;
+                }
+                return this;
+            },
+            before(content, options) {
+                state.before.push(rewriterContent(content, options));
+                return this;
+            },
+            after(content, options) {
+                state.after.unshift(rewriterContent(content, options));
+                return this;
+            },
+            prepend(content, options) {
+                state.prepend.unshift(rewriterContent(content, options));
+                return this;
+            },
+            append(content, options) {
+                state.append.push(rewriterContent(content, options));
+                return this;
+            },
+            setInnerContent(content, options) {
+                state.inner = rewriterContent(content, options);
+                state.prepend.length = 0;
+                state.append.length = 0;
+                return this;
+            },
+            replace(content, options) {
+                state.replacement = rewriterContent(content, options);
+                state.removed = true;
+                state.keepContent = false;
+                return this;
+            },
+            remove() {
+                state.replacement = '';
+                state.removed = true;
+                state.keepContent = false;
+                return this;
+            },
+            removeAndKeepContent() {
+                state.removed = true;
+                state.keepContent = true;
+                return this;
+            },
+            onEndTag(handler) {
+                if (typeof handler !== 'function') {
+                    throw new TypeError('HTMLRewriter: onEndTag() expects a function');
+                }
+                if (!this.canHaveContent) {
+                    throw new TypeError(`HTMLRewriter: <${state.name}> has no end tag`);
+                }
+                state.endTagHandlers.push(handler);
+            },
+        };
+
+        function startTag() {
+            if (!state.changed) return token.raw;
+            const attributes = state.attributes
+                .map(([name, value]) => ` ${name}="${value.replace(/"/g, '&quot;')}"`)
+                .join('');
+            return `<${state.name}${attributes}${token.selfClosing ? ' /' : ''}>`;
+        }
+
+        // Everything up to the element's content
+        function open() {
+            let out = state.before.join('');
+            if (state.removed && !state.keepContent) return out + state.replacement;
+            if (!state.removed) out += startTag();
+            out += state.prepend.join('');
+            if (state.inner !== null) out += state.inner;
+            return out;
+        }
+
+        // The end of the element; `raw` is empty if it was closed implicitly
+        async function close(raw) {
+            let out = '';
+            if (!state.removed || state.keepContent) {
+                const end = { before: [], after: [], removed: false, name: state.name };
+                const endTag = {
+                    get name() {
+                        return end.name;
+                    },
+                    set name(name) {
+                        end.name = String(name).toLowerCase();
+                    },
+                    before(content, options) {
+                        end.before.push(rewriterContent(content, options));
+                        return this;
+                    },
+                    after(content, options) {
+                        end.after.unshift(rewriterContent(content, options));
+                        return this;
+                    },
+                    remove() {
+                        end.removed = true;
+                        return this;
+                    },
+                };
+                for (const handler of state.endTagHandlers) await handler(endTag);
+                out += state.append.join('') + end.before.join('');
+                if (raw && !state.removed && !end.removed) {
+                    out += end.name === token.name ? raw : `</${end.name}>`;
+                }
+                out += end.after.join('');
+            }
+            return out + state.after.join('');
+        }
+
+        // Whether the element's original content is dropped
+        const skipsContent = () => (state.removed && !state.keepContent) || state.inner !== null;
+
+        return { element, open, close, skipsContent, void: !element.canHaveContent };
+    }
+
+    function chunkBytes(chunk) {
+        if (typeof chunk === 'string') return new TextEncoder().encode(chunk);
+        return compressionChunk(chunk);
+    }
+
+    // Accepts a string, bytes, an (async) iterable of either, or anything
+    // with getReader(), such as vortex.request.body
+    async function* rewriterInput(input) {
+        if (typeof input === 'string' || input instanceof ArrayBuffer || ArrayBuffer.isView(input)) {
+            yield chunkBytes(input);
+        } else if (input != null && typeof input[Symbol.asyncIterator] === 'function') {
+            for await (const chunk of input) yield chunkBytes(chunk);
+        } else if (input != null && typeof input.getReader === 'function') {
+            const reader = input.getReader();
+            try {
+                for (;;) {
+                    const { value, done } = await reader.read();
+                    if (done) return;
+                    yield chunkBytes(value);
+                }
+            } finally {
+                reader.releaseLock?.();
+            }
+        } else if (input != null && typeof input[Symbol.iterator] === 'function') {
+            for (const chunk of input) yield chunkBytes(chunk);
+        } else {
+            throw new TypeError('HTMLRewriter: transform() expects a string, bytes or a stream');
+        }
+    }
+
+    async function* rewrite(input, elementHandlers, documentHandlers) {
+        const id = ops.op_html_tokenizer_new();
+        let open = true;
+        // Open elements, innermost last
+        const stack = [];
+        const encoder = new TextEncoder();
+
+        const suppressed = () => stack.length > 0 && (stack.at(-1).hidden || stack.at(-1).rewriter.skipsContent());
+
+        // Handlers registered for a kind of node inside the current element
+        function handlersFor(kind) {
+            const handlers = [];
+            for (const entry of stack) {
+                for (const handler of entry.matched) {
+                    if (typeof handler[kind] === 'function') handlers.push(handler);
+                }
+            }
+            for (const handler of documentHandlers) {
+                if (typeof handler[kind] === 'function') handlers.push(handler);
+            }
+            return handlers;
+        }
+
+        async function closeUntil(depth, raw) {
+            let out = '';
+            while (stack.length > depth) {
+                const entry = stack.pop();
+                const last = stack.length === depth;
+                if (!entry.hidden) out += await entry.rewriter.close(last ? raw : '');
+            }
+            return out;
+        }
+
+        async function startTag(token) {
+            let out = '';
+            const top = stack.at(-1)?.node.name;
+            if ((top === 'p' && CLOSES_P.has(token.name)) || (top === token.name && CLOSED_BY_SIBLING.has(token.name))) {
+                out += await closeUntil(stack.length - 1, '');
+            }
+
+            const hidden = suppressed();
+            const node = {
+                name: token.name,
+                attributes: new Map(token.attributes.map(([name, value]) => [name, decodeEntities(value)])),
+            };
+            const path = [...stack.map((entry) => entry.node), node];
+            const matched = hidden
+                ? []
+                : elementHandlers
+                      .filter(({ selectors }) => selectors.some((selector) => matchesSelector(selector, path)))
+                      .map(({ handlers }) => handlers);
+            const rewriter = rewriterElement(token);
+            for (const handlers of matched) {
+                if (typeof handlers.element === 'function') await handlers.element(rewriter.element);
+            }
+
+            if (!hidden) out += rewriter.open();
+            if (rewriter.void) {
+                if (!hidden) out += await rewriter.close('');
+            } else {
+                stack.push({ node, rewriter, matched, hidden });
+            }
+            return out;
+        }
+
+        async function endTag(token) {
+            let depth = stack.length - 1;
+            while (depth >= 0 && stack[depth].node.name !== token.name) depth--;
+            // A stray end tag is passed through
+            if (depth < 0) return suppressed() ? '' : token.raw;
+            return closeUntil(depth, token.raw);
+        }
+
+        async function process(tokens) {
+            let out = '';
+            for (const token of tokens) {
+                switch (token.type) {
+                    case 'startTag':
+                        out += await startTag(token);
+                        break;
+                    case 'endTag':
+                        out += await endTag(token);
+                        break;
+                    case 'text': {
+                        if (suppressed()) break;
+                        const { chunk, output } = textChunk(token);
+                        for (const handlers of handlersFor('text')) await handlers.text(chunk);
+                        out += output();
+                        break;
+                    }
+                    case 'comment': {
+                        if (suppressed()) break;
+                        const { comment, output } = commentNode(token);
+                        for (const handlers of handlersFor('comments')) await handlers.comments(comment);
+                        out += output();
+                        break;
+                    }
+                    case 'doctype': {
+                        const { doctype, output } = doctypeNode(token);
+                        for (const handlers of documentHandlers) {
+                            if (typeof handlers.doctype === 'function') await handlers.doctype(doctype);
+                        }
+                        out += output();
+                        break;
+                    }
+                }
+            }
+            return out;
+        }
+
+        try {
+            for await (const chunk of rewriterInput(input)) {
+                const out = await process(ops.op_html_tokenizer_write(id, chunk));
+                if (out) yield encoder.encode(out);
+            }
+            const tokens = ops.op_html_tokenizer_finish(id);
+            open = false;
+            let out = (await process(tokens)) + (await closeUntil(0, ''));
+            const appended = [];
+            const end = {
+                append(content, options) {
+                    appended.push(rewriterContent(content, options));
+                    return this;
+                },
+            };
+            for (const handlers of documentHandlers) {
+                if (typeof handlers.end === 'function') await handlers.end(end);
+            }
+            out += appended.join('');
+            if (out) yield encoder.encode(out);
+        } finally {
+            if (open) ops.op_html_tokenizer_close(id);
+        }
+    }
+
+    class HTMLRewriter {
+        #elementHandlers = [];
+        #documentHandlers = [];
+
+        // `handlers` may be an object or class instance with element(),
+        // comments() and text() methods; each may return a promise
+        on(selector, handlers) {
+            if (handlers == null || typeof handlers !== 'object') {
+                throw new TypeError('HTMLRewriter: on() expects a handlers object');
+            }
+            this.#elementHandlers.push({ selectors: parseSelector(selector), handlers });
+            return this;
+        }
+
+        // doctype(), comments(), text() and end() for the whole document
+        onDocument(handlers) {
+            if (handlers == null || typeof handlers !== 'object') {
+                throw new TypeError('HTMLRewriter: onDocument() expects a handlers object');
+            }
+            this.#documentHandlers.push(handlers);
+            return this;
+        }
+
+        // A Response is rewritten into a new Response; anything else into a
+        // readable stream of the document's bytes. Input is pulled as the
+        // output is read.
+        transform(input) {
+            const source = input instanceof Response ? input.body ?? '' : input;
+            const iterator = rewrite(source, [...this.#elementHandlers], [...this.#documentHandlers]);
+            if (input instanceof Response) {
+                const headers = new Headers(input.headers);
+                headers.delete('content-length');
+                return new Response(iteratorStream(iterator), {
+                    status: input.status,
+                    statusText: input.statusText,
+                    headers,
+                });
+            }
+            return Object.assign(iteratorStream(iterator), {
+                async text() {
+                    const decoder = new TextDecoder();
+                    let text = '';
+                    for await (const chunk of this) text += decoder.decode(chunk, { stream: true });
+                    return text + decoder.decode();
+                },
+            });
+        }
+    }
+
+    // =========================================================================
+    // Buffer (always installed: many libraries feature-detect `Buffer`)
+    // =========================================================================
+
+    function bytesToLatin1(bytes) {
+        let out = '';
+        for (let i = 0; i < bytes.length; i += 0x8000) {
+            out += String.fromCharCode.apply(null, bytes.subarray(i, i + 0x8000));
+        }
+        return out;
+    }
+
+    function latin1ToBytes(str) {
+        const bytes = new Uint8Array(str.length);
+        for (let i = 0; i < str.length; i++) bytes[i] = str.charCodeAt(i) & 0xff;
+        return bytes;
+    }
+
+    function normalizeEncoding(encoding = 'utf8') {
+        const normalized = String(encoding).toLowerCase();
+        switch (normalized) {
+            case 'utf8':
+            case 'utf-8':
+                return 'utf8';
+            case 'ucs2':
+            case 'ucs-2':
+            case 'utf16le':
+            case 'utf-16le':
+                return 'utf16le';
+            case 'latin1':
+            case 'binary':
+                return 'latin1';
+            case 'hex':
+            case 'base64':
+            case 'base64url':
+            case 'ascii':
+                return normalized;
+            default:
+                throw new TypeError(`Unknown encoding: ${encoding}`);
+        }
+    }
+
+    function encodeString(string, encoding) {
+        string = String(string);
+        switch (normalizeEncoding(encoding)) {
+            case 'utf8':
+                return utf8Encode(string);
+            case 'hex': {
+                const bytes = new Uint8Array(Math.floor(string.length / 2));
+                for (let i = 0; i < bytes.length; i++) {
+                    // parseInt alone would accept pairs like '1g' as 0x01
+                    const pair = string.substr(i * 2, 2);
+                    if (!/^[0-9a-f]{2}$/i.test(pair)) return bytes.subarray(0, i);
+                    bytes[i] = parseInt(pair, 16);
+                }
+                return bytes;
+            }
+            case 'base64':
+            case 'base64url': {
+                // Node accepts both alphabets and missing padding for either encoding
+                const normalized = string.replace(/[^A-Za-z0-9+/\-_]/g, '')
+                    .replace(/-/g, '+')
+                    .replace(/_/g, '/');
+                return latin1ToBytes(atob(normalized.length % 4 === 1 ? normalized.slice(0, -1) : normalized));
+            }
+            case 'latin1':
+            case 'ascii':
+                return latin1ToBytes(string);
+            case 'utf16le': {
+                const bytes = new Uint8Array(string.length * 2);
+                for (let i = 0; i < string.length; i++) {
+                    const code = string.charCodeAt(i);
+                    bytes[i * 2] = code & 0xff;
+                    bytes[i * 2 + 1] = code >> 8;
+                }
+                return bytes;
+            }
+        }
+    }
+
+    function decodeBytes(bytes, encoding) {
+        switch (normalizeEncoding(encoding)) {
+            case 'utf8':
+                return utf8Decode(bytes, false);
+            case 'hex':
+                return Array.from(bytes, (b) => b.toString(16).padStart(2, '0')).join('');
+            case 'base64':
+                return btoa(bytesToLatin1(bytes));
+            case 'base64url':
+                return btoa(bytesToLatin1(bytes)).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+            case 'latin1':
+                return bytesToLatin1(bytes);
+            case 'ascii':
+                return bytesToLatin1(bytes.map((b) => b & 0x7f));
+            case 'utf16le': {
+                let out = '';
+                for (let i = 0; i + 1 < bytes.length; i += 2) {
+                    out += String.fromCharCode(bytes[i] | (bytes[i + 1] << 8));
+                }
+                return out;
+            }
+        }
+    }
+
+    class Buffer extends Uint8Array {
+        static from(value, encodingOrOffset, length) {
+            if (typeof value === 'string') {
+                const bytes = encodeString(value, encodingOrOffset);
+                return new Buffer(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+            }
+            if (value instanceof ArrayBuffer) {
+                const offset = encodingOrOffset || 0;
+                return new Buffer(value, offset, length ?? value.byteLength - offset);
+            }
+            if (ArrayBuffer.isView(value) || Array.isArray(value)) {
+                const buffer = new Buffer(value.length);
+                buffer.set(value);
+                return buffer;
+            }
+            if (value && value.type === 'Buffer' && Array.isArray(value.data)) {
+                return Buffer.from(value.data);
+            }
+            throw new TypeError('The first argument must be a string, Buffer, ArrayBuffer, Array, or array-like object');
+        }
+
+        static alloc(size, fill = 0, encoding) {
+            const buffer = new Buffer(size);
+            if (fill !== 0) buffer.fill(fill, 0, size, encoding);
+            return buffer;
+        }
+
+        static allocUnsafe(size) {
+            return new Buffer(size);
+        }
+
+        static isBuffer(value) {
+            return value instanceof Buffer;
+        }
+
+        static isEncoding(encoding) {
+            try {
+                normalizeEncoding(encoding);
+                return true;
+            } catch (e) {
+                return false;
+            }
+        }
+
+        static byteLength(value, encoding) {
+            if (typeof value === 'string') return encodeString(value, encoding).length;
+            return value.byteLength;
+        }
+
+        static compare(a, b) {
+            const length = Math.min(a.length, b.length);
+            for (let i = 0; i < length; i++) {
+                if (a[i] !== b[i]) return a[i] < b[i] ? -1 : 1;
+            }
+            return a.length === b.length ? 0 : a.length < b.length ? -1 : 1;
+        }
+
+        static concat(list, totalLength) {
+            const length = totalLength ?? list.reduce((sum, item) => sum + item.length, 0);
+            const result = Buffer.alloc(length);
+            let offset = 0;
+            for (const item of list) {
+                if (offset >= length) break;
+                result.set(item.subarray(0, length - offset), offset);
+                offset += item.length;
+            }
+            return result;
+        }
+
+        toString(encoding = 'utf8', start = 0, end = this.length) {
+            return decodeBytes(this.subarray(start, end), encoding);
+        }
+
+        toJSON() {
+            return { type: 'Buffer', data: Array.from(this) };
+        }
+
+        equals(other) {
+            return Buffer.compare(this, other) === 0;
+        }
+
+        compare(other) {
+            return Buffer.compare(this, other);
+        }
+
+        // Node's slice() returns a view over the same memory, unlike Uint8Array's copy
+        slice(start, end) {
+            return this.subarray(start, end);
+        }
+
+        fill(value, offset = 0, end = this.length, encoding) {
+            if (typeof offset === 'string') {
+                encoding = offset;
+                offset = 0;
+                end = this.length;
+            }
+            if (typeof value !== 'string') {
+                return super.fill(typeof value === 'number' ? value & 0xff : value, offset, end);
+            }
+            const bytes = encodeString(value, encoding);
+            for (let i = offset, j = 0; i < end && bytes.length > 0; i++, j++) {
+                this[i] = bytes[j % bytes.length];
+            }
+            return this;
+        }
+
+        write(string, offset = 0, length = this.length - offset, encoding = 'utf8') {
+            if (typeof offset === 'string') {
+                encoding = offset;
+                offset = 0;
+                length = this.length;
+            } else if (typeof length === 'string') {
+                encoding = length;
+                length = this.length - offset;
+            }
+            const bytes = encodeString(string, encoding).subarray(0, Math.min(length, this.length - offset));
+            this.set(bytes, offset);
+            return bytes.length;
+        }
+
+        copy(target, targetStart = 0, sourceStart = 0, sourceEnd = this.length) {
+            const bytes = this.subarray(sourceStart, Math.min(sourceEnd, sourceStart + target.length - targetStart));
+            target.set(bytes, targetStart);
+            return bytes.length;
+        }
+
+        indexOf(value, byteOffset = 0, encoding) {
+            if (typeof value === 'number') return super.indexOf(value & 0xff, byteOffset);
+            const needle = typeof value === 'string' ? encodeString(value, encoding) : value;
+            if (needle.length === 0) return Math.min(byteOffset, this.length);
+            outer: for (let i = byteOffset; i <= this.length - needle.length; i++) {
+                for (let j = 0; j < needle.length; j++) {
+                    if (this[i + j] !== needle[j]) continue outer;
+                }
+                return i;
+            }
+            return -1;
+        }
+
+        includes(value, byteOffset, encoding) {
+            return this.indexOf(value, byteOffset, encoding) !== -1;
+        }
+
+        #view() {
+            return new DataView(this.buffer, this.byteOffset, this.byteLength);
+        }
+
+        readUInt8(offset = 0) { return this.#view().getUint8(offset); }
+        readUInt16LE(offset = 0) { return this.#view().getUint16(offset, true); }
+        readUInt16BE(offset = 0) { return this.#view().getUint16(offset, false); }
+        readUInt32LE(offset = 0) { return this.#view().getUint32(offset, true); }
+        readUInt32BE(offset = 0) { return this.#view().getUint32(offset, false); }
+        readInt8(offset = 0) { return this.#view().getInt8(offset); }
+        readInt16LE(offset = 0) { return this.#view().getInt16(offset, true); }
+        readInt16BE(offset = 0) { return this.#view().getInt16(offset, false); }
+        readInt32LE(offset = 0) { return this.#view().getInt32(offset, true); }
+        readInt32BE(offset = 0) { return this.#view().getInt32(offset, false); }
+        readBigUInt64LE(offset = 0) { return this.#view().getBigUint64(offset, true); }
+        readBigUInt64BE(offset = 0) { return this.#view().getBigUint64(offset, false); }
+        readFloatLE(offset = 0) { return this.#view().getFloat32(offset, true); }
+        readFloatBE(offset = 0) { return this.#view().getFloat32(offset, false); }
+        readDoubleLE(offset = 0) { return this.#view().getFloat64(offset, true); }
+        readDoubleBE(offset = 0) { return this.#view().getFloat64(offset, false); }
+
+        writeUInt8(value, offset = 0) { this.#view().setUint8(offset, value); return offset + 1; }
+        writeUInt16LE(value, offset = 0) { this.#view().setUint16(offset, value, true); return offset + 2; }
+        writeUInt16BE(value, offset = 0) { this.#view().setUint16(offset, value, false); return offset + 2; }
+        writeUInt32LE(value, offset = 0) { this.#view().setUint32(offset, value, true); return offset + 4; }
+        writeUInt32BE(value, offset = 0) { this.#view().setUint32(offset, value, false); return offset + 4; }
+        writeInt8(value, offset = 0) { this.#view().setInt8(offset, value); return offset + 1; }
+        writeInt16LE(value, offset = 0) { this.#view().setInt16(offset, value, true); return offset + 2; }
+        writeInt16BE(value, offset = 0) { this.#view().setInt16(offset, value, false); return offset + 2; }
+        writeInt32LE(value, offset = 0) { this.#view().setInt32(offset, value, true); return offset + 4; }
+        writeInt32BE(value, offset = 0) { this.#view().setInt32(offset, value, false); return offset + 4; }
+        writeDoubleLE(value, offset = 0) { this.#view().setFloat64(offset, value, true); return offset + 8; }
+        writeDoubleBE(value, offset = 0) { this.#view().setFloat64(offset, value, false); return offset + 8; }
+    }
+
+    // =========================================================================
+    // Node.js compatibility layer (opt-in via WorkerOptions::node_compat)
+    // =========================================================================
+
+    class Hash {
+        #algorithm;
+        #chunks = [];
+
+        constructor(algorithm) {
+            this.#algorithm = String(algorithm);
+        }
+
+        update(data, encoding) {
+            this.#chunks.push(typeof data === 'string' ? encodeString(data, encoding) : toUint8Array(data));
+            return this;
+        }
+
+        digest(encoding) {
+            const digest = ops.op_crypto_hash(this.#algorithm, Buffer.concat(this.#chunks));
+            const buffer = Buffer.from(digest);
+            return encoding === undefined ? buffer : buffer.toString(encoding);
+        }
+    }
+
+    const nodeCrypto = {
+        createHash: (algorithm) => new Hash(algorithm),
+        randomBytes: (size) => crypto.getRandomValues(Buffer.alloc(size)),
+        randomUUID: () => crypto.randomUUID(),
+        getRandomValues: (array) => crypto.getRandomValues(array),
+        timingSafeEqual(a, b) {
+            if (a.byteLength !== b.byteLength) {
+                throw new RangeError('Input buffers must have the same byte length');
+            }
+            const left = toUint8Array(a);
+            const right = toUint8Array(b);
+            let diff = 0;
+            for (let i = 0; i < left.length; i++) diff |= left[i] ^ right[i];
+            return diff === 0;
+        },
+        webcrypto: crypto,
+    };
+
+    function inspect(value) {
+        if (typeof value === 'string') return `'${value}'`;
+        if (typeof value === 'function') return `[Function: ${value.name || '(anonymous)'}]`;
+        if (typeof value === 'bigint') return `${value}n`;
+        if (typeof value === 'symbol' || value === undefined || value === null) return String(value);
+        if (value instanceof Error) return value.stack || String(value);
+        if (typeof value === 'object') {
+            try {
+                return JSON.stringify(value);
+            } catch (e) {
+                return String(value);
+            }
+        }
+        return String(value);
+    }
+
+    function format(template, ...args) {
+        if (typeof template !== 'string') {
+            return [template, ...args].map((arg) => (typeof arg === 'string' ? arg : inspect(arg))).join(' ');
+        }
+        let index = 0;
+        const formatted = template.replace(/%([sdifjoO%])/g, (match, spec) => {
+            if (spec === '%') return '%';
+            if (index >= args.length) return match;
+            const arg = args[index++];
+            switch (spec) {
+                case 's': return typeof arg === 'string' ? arg : inspect(arg);
+                case 'd': return String(Number(arg));
+                case 'i': return String(parseInt(arg, 10));
+                case 'f': return String(parseFloat(arg));
+                case 'j': return JSON.stringify(arg);
+                default: return inspect(arg);
+            }
+        });
+        const rest = args.slice(index).map((arg) => (typeof arg === 'string' ? arg : inspect(arg)));
+        return [formatted, ...rest].join(' ');
+    }
+
+    const nodeUtil = {
+        format,
+        inspect,
+        promisify(fn) {
+            return (...args) => new Promise((resolve, reject) => {
+                fn(...args, (err, value) => (err ? reject(err) : resolve(value)));
+            });
+        },
+        inherits(ctor, superCtor) {
+            Object.setPrototypeOf(ctor.prototype, superCtor.prototype);
+            Object.setPrototypeOf(ctor, superCtor);
+        },
+        deprecate: (fn) => fn,
+        isDeepStrictEqual: (a, b) => JSON.stringify(a) === JSON.stringify(b),
+        TextEncoder,
+        TextDecoder,
+    };
+
+    const nodeBuiltins = {
+        buffer: { Buffer },
+        crypto: nodeCrypto,
+        util: nodeUtil,
+    };
+
+    // process.env is a read-only view of the env map injected by the host.
+    // The host process's own environment is never copied into the isolate.
+    const readOnlyEnv = (name) => () => {
+        throw new TypeError(`Cannot modify process.env.${String(name)}: environment is read-only in Vortex`);
+    };
+    const env = new Proxy(Object.freeze({ ...config.env }), {
+        set: (_target, name) => readOnlyEnv(name)(),
+        deleteProperty: (_target, name) => readOnlyEnv(name)(),
+        defineProperty: (_target, name) => readOnlyEnv(name)(),
+    });
+
+    const process = Object.freeze({
+        env,
+        // Stubs so version/platform checks in Node-targeted libraries take the POSIX path
+        version: 'v20.0.0',
+        versions: Object.freeze({ node: '20.0.0', vortex: vortex.version }),
+        platform: 'linux',
+        arch: 'x64',
+        argv: Object.freeze([]),
+        release: Object.freeze({ name: 'node' }),
+        cwd: () => '/',
+        nextTick: (callback, ...args) => globalThis.queueMicrotask(() => callback(...args)),
+        getBuiltinModule(id) {
+            const name = String(id).replace(/^node:/, '');
+            return Object.prototype.hasOwnProperty.call(nodeBuiltins, name) ? nodeBuiltins[name] : undefined;
+        },
+    });
+    nodeBuiltins.process = process;
+
+    // =========================================================================
+    // CommonJS interop (opt-in via WorkerOptions::commonjs)
+    // =========================================================================
+
+    // Captured before eval may be replaced below; only reachable through require()
+    const evaluate = globalThis.eval;
+
+    // Loaded modules by bundle path. Shared by every require() so each file
+    // runs once, and a cycle sees the partial exports, as in Node.
+    const commonjsCache = new Map();
+
+    function moduleError(message, code) {
+        const error = new Error(message);
+        error.code = code;
+        return error;
+    }
+
+    function requireBuiltin(specifier) {
+        const name = specifier.replace(/^node:/, '');
+        if (!Object.prototype.hasOwnProperty.call(nodeBuiltins, name)) {
+            throw moduleError(`No such built-in module: ${specifier}`, 'ERR_UNKNOWN_BUILTIN_MODULE');
+        }
+        if (!config.nodeCompat) {
+            throw moduleError(
+                `Cannot require '${specifier}': Node.js built-in modules need Node.js compatibility (node_compat)`,
+                'MODULE_NOT_FOUND',
+            );
+        }
+        return nodeBuiltins[name];
+    }
+
+    // A require() for the bundled file at `referrer` ('' for the function itself)
+    function createRequire(referrer) {
+        const resolve = (specifier) => {
+            try {
+                return ops.op_require_resolve(specifier, referrer);
+            } catch (e) {
+                throw moduleError(e.message, 'MODULE_NOT_FOUND');
+            }
+        };
+
+        function require(specifier) {
+            if (typeof specifier !== 'string') {
+                throw new TypeError(`The "id" argument must be of type string. Received ${typeof specifier}`);
+            }
+            if (specifier.startsWith('node:') || Object.prototype.hasOwnProperty.call(nodeBuiltins, specifier)) {
+                return requireBuiltin(specifier);
+            }
+            const path = resolve(specifier);
+            const cached = commonjsCache.get(path);
+            if (cached) return cached.exports;
+
+            const { format, source } = ops.op_require_source(path);
+            if (format === 'module') {
+                throw moduleError(
+                    `require() of ES module /${path} is not supported: load it with import instead`,
+                    'ERR_REQUIRE_ESM',
+                );
+            }
+            const filename = `/${path}`;
+            const module = { id: filename, filename, exports: {}, loaded: false };
+            commonjsCache.set(path, module);
+            try {
+                if (format === 'json') {
+                    module.exports = JSON.parse(source);
+                } else {
+                    const wrapper = evaluate(
+                        `(function (exports, require, module, __filename, __dirname) {${source}\n})` +
+                        `\n//# sourceURL=file://${filename}`,
+                    );
+                    const dirname = filename.slice(0, filename.lastIndexOf('/')) || '/';
+                    wrapper.call(module.exports, module.exports, createRequire(path), module, filename, dirname);
+                }
+            } catch (e) {
+                commonjsCache.delete(path);
+                throw e;
+            }
+            module.loaded = true;
+            return module.exports;
+        }
+        require.resolve = (specifier) => `/${resolve(specifier)}`;
+        return require;
+    }
+
+    // =========================================================================
+    // Install globals
+    // =========================================================================
+
+    const globals = {
+        Buffer,
+        DOMException,
+        Event,
+        EventTarget,
+        AbortController,
+        AbortSignal,
+        TextEncoder,
+        TextDecoder,
+        URL,
+        URLSearchParams,
+        atob,
+        btoa,
+        structuredClone,
+        performance,
+        navigator,
+        crypto,
+        CompressionStream,
+        DecompressionStream,
+        HTMLRewriter,
+        Headers,
+        ReadableStream,
+        Request,
+        Response,
+        fetch,
+        WebSocketPair,
+        MessageEvent,
+        CloseEvent,
+        Cache,
+        CacheStorage,
+        caches,
+    };
+
+    for (const [name, value] of Object.entries(globals)) {
+        Object.defineProperty(globalThis, name, {
+            value,
+            writable: true,
+            enumerable: false,
+            configurable: true,
+        });
+    }
+
+    // WinterCG requires `self` to alias the global object
+    globalThis.self = globalThis;
+
+    // V8 installs Temporal in every isolate of the process; only keep it
+    // where the worker asked for it
+    if (!config.temporal) {
+        delete globalThis.Temporal;
+    }
+
+    // With code generation disabled V8 throws a generic EvalError from eval();
+    // replace it so users learn why. V8 itself still blocks `new Function`.
+    if (config.disableCodeGeneration) {
+        Object.defineProperty(globalThis, 'eval', {
+            value: function eval() {
+                throw new EvalError(
+                    'Code generation from strings is disabled for this function: eval() and new Function() are not allowed',
+                );
+            },
+            writable: true,
+            enumerable: false,
+            configurable: true,
+        });
+    }
+
+    // Namespaces left out of config.apis throw when accessed, so a plan
+    // without e.g. sql gets a clear error rather than `undefined`. Output
+    // (including vortex.sse) and logging don't reach outside the invocation
+    // and are always available.
+    if (config.apis) {
+        const enabled = new Set(config.apis);
+        const always = new Set(['version', 'platform', 'errors', 'log', 'write', 'end', 'setResult', 'sse']);
+        for (const name of Object.keys(vortex)) {
+            if (always.has(name) || enabled.has(name)) continue;
+            Object.defineProperty(vortex, name, {
+                get() {
+                    throw new VortexCapabilityError(name);
+                },
+                enumerable: true,
+                configurable: false,
+            });
+        }
+    }
+
+    // console and vortex are what log capture and platform APIs hang off, so
+    // user code must not be able to replace or monkey-patch them.
+    for (const [name, value] of Object.entries({ console, vortex })) {
+        Object.defineProperty(globalThis, name, {
+            value: Object.freeze(value),
+            writable: false,
+            enumerable: false,
+            configurable: false,
+        });
+    }
+
+    if (config.nodeCompat) {
+        Object.defineProperty(globalThis, 'process', {
+            value: process,
+            writable: true,
+            enumerable: false,
+            configurable: true,
+        });
+    }
+
+    // Also what CommonJS files imported from ES modules are loaded with
+    if (config.commonjs) {
+        Object.defineProperty(globalThis, 'require', {
+            value: createRequire(''),
+            writable: true,
+            enumerable: false,
+            configurable: true,
+        });
+    }
+
+    // =========================================================================
+    // Hardening (opt-in via WorkerOptions::harden)
+    // =========================================================================
+
+    // Freezing a prototype turns every inherited data property into a trap:
+    // `obj.toString = fn` silently fails (or throws in strict mode) because the
+    // frozen prototype's property is non-writable. Like SES, we convert the
+    // commonly overridden properties into accessors whose setter defines an
+    // own property on the receiver instead.
+    const OVERRIDABLE_PROPERTIES = [
+        [Object.prototype, ['constructor', 'toString', 'toLocaleString', 'valueOf', 'hasOwnProperty',
+            'isPrototypeOf', 'propertyIsEnumerable']],
+        [Function.prototype, ['constructor', 'toString']],
+        [Array.prototype, ['constructor', 'toString', 'push']],
+        [Promise.prototype, ['constructor', 'then']],
+        [Error.prototype, ['constructor', 'name', 'message', 'toString']],
+        ...[EvalError, RangeError, ReferenceError, SyntaxError, TypeError, URIError]
+            .map((E) => [E.prototype, ['constructor', 'name', 'message']]),
+    ];
+
+    function enablePropertyOverride(object, key) {
+        const desc = Object.getOwnPropertyDescriptor(object, key);
+        if (!desc || !('value' in desc) || !desc.configurable) return;
+        const value = desc.value;
+        Object.defineProperty(object, key, {
+            get() {
+                return value;
+            },
+            set(newValue) {
+                if (this === object) {
+                    throw new TypeError(`Cannot assign to read only property '${String(key)}' of hardened intrinsic`);
+                }
+                Object.defineProperty(this, key, {
+                    value: newValue,
+                    writable: true,
+                    enumerable: true,
+                    configurable: true,
+                });
+            },
+            enumerable: desc.enumerable,
+            configurable: false,
+        });
+    }
+
+    // Intrinsics that are not reachable as properties of the global object
+    const hiddenIntrinsics = () => [
+        Object.getPrototypeOf(function* () {}),
+        Object.getPrototypeOf(async function () {}),
+        Object.getPrototypeOf(async function* () {}),
+        Object.getPrototypeOf(Int8Array),
+        Object.getPrototypeOf([][Symbol.iterator]()),
+        Object.getPrototypeOf(new Map()[Symbol.iterator]()),
+        Object.getPrototypeOf(new Set()[Symbol.iterator]()),
+        Object.getPrototypeOf(''[Symbol.iterator]()),
+        Object.getPrototypeOf(/x/[Symbol.matchAll]('')),
+    ];
+
+    // Transitively freeze everything reachable from the given roots
+    function harden(roots, skip) {
+        const seen = new Set(skip);
+        const queue = [...roots];
+        while (queue.length > 0) {
+            const value = queue.pop();
+            if (value === null || (typeof value !== 'object' && typeof value !== 'function') || seen.has(value)) {
+                continue;
+            }
+            seen.add(value);
+            Object.freeze(value);
+            queue.push(Object.getPrototypeOf(value));
+            for (const key of Reflect.ownKeys(value)) {
+                const desc = Object.getOwnPropertyDescriptor(value, key);
+                if ('value' in desc) {
+                    queue.push(desc.value);
+                } else {
+                    queue.push(desc.get, desc.set);
+                }
+            }
+        }
+    }
+
+    // Prevent access to potentially dangerous globals
+    delete globalThis.Deno;
+
+    // A Response returned by the function is streamed out through the output
+    // ops, status and headers first and then its body as it is read, instead
+    // of being serialized; one that accepts a WebSocket upgrade holds the
+    // invocation open until the connection is closed. Anything else is
+    // passed on as the result.
+    async function settleResult(result) {
+        const value = await result;
+        if (!(value instanceof Response)) return value;
+        if (value.type === 'error') throw new TypeError('Response.error() cannot be returned');
+        if (value.bodyUsed || value.body?.locked) throw new TypeError('Body has already been used');
+        if (value.webSocket !== null) {
+            const closed = webSocketClosed(value.webSocket);
+            ops.op_response_head(value.status, [...value.headers]);
+            await closed;
+            ops.op_end();
+            return undefined;
+        }
+        ops.op_response_head(value.status, [...value.headers]);
+        const chunks = value.body === null ? null : bodyChunks.get(value.body)();
+        if (chunks) {
+            // Each chunk waits for the client to make room for it
+            for await (const chunk of chunks) await ops.op_response_write(chunk);
+        }
+        ops.op_end();
+        return undefined;
+    }
+
+    // The script's completion value, which only the host sees. `harden`
+    // hardens the environment after the tenant's bootstrap scripts have run,
+    // so the polyfills they install are frozen along with everything else;
+    // `settle` is applied to the promise of every user script.
+    return {
+        harden: () => {
+            if (!config.harden) return;
+            for (const [object, keys] of OVERRIDABLE_PROPERTIES) {
+                for (const key of keys) enablePropertyOverride(object, key);
+            }
+            const roots = Reflect.ownKeys(globalThis).map((name) => globalThis[name]);
+            harden([...roots, ...hiddenIntrinsics()], [globalThis, core]);
+        },
+        settle: settleResult,
+    };
+})(globalThis, Deno.core);
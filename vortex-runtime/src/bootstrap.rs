@@ -3,120 +3,46 @@
 //! This module provides the initialization JavaScript that:
 //! - Polyfills `console.log` to route through our `op_log` operation
 //! - Sets up the global `vortex` object for future API extensions
-//! - Provides a `setTimeout` polyfill for async operations
+//! - Provides timer polyfills (`setTimeout`, `setInterval`) backed by `op_sleep`
+//! - Installs the WinterCG web globals (URL, TextEncoder, crypto, etc.)
+//!
+//! Embedders can run their own [`BootstrapScript`]s after it, e.g. a helper
+//! SDK for a tenant's plan, without patching `bootstrap.js`.
 
 /// Bootstrap JavaScript code that initializes the runtime environment.
 ///
 /// This code runs once when a VortexWorker is created, before any user code executes.
 /// It establishes the bridge between JavaScript's standard APIs and our Rust operations.
-pub const BOOTSTRAP_JS: &str = r#"
-// Store the core ops reference for faster access
-const ops = Deno.core.ops;
-
-// Polyfill console object to capture logs via our custom op
-// The real console.log would print to stdout, but we need to capture it
-globalThis.console = {
-  log: (...args) => {
-    const message = args.map(arg => {
-      if (arg === null) return 'null';
-      if (arg === undefined) return 'undefined';
-      if (typeof arg === 'object') {
-        try {
-          return JSON.stringify(arg);
-        } catch (e) {
-          return String(arg);
-        }
-      }
-      return String(arg);
-    }).join(' ');
-    ops.op_log(message);
-  },
-  error: (...args) => {
-    globalThis.console.log('[ERROR]', ...args);
-  },
-  warn: (...args) => {
-    globalThis.console.log('[WARN]', ...args);
-  },
-  info: (...args) => {
-    globalThis.console.log('[INFO]', ...args);
-  },
-  debug: (...args) => {
-    globalThis.console.log('[DEBUG]', ...args);
-  }
-};
-
-// Global vortex object for future API extensions
-// This will be expanded as we add more platform capabilities
-globalThis.vortex = {
-  version: '0.1.0',
-  platform: 'vortex-runtime',
-  
-  // Future: KV storage, Durable Objects, etc.
-};
-
-// Internal timer tracking for setTimeout implementation
-let __timerId = 0;
-const __activeTimers = new Map();
-
-// setTimeout polyfill using op_sleep for proper async sleeping
-// This properly yields to the tokio runtime instead of busy-waiting
-globalThis.setTimeout = (callback, delay = 0) => {
-  const id = ++__timerId;
-  
-  const timerPromise = (async () => {
-    // Use op_sleep for proper async waiting (backed by tokio::time::sleep)
-    // BigInt is required because op_sleep expects a bigint parameter
-    await ops.op_sleep(BigInt(Math.max(0, delay)));
-    
-    // Only call callback if timer wasn't cleared
-    if (__activeTimers.has(id)) {
-      __activeTimers.delete(id);
-      if (typeof callback === 'function') {
-        callback();
-      }
-    }
-  })();
-  
-  __activeTimers.set(id, timerPromise);
-  return id;
-};
+///
+/// It isn't part of the V8 snapshot built by `build.rs`: it reads the worker's
+/// configuration as it runs, so each worker evaluates it on top of the snapshot.
+pub const BOOTSTRAP_JS: &str = include_str!("bootstrap.js");
 
-// clearTimeout implementation
-globalThis.clearTimeout = (id) => {
-  __activeTimers.delete(id);
-};
+/// Script name tenant bootstrap scripts are executed under; appears in their
+/// stack traces.
+pub(crate) const TENANT_BOOTSTRAP_NAME: &str = "[vortex:tenant_bootstrap]";
 
-// setInterval polyfill (limited implementation for basic use)
-globalThis.setInterval = (callback, delay = 0) => {
-  const id = ++__timerId;
-  let running = true;
-  
-  const intervalLoop = async () => {
-    while (running && __activeTimers.has(id)) {
-      const startTime = ops.op_get_time_ms();
-      while (ops.op_get_time_ms() - startTime < delay) {
-        await Promise.resolve();
-      }
-      if (running && __activeTimers.has(id) && typeof callback === 'function') {
-        callback();
-      }
+/// JavaScript an embedder runs in every worker of a tenant, after the core
+/// bootstrap and before any user code (see
+/// [`WorkerOptions::bootstrap_scripts`](crate::WorkerOptions::bootstrap_scripts)).
+///
+/// It runs as a classic script with the same globals user code sees, so it
+/// can install helpers such as `globalThis.acme = { ... }` but can't reach
+/// the runtime's ops. It runs before `harden`, so it can also install
+/// polyfills on the intrinsics; `harden` then freezes those and the globals
+/// it defines along with the rest of the environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootstrapScript {
+    /// Identifies the script in error messages, e.g. `acme-sdk`
+    pub name: String,
+    pub source: String,
+}
+
+impl BootstrapScript {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            source: source.into(),
+        }
     }
-  };
-  
-  __activeTimers.set(id, { running: true });
-  intervalLoop();
-  return id;
-};
-
-globalThis.clearInterval = (id) => {
-  const timer = __activeTimers.get(id);
-  if (timer) {
-    timer.running = false;
-    __activeTimers.delete(id);
-  }
-};
-
-// Prevent access to potentially dangerous globals
-// These would allow escaping the sandbox
-delete globalThis.Deno;
-"#;
+}
@@ -0,0 +1,121 @@
+//! Bundling of multi-file projects into a single ES module.
+//!
+//! The Go API stores one blob per function. `vortex-runtime bundle <entry>`
+//! (or [`bundle`] from Rust) resolves the local module graph starting at the
+//! entrypoint and flattens it into one ESM file plus a source map, so
+//! multi-file projects fit that model without shipping an archive.
+//!
+//! Only local `file:` imports are followed; remote specifiers are rejected so
+//! a bundle never depends on what the network served at build time.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use deno_emit::{
+    BundleOptions, BundleType, EmitOptions, LoadFuture, LoadOptions, LoadResponse, Loader,
+    ModuleSpecifier, SourceMapOption,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::transpile::JsxOptions;
+
+/// Subcommand that bundles a project instead of running it.
+pub const BUNDLE_SUBCOMMAND: &str = "bundle";
+
+/// A bundled function.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bundle {
+    /// The flattened ES module
+    pub code: String,
+    /// Source map (JSON) from `code` back to the original files
+    pub source_map: Option<String>,
+}
+
+/// Reads modules from the local filesystem.
+struct FileLoader;
+
+impl Loader for FileLoader {
+    fn load(&self, specifier: &ModuleSpecifier, _options: LoadOptions) -> LoadFuture {
+        let specifier = specifier.clone();
+        Box::pin(async move {
+            if specifier.scheme() != "file" {
+                return Err(anyhow!(
+                    "Cannot bundle '{}': only local imports are supported",
+                    specifier
+                ));
+            }
+            let path = specifier
+                .to_file_path()
+                .map_err(|_| anyhow!("Invalid file URL '{}'", specifier))?;
+            let content = match tokio::fs::read(&path).await {
+                Ok(content) => content,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(anyhow!("Failed to read '{}': {}", path.display(), e)),
+            };
+            Ok(Some(LoadResponse::Module {
+                content: Arc::from(content),
+                specifier,
+                maybe_headers: None,
+            }))
+        })
+    }
+}
+
+/// Bundle the module graph rooted at `entrypoint` into a single ES module.
+///
+/// TypeScript and JSX modules in the graph are transpiled with `jsx`.
+pub async fn bundle(entrypoint: &Path, jsx: &JsxOptions) -> Result<Bundle> {
+    let entrypoint = std::fs::canonicalize(entrypoint)
+        .map_err(|e| anyhow!("Failed to read '{}': {}", entrypoint.display(), e))?;
+    let root = ModuleSpecifier::from_file_path(&entrypoint)
+        .map_err(|_| anyhow!("Invalid entrypoint '{}'", entrypoint.display()))?;
+
+    let options = BundleOptions {
+        bundle_type: BundleType::Module,
+        transpile_options: jsx.transpile_options(),
+        emit_options: EmitOptions {
+            source_map: SourceMapOption::Separate,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let emit = deno_emit::bundle(root, &mut FileLoader, None, options)
+        .await
+        .map_err(|e| anyhow!("Bundling failed: {}", e))?;
+
+    Ok(Bundle {
+        code: emit.code,
+        source_map: emit.maybe_map,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bundle_local_graph() {
+        let dir = std::env::temp_dir().join(format!("vortex-bundle-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join("index.js"),
+            "import { greet } from './lib/greet.js';\nexport default () => greet('vortex');\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("lib/greet.js"),
+            "export const greet = (name) => `hello ${name}`;\n",
+        )
+        .unwrap();
+
+        let bundle = bundle(&dir.join("index.js"), &JsxOptions::default())
+            .await
+            .unwrap();
+        assert!(bundle.code.contains("hello"));
+        assert!(!bundle.code.contains("./lib/greet.js"));
+        assert!(bundle.source_map.is_some());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
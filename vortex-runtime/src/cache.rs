@@ -0,0 +1,313 @@
+//! Response storage behind the Cache API (`caches.default`, `caches.open()`).
+//!
+//! Functions use it the way Workers code does, to keep upstream responses
+//! across invocations:
+//!
+//! ```js
+//! let response = await caches.default.match(request);
+//! if (!response) {
+//!     response = await fetch(request);
+//!     await caches.default.put(request, response.clone());
+//! }
+//! ```
+//!
+//! Entries are keyed by cache name and request URL and namespaced by
+//! function ID. They are kept in Redis when the worker has a Redis client,
+//! so every host sees the same entries, or in a local directory when
+//! [`CacheOptions::dir`] is set, which takes precedence.
+//!
+//! How long an entry is kept follows the response's headers, as for a
+//! shared cache: `s-maxage`, then `max-age`, then `Expires`, less any `Age`
+//! the response already had, falling back to [`CacheOptions::default_ttl`].
+//! Responses marked `no-store`, `no-cache` or `private`, or that set
+//! cookies, are not stored.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Default lifetime of a response that doesn't say how long it is fresh.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Default limit on the size of a cached response body.
+pub const DEFAULT_CACHE_MAX_ENTRY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Where and for how long the Cache API keeps responses.
+#[derive(Debug, Clone)]
+pub struct CacheOptions {
+    /// Keep entries in this directory instead of Redis. File names are
+    /// hashes, so function IDs and URLs can't escape it.
+    pub dir: Option<PathBuf>,
+    /// Lifetime of responses without `max-age`, `s-maxage` or `Expires`.
+    pub default_ttl: Duration,
+    /// Largest response body `cache.put()` accepts, in bytes.
+    pub max_entry_bytes: usize,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            default_ttl: DEFAULT_CACHE_TTL,
+            max_entry_bytes: DEFAULT_CACHE_MAX_ENTRY_BYTES,
+        }
+    }
+}
+
+/// The Cache API of one worker: its options and the namespace its entries
+/// are kept under.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseCache {
+    pub options: CacheOptions,
+    namespace: String,
+}
+
+impl ResponseCache {
+    pub fn new(options: CacheOptions, namespace: impl Into<String>) -> Self {
+        Self {
+            options,
+            namespace: namespace.into(),
+        }
+    }
+
+    /// Key of `url` in the cache `name`, unique within the namespace.
+    pub fn key(name: &str, url: &str) -> String {
+        format!("{}:{}", name, url)
+    }
+
+    /// File that holds `key` when entries are kept on disk.
+    pub fn path(&self, key: &str) -> Option<PathBuf> {
+        let dir = self.options.dir.as_ref()?;
+        Some(dir.join(hex_digest(&self.namespace)).join(hex_digest(key)))
+    }
+}
+
+fn hex_digest(value: &str) -> String {
+    Sha256::digest(value.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// A stored response. The body follows the JSON head on its own line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedResponse {
+    pub status: u16,
+    pub status_text: String,
+    pub headers: Vec<(String, String)>,
+    /// When the entry was stored, in milliseconds since the epoch
+    #[serde(default)]
+    pub stored_at_ms: i64,
+    /// When the entry stops being served, in milliseconds since the epoch
+    pub expires_at_ms: i64,
+    /// Request headers the response varies on, with the values they had
+    #[serde(default)]
+    pub vary: Vec<(String, String)>,
+    #[serde(skip)]
+    pub body: Vec<u8>,
+}
+
+impl CachedResponse {
+    pub fn encode(&self) -> Vec<u8> {
+        // Compact JSON escapes newlines, so the first one ends the head
+        let mut bytes = serde_json::to_vec(self).expect("cached response serializes");
+        bytes.push(b'\n');
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+
+    /// Parse an entry written by [`encode`](Self::encode); `None` if it is
+    /// corrupt.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let split = bytes.iter().position(|&byte| byte == b'\n')?;
+        let mut response: Self = serde_json::from_slice(&bytes[..split]).ok()?;
+        response.body = bytes[split + 1..].to_vec();
+        Some(response)
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at_ms <= now.timestamp_millis()
+    }
+}
+
+/// How long a response with `headers` may be kept by a shared cache, or
+/// `None` if it must not be stored.
+pub fn cache_ttl(
+    headers: &[(String, String)],
+    default_ttl: Duration,
+    now: DateTime<Utc>,
+) -> Option<Duration> {
+    let values = |name| header_values(headers, name);
+    if values("set-cookie").next().is_some() || values("vary").any(|vary| vary.trim() == "*") {
+        return None;
+    }
+
+    let mut max_age = None;
+    let mut s_maxage = None;
+    for directive in values("cache-control").flat_map(|value| value.split(',')) {
+        let (name, value) = match directive.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+            None => (directive.trim(), None),
+        };
+        let seconds = value.and_then(|value| value.parse::<u64>().ok());
+        match name.to_ascii_lowercase().as_str() {
+            "no-store" | "no-cache" | "private" => return None,
+            "max-age" => max_age = seconds.or(Some(0)),
+            "s-maxage" => s_maxage = seconds.or(Some(0)),
+            _ => {}
+        }
+    }
+
+    let lifetime = match s_maxage.or(max_age) {
+        Some(seconds) => Duration::from_secs(seconds),
+        None => match values("expires").next() {
+            // An invalid date means already expired
+            Some(expires) => DateTime::parse_from_rfc2822(expires.trim())
+                .ok()
+                .and_then(|expires| (expires.with_timezone(&Utc) - now).to_std().ok())
+                .unwrap_or_default(),
+            None => default_ttl,
+        },
+    };
+    let age = values("age")
+        .next()
+        .and_then(|age| age.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_default();
+    Some(lifetime.saturating_sub(age)).filter(|ttl| !ttl.is_zero())
+}
+
+fn header_values<'a>(
+    headers: &'a [(String, String)],
+    name: &'a str,
+) -> impl Iterator<Item = &'a str> + 'a {
+    headers
+        .iter()
+        .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Read the entry at `path`, removing it if it has expired.
+pub async fn read_entry(path: &Path, now: DateTime<Utc>) -> io::Result<Option<CachedResponse>> {
+    let bytes = match tokio::fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    match CachedResponse::decode(&bytes) {
+        Some(response) if !response.is_expired(now) => Ok(Some(response)),
+        _ => {
+            remove_entry(path).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Write the entry at `path`. Readers see either the old entry or the new
+/// one, never a partial write.
+pub async fn write_entry(path: &Path, response: &CachedResponse) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let temp = path.with_extension(format!("{:016x}.tmp", rand::random::<u64>()));
+    tokio::fs::write(&temp, response.encode()).await?;
+    if let Err(e) = tokio::fs::rename(&temp, path).await {
+        let _ = tokio::fs::remove_file(&temp).await;
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Remove the entry at `path`, returning whether there was one.
+pub async fn remove_entry(path: &Path) -> io::Result<bool> {
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_cache_ttl() {
+        let now = DateTime::parse_from_rfc2822("Fri, 16 Oct 2026 12:00:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        let default = Duration::from_secs(3600);
+        let ttl = |pairs: &[(&str, &str)]| cache_ttl(&headers(pairs), default, now);
+
+        assert_eq!(ttl(&[]), Some(default));
+        assert_eq!(
+            ttl(&[("Cache-Control", "public, max-age=60")]),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            ttl(&[("cache-control", "max-age=60, s-maxage=\"300\"")]),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(
+            ttl(&[("cache-control", "max-age=60"), ("age", "45")]),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            ttl(&[("expires", "Fri, 16 Oct 2026 12:10:00 GMT")]),
+            Some(Duration::from_secs(600))
+        );
+        assert_eq!(ttl(&[("cache-control", "max-age=0")]), None);
+        assert_eq!(ttl(&[("cache-control", "max-age=60"), ("age", "90")]), None);
+        assert_eq!(ttl(&[("expires", "Fri, 16 Oct 2026 11:00:00 GMT")]), None);
+        assert_eq!(ttl(&[("expires", "0")]), None);
+        assert_eq!(ttl(&[("Cache-Control", "No-Store")]), None);
+        assert_eq!(ttl(&[("cache-control", "private, max-age=60")]), None);
+        assert_eq!(ttl(&[("cache-control", "no-cache")]), None);
+        assert_eq!(ttl(&[("set-cookie", "id=1")]), None);
+        assert_eq!(ttl(&[("vary", "*")]), None);
+    }
+
+    #[test]
+    fn test_entry_round_trip() {
+        let response = CachedResponse {
+            status: 200,
+            status_text: "OK".to_string(),
+            headers: headers(&[("content-type", "text/plain\nx")]),
+            stored_at_ms: 500,
+            expires_at_ms: 1_000,
+            vary: headers(&[("accept-encoding", "gzip")]),
+            body: b"line one\nline two".to_vec(),
+        };
+        assert_eq!(CachedResponse::decode(&response.encode()), Some(response));
+        assert_eq!(CachedResponse::decode(b"not an entry"), None);
+    }
+
+    #[test]
+    fn test_paths_stay_in_dir() {
+        let cache = ResponseCache::new(
+            CacheOptions {
+                dir: Some(PathBuf::from("/var/cache/vortex")),
+                ..Default::default()
+            },
+            "../../etc",
+        );
+        let path = cache
+            .path(&ResponseCache::key("default", "https://example.com/"))
+            .unwrap();
+        assert!(path.starts_with("/var/cache/vortex"));
+        assert_eq!(path.components().count(), 6);
+        assert!(ResponseCache::default().path("default:x").is_none());
+    }
+}
@@ -0,0 +1,362 @@
+//! CommonJS interop (`require()`) for bundled npm packages.
+//!
+//! Plenty of small npm utilities still ship only as CommonJS. With
+//! [`WorkerOptions::commonjs`](crate::WorkerOptions::commonjs) set, such a
+//! package can be bundled as-is, as a `node_modules` directory inside the
+//! function archive, instead of being pre-bundled by the user:
+//!
+//! - a global `require()` loads CommonJS and JSON files from
+//!   [`WorkerOptions::modules`](crate::WorkerOptions::modules), resolving
+//!   specifiers the way Node does: relative and absolute paths, then
+//!   `node_modules` directories up the tree, trying the exact file, `.js`,
+//!   `.cjs` and `.json`, then the `package.json` `exports`/`main` entry and
+//!   `index` files;
+//! - ES modules may import packages by bare name. A CommonJS file is served
+//!   as a module whose default export is its `module.exports`, so
+//!   `import _ from 'lodash'` works (named imports don't).
+//!
+//! A `.js` file is CommonJS if the nearest `package.json` says
+//! `"type": "commonjs"`, or if it lives under `node_modules` and that field is
+//! missing; the function's own files stay ES modules by default. As in Node,
+//! `require()` of an ES module throws. A package's `exports` map is only
+//! consulted for its main entry; subpaths such as `lodash/fp` are looked up
+//! as files.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::Value;
+
+/// `exports` conditions matched for `require()`, in order of preference.
+pub const REQUIRE_CONDITIONS: &[&str] = &["require", "node", "default"];
+
+/// `exports` conditions matched for `import`, in order of preference.
+pub const IMPORT_CONDITIONS: &[&str] = &["import", "node", "default"];
+
+/// Extensions tried, in order, for a path that doesn't name a file.
+const EXTENSIONS: &[&str] = &[".js", ".cjs", ".json"];
+
+/// The bundled files `require()` resolves against, stored in OpState.
+#[derive(Debug, Clone, Default)]
+pub struct BundleFiles(pub Rc<HashMap<String, String>>);
+
+/// How a bundled file is evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleFormat {
+    /// Run inside a `(exports, require, module, __filename, __dirname)` wrapper
+    CommonJs,
+    /// Parsed with `JSON.parse`
+    Json,
+    /// An ES module; only `import` can load it
+    Module,
+}
+
+/// Resolve `specifier`, required or imported by the bundled file `referrer`
+/// (empty for the function's own script), to the path of a bundled file.
+pub fn resolve(
+    files: &HashMap<String, String>,
+    specifier: &str,
+    referrer: &str,
+    conditions: &[&str],
+) -> Result<String> {
+    let resolved = if let Some(absolute) = specifier.strip_prefix('/') {
+        normalize("", absolute).and_then(|path| load_path(files, &path, conditions))
+    } else if is_relative(specifier) {
+        normalize(dirname(referrer), specifier).and_then(|path| load_path(files, &path, conditions))
+    } else {
+        load_node_modules(files, specifier, dirname(referrer), conditions)
+    };
+    resolved.ok_or_else(|| {
+        let from = if referrer.is_empty() {
+            "the function"
+        } else {
+            referrer
+        };
+        anyhow!("Cannot find module '{}' from '{}'", specifier, from)
+    })
+}
+
+/// Whether `specifier` is resolved in `node_modules` rather than as a path.
+pub fn is_bare(specifier: &str) -> bool {
+    !specifier.starts_with('/') && !is_relative(specifier) && !specifier.contains(':')
+}
+
+/// How the bundled file at `path` is evaluated; see the module docs.
+pub fn format(files: &HashMap<String, String>, path: &str) -> ModuleFormat {
+    match path.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("json") => ModuleFormat::Json,
+        Some("cjs") => ModuleFormat::CommonJs,
+        Some("js") => package_format(files, path),
+        _ => ModuleFormat::Module,
+    }
+}
+
+fn is_relative(specifier: &str) -> bool {
+    matches!(specifier, "." | "..") || specifier.starts_with("./") || specifier.starts_with("../")
+}
+
+/// Directory part of a bundle path (`""` for the root).
+fn dirname(path: &str) -> &str {
+    path.rfind('/').map_or("", |index| &path[..index])
+}
+
+fn parent(dir: &str) -> Option<&str> {
+    (!dir.is_empty()).then(|| dirname(dir))
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Apply `relative` to `base`, resolving `.` and `..`; `None` if it climbs
+/// above the bundle root.
+fn normalize(base: &str, relative: &str) -> Option<String> {
+    let mut segments: Vec<&str> = base.split('/').filter(|s| !s.is_empty()).collect();
+    for segment in relative.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments.join("/"))
+}
+
+fn load_path(files: &HashMap<String, String>, path: &str, conditions: &[&str]) -> Option<String> {
+    load_file(files, path).or_else(|| load_directory(files, path, conditions))
+}
+
+fn load_file(files: &HashMap<String, String>, path: &str) -> Option<String> {
+    if path.is_empty() {
+        return None;
+    }
+    std::iter::once(path.to_string())
+        .chain(
+            EXTENSIONS
+                .iter()
+                .map(|extension| format!("{}{}", path, extension)),
+        )
+        .find(|candidate| files.contains_key(candidate))
+}
+
+fn load_index(files: &HashMap<String, String>, dir: &str) -> Option<String> {
+    EXTENSIONS
+        .iter()
+        .map(|extension| join(dir, &format!("index{}", extension)))
+        .find(|candidate| files.contains_key(candidate))
+}
+
+fn load_directory(
+    files: &HashMap<String, String>,
+    dir: &str,
+    conditions: &[&str],
+) -> Option<String> {
+    let manifest = package_json(files, dir);
+    let entry = manifest.as_ref().and_then(|manifest| {
+        let exports = manifest.get("exports");
+        exports
+            .and_then(|exports| export_target(exports, conditions))
+            .or_else(|| manifest.get("main")?.as_str())
+    });
+    entry
+        .and_then(|entry| normalize(dir, entry))
+        .and_then(|path| load_file(files, &path).or_else(|| load_index(files, &path)))
+        .or_else(|| load_index(files, dir))
+}
+
+fn package_json(files: &HashMap<String, String>, dir: &str) -> Option<Value> {
+    serde_json::from_str(files.get(&join(dir, "package.json"))?).ok()
+}
+
+/// The main entry of a package's `exports` field.
+fn export_target<'a>(exports: &'a Value, conditions: &[&str]) -> Option<&'a str> {
+    match exports {
+        Value::String(target) => Some(target),
+        Value::Array(targets) => targets.iter().find_map(|t| export_target(t, conditions)),
+        Value::Object(map) if map.keys().any(|key| key.starts_with('.')) => {
+            export_target(map.get(".")?, conditions)
+        }
+        Value::Object(map) => conditions
+            .iter()
+            .find_map(|condition| export_target(map.get(*condition)?, conditions)),
+        _ => None,
+    }
+}
+
+fn load_node_modules(
+    files: &HashMap<String, String>,
+    specifier: &str,
+    start: &str,
+    conditions: &[&str],
+) -> Option<String> {
+    // `@scope/name/sub/path` and `name/sub/path`
+    let scoped = specifier.starts_with('@');
+    let (name, subpath) = match specifier.match_indices('/').nth(usize::from(scoped)) {
+        Some((index, _)) => (&specifier[..index], Some(&specifier[index + 1..])),
+        None => (specifier, None),
+    };
+    if name.is_empty() {
+        return None;
+    }
+    let mut dir = Some(start);
+    while let Some(current) = dir {
+        dir = parent(current);
+        if current.rsplit('/').next() == Some("node_modules") {
+            continue;
+        }
+        let package = join(&join(current, "node_modules"), name);
+        let found = match subpath {
+            None => load_path(files, &package, conditions),
+            Some(subpath) => {
+                normalize(&package, subpath).and_then(|path| load_path(files, &path, conditions))
+            }
+        };
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+/// Format of a `.js` file, decided by the nearest `package.json`.
+fn package_format(files: &HashMap<String, String>, path: &str) -> ModuleFormat {
+    let in_node_modules = path.split('/').any(|segment| segment == "node_modules");
+    let default = if in_node_modules {
+        ModuleFormat::CommonJs
+    } else {
+        ModuleFormat::Module
+    };
+    let mut dir = Some(dirname(path));
+    while let Some(current) = dir {
+        if let Some(manifest) = package_json(files, current) {
+            return match manifest.get("type").and_then(Value::as_str) {
+                Some("module") => ModuleFormat::Module,
+                Some("commonjs") => ModuleFormat::CommonJs,
+                _ => default,
+            };
+        }
+        dir = parent(current);
+    }
+    default
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle(files: &[(&str, &str)]) -> HashMap<String, String> {
+        files
+            .iter()
+            .map(|(path, source)| (path.to_string(), source.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_paths_and_packages() {
+        let files = bundle(&[
+            ("index.js", ""),
+            ("lib/util.cjs", ""),
+            ("lib/data.json", "{}"),
+            ("node_modules/ms/package.json", r#"{"main": "./dist/ms"}"#),
+            ("node_modules/ms/dist/ms.js", ""),
+            ("node_modules/@scope/pkg/index.js", ""),
+            ("node_modules/@scope/pkg/fp/map.js", ""),
+            (
+                "node_modules/dual/package.json",
+                r#"{"exports": {".": {"import": "./esm.mjs", "require": "./cjs.cjs"}}}"#,
+            ),
+            ("node_modules/dual/esm.mjs", ""),
+            ("node_modules/dual/cjs.cjs", ""),
+            ("node_modules/ms/node_modules/dep/index.js", ""),
+        ]);
+        let resolve =
+            |specifier, referrer| resolve(&files, specifier, referrer, REQUIRE_CONDITIONS);
+
+        assert_eq!(resolve("./lib/util", "").unwrap(), "lib/util.cjs");
+        assert_eq!(
+            resolve("../data.json", "lib/sub/x.js").unwrap(),
+            "lib/data.json"
+        );
+        assert_eq!(resolve("/index.js", "lib/util.cjs").unwrap(), "index.js");
+        assert_eq!(resolve("ms", "").unwrap(), "node_modules/ms/dist/ms.js");
+        assert_eq!(
+            resolve("@scope/pkg", "lib/util.cjs").unwrap(),
+            "node_modules/@scope/pkg/index.js"
+        );
+        assert_eq!(
+            resolve("@scope/pkg/fp/map", "").unwrap(),
+            "node_modules/@scope/pkg/fp/map.js"
+        );
+        assert_eq!(resolve("dual", "").unwrap(), "node_modules/dual/cjs.cjs");
+        assert_eq!(
+            super::resolve(&files, "dual", "", IMPORT_CONDITIONS).unwrap(),
+            "node_modules/dual/esm.mjs"
+        );
+
+        // Nested node_modules are searched before the outer ones, and only
+        // from within the package that owns them
+        assert_eq!(
+            resolve("dep", "node_modules/ms/dist/ms.js").unwrap(),
+            "node_modules/ms/node_modules/dep/index.js"
+        );
+        assert!(resolve("dep", "index.js").is_err());
+
+        let error = resolve("left-pad", "lib/util.cjs").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cannot find module 'left-pad' from 'lib/util.cjs'"
+        );
+        assert!(resolve("../../escape", "lib/util.cjs").is_err());
+    }
+
+    #[test]
+    fn test_module_format() {
+        let files = bundle(&[
+            ("index.js", ""),
+            ("node_modules/ms/package.json", r#"{"name": "ms"}"#),
+            ("node_modules/ms/index.js", ""),
+            (
+                "node_modules/esm-only/package.json",
+                r#"{"type": "module"}"#,
+            ),
+            ("node_modules/esm-only/index.js", ""),
+            ("legacy/package.json", r#"{"type": "commonjs"}"#),
+            ("legacy/helper.js", ""),
+        ]);
+        assert_eq!(format(&files, "index.js"), ModuleFormat::Module);
+        assert_eq!(
+            format(&files, "node_modules/ms/index.js"),
+            ModuleFormat::CommonJs
+        );
+        assert_eq!(
+            format(&files, "node_modules/esm-only/index.js"),
+            ModuleFormat::Module
+        );
+        assert_eq!(format(&files, "legacy/helper.js"), ModuleFormat::CommonJs);
+        assert_eq!(format(&files, "lib/util.cjs"), ModuleFormat::CommonJs);
+        assert_eq!(
+            format(&files, "node_modules/x/index.mjs"),
+            ModuleFormat::Module
+        );
+        assert_eq!(format(&files, "data.json"), ModuleFormat::Json);
+    }
+
+    #[test]
+    fn test_is_bare() {
+        assert!(is_bare("lodash"));
+        assert!(is_bare("@scope/pkg/sub"));
+        assert!(!is_bare("./util.js"));
+        assert!(!is_bare("/index.js"));
+        assert!(!is_bare("node:crypto"));
+        assert!(!is_bare("https://example.com/x.js"));
+    }
+}
@@ -0,0 +1,165 @@
+//! WinterCG Minimum Common API conformance tracking.
+//!
+//! The [WinterCG Minimum Common API](https://common-min-api.proposal.wintercg.org/)
+//! defines the subset of web platform globals that every server-side runtime
+//! should provide. This module records which of those globals the Vortex
+//! bootstrap implements today, and which are still outstanding, so that the
+//! conformance claim we advertise is checked by the test suite below.
+
+/// WinterCG globals installed by the bootstrap JavaScript.
+pub const SUPPORTED_GLOBALS: &[&str] = &[
+    "AbortController",
+    "AbortSignal",
+    "CompressionStream",
+    "DOMException",
+    "DecompressionStream",
+    "Event",
+    "EventTarget",
+    "Headers",
+    "ReadableStream",
+    "Request",
+    "Response",
+    "TextDecoder",
+    "TextEncoder",
+    "URL",
+    "URLSearchParams",
+    "WebAssembly",
+    "atob",
+    "btoa",
+    "clearInterval",
+    "clearTimeout",
+    "console",
+    "crypto",
+    "fetch",
+    "globalThis",
+    "navigator",
+    "performance",
+    "queueMicrotask",
+    "self",
+    "setInterval",
+    "setTimeout",
+    "structuredClone",
+];
+
+/// WinterCG globals that are not implemented yet.
+///
+/// Most of these depend on writable and transform streams, which the sandbox
+/// doesn't expose today.
+pub const PENDING_GLOBALS: &[&str] = &[
+    "Blob",
+    "ByteLengthQueuingStrategy",
+    "CountQueuingStrategy",
+    "CryptoKey",
+    "File",
+    "FormData",
+    "SubtleCrypto",
+    "TextDecoderStream",
+    "TextEncoderStream",
+    "TransformStream",
+    "WritableStream",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VortexWorker;
+
+    /// Evaluate a JavaScript expression in a fresh worker and return its JSON value.
+    async fn eval(expr: &str) -> serde_json::Value {
+        let mut worker = VortexWorker::new().unwrap();
+        let result = worker.run(&format!("return {}", expr)).await.unwrap();
+        result.output.unwrap_or(serde_json::Value::Null)
+    }
+
+    #[tokio::test]
+    async fn test_supported_globals_are_defined() {
+        let names = serde_json::to_string(SUPPORTED_GLOBALS).unwrap();
+        let missing = eval(&format!(
+            "{}.filter(name => typeof globalThis[name] === 'undefined')",
+            names
+        ))
+        .await;
+        assert_eq!(missing, serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_pending_globals_are_not_advertised() {
+        for name in PENDING_GLOBALS {
+            assert!(!SUPPORTED_GLOBALS.contains(name), "{} listed twice", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_text_encoding_round_trip() {
+        let output = eval("new TextDecoder().decode(new TextEncoder().encode('héllo 😀'))").await;
+        assert_eq!(output, serde_json::json!("héllo 😀"));
+    }
+
+    #[tokio::test]
+    async fn test_base64() {
+        let output = eval("[btoa('hello'), atob('aGVsbG8=')]").await;
+        assert_eq!(output, serde_json::json!(["aGVsbG8=", "hello"]));
+    }
+
+    #[tokio::test]
+    async fn test_url_and_search_params() {
+        let output = eval(
+            "(() => { const u = new URL('/a?x=1', 'https://example.com'); \
+             u.searchParams.append('y', 'a b'); return [u.href, u.hostname]; })()",
+        )
+        .await;
+        assert_eq!(
+            output,
+            serde_json::json!(["https://example.com/a?x=1&y=a+b", "example.com"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_url_throws_type_error() {
+        let output = eval("(() => { try { new URL('not a url'); } catch (e) { return e instanceof TypeError; } })()").await;
+        assert_eq!(output, serde_json::json!(true));
+    }
+
+    #[tokio::test]
+    async fn test_structured_clone() {
+        let output = eval(
+            "(() => { const a = { m: new Map([[1, 2]]) }; a.self = a; \
+             const b = structuredClone(a); return [b !== a, b.self === b, b.m.get(1)]; })()",
+        )
+        .await;
+        assert_eq!(output, serde_json::json!([true, true, 2]));
+    }
+
+    #[tokio::test]
+    async fn test_abort_controller() {
+        let output = eval(
+            "(() => { const c = new AbortController(); let fired = 0; \
+             c.signal.addEventListener('abort', () => fired++); c.abort(); \
+             return [c.signal.aborted, fired, c.signal.reason.name]; })()",
+        )
+        .await;
+        assert_eq!(output, serde_json::json!([true, 1, "AbortError"]));
+    }
+
+    #[tokio::test]
+    async fn test_crypto_random_uuid() {
+        let output = eval("crypto.randomUUID()").await;
+        let uuid = output.as_str().unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+    }
+
+    #[tokio::test]
+    async fn test_clear_timeout_cancels_callback() {
+        let mut worker = VortexWorker::new().unwrap();
+        let code = r#"
+            let fired = false;
+            const id = setTimeout(() => { fired = true; }, 5);
+            clearTimeout(id);
+            await new Promise(resolve => setTimeout(resolve, 20));
+            return fired;
+        "#;
+        let result = worker.run(code).await.unwrap();
+        assert_eq!(result.output, Some(serde_json::json!(false)));
+    }
+}
@@ -0,0 +1,398 @@
+//! Structured errors thrown by user code.
+//!
+//! A failed invocation still produces an `anyhow::Error` whose message is the
+//! familiar "Script execution failed: ..." text, but the error is an
+//! [`ExecutionError`] underneath. Callers that want more than a string can
+//! downcast to it and get the thrown value as a [`ScriptError`], including its
+//! `cause` chain and the individual errors inside an `AggregateError`:
+//!
+//! ```json
+//! {
+//!   "name": "AggregateError",
+//!   "message": "All promises were rejected",
+//!   "stack": "AggregateError: All promises were rejected\n    at ...",
+//!   "errors": [{ "name": "Error", "message": "primary down" }],
+//!   "cause": { "name": "TypeError", "message": "fetch failed" }
+//! }
+//! ```
+//!
+//! Every failure also carries the logs captured before it happened, so a crash
+//! doesn't swallow the `console.log` output that would explain it.
+//!
+//! Own enumerable properties of a thrown error (`code`, `details`, ...) are
+//! kept in `properties`, so custom error classes can hand machine-readable
+//! failure data to API consumers.
+//!
+//! An invocation that times out lists the async ops it was still waiting
+//! for in `pending_ops`, e.g. a `fetch` to a host that never answered.
+//!
+//! Code written for Node.js or Deno often fails on its first line with
+//! `ReferenceError: require is not defined`. For the globals the sandbox
+//! leaves out on purpose (`require`, `process`, `Deno`, `XMLHttpRequest`)
+//! the error is reported as a `VortexError` that says so and names what to
+//! use instead, with the global in `properties.global`. The globals stay
+//! undefined, so feature checks like `typeof process !== 'undefined'` work.
+
+use deno_core::error::JsError;
+use deno_core::v8;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::logging::RuntimeLog;
+use crate::ops::{LogEntry, PendingOp};
+use crate::worker::ExecutionMetrics;
+
+/// Keys with dedicated [`ScriptError`] fields; not repeated in `properties`.
+const RESERVED_KEYS: &[&str] = &["name", "message", "stack", "cause", "errors"];
+
+/// How deep `cause` / `errors` nesting is followed when collecting properties.
+const MAX_DEPTH: usize = 8;
+
+/// Globals the sandbox deliberately doesn't provide, and what to use instead.
+const MISSING_GLOBALS: &[(&str, &str)] = &[
+    (
+        "require",
+        "require() is not available: CommonJS interop is not enabled for this function. \
+         Use import instead and bundle npm dependencies with `vortex-runtime bundle`, \
+         or enable it (--commonjs) to load bundled CommonJS packages",
+    ),
+    (
+        "process",
+        "process is not available: functions don't see the host process. Enable \
+         Node.js compatibility (--node-compat) for process.env, or pass settings in the input",
+    ),
+    (
+        "Deno",
+        "Deno is not available: the runtime's internals are not exposed to functions. \
+         Use the web APIs (fetch, crypto, ...) and the vortex namespace instead",
+    ),
+    (
+        "XMLHttpRequest",
+        "XMLHttpRequest is not available: functions make HTTP requests with fetch()",
+    ),
+];
+
+/// An exception thrown by JavaScript, serialized for API consumers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptError {
+    /// Error class name, e.g. `TypeError`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// `error.message`, or the stringified value for non-Error throws
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stack: Option<String>,
+    /// `error.cause`, recursively
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cause: Option<Box<ScriptError>>,
+    /// The errors inside an `AggregateError`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ScriptError>,
+    /// Own enumerable properties of the thrown value, e.g. `code` and `details`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub properties: Map<String, Value>,
+}
+
+impl From<&JsError> for ScriptError {
+    fn from(error: &JsError) -> Self {
+        let message = error.message.clone().unwrap_or_else(|| {
+            error
+                .exception_message
+                .strip_prefix("Uncaught ")
+                .unwrap_or(&error.exception_message)
+                .to_string()
+        });
+        Self {
+            name: error.name.clone(),
+            message,
+            stack: error.stack.clone(),
+            cause: error.cause.as_deref().map(|cause| Box::new(cause.into())),
+            errors: error
+                .aggregated
+                .iter()
+                .flatten()
+                .map(ScriptError::from)
+                .collect(),
+            properties: Map::new(),
+        }
+    }
+}
+
+impl ScriptError {
+    /// Turn a `ReferenceError` for one of [`MISSING_GLOBALS`] into a
+    /// `VortexError` explaining the sandbox. Returns whether it did.
+    pub(crate) fn explain_missing_global(&mut self) -> bool {
+        if self.name.as_deref() != Some("ReferenceError") {
+            return false;
+        }
+        let Some(global) = self.message.strip_suffix(" is not defined") else {
+            return false;
+        };
+        let Some((global, explanation)) = MISSING_GLOBALS.iter().find(|(name, _)| *name == global)
+        else {
+            return false;
+        };
+        self.name = Some("VortexError".to_string());
+        self.message = explanation.to_string();
+        self.properties
+            .insert("global".to_string(), Value::from(*global));
+        true
+    }
+
+    /// Fill in `properties` (recursively through `cause` and `errors`) from
+    /// the thrown value this error was built from.
+    ///
+    /// Values that cannot be represented as JSON, and getters that throw,
+    /// are skipped.
+    pub(crate) fn attach_properties(
+        &mut self,
+        scope: &mut v8::HandleScope,
+        exception: v8::Local<v8::Value>,
+    ) {
+        let scope = &mut v8::TryCatch::new(scope);
+        self.attach_properties_at_depth(scope, exception, 0);
+    }
+
+    fn attach_properties_at_depth(
+        &mut self,
+        scope: &mut v8::HandleScope,
+        exception: v8::Local<v8::Value>,
+        depth: usize,
+    ) {
+        if depth > MAX_DEPTH {
+            return;
+        }
+        let Ok(object) = v8::Local::<v8::Object>::try_from(exception) else {
+            return;
+        };
+
+        if let Some(keys) = object.get_own_property_names(scope, Default::default()) {
+            for index in 0..keys.length() {
+                let Some(key) = keys.get_index(scope, index) else {
+                    continue;
+                };
+                let name = key.to_rust_string_lossy(scope);
+                if RESERVED_KEYS.contains(&name.as_str()) {
+                    continue;
+                }
+                let json = object
+                    .get(scope, key)
+                    .and_then(|value| v8::json::stringify(scope, value))
+                    .map(|json| json.to_rust_string_lossy(scope))
+                    .and_then(|json| serde_json::from_str(&json).ok());
+                if let Some(value) = json {
+                    self.properties.insert(name, value);
+                }
+            }
+        }
+
+        if let Some(cause) = self.cause.as_deref_mut() {
+            if let Some(value) = get(scope, object, "cause") {
+                cause.attach_properties_at_depth(scope, value, depth + 1);
+            }
+        }
+        if let Some(errors) = get(scope, object, "errors")
+            .and_then(|value| v8::Local::<v8::Array>::try_from(value).ok())
+        {
+            for (index, nested) in self.errors.iter_mut().enumerate() {
+                if let Some(value) = errors.get_index(scope, index as u32) {
+                    nested.attach_properties_at_depth(scope, value, depth + 1);
+                }
+            }
+        }
+    }
+}
+
+fn get<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    object: v8::Local<v8::Object>,
+    key: &str,
+) -> Option<v8::Local<'s, v8::Value>> {
+    let key = v8::String::new(scope, key)?;
+    object.get(scope, key.into())
+}
+
+/// A failed invocation. `Display` gives the human-readable message.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExecutionError {
+    /// Full message, e.g. `Script execution failed: Uncaught Error: boom ...`
+    pub message: String,
+    /// The thrown value, when the failure was an exception in user code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ScriptError>,
+    /// Logs captured up to the failure
+    #[serde(default)]
+    pub logs: Vec<LogEntry>,
+    /// Time spent before the failure
+    #[serde(default)]
+    pub execution_time_ms: u64,
+    /// The failed run's invocation ID
+    #[serde(default)]
+    pub invocation_id: String,
+    /// Resources used before the failure
+    #[serde(default)]
+    pub metrics: ExecutionMetrics,
+    /// Async ops still in flight when the invocation timed out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending_ops: Vec<PendingOp>,
+    /// Warnings of the runtime itself raised during the failed run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub runtime_logs: Vec<RuntimeLog>,
+}
+
+impl std::fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ExecutionError {}
+
+impl ExecutionError {
+    /// The structured exception behind `error`, if it came from user code.
+    pub fn script_error(error: &anyhow::Error) -> Option<&ScriptError> {
+        error
+            .downcast_ref::<ExecutionError>()
+            .and_then(|execution| execution.error.as_ref())
+    }
+
+    /// Logs the invocation behind `error` produced before failing.
+    pub fn partial_logs(error: &anyhow::Error) -> &[LogEntry] {
+        error
+            .downcast_ref::<ExecutionError>()
+            .map(|execution| execution.logs.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Invocation ID of the failed run behind `error`, if one was assigned.
+    pub fn invocation_id(error: &anyhow::Error) -> Option<&str> {
+        error
+            .downcast_ref::<ExecutionError>()
+            .map(|execution| execution.invocation_id.as_str())
+            .filter(|id| !id.is_empty())
+    }
+
+    /// Resources the failed run behind `error` used before failing.
+    pub fn metrics(error: &anyhow::Error) -> Option<&ExecutionMetrics> {
+        error
+            .downcast_ref::<ExecutionError>()
+            .map(|execution| &execution.metrics)
+    }
+
+    /// Runtime warnings raised during the failed run behind `error`.
+    pub fn runtime_logs(error: &anyhow::Error) -> &[RuntimeLog] {
+        error
+            .downcast_ref::<ExecutionError>()
+            .map(|execution| execution.runtime_logs.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Async ops the run behind `error` was still waiting for when it timed out.
+    pub fn pending_ops(error: &anyhow::Error) -> &[PendingOp] {
+        error
+            .downcast_ref::<ExecutionError>()
+            .map(|execution| execution.pending_ops.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VortexWorker;
+
+    #[tokio::test]
+    async fn test_cause_chain_and_aggregate_errors() {
+        let mut worker = VortexWorker::new().unwrap();
+        let error = worker
+            .run(
+                r#"
+                const root = new TypeError('socket closed');
+                try {
+                    await Promise.any([
+                        Promise.reject(new Error('primary down')),
+                        Promise.reject(new Error('replica down')),
+                    ]);
+                } catch (e) {
+                    throw new Error('query failed', { cause: new AggregateError(e.errors, 'all failed', { cause: root }) });
+                }
+                "#,
+            )
+            .await
+            .unwrap_err();
+
+        let script_error = ExecutionError::script_error(&error).unwrap();
+        assert_eq!(script_error.message, "query failed");
+        let cause = script_error.cause.as_ref().unwrap();
+        assert_eq!(cause.name.as_deref(), Some("AggregateError"));
+        assert_eq!(cause.errors.len(), 2);
+        assert_eq!(cause.errors[1].message, "replica down");
+        assert_eq!(cause.cause.as_ref().unwrap().name.as_deref(), Some("TypeError"));
+    }
+
+    #[tokio::test]
+    async fn test_custom_error_properties() {
+        let mut worker = VortexWorker::new().unwrap();
+        let error = worker
+            .run(
+                r#"
+                class PaymentError extends Error {
+                    constructor(message, details) {
+                        super(message);
+                        this.name = 'PaymentError';
+                        this.code = 'card_declined';
+                        this.details = details;
+                    }
+                }
+                throw new PaymentError('declined', { retryable: false });
+                "#,
+            )
+            .await
+            .unwrap_err();
+
+        let script_error = ExecutionError::script_error(&error).unwrap();
+        assert_eq!(script_error.name.as_deref(), Some("PaymentError"));
+        assert_eq!(script_error.properties["code"], "card_declined");
+        assert_eq!(
+            script_error.properties["details"],
+            serde_json::json!({ "retryable": false })
+        );
+        assert!(!script_error.properties.contains_key("name"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_global_errors() {
+        let mut worker = VortexWorker::new().unwrap();
+        let error = worker
+            .run("const _ = require('lodash');")
+            .await
+            .unwrap_err();
+        let script_error = ExecutionError::script_error(&error).unwrap();
+        assert_eq!(script_error.name.as_deref(), Some("VortexError"));
+        assert!(script_error.message.starts_with("require() is not available"));
+        assert_eq!(script_error.properties["global"], "require");
+        assert!(error
+            .to_string()
+            .contains("Uncaught VortexError: require() is not available"));
+
+        for global in ["process", "Deno", "XMLHttpRequest"] {
+            let error = worker.run(&format!("{}.foo", global)).await.unwrap_err();
+            let script_error = ExecutionError::script_error(&error).unwrap();
+            assert_eq!(script_error.name.as_deref(), Some("VortexError"));
+            assert_eq!(script_error.properties["global"], global);
+        }
+
+        // Feature detection still sees the globals as missing
+        let result = worker
+            .run("return typeof require + typeof process + typeof Deno + typeof XMLHttpRequest")
+            .await
+            .unwrap();
+        assert_eq!(result.output, Some("undefined".repeat(4).into()));
+
+        // Other undefined names are left alone
+        let error = worker.run("missingHelper()").await.unwrap_err();
+        let script_error = ExecutionError::script_error(&error).unwrap();
+        assert_eq!(script_error.name.as_deref(), Some("ReferenceError"));
+        assert!(script_error.properties.is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+//! Invocation lifecycle events published to Redis.
+//!
+//! Log entries stream on the function's log channel, but a UI following
+//! them can't tell from the entries alone when an invocation is over. A
+//! worker that publishes logs (it has a Redis client and a function ID)
+//! therefore also publishes [`InvocationEvent`]s, one JSON object per
+//! message, to `events:{function}` (see
+//! [`LogChannels::events_template`](crate::LogChannels::events_template)):
+//!
+//! ```text
+//! {"function_id":"fn-1","event":"execution_completed","invocation_id":"4f1c...","status":"error","duration_ms":12,"error":"Uncaught Error: boom","log_count":3}
+//! ```
+//!
+//! With [`WorkerOptions::heartbeat_interval`](crate::WorkerOptions::heartbeat_interval)
+//! set, a long invocation also sends a heartbeat at that interval while it
+//! runs:
+//!
+//! ```text
+//! {"function_id":"fn-1","event":"heartbeat","invocation_id":"4f1c...","elapsed_ms":10000,"pending_ops":2}
+//! ```
+//!
+//! Heartbeats are sent from the worker's event loop, so they stop while a
+//! function runs JavaScript that never yields (`while (true) {}`); a gap
+//! before `execution_completed` is how a dashboard tells a hung invocation
+//! from one waiting on slow I/O.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+/// How an invocation ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionStatus {
+    Success,
+    Error,
+}
+
+/// Something that happened to an invocation, tagged by `event`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum InvocationEvent {
+    /// The invocation finished. Always the last event of an invocation.
+    ExecutionCompleted {
+        invocation_id: String,
+        status: ExecutionStatus,
+        duration_ms: u64,
+        /// First line of the error message, when the invocation failed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        /// Log entries the invocation captured, as in the result's `logs`
+        log_count: usize,
+    },
+    /// The invocation is still running.
+    Heartbeat {
+        invocation_id: String,
+        elapsed_ms: u64,
+        /// Async ops and timers the invocation is waiting for
+        pending_ops: usize,
+    },
+}
+
+impl InvocationEvent {
+    /// The event for an invocation that finished after `duration_ms`, with
+    /// `error` if it failed.
+    pub fn completed(
+        invocation_id: &str,
+        duration_ms: u64,
+        error: Option<&anyhow::Error>,
+        log_count: usize,
+    ) -> Self {
+        Self::ExecutionCompleted {
+            invocation_id: invocation_id.to_string(),
+            status: match error {
+                Some(_) => ExecutionStatus::Error,
+                None => ExecutionStatus::Success,
+            },
+            duration_ms,
+            error: error.map(|error| {
+                let message = error.to_string();
+                message.lines().next().unwrap_or_default().to_string()
+            }),
+            log_count,
+        }
+    }
+}
+
+/// An event as published: the event plus the function it belongs to.
+#[derive(Serialize)]
+struct PublishedEvent<'a> {
+    function_id: &'a str,
+    #[serde(flatten)]
+    event: &'a InvocationEvent,
+}
+
+/// Publishes one function's events through a background Redis publisher.
+#[derive(Clone)]
+pub(crate) struct EventPublisher {
+    function_id: String,
+    sender: mpsc::UnboundedSender<String>,
+}
+
+impl EventPublisher {
+    pub(crate) fn new(function_id: String, sender: mpsc::UnboundedSender<String>) -> Self {
+        Self {
+            function_id,
+            sender,
+        }
+    }
+
+    /// Publish `event`; like log publishing this is best-effort.
+    pub(crate) fn publish(&self, event: &InvocationEvent) {
+        let published = PublishedEvent {
+            function_id: &self.function_id,
+            event,
+        };
+        if let Ok(json) = serde_json::to_string(&published) {
+            let _ = self.sender.send(json);
+        }
+    }
+}
+
+/// Heartbeats for one invocation.
+pub(crate) struct Heartbeat {
+    pub publisher: EventPublisher,
+    pub interval: Duration,
+    pub invocation_id: String,
+    pub started_at: Instant,
+    /// Counts the invocation's pending async ops when a heartbeat is sent
+    pub pending_ops: Box<dyn Fn() -> usize>,
+}
+
+/// Drive `future` to completion, sending heartbeats while it runs.
+pub(crate) async fn with_heartbeats<F: Future>(
+    future: F,
+    heartbeat: Option<Heartbeat>,
+) -> F::Output {
+    let Some(heartbeat) = heartbeat else {
+        return future.await;
+    };
+    tokio::pin!(future);
+    let start = tokio::time::Instant::now() + heartbeat.interval;
+    let mut ticks = tokio::time::interval_at(start, heartbeat.interval);
+    loop {
+        tokio::select! {
+            output = &mut future => return output,
+            _ = ticks.tick() => heartbeat.publisher.publish(&InvocationEvent::Heartbeat {
+                invocation_id: heartbeat.invocation_id.clone(),
+                elapsed_ms: heartbeat.started_at.elapsed().as_millis() as u64,
+                pending_ops: (heartbeat.pending_ops)(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_publish_completed() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let publisher = EventPublisher::new("fn-1".to_string(), sender);
+
+        let error = anyhow!("Uncaught Error: boom\n    at [vortex:user_script]:1:7");
+        publisher.publish(&InvocationEvent::completed("inv-1", 12, Some(&error), 3));
+        let json: serde_json::Value = serde_json::from_str(&receiver.try_recv().unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "function_id": "fn-1",
+                "event": "execution_completed",
+                "invocation_id": "inv-1",
+                "status": "error",
+                "duration_ms": 12,
+                "error": "Uncaught Error: boom",
+                "log_count": 3,
+            })
+        );
+
+        publisher.publish(&InvocationEvent::completed("inv-2", 1, None, 0));
+        let json: serde_json::Value = serde_json::from_str(&receiver.try_recv().unwrap()).unwrap();
+        assert_eq!(json["status"], "success");
+        assert!(json.get("error").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_heartbeats() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let heartbeat = Heartbeat {
+            publisher: EventPublisher::new("fn-1".to_string(), sender),
+            interval: Duration::from_millis(50),
+            invocation_id: "inv-1".to_string(),
+            started_at: Instant::now(),
+            pending_ops: Box::new(|| 2),
+        };
+        let output = with_heartbeats(
+            async {
+                tokio::time::sleep(Duration::from_millis(175)).await;
+                42
+            },
+            Some(heartbeat),
+        )
+        .await;
+        assert_eq!(output, 42);
+
+        let mut beats = 0;
+        while let Ok(json) = receiver.try_recv() {
+            let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(json["event"], "heartbeat");
+            assert_eq!(json["invocation_id"], "inv-1");
+            assert_eq!(json["pending_ops"], 2);
+            beats += 1;
+        }
+        // At 50, 100 and 150ms, give or take a late timer
+        assert!((2..=3).contains(&beats), "{}", beats);
+
+        // Without heartbeats the future just runs
+        assert_eq!(with_heartbeats(async { 7 }, None).await, 7);
+    }
+}
@@ -0,0 +1,293 @@
+//! Governor - process-wide limits on isolates and heap.
+//!
+//! Each [`PoolManager`](crate::PoolManager) limits its own tenants, but the
+//! scheduler runs one pool per thread, and nothing stops the pools together
+//! from creating more isolates than the node has memory for. A [`Governor`]
+//! is shared by all of them: every live isolate holds an [`IsolateSlot`], and
+//! a new execution is only admitted while the process is under
+//! `max_isolates` and the isolates' heaps add up to less than
+//! `max_heap_bytes`.
+//!
+//! An execution that isn't admitted waits up to `queue_timeout` for another
+//! to finish, then fails with a [`CapacityError`]:
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//!
+//! use vortex_runtime::{Governor, GovernorOptions, PoolOptions, SchedulerOptions};
+//!
+//! let options = SchedulerOptions {
+//!     pool: PoolOptions {
+//!         governor: Some(Governor::new(GovernorOptions {
+//!             max_isolates: Some(64),
+//!             max_heap_bytes: Some(4 << 30),
+//!             queue_timeout: Duration::from_millis(500),
+//!         })),
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! ```
+//!
+//! Heap sizes are measured when a worker is created and each time a pooled
+//! worker is returned, so the heap limit lags behind an invocation that is
+//! still allocating; per-isolate limits (`--max-old-space-size`) bound that.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::Notify;
+
+/// Limits enforced by a [`Governor`].
+#[derive(Debug, Clone, Default)]
+pub struct GovernorOptions {
+    /// Maximum number of isolates alive at once, idle ones included
+    pub max_isolates: Option<usize>,
+    /// Maximum total heap size of all isolates, in bytes
+    pub max_heap_bytes: Option<u64>,
+    /// How long an execution waits for capacity before failing; zero fails
+    /// at once
+    pub queue_timeout: Duration,
+}
+
+/// The limit a [`CapacityError`] ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CapacityResource {
+    Isolates,
+    HeapBytes,
+}
+
+/// An execution the [`Governor`] didn't admit. Downcast an `anyhow::Error`
+/// to it to tell overload (retry elsewhere, or later) from a failure.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CapacityError {
+    pub resource: CapacityResource,
+    pub limit: u64,
+    pub in_use: u64,
+}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let resource = match self.resource {
+            CapacityResource::Isolates => "isolates",
+            CapacityResource::HeapBytes => "heap bytes",
+        };
+        write!(
+            f,
+            "Runtime at capacity: {} of {} {} in use",
+            self.in_use, self.limit, resource
+        )
+    }
+}
+
+impl std::error::Error for CapacityError {}
+
+/// Point-in-time counters of a [`Governor`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GovernorMetrics {
+    /// Isolates currently alive
+    pub isolates: usize,
+    /// Their total heap size when last measured
+    pub heap_bytes: u64,
+    /// Executions that failed with a [`CapacityError`]
+    pub rejected: u64,
+}
+
+#[derive(Debug)]
+struct GovernorState {
+    options: GovernorOptions,
+    isolates: AtomicUsize,
+    heap_bytes: AtomicU64,
+    rejected: AtomicU64,
+    /// Notified when an isolate goes away or a heap shrinks
+    released: Notify,
+}
+
+/// Process-wide admission control, shared by cloning.
+#[derive(Debug, Clone)]
+pub struct Governor {
+    state: Arc<GovernorState>,
+}
+
+impl Governor {
+    pub fn new(options: GovernorOptions) -> Self {
+        Self {
+            state: Arc::new(GovernorState {
+                options,
+                isolates: AtomicUsize::new(0),
+                heap_bytes: AtomicU64::new(0),
+                rejected: AtomicU64::new(0),
+                released: Notify::new(),
+            }),
+        }
+    }
+
+    /// Admit an execution right away, reserving an isolate for it if it
+    /// needs a new one.
+    pub fn try_admit(&self, new_isolate: bool) -> Result<Option<IsolateSlot>, CapacityError> {
+        let state = &self.state;
+        if let Some(limit) = state.options.max_heap_bytes {
+            let in_use = state.heap_bytes.load(Ordering::Acquire);
+            if in_use >= limit {
+                return Err(CapacityError {
+                    resource: CapacityResource::HeapBytes,
+                    limit,
+                    in_use,
+                });
+            }
+        }
+        if !new_isolate {
+            return Ok(None);
+        }
+        let max = state.options.max_isolates;
+        state
+            .isolates
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |isolates| {
+                max.is_none_or(|max| isolates < max).then_some(isolates + 1)
+            })
+            .map_err(|in_use| CapacityError {
+                resource: CapacityResource::Isolates,
+                limit: max.unwrap_or_default() as u64,
+                in_use: in_use as u64,
+            })?;
+        Ok(Some(IsolateSlot {
+            state: Arc::clone(state),
+            heap_bytes: 0,
+        }))
+    }
+
+    /// Admit an execution, waiting up to `queue_timeout` for capacity.
+    pub async fn admit(&self, new_isolate: bool) -> Result<Option<IsolateSlot>, CapacityError> {
+        let deadline = tokio::time::Instant::now() + self.state.options.queue_timeout;
+        loop {
+            // Registered before checking, so a release in between isn't missed
+            let released = self.state.released.notified();
+            let error = match self.try_admit(new_isolate) {
+                Ok(slot) => return Ok(slot),
+                Err(error) => error,
+            };
+            if tokio::time::timeout_at(deadline, released).await.is_err() {
+                self.state.rejected.fetch_add(1, Ordering::Relaxed);
+                return Err(error);
+            }
+        }
+    }
+
+    /// Current governor counters.
+    pub fn metrics(&self) -> GovernorMetrics {
+        GovernorMetrics {
+            isolates: self.state.isolates.load(Ordering::Acquire),
+            heap_bytes: self.state.heap_bytes.load(Ordering::Acquire),
+            rejected: self.state.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// One live isolate counted against a [`Governor`]. Dropping it, with the
+/// isolate, frees its share of the limits.
+#[derive(Debug)]
+pub struct IsolateSlot {
+    state: Arc<GovernorState>,
+    heap_bytes: u64,
+}
+
+impl IsolateSlot {
+    /// Record the isolate's current heap size.
+    pub fn set_heap_bytes(&mut self, heap_bytes: u64) {
+        let previous = std::mem::replace(&mut self.heap_bytes, heap_bytes);
+        if heap_bytes >= previous {
+            self.state
+                .heap_bytes
+                .fetch_add(heap_bytes - previous, Ordering::AcqRel);
+        } else {
+            self.state
+                .heap_bytes
+                .fetch_sub(previous - heap_bytes, Ordering::AcqRel);
+            self.state.released.notify_waiters();
+        }
+    }
+}
+
+impl Drop for IsolateSlot {
+    fn drop(&mut self) {
+        self.state
+            .heap_bytes
+            .fetch_sub(self.heap_bytes, Ordering::AcqRel);
+        self.state.isolates.fetch_sub(1, Ordering::AcqRel);
+        self.state.released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_isolate_limit() {
+        let governor = Governor::new(GovernorOptions {
+            max_isolates: Some(1),
+            queue_timeout: Duration::from_millis(20),
+            ..Default::default()
+        });
+        let slot = governor.try_admit(true).unwrap();
+        assert!(slot.is_some());
+        // Reusing an isolate doesn't need a slot
+        assert!(governor.try_admit(false).unwrap().is_none());
+
+        let error = governor.admit(true).await.unwrap_err();
+        assert_eq!(error.resource, CapacityResource::Isolates);
+        assert_eq!(
+            error.to_string(),
+            "Runtime at capacity: 1 of 1 isolates in use"
+        );
+        assert_eq!(governor.metrics().rejected, 1);
+        drop(slot);
+        assert!(governor.try_admit(true).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_waits_for_a_free_isolate() {
+        let governor = Governor::new(GovernorOptions {
+            max_isolates: Some(1),
+            queue_timeout: Duration::from_secs(5),
+            ..Default::default()
+        });
+        let slot = governor.try_admit(true).unwrap();
+        let waiting = tokio::spawn({
+            let governor = governor.clone();
+            async move { governor.admit(true).await.map(|slot| slot.is_some()) }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+
+        drop(slot);
+        assert_eq!(waiting.await.unwrap(), Ok(true));
+    }
+
+    #[test]
+    fn test_heap_limit() {
+        let governor = Governor::new(GovernorOptions {
+            max_heap_bytes: Some(1000),
+            ..Default::default()
+        });
+        let mut first = governor.try_admit(true).unwrap().unwrap();
+        first.set_heap_bytes(600);
+        let mut second = governor.try_admit(true).unwrap().unwrap();
+        second.set_heap_bytes(500);
+        assert_eq!(governor.metrics().heap_bytes, 1100);
+
+        let error = governor.try_admit(false).unwrap_err();
+        assert_eq!(error.resource, CapacityResource::HeapBytes);
+        assert_eq!(error.in_use, 1100);
+
+        second.set_heap_bytes(100);
+        assert!(governor.try_admit(false).is_ok());
+        drop(first);
+        assert_eq!(governor.metrics().heap_bytes, 100);
+        assert_eq!(governor.metrics().isolates, 1);
+    }
+}
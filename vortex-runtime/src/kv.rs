@@ -0,0 +1,721 @@
+//! Storage behind `vortex.kv`.
+//!
+//! The ops only validate arguments and hand them to a [`KvBackend`], so a
+//! self-hosted deployment can keep KV data in the store it already runs
+//! (DynamoDB, FoundationDB, ...) by implementing the trait and passing it
+//! as [`WorkerOptions::kv`](crate::WorkerOptions::kv). Two backends ship
+//! with the runtime:
+//!
+//! * [`RedisKv`], used by default when the worker has a Redis client, so
+//!   every host sees the same data;
+//! * [`MemoryKv`], for tests and local development.
+//!
+//! A backend serves every function of the process: each call names the
+//! namespace (the function ID) it acts on, and namespaces never share keys.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::ops::Bound;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use redis::aio::MultiplexedConnection;
+use serde::Serialize;
+use tokio::sync::OnceCell;
+
+/// A value and the revision of the write that stored it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KvEntry {
+    /// JSON text
+    pub value: String,
+    pub revision: u64,
+}
+
+/// One page of keys from [`KvBackend::list`].
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct KvListPage {
+    /// Keys in byte order
+    pub keys: Vec<String>,
+    /// Pass back as `cursor` for the next page; `None` on the last page
+    pub cursor: Option<String>,
+}
+
+/// How [`KvBackend::put`] writes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KvPut {
+    /// Remove the key after this long; `None` keeps it until deleted
+    pub ttl: Option<Duration>,
+    /// Only write if the key is at this revision, 0 meaning it must not
+    /// exist
+    pub expected: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KvError {
+    /// [`KvBackend::incr`] on a value that isn't an integer
+    NotAnInteger,
+    /// [`KvBackend::incr`] would leave the safe-integer range of JavaScript
+    Overflow,
+    /// The store failed, e.g. lost its connection
+    Backend(String),
+}
+
+impl fmt::Display for KvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KvError::NotAnInteger => write!(f, "value is not an integer"),
+            KvError::Overflow => write!(f, "result is not a safe integer"),
+            KvError::Backend(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for KvError {}
+
+impl From<redis::RedisError> for KvError {
+    fn from(e: redis::RedisError) -> Self {
+        KvError::Backend(e.to_string())
+    }
+}
+
+/// Future returned by the methods of [`KvBackend`].
+pub type KvFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, KvError>> + Send + 'a>>;
+
+/// Largest integer [`KvBackend::incr`] may produce (`Number.MAX_SAFE_INTEGER`).
+pub const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// A key-value store for `vortex.kv`.
+///
+/// Every write (`put`, `incr`) gives the key a new revision, higher than
+/// any revision the namespace has had before, so a key that is deleted and
+/// written again can't be mistaken for the version a caller read. Each
+/// method must be atomic with respect to concurrent calls from other
+/// workers and hosts.
+pub trait KvBackend: fmt::Debug + Send + Sync {
+    /// The value under `key` and its revision, if it exists and hasn't
+    /// expired.
+    fn get<'a>(&'a self, namespace: &'a str, key: &'a str) -> KvFuture<'a, Option<KvEntry>>;
+
+    /// Store `value` (JSON text) under `key`, replacing its expiry with
+    /// `options.ttl`. Returns the new revision, or `None` if
+    /// `options.expected` didn't match and nothing was written.
+    fn put<'a>(
+        &'a self,
+        namespace: &'a str,
+        key: &'a str,
+        value: String,
+        options: KvPut,
+    ) -> KvFuture<'a, Option<u64>>;
+
+    /// Add `delta` to the integer under `key` (0 if missing), keeping its
+    /// expiry, and return the result.
+    fn incr<'a>(&'a self, namespace: &'a str, key: &'a str, delta: i64) -> KvFuture<'a, i64>;
+
+    /// Remove `key`, returning whether it existed.
+    fn delete<'a>(&'a self, namespace: &'a str, key: &'a str) -> KvFuture<'a, bool>;
+
+    /// Up to `limit` live keys starting with `prefix`, in byte order, after
+    /// `cursor` (the last key of the previous page) if given.
+    fn list<'a>(
+        &'a self,
+        namespace: &'a str,
+        prefix: &'a str,
+        cursor: Option<&'a str>,
+        limit: usize,
+    ) -> KvFuture<'a, KvListPage>;
+}
+
+/// Keys in Redis, as a hash of the value (`v`) and its revision (`r`) per
+/// key, plus a sorted set of each namespace's keys that `list` pages
+/// through.
+///
+/// Give each worker its own: the connection it opens on first use belongs
+/// to the worker's Tokio runtime.
+pub struct RedisKv {
+    client: redis::Client,
+    connection: OnceCell<MultiplexedConnection>,
+}
+
+impl RedisKv {
+    pub fn new(client: redis::Client) -> Self {
+        Self {
+            client,
+            connection: OnceCell::new(),
+        }
+    }
+
+    async fn connection(&self) -> Result<MultiplexedConnection, KvError> {
+        let connection = self
+            .connection
+            .get_or_try_init(|| self.client.get_multiplexed_async_connection())
+            .await
+            .map_err(|e| KvError::Backend(format!("failed to connect to Redis: {}", e)))?;
+        Ok(connection.clone())
+    }
+
+    fn value_key(namespace: &str, key: &str) -> String {
+        format!("vortex:kv:{}:{}", namespace, key)
+    }
+
+    fn index_key(namespace: &str) -> String {
+        format!("vortex:kv-index:{}:", namespace)
+    }
+
+    /// Counter every write of the namespace draws its revision from
+    fn revision_key(namespace: &str) -> String {
+        format!("vortex:kv-revision:{}:", namespace)
+    }
+}
+
+impl fmt::Debug for RedisKv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisKv").finish_non_exhaustive()
+    }
+}
+
+/// Store a value if the key's revision is `ARGV[4]` (or unconditionally
+/// when empty), with the next revision of the namespace.
+const PUT_SCRIPT: &str = r#"
+if ARGV[4] ~= '' and (redis.call('HGET', KEYS[1], 'r') or '0') ~= ARGV[4] then
+    return 0
+end
+local revision = redis.call('INCR', KEYS[3])
+redis.call('HSET', KEYS[1], 'v', ARGV[2], 'r', revision)
+if tonumber(ARGV[3]) > 0 then
+    redis.call('PEXPIRE', KEYS[1], ARGV[3])
+else
+    redis.call('PERSIST', KEYS[1])
+end
+redis.call('ZADD', KEYS[2], 0, ARGV[1])
+return revision
+"#;
+
+/// Add `ARGV[2]` to the integer under the key. Returns `{status, value}`:
+/// 0 on success, 1 if the value isn't an integer, 2 on overflow.
+const INCR_SCRIPT: &str = r#"
+local current = redis.call('HGET', KEYS[1], 'v')
+local n = 0
+if current then
+    n = tonumber(current)
+    if n == nil or n ~= math.floor(n) then
+        return {1, 0}
+    end
+end
+n = n + tonumber(ARGV[2])
+if math.abs(n) > tonumber(ARGV[3]) then
+    return {2, 0}
+end
+local revision = redis.call('INCR', KEYS[3])
+redis.call('HSET', KEYS[1], 'v', string.format('%d', n), 'r', revision)
+redis.call('ZADD', KEYS[2], 0, ARGV[1])
+return {0, n}
+"#;
+
+/// Up to `ARGV[3]` keys of the index between `ARGV[1]` and `ARGV[2]` whose
+/// values (under the prefix `ARGV[4]`) still exist. Keys that expired are
+/// dropped from the index on the way. Reading keys not passed in `KEYS`
+/// rules out Redis Cluster, which the other stores don't support either.
+const LIST_SCRIPT: &str = r#"
+local min, limit = ARGV[1], tonumber(ARGV[3])
+local keys = {}
+while #keys < limit do
+    local batch = redis.call('ZRANGEBYLEX', KEYS[1], min, ARGV[2], 'LIMIT', 0, limit - #keys)
+    if #batch == 0 then
+        break
+    end
+    for _, key in ipairs(batch) do
+        if redis.call('EXISTS', ARGV[4] .. key) == 1 then
+            table.insert(keys, key)
+        else
+            redis.call('ZREM', KEYS[1], key)
+        end
+    end
+    min = '(' .. batch[#batch]
+end
+return keys
+"#;
+
+impl KvBackend for RedisKv {
+    fn get<'a>(&'a self, namespace: &'a str, key: &'a str) -> KvFuture<'a, Option<KvEntry>> {
+        Box::pin(async move {
+            let mut connection = self.connection().await?;
+            let (value, revision): (Option<String>, Option<u64>) = redis::cmd("HMGET")
+                .arg(Self::value_key(namespace, key))
+                .arg("v")
+                .arg("r")
+                .query_async(&mut connection)
+                .await?;
+            Ok(value.map(|value| KvEntry {
+                value,
+                revision: revision.unwrap_or_default(),
+            }))
+        })
+    }
+
+    fn put<'a>(
+        &'a self,
+        namespace: &'a str,
+        key: &'a str,
+        value: String,
+        options: KvPut,
+    ) -> KvFuture<'a, Option<u64>> {
+        Box::pin(async move {
+            let mut connection = self.connection().await?;
+            let ttl_ms = options.ttl.map_or(0, |ttl| ttl.as_millis().max(1) as u64);
+            let expected = options.expected.map(|revision| revision.to_string());
+            let revision: u64 = redis::Script::new(PUT_SCRIPT)
+                .key(Self::value_key(namespace, key))
+                .key(Self::index_key(namespace))
+                .key(Self::revision_key(namespace))
+                .arg(key)
+                .arg(value)
+                .arg(ttl_ms)
+                .arg(expected.unwrap_or_default())
+                .invoke_async(&mut connection)
+                .await?;
+            // Revisions start at 1
+            Ok(Some(revision).filter(|&revision| revision > 0))
+        })
+    }
+
+    fn incr<'a>(&'a self, namespace: &'a str, key: &'a str, delta: i64) -> KvFuture<'a, i64> {
+        Box::pin(async move {
+            let mut connection = self.connection().await?;
+            let (status, value): (u8, i64) = redis::Script::new(INCR_SCRIPT)
+                .key(Self::value_key(namespace, key))
+                .key(Self::index_key(namespace))
+                .key(Self::revision_key(namespace))
+                .arg(key)
+                .arg(delta)
+                .arg(MAX_SAFE_INTEGER)
+                .invoke_async(&mut connection)
+                .await?;
+            match status {
+                0 => Ok(value),
+                1 => Err(KvError::NotAnInteger),
+                _ => Err(KvError::Overflow),
+            }
+        })
+    }
+
+    fn delete<'a>(&'a self, namespace: &'a str, key: &'a str) -> KvFuture<'a, bool> {
+        Box::pin(async move {
+            let mut connection = self.connection().await?;
+            let (deleted,): (u64,) = redis::pipe()
+                .atomic()
+                .del(Self::value_key(namespace, key))
+                .zrem(Self::index_key(namespace), key)
+                .ignore()
+                .query_async(&mut connection)
+                .await?;
+            Ok(deleted > 0)
+        })
+    }
+
+    fn list<'a>(
+        &'a self,
+        namespace: &'a str,
+        prefix: &'a str,
+        cursor: Option<&'a str>,
+        limit: usize,
+    ) -> KvFuture<'a, KvListPage> {
+        Box::pin(async move {
+            let mut connection = self.connection().await?;
+            let (min, max) = lex_range(prefix, cursor);
+            let keys: Vec<String> = redis::Script::new(LIST_SCRIPT)
+                .key(Self::index_key(namespace))
+                .arg(min)
+                .arg(max)
+                .arg(limit + 1)
+                .arg(Self::value_key(namespace, ""))
+                .invoke_async(&mut connection)
+                .await?;
+            Ok(page(keys, limit))
+        })
+    }
+}
+
+/// `ZRANGEBYLEX` bounds for the keys starting with `prefix` that sort after
+/// `cursor`.
+fn lex_range(prefix: &str, cursor: Option<&str>) -> (Vec<u8>, Vec<u8>) {
+    let min = match cursor {
+        Some(cursor) if cursor >= prefix => [b"(", cursor.as_bytes()].concat(),
+        _ if prefix.is_empty() => b"-".to_vec(),
+        _ => [b"[", prefix.as_bytes()].concat(),
+    };
+    // No UTF-8 string contains 0xff, so it sorts after every key with the prefix
+    let max = match prefix {
+        "" => b"+".to_vec(),
+        prefix => [b"(", prefix.as_bytes(), b"\xff"].concat(),
+    };
+    (min, max)
+}
+
+/// A page of at most `limit` of `keys`, which hold one key more than that
+/// if there is another page.
+fn page(mut keys: Vec<String>, limit: usize) -> KvListPage {
+    let cursor = if keys.len() > limit {
+        keys.truncate(limit);
+        keys.last().cloned()
+    } else {
+        None
+    };
+    KvListPage { keys, cursor }
+}
+
+/// Keys in this process, lost when it exits. Clones share their data.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryKv {
+    namespaces: Arc<Mutex<HashMap<String, MemoryNamespace>>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryNamespace {
+    entries: BTreeMap<String, MemoryEntry>,
+    /// Last revision handed out
+    revision: u64,
+}
+
+#[derive(Debug)]
+struct MemoryEntry {
+    value: String,
+    revision: u64,
+    expires_at: Option<Instant>,
+}
+
+impl MemoryNamespace {
+    /// The entry under `key`, dropping it if it has expired.
+    fn live(&mut self, key: &str, now: Instant) -> Option<&mut MemoryEntry> {
+        if self.entries.get(key)?.is_expired(now) {
+            self.entries.remove(key);
+            return None;
+        }
+        self.entries.get_mut(key)
+    }
+}
+
+impl MemoryEntry {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+impl MemoryKv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_namespace<T>(&self, namespace: &str, f: impl FnOnce(&mut MemoryNamespace) -> T) -> T {
+        let mut namespaces = self.namespaces.lock().unwrap_or_else(|e| e.into_inner());
+        f(namespaces.entry(namespace.to_string()).or_default())
+    }
+}
+
+impl KvBackend for MemoryKv {
+    fn get<'a>(&'a self, namespace: &'a str, key: &'a str) -> KvFuture<'a, Option<KvEntry>> {
+        let entry = self.with_namespace(namespace, |namespace| {
+            namespace.live(key, Instant::now()).map(|entry| KvEntry {
+                value: entry.value.clone(),
+                revision: entry.revision,
+            })
+        });
+        Box::pin(std::future::ready(Ok(entry)))
+    }
+
+    fn put<'a>(
+        &'a self,
+        namespace: &'a str,
+        key: &'a str,
+        value: String,
+        options: KvPut,
+    ) -> KvFuture<'a, Option<u64>> {
+        let revision = self.with_namespace(namespace, |namespace| {
+            let now = Instant::now();
+            if let Some(expected) = options.expected {
+                let current = namespace.live(key, now).map_or(0, |entry| entry.revision);
+                if current != expected {
+                    return None;
+                }
+            }
+            namespace.revision += 1;
+            let entry = MemoryEntry {
+                value,
+                revision: namespace.revision,
+                expires_at: options.ttl.map(|ttl| now + ttl),
+            };
+            namespace.entries.insert(key.to_string(), entry);
+            Some(namespace.revision)
+        });
+        Box::pin(std::future::ready(Ok(revision)))
+    }
+
+    fn incr<'a>(&'a self, namespace: &'a str, key: &'a str, delta: i64) -> KvFuture<'a, i64> {
+        let result = self.with_namespace(namespace, |namespace| {
+            let now = Instant::now();
+            let (current, expires_at) = match namespace.live(key, now) {
+                Some(entry) => {
+                    let current = serde_json::from_str::<serde_json::Value>(&entry.value)
+                        .ok()
+                        .and_then(|value| value.as_i64())
+                        .ok_or(KvError::NotAnInteger)?;
+                    (current, entry.expires_at)
+                }
+                None => (0, None),
+            };
+            let value = current
+                .checked_add(delta)
+                .filter(|value| value.abs() <= MAX_SAFE_INTEGER)
+                .ok_or(KvError::Overflow)?;
+            namespace.revision += 1;
+            let entry = MemoryEntry {
+                value: value.to_string(),
+                revision: namespace.revision,
+                expires_at,
+            };
+            namespace.entries.insert(key.to_string(), entry);
+            Ok(value)
+        });
+        Box::pin(std::future::ready(result))
+    }
+
+    fn delete<'a>(&'a self, namespace: &'a str, key: &'a str) -> KvFuture<'a, bool> {
+        let deleted = self.with_namespace(namespace, |namespace| {
+            let now = Instant::now();
+            namespace
+                .entries
+                .remove(key)
+                .is_some_and(|entry| !entry.is_expired(now))
+        });
+        Box::pin(std::future::ready(Ok(deleted)))
+    }
+
+    fn list<'a>(
+        &'a self,
+        namespace: &'a str,
+        prefix: &'a str,
+        cursor: Option<&'a str>,
+        limit: usize,
+    ) -> KvFuture<'a, KvListPage> {
+        let keys = self.with_namespace(namespace, |namespace| {
+            let now = Instant::now();
+            namespace.entries.retain(|_, entry| !entry.is_expired(now));
+            let start = match cursor {
+                Some(cursor) if cursor >= prefix => Bound::Excluded(cursor),
+                _ => Bound::Included(prefix),
+            };
+            namespace
+                .entries
+                .range::<str, _>((start, Bound::Unbounded))
+                .map(|(key, _)| key)
+                .take_while(|key| key.starts_with(prefix))
+                .take(limit + 1)
+                .cloned()
+                .collect()
+        });
+        Box::pin(std::future::ready(Ok(page(keys, limit))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expiry, `incr` and conditional writes as every backend must behave,
+    /// in a namespace of its own. For `RedisKv` this exercises the scripts.
+    async fn check_backend(kv: &dyn KvBackend, namespace: &str) {
+        let put = |key: &'static str, value: &str, ttl: Option<u64>, expected| {
+            let options = KvPut {
+                ttl: ttl.map(Duration::from_millis),
+                expected,
+            };
+            kv.put(namespace, key, value.to_string(), options)
+        };
+        let value = |key: &'static str| async move {
+            let entry = kv.get(namespace, key).await.unwrap();
+            entry.map(|entry| entry.value)
+        };
+
+        // Conditional writes: 0 means "must not exist"
+        let first = put("cart", "[]", None, Some(0)).await.unwrap().unwrap();
+        assert_eq!(put("cart", "[1]", None, Some(0)).await, Ok(None));
+        assert_eq!(put("cart", "[1]", None, Some(first + 1)).await, Ok(None));
+        assert_eq!(value("cart").await.as_deref(), Some("[]"));
+        let second = put("cart", "[1]", None, Some(first))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(second > first);
+        // A stale revision loses, even once the key has been recreated
+        assert_eq!(put("cart", "[2]", None, Some(first)).await, Ok(None));
+        assert_eq!(kv.delete(namespace, "cart").await, Ok(true));
+        let third = put("cart", "[]", None, None).await.unwrap().unwrap();
+        assert!(third > second);
+        assert_eq!(put("cart", "[3]", None, Some(second)).await, Ok(None));
+        assert_eq!(value("cart").await.as_deref(), Some("[]"));
+
+        // Expiry, and a put without a TTL clearing an earlier one
+        put("session", "1", Some(50), None).await.unwrap();
+        put("kept", "1", Some(50), None).await.unwrap();
+        put("kept", "2", None, None).await.unwrap();
+
+        // incr keeps the expiry of the key it updates
+        put("visits", "40", Some(50), None).await.unwrap();
+        assert_eq!(kv.incr(namespace, "visits", 2).await, Ok(42));
+        assert_eq!(value("visits").await.as_deref(), Some("42"));
+        let revision = kv.get(namespace, "visits").await.unwrap().unwrap().revision;
+        assert!(revision > third);
+
+        // incr on a missing key starts from 0 and never expires
+        assert_eq!(kv.incr(namespace, "total", -3).await, Ok(-3));
+        put("name", "\"alice\"", None, None).await.unwrap();
+        assert_eq!(
+            kv.incr(namespace, "name", 1).await,
+            Err(KvError::NotAnInteger)
+        );
+        put("float", "1.5", None, None).await.unwrap();
+        assert_eq!(
+            kv.incr(namespace, "float", 1).await,
+            Err(KvError::NotAnInteger)
+        );
+        put("max", &MAX_SAFE_INTEGER.to_string(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(kv.incr(namespace, "max", 1).await, Err(KvError::Overflow));
+        assert_eq!(value("max").await, Some(MAX_SAFE_INTEGER.to_string()));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(value("session").await, None);
+        assert_eq!(value("visits").await, None);
+        assert_eq!(value("kept").await.as_deref(), Some("2"));
+        assert_eq!(value("total").await.as_deref(), Some("-3"));
+        // An expired key counts as missing for conditional writes
+        assert!(put("session", "2", None, Some(0)).await.unwrap().is_some());
+        let page = kv.list(namespace, "", None, 10).await.unwrap();
+        assert_eq!(
+            page.keys,
+            ["cart", "float", "kept", "max", "name", "session", "total"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_kv_semantics() {
+        check_backend(&MemoryKv::new(), "fn").await;
+    }
+
+    /// The Lua scripts need a server: set `VORTEX_TEST_REDIS_URL` to run
+    /// this against one. It only touches keys of a fresh namespace.
+    #[tokio::test]
+    async fn test_redis_kv_semantics() {
+        let Ok(url) = std::env::var("VORTEX_TEST_REDIS_URL") else {
+            eprintln!("VORTEX_TEST_REDIS_URL is not set; skipping");
+            return;
+        };
+        let kv = RedisKv::new(redis::Client::open(url).unwrap());
+        let namespace = format!("test-{:016x}", rand_namespace());
+        check_backend(&kv, &namespace).await;
+    }
+
+    /// A namespace suffix unlikely to collide with an earlier run's.
+    fn rand_namespace() -> u128 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+            ^ u128::from(std::process::id())
+    }
+
+    #[test]
+    fn test_lex_range() {
+        let range = |prefix, cursor| {
+            let (min, max) = lex_range(prefix, cursor);
+            (String::from_utf8_lossy(&min).into_owned(), max)
+        };
+        assert_eq!(range("", None), ("-".to_string(), b"+".to_vec()));
+        assert_eq!(
+            range("user:", None),
+            ("[user:".to_string(), b"(user:\xff".to_vec())
+        );
+        assert_eq!(
+            range("user:", Some("user:42")),
+            ("(user:42".to_string(), b"(user:\xff".to_vec())
+        );
+        // A cursor from before the prefix doesn't skip any of its keys
+        assert_eq!(range("user:", Some("order:1")).0, "[user:");
+        assert_eq!(range("", Some("b")).0, "(b");
+    }
+
+    #[tokio::test]
+    async fn test_memory_kv() {
+        let kv = MemoryKv::new();
+        let put = |key: &'static str, value: &str, expected| {
+            let options = KvPut {
+                expected,
+                ..Default::default()
+            };
+            kv.put("fn", key, value.to_string(), options)
+        };
+
+        assert_eq!(put("a", "1", None).await, Ok(Some(1)));
+        let entry = kv.get("fn", "a").await.unwrap().unwrap();
+        assert_eq!((entry.value.as_str(), entry.revision), ("1", 1));
+        assert_eq!(kv.get("other", "a").await, Ok(None));
+
+        // Conditional writes
+        assert_eq!(put("a", "2", Some(0)).await, Ok(None));
+        assert_eq!(put("b", "2", Some(0)).await, Ok(Some(2)));
+        assert_eq!(put("a", "3", Some(1)).await, Ok(Some(3)));
+        assert_eq!(put("a", "4", Some(1)).await, Ok(None));
+
+        // Revisions are never reused, even for a key written again
+        assert_eq!(kv.delete("fn", "a").await, Ok(true));
+        assert_eq!(kv.delete("fn", "a").await, Ok(false));
+        assert_eq!(put("a", "5", None).await, Ok(Some(4)));
+
+        assert_eq!(kv.incr("fn", "count", 5).await, Ok(5));
+        assert_eq!(kv.incr("fn", "count", -7).await, Ok(-2));
+        put("text", "\"x\"", None).await.unwrap();
+        assert_eq!(kv.incr("fn", "text", 1).await, Err(KvError::NotAnInteger));
+        put("big", &MAX_SAFE_INTEGER.to_string(), None)
+            .await
+            .unwrap();
+        assert_eq!(kv.incr("fn", "big", 1).await, Err(KvError::Overflow));
+    }
+
+    #[tokio::test]
+    async fn test_memory_kv_list_and_expiry() {
+        let kv = MemoryKv::new();
+        let put = |key: &'static str, ttl| {
+            let options = KvPut {
+                ttl,
+                ..Default::default()
+            };
+            kv.put("fn", key, "null".to_string(), options)
+        };
+        for key in ["user:1", "user:2", "user:3", "users", "order:1"] {
+            put(key, None).await.unwrap();
+        }
+        put("user:0", Some(Duration::from_millis(20)))
+            .await
+            .unwrap();
+
+        let first = kv.list("fn", "user:", None, 2).await.unwrap();
+        assert_eq!(first.keys, ["user:0", "user:1"]);
+        let cursor = first.cursor.as_deref();
+        let second = kv.list("fn", "user:", cursor, 2).await.unwrap();
+        assert_eq!(second.keys, ["user:2", "user:3"]);
+        assert_eq!(second.cursor, None);
+        // The same cursor handling as lex_range(): one from before the
+        // prefix skips nothing, and without a prefix it's exclusive
+        let from_before = kv.list("fn", "user:", Some("order:1"), 10).await.unwrap();
+        assert_eq!(from_before.keys, ["user:0", "user:1", "user:2", "user:3"]);
+        let after = kv.list("fn", "", Some("user:2"), 10).await.unwrap();
+        assert_eq!(after.keys, ["user:3", "users"]);
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(kv.get("fn", "user:0").await, Ok(None));
+        let all = kv.list("fn", "", None, 10).await.unwrap();
+        assert_eq!(all.keys, ["order:1", "user:1", "user:2", "user:3", "users"]);
+    }
+}
@@ -4,9 +4,69 @@
 //! `deno_core` and the V8 engine. It captures console output, supports async/await,
 //! and provides execution timing metrics.
 
+mod actors;
+pub mod archive;
+pub mod audit;
 mod bootstrap;
+pub mod bundle;
+pub mod cache;
+pub mod commonjs;
+pub mod conformance;
+pub mod error;
+pub mod events;
+pub mod fetch;
+pub mod governor;
+pub mod http;
+pub mod kv;
+pub mod logging;
+pub mod manifest;
+pub mod modules;
 mod ops;
+pub mod permissions;
+pub mod postgres;
+mod pool;
+pub mod protocol;
+pub mod resources;
+pub mod sandbox;
+mod scheduler;
+pub mod secrets;
+pub mod selftest;
+pub mod server;
+pub mod snapshot;
+mod snapshot_builder;
+pub mod spill;
+pub mod sql;
+pub mod sqlite;
+pub mod supervisor;
+pub mod transpile;
+mod watchdog;
 mod worker;
 
-pub use ops::LogEntry;
-pub use worker::{ExecutionResult, VortexWorker};
+pub use actors::{ActorRegistry, ActorRegistryOptions, ActorRequest, ActorStore};
+pub use audit::{AuditEntry, AuditLog, AuditSink, FileAuditSink, MemoryAuditSink};
+pub use bootstrap::BootstrapScript;
+pub use cache::CacheOptions;
+pub use error::{ExecutionError, ScriptError};
+pub use events::{ExecutionStatus, InvocationEvent};
+pub use fetch::{
+    EgressCounts, EgressMetrics, FetchCache, FetchClient, FetchOptions, FetchProxy,
+    MemoryFetchCache,
+};
+pub use governor::{CapacityError, CapacityResource, Governor, GovernorMetrics, GovernorOptions};
+pub use kv::{KvBackend, MemoryKv, RedisKv};
+pub use logging::{
+    FileLogSink, LogChannels, LogHistory, LogLevel, LogSampling, LogSink, RedisLogSink, RuntimeLog,
+};
+pub use ops::{LogEntry, OutputChunk, PendingOp, RequestBodyStream, OUTPUT_CHANNEL_CAPACITY};
+pub use permissions::{Capability, Permissions};
+pub use postgres::{PostgresOptions, PostgresPool};
+pub use pool::{PoolManager, PoolMetrics, PoolOptions, PooledWorker};
+pub use scheduler::{ExecutionRequest, Scheduler, SchedulerOptions};
+pub use secrets::{SecretsProvider, StaticSecrets};
+pub use sqlite::{SqliteDatabases, SqliteOptions};
+pub use supervisor::{Supervisor, SupervisorOptions};
+pub use worker::{
+    new_invocation_id, ExecutionEvent, ExecutionMetrics, ExecutionResult, ExecutionTiming,
+    OutputDropped, OutputTruncated, VortexWorker, WorkerHooks, WorkerOptions,
+    LOG_RECEIVER_CAPACITY,
+};
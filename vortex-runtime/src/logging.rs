@@ -0,0 +1,783 @@
+//! Log capture policies - keeping a noisy function's logs in check.
+//!
+//! A function that logs in a tight loop can produce more entries than the
+//! Redis channel, and whoever stores the result, can take. [`LogSampling`]
+//! bounds that per invocation: the first entries are kept, later ones are
+//! sampled, and a per-second rate limit caps bursts:
+//!
+//! ```rust
+//! use vortex_runtime::{LogSampling, WorkerOptions};
+//!
+//! let options = WorkerOptions {
+//!     // Keep 100 entries, then 1 in 50, and never more than 20 a second
+//!     log_sampling: LogSampling {
+//!         keep_first: Some(100),
+//!         one_in: 50,
+//!         max_per_second: Some(20),
+//!     },
+//!     ..Default::default()
+//! };
+//! ```
+//!
+//! Entries that are dropped never reach the result, Redis or log listeners,
+//! and don't use up a `seq`. Instead, the invocation ends with one entry
+//! counting them:
+//!
+//! ```text
+//! [vortex] Suppressed 9500 log entries (9000 sampled out, 500 over the rate limit)
+//! ```
+//!
+//! With [`WorkerOptions::coalesce_logs`](crate::WorkerOptions::coalesce_logs),
+//! an entry repeating the one before it (same level, message and fields)
+//! isn't kept either; the earlier entry's [`LogEntry::repeated`] counts it
+//! instead, the way journald reports "message repeated N times". Repeats are
+//! folded in before sampling, so a line logged in a loop takes a single place.
+//!
+//! Entries are published to the Redis channel `logs:{function}`. A host that
+//! namespaces its channels sets [`LogChannels`] instead, and can have
+//! error-level entries published to an alerting channel too:
+//!
+//! ```rust
+//! use vortex_runtime::{LogChannels, WorkerOptions};
+//!
+//! let options = WorkerOptions {
+//!     function_id: Some("fn-1".to_string()),
+//!     log_channels: LogChannels {
+//!         template: Some("tenant:{tenant}:logs:{function}".to_string()),
+//!         alert_template: Some("tenant:{tenant}:alerts".to_string()),
+//!         tenant: Some("acme".to_string()),
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//! ```
+//!
+//! Pub/sub only reaches subscribers that are already listening. With
+//! [`LogChannels::history`], entries are also appended to a capped Redis list
+//! per invocation, `logs:{function}:{invocation}` by default, so a log view
+//! opened after the invocation started can read the earlier lines with
+//! `LRANGE` before following the channel (using `seq` to skip what it has).
+//!
+//! Besides the worker's own channel, entries can be fanned out to any number
+//! of [`LogSink`]s set in [`WorkerOptions::log_sinks`](crate::WorkerOptions::log_sinks),
+//! e.g. a [`RedisLogSink`] for live streaming next to a [`FileLogSink`] that
+//! archives them as NDJSON. The CLI's repeatable `--log-destination` flag
+//! creates one per destination.
+//!
+//! Problems of the runtime itself, such as a Redis publish that failed, are
+//! not the function's logs. They are reported as [`RuntimeLog`]s instead: in
+//! the result's `runtime_logs`, on the Redis channel `runtime:{function}`
+//! (see [`LogChannels::runtime_template`]) and on stderr.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Map;
+use tokio::sync::mpsc;
+
+use crate::ops::LogEntry;
+
+/// Severity of a log entry, from the console method that wrote it.
+/// `console.log` and `vortex.log` write [`LogLevel::Info`] unless
+/// `vortex.log` is given a `level`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    /// Level named by the JavaScript side; anything unknown is `Info`.
+    pub fn parse(name: &str) -> Self {
+        match name {
+            "debug" => Self::Debug,
+            "warn" => Self::Warn,
+            "error" => Self::Error,
+            _ => Self::Info,
+        }
+    }
+}
+
+/// Limits on the log entries captured per invocation. The default keeps
+/// every entry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LogSampling {
+    /// Entries kept before sampling starts; `None` doesn't sample
+    pub keep_first: Option<u64>,
+    /// Past `keep_first`, keep one entry in this many; 0 keeps none
+    pub one_in: u64,
+    /// Most entries kept in any one second of the invocation
+    pub max_per_second: Option<u64>,
+}
+
+/// Applies [`LogSampling`] to one invocation's entries as they are logged.
+#[derive(Debug, Default)]
+pub struct LogFilter {
+    sampling: LogSampling,
+    /// Whether repeats of the last entry are folded into it
+    coalesce: bool,
+    /// Entries logged so far
+    seen: u64,
+    /// Second of the invocation the rate limit is counting
+    window: u64,
+    /// Entries kept in that second
+    kept_in_window: u64,
+    sampled_out: u64,
+    rate_limited: u64,
+}
+
+/// Type alias for the log filter shared between the worker and ops
+pub type LogFilterState = Rc<RefCell<LogFilter>>;
+
+impl LogFilter {
+    pub fn new(sampling: LogSampling, coalesce: bool) -> Self {
+        Self {
+            sampling,
+            coalesce,
+            ..Default::default()
+        }
+    }
+
+    /// Prepare for a new invocation.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.sampling, self.coalesce);
+    }
+
+    /// Fold an entry into `last` if it repeats it, returning whether it did.
+    pub fn coalesce(&self, last: &mut LogEntry, entry: &LogEntry) -> bool {
+        if !self.coalesce
+            || last.level != entry.level
+            || last.message != entry.message
+            || last.fields != entry.fields
+        {
+            return false;
+        }
+        last.repeated += 1;
+        true
+    }
+
+    /// Whether to keep an entry logged `elapsed_ms` into the invocation.
+    pub fn admit(&mut self, elapsed_ms: u64) -> bool {
+        self.seen += 1;
+        if let Some(keep_first) = self.sampling.keep_first {
+            if self.seen > keep_first {
+                let one_in = self.sampling.one_in;
+                if one_in == 0 || !(self.seen - keep_first - 1).is_multiple_of(one_in) {
+                    self.sampled_out += 1;
+                    return false;
+                }
+            }
+        }
+        if let Some(max_per_second) = self.sampling.max_per_second {
+            let window = elapsed_ms / 1000;
+            if window != self.window {
+                self.window = window;
+                self.kept_in_window = 0;
+            }
+            if self.kept_in_window >= max_per_second {
+                self.rate_limited += 1;
+                return false;
+            }
+            self.kept_in_window += 1;
+        }
+        true
+    }
+
+    /// Entry counting what was dropped since the last summary, if anything
+    /// was.
+    pub fn take_summary(&mut self) -> Option<LogEntry> {
+        let (sampled_out, rate_limited) = (self.sampled_out, self.rate_limited);
+        let suppressed = sampled_out + rate_limited;
+        if suppressed == 0 {
+            return None;
+        }
+        self.sampled_out = 0;
+        self.rate_limited = 0;
+        let message = format!(
+            "[vortex] Suppressed {} log entries ({} sampled out, {} over the rate limit)",
+            suppressed, sampled_out, rate_limited
+        );
+        let mut fields = Map::new();
+        fields.insert("suppressed".to_string(), suppressed.into());
+        fields.insert("sampled_out".to_string(), sampled_out.into());
+        fields.insert("rate_limited".to_string(), rate_limited.into());
+        Some(LogEntry {
+            level: LogLevel::Warn,
+            fields,
+            ..LogEntry::new(0, message)
+        })
+    }
+}
+
+/// Channel the logs of a function are published to when the template
+/// isn't set.
+pub const DEFAULT_LOG_CHANNEL: &str = "logs:{function}";
+
+/// Channel a function's [`InvocationEvent`](crate::events::InvocationEvent)s
+/// are published to when the template isn't set.
+pub const DEFAULT_EVENTS_CHANNEL: &str = "events:{function}";
+
+/// Channel runtime warnings are published to when the template isn't set.
+pub const DEFAULT_RUNTIME_CHANNEL: &str = "runtime:{function}";
+
+/// Key of an invocation's log history when the template isn't set.
+pub const DEFAULT_LOG_HISTORY_KEY: &str = "logs:{function}:{invocation}";
+
+/// A capped Redis list of each invocation's log entries, for readers that
+/// start following the logs late.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogHistory {
+    /// Key of an invocation's list, which may also use `{invocation}`;
+    /// defaults to [`DEFAULT_LOG_HISTORY_KEY`]
+    pub key_template: Option<String>,
+    /// Most entries kept per invocation; the oldest are trimmed
+    pub max_entries: usize,
+    /// How long a list is kept after its last entry was appended
+    pub ttl: Duration,
+    /// Only append entries to the history, without publishing them on the
+    /// log channel
+    pub exclusive: bool,
+}
+
+impl Default for LogHistory {
+    fn default() -> Self {
+        Self {
+            key_template: None,
+            max_entries: 1000,
+            ttl: Duration::from_secs(60 * 60),
+            exclusive: false,
+        }
+    }
+}
+
+/// Names of the Redis channels log entries and events are published to.
+/// Templates may use `{function}` for the function ID and `{tenant}` for
+/// [`Self::tenant`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogChannels {
+    /// Channel every entry is published to; defaults to [`DEFAULT_LOG_CHANNEL`]
+    pub template: Option<String>,
+    /// Channel error-level entries are also published to, for alerting
+    pub alert_template: Option<String>,
+    /// Channel invocation events are published to; defaults to
+    /// [`DEFAULT_EVENTS_CHANNEL`]
+    pub events_template: Option<String>,
+    /// Channel [`RuntimeLog`]s are published to; defaults to
+    /// [`DEFAULT_RUNTIME_CHANNEL`]
+    pub runtime_template: Option<String>,
+    /// Substituted for `{tenant}`. [`PoolManager::acquire`](crate::PoolManager::acquire)
+    /// sets it to the tenant it acquires for, unless it is already set.
+    pub tenant: Option<String>,
+    /// Also keep each invocation's entries in a Redis list
+    pub history: Option<LogHistory>,
+}
+
+impl LogChannels {
+    /// The log channel of `function_id`, and its alert channel if one is set.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a template uses an unknown placeholder, or `{tenant}`
+    /// without a tenant.
+    pub fn resolve(&self, function_id: &str) -> Result<(String, Option<String>)> {
+        let template = self.template.as_deref().unwrap_or(DEFAULT_LOG_CHANNEL);
+        let channel = self.render(template, function_id, false)?;
+        let alert_channel = match &self.alert_template {
+            Some(template) => Some(self.render(template, function_id, false)?),
+            None => None,
+        };
+        Ok((channel, alert_channel))
+    }
+
+    /// The events channel of `function_id`.
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`Self::resolve`].
+    pub fn events_channel(&self, function_id: &str) -> Result<String> {
+        let template = self
+            .events_template
+            .as_deref()
+            .unwrap_or(DEFAULT_EVENTS_CHANNEL);
+        self.render(template, function_id, false)
+    }
+
+    /// The runtime warnings channel of `function_id`.
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`Self::resolve`].
+    pub fn runtime_channel(&self, function_id: &str) -> Result<String> {
+        let template = self
+            .runtime_template
+            .as_deref()
+            .unwrap_or(DEFAULT_RUNTIME_CHANNEL);
+        self.render(template, function_id, false)
+    }
+
+    /// The key template of `function_id`'s log history, with `{invocation}`
+    /// left in for [`history_key`]; `None` without [`Self::history`].
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`Self::resolve`].
+    pub fn history_key_template(&self, function_id: &str) -> Result<Option<String>> {
+        let Some(history) = &self.history else {
+            return Ok(None);
+        };
+        let template = history
+            .key_template
+            .as_deref()
+            .unwrap_or(DEFAULT_LOG_HISTORY_KEY);
+        self.render(template, function_id, true).map(Some)
+    }
+
+    fn render(&self, template: &str, function_id: &str, history: bool) -> Result<String> {
+        let mut channel = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            channel.push_str(&rest[..start]);
+            let Some(len) = rest[start..].find('}') else {
+                return Err(anyhow!(
+                    "Log channel template '{}' has an unclosed '{{'",
+                    template
+                ));
+            };
+            let end = start + len;
+            channel.push_str(match &rest[start + 1..end] {
+                "function" => function_id,
+                "invocation" if history => "{invocation}",
+                "tenant" => self.tenant.as_deref().ok_or_else(|| {
+                    anyhow!(
+                        "Log channel template '{}' uses {{tenant}}, but no tenant is set",
+                        template
+                    )
+                })?,
+                name => {
+                    return Err(anyhow!(
+                        "Unknown placeholder '{{{}}}' in log channel template '{}'",
+                        name,
+                        template
+                    ))
+                }
+            });
+            rest = &rest[end + 1..];
+        }
+        channel.push_str(rest);
+        Ok(channel)
+    }
+}
+
+/// Key of `invocation_id`'s log history, from a template returned by
+/// [`LogChannels::history_key_template`].
+pub fn history_key(template: &str, invocation_id: &str) -> String {
+    template.replace("{invocation}", invocation_id)
+}
+
+/// A destination log entries are written to as they are recorded, after
+/// sampling and coalescing.
+///
+/// Sinks are called on the worker's thread, in the middle of the
+/// invocation, so they should not block for long; [`RedisLogSink`] hands its
+/// entries to a background task.
+pub trait LogSink: fmt::Debug + Send + Sync {
+    /// Write `entry`, logged by `function_id` if the worker has one.
+    fn write(&self, function_id: Option<&str>, entry: &LogEntry) -> io::Result<()>;
+}
+
+/// An entry as a sink writes it: the entry plus the function that wrote it.
+#[derive(Serialize)]
+struct SinkEntry<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function_id: Option<&'a str>,
+    #[serde(flatten)]
+    entry: &'a LogEntry,
+}
+
+fn to_json(function_id: Option<&str>, entry: &LogEntry) -> io::Result<String> {
+    Ok(serde_json::to_string(&SinkEntry { function_id, entry })?)
+}
+
+/// Entries appended to a local file, one JSON object per line.
+#[derive(Debug)]
+pub struct FileLogSink {
+    file: Mutex<File>,
+}
+
+impl FileLogSink {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl LogSink for FileLogSink {
+    fn write(&self, function_id: Option<&str>, entry: &LogEntry) -> io::Result<()> {
+        let mut line = to_json(function_id, entry)?;
+        line.push('\n');
+        // One write per line, so workers sharing the file don't interleave
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(line.as_bytes())
+    }
+}
+
+/// Entries published to a Redis channel. Like the worker's own log channel,
+/// publishing is best-effort and happens on a background task, started on
+/// the first entry.
+pub struct RedisLogSink {
+    client: redis::Client,
+    channel: String,
+    sender: OnceLock<mpsc::UnboundedSender<String>>,
+}
+
+impl RedisLogSink {
+    pub fn new(client: redis::Client, channel: impl Into<String>) -> Self {
+        Self {
+            client,
+            channel: channel.into(),
+            sender: OnceLock::new(),
+        }
+    }
+}
+
+impl fmt::Debug for RedisLogSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisLogSink")
+            .field("channel", &self.channel)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LogSink for RedisLogSink {
+    fn write(&self, function_id: Option<&str>, entry: &LogEntry) -> io::Result<()> {
+        let json = to_json(function_id, entry)?;
+        let sender = self
+            .sender
+            .get_or_init(|| spawn_redis_publisher(self.client.clone(), self.channel.clone(), None));
+        // Ignore send errors - Redis publishing is best-effort
+        let _ = sender.send(json);
+        Ok(())
+    }
+}
+
+/// Most runtime warnings one result carries; later ones still reach stderr
+/// and Redis.
+pub const MAX_RUNTIME_LOGS: usize = 100;
+
+/// A warning from the runtime itself rather than the function, e.g. that
+/// Redis couldn't be reached and logs won't stream.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeLog {
+    pub timestamp: DateTime<Utc>,
+    pub level: LogLevel,
+    pub message: String,
+}
+
+/// A runtime warning as published: the warning plus the worker's function.
+#[derive(Serialize)]
+struct PublishedRuntimeLog<'a> {
+    function_id: &'a str,
+    #[serde(flatten)]
+    log: &'a RuntimeLog,
+}
+
+/// Where a worker reports its [`RuntimeLog`]s. Clones share the warnings,
+/// so the worker's background tasks can report through their own.
+///
+/// Warnings are kept until the worker takes them for a result; one raised
+/// by a background task between two invocations is reported with the next.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RuntimeLogger {
+    logs: Arc<Mutex<Vec<RuntimeLog>>>,
+    publisher: Option<(String, mpsc::UnboundedSender<String>)>,
+}
+
+impl RuntimeLogger {
+    /// Also publish warnings, for `function_id`, through `sender`. Only
+    /// clones made afterwards publish.
+    pub(crate) fn publish_to(
+        &mut self,
+        function_id: String,
+        sender: mpsc::UnboundedSender<String>,
+    ) {
+        self.publisher = Some((function_id, sender));
+    }
+
+    pub(crate) fn warn(&self, message: impl Into<String>) {
+        let log = RuntimeLog {
+            timestamp: Utc::now(),
+            level: LogLevel::Warn,
+            message: message.into(),
+        };
+        eprintln!("{}", log.message);
+        if let Some((function_id, sender)) = &self.publisher {
+            let published = PublishedRuntimeLog {
+                function_id,
+                log: &log,
+            };
+            if let Ok(json) = serde_json::to_string(&published) {
+                let _ = sender.send(json);
+            }
+        }
+        let mut logs = self.logs.lock().unwrap_or_else(|e| e.into_inner());
+        if logs.len() < MAX_RUNTIME_LOGS {
+            logs.push(log);
+        }
+    }
+
+    /// The warnings reported since the last call.
+    pub(crate) fn take(&self) -> Vec<RuntimeLog> {
+        std::mem::take(&mut *self.logs.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// Spawn a background task that publishes each message sent on the returned
+/// channel to the Redis `channel`.
+///
+/// Publishing is fire-and-forget: it runs independently of the V8 event loop
+/// and errors are reported through `runtime_logs`, or only on stderr without
+/// it, without affecting the invocation.
+pub(crate) fn spawn_redis_publisher(
+    client: redis::Client,
+    channel: String,
+    runtime_logs: Option<RuntimeLogger>,
+) -> mpsc::UnboundedSender<String> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        // Get async connection to Redis
+        match client.get_multiplexed_async_connection().await {
+            Ok(mut conn) => {
+                // Process messages from the channel
+                while let Some(msg) = rx.recv().await {
+                    // Publish to Redis, ignoring errors (fire-and-forget)
+                    let publish_result: Result<(), redis::RedisError> = redis::cmd("PUBLISH")
+                        .arg(&channel)
+                        .arg(&msg)
+                        .query_async(&mut conn)
+                        .await;
+
+                    if let Err(e) = publish_result {
+                        warn(
+                            runtime_logs.as_ref(),
+                            format!("Redis publish error (non-fatal): {}", e),
+                        );
+                    }
+                }
+            }
+            Err(e) => {
+                warn(
+                    runtime_logs.as_ref(),
+                    format!(
+                        "Failed to connect to Redis ({} won't stream): {}",
+                        channel, e
+                    ),
+                );
+                // Still drain the channel to avoid memory buildup
+                while rx.recv().await.is_some() {}
+            }
+        }
+    });
+    tx
+}
+
+/// Report `message` through `runtime_logs`, or only on stderr without it.
+fn warn(runtime_logs: Option<&RuntimeLogger>, message: String) {
+    match runtime_logs {
+        Some(runtime_logs) => runtime_logs.warn(message),
+        None => eprintln!("{}", message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keep_first_then_sample() {
+        let mut filter = LogFilter::new(
+            LogSampling {
+                keep_first: Some(3),
+                one_in: 4,
+                ..Default::default()
+            },
+            false,
+        );
+        let kept: Vec<u64> = (1..=12).filter(|_| filter.admit(0)).collect();
+        // Entries 1-3, then the 4th, 8th and 12th
+        assert_eq!(kept.len(), 6);
+
+        let summary = filter.take_summary().unwrap();
+        assert_eq!(
+            summary.message,
+            "[vortex] Suppressed 6 log entries (6 sampled out, 0 over the rate limit)"
+        );
+        assert_eq!(summary.level, LogLevel::Warn);
+        assert_eq!(summary.fields["sampled_out"], 6);
+        assert!(filter.take_summary().is_none());
+    }
+
+    #[test]
+    fn test_rate_limit_per_second() {
+        let mut filter = LogFilter::new(
+            LogSampling {
+                max_per_second: Some(2),
+                ..Default::default()
+            },
+            false,
+        );
+        let kept = [0, 10, 20, 999, 1000, 1001, 1002, 2500]
+            .into_iter()
+            .filter(|&elapsed_ms| filter.admit(elapsed_ms))
+            .collect::<Vec<_>>();
+        assert_eq!(kept, [0, 10, 1000, 1001, 2500]);
+        assert_eq!(filter.take_summary().unwrap().fields["rate_limited"], 3);
+
+        filter.reset();
+        assert!(filter.admit(0));
+        assert!(filter.take_summary().is_none());
+    }
+
+    #[test]
+    fn test_default_keeps_everything() {
+        let mut filter = LogFilter::default();
+        assert!((0..10_000).all(|_| filter.admit(0)));
+        assert!(filter.take_summary().is_none());
+    }
+
+    #[test]
+    fn test_coalesce_repeats() {
+        let entry = LogEntry::new(0, "retrying".to_string());
+        let mut last = entry.clone();
+        assert!(!LogFilter::default().coalesce(&mut last, &entry));
+
+        let filter = LogFilter::new(LogSampling::default(), true);
+        assert!(filter.coalesce(&mut last, &entry));
+        assert!(filter.coalesce(&mut last, &entry));
+        assert_eq!(last.repeated, 2);
+
+        assert!(!filter.coalesce(&mut last, &LogEntry::new(0, "done".to_string())));
+        let mut with_fields = entry.clone();
+        with_fields.fields.insert("attempt".to_string(), 3.into());
+        assert!(!filter.coalesce(&mut last, &with_fields));
+        let warning = LogEntry {
+            level: LogLevel::Warn,
+            ..entry
+        };
+        assert!(!filter.coalesce(&mut last, &warning));
+        assert_eq!(last.repeated, 2);
+    }
+
+    #[test]
+    fn test_resolve_log_channels() {
+        let channels = LogChannels::default();
+        assert_eq!(
+            channels.resolve("fn-1").unwrap(),
+            ("logs:fn-1".to_string(), None)
+        );
+        assert_eq!(channels.events_channel("fn-1").unwrap(), "events:fn-1");
+
+        let channels = LogChannels {
+            template: Some("tenant:{tenant}:logs:{function}".to_string()),
+            alert_template: Some("alerts:{tenant}".to_string()),
+            events_template: Some("tenant:{tenant}:events:{function}".to_string()),
+            tenant: Some("acme".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            channels.resolve("fn-1").unwrap(),
+            (
+                "tenant:acme:logs:fn-1".to_string(),
+                Some("alerts:acme".to_string())
+            )
+        );
+        assert_eq!(
+            channels.events_channel("fn-1").unwrap(),
+            "tenant:acme:events:fn-1"
+        );
+
+        let without_tenant = LogChannels {
+            tenant: None,
+            ..channels
+        };
+        let error = without_tenant.resolve("fn-1").unwrap_err().to_string();
+        assert!(error.contains("no tenant is set"), "{}", error);
+
+        for template in ["logs:{fn}", "logs:{function"] {
+            let channels = LogChannels {
+                template: Some(template.to_string()),
+                ..Default::default()
+            };
+            assert!(channels.resolve("fn-1").is_err(), "{}", template);
+        }
+    }
+
+    #[test]
+    fn test_history_key() {
+        let mut channels = LogChannels {
+            tenant: Some("acme".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(channels.history_key_template("fn-1").unwrap(), None);
+
+        channels.history = Some(LogHistory::default());
+        let template = channels.history_key_template("fn-1").unwrap().unwrap();
+        assert_eq!(history_key(&template, "inv-1"), "logs:fn-1:inv-1");
+
+        channels.history = Some(LogHistory {
+            key_template: Some("tenant:{tenant}:history:{invocation}".to_string()),
+            ..Default::default()
+        });
+        let template = channels.history_key_template("fn-1").unwrap().unwrap();
+        assert_eq!(history_key(&template, "inv-1"), "tenant:acme:history:inv-1");
+
+        // Channels are per function, so they can't name an invocation
+        channels.template = Some("logs:{invocation}".to_string());
+        assert!(channels.resolve("fn-1").is_err());
+    }
+
+    #[test]
+    fn test_runtime_logger() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut logger = RuntimeLogger::default();
+        logger.publish_to("fn-1".to_string(), sender);
+        let background = logger.clone();
+        background.warn("Redis publish error (non-fatal): broken pipe");
+
+        let json: serde_json::Value = serde_json::from_str(&receiver.try_recv().unwrap()).unwrap();
+        assert_eq!(json["function_id"], "fn-1");
+        assert_eq!(json["level"], "warn");
+        assert_eq!(
+            json["message"],
+            "Redis publish error (non-fatal): broken pipe"
+        );
+
+        let logs = logger.take();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(
+            logs[0].message,
+            "Redis publish error (non-fatal): broken pipe"
+        );
+        assert!(logger.take().is_empty());
+
+        // A worker that keeps failing doesn't grow its result without bound
+        for _ in 0..MAX_RUNTIME_LOGS + 10 {
+            background.warn("Log sink error (non-fatal): disk full");
+        }
+        assert_eq!(logger.take().len(), MAX_RUNTIME_LOGS);
+    }
+}
@@ -5,28 +5,259 @@
 //! the Vortex API (Go) for function execution.
 //!
 //! Usage:
-//!   vortex-runtime <path-to-js-file> [--redis-url <url>] [--function-id <id>]
+//!   vortex-runtime <function.tar.gz|function.tgz|function.tar|function.zip> [options]
+//!   vortex-runtime --listen <unix:<path>|stdio> [--processes <n>] [--max-isolates <n>]
+//!                  [--max-heap-bytes <bytes>] [--capacity-wait-ms <ms>] [options]
+//!   vortex-runtime <function> --serve <host:port> [--max-concurrent-requests <n>]
+//!                  [--max-body-bytes <bytes>] [--max-form-part-bytes <bytes>]
+//!                  [--tls-cert <path> --tls-key <path>] [options]
+//!   vortex-runtime worker-process [options]
+//!   vortex-runtime bundle <entry.js> [--out <path>]
+//!   vortex-runtime snapshot --out <path> [--warmup <path> | --no-warmup]
+//!   vortex-runtime selftest
+//!   vortex-runtime <path-to-js-file> [--redis-url <url>] [--function-id <id>] [--node-compat]
+//!                  [--commonjs] [--env KEY=VALUE]... [--env-file <path>] [--harden] [--disable-eval]
+//!                  [--strict-mode] [--verify-sandbox] [--bootstrap-script <path>]...
+//!                  [--max-script-size <bytes>] [--compile-timeout-ms <ms>] [--timeout-ms <ms>]
+//!                  [--cpu-budget-ms <ms>] [--max-pending-ops <n>] [--max-fs-bytes <bytes>]
+//!                  [--max-output-bytes <bytes>] [--output-storage <url>]
+//!                  [--log-keep-first <n>] [--log-sample-one-in <n>] [--log-max-per-second <n>]
+//!                  [--coalesce-logs] [--log-channel <template>] [--alert-channel <template>]
+//!                  [--events-channel <template>] [--runtime-channel <template>] [--tenant <id>]
+//!                  [--heartbeat-ms <ms>]
+//!                  [--log-history <n>] [--log-history-key <template>] [--log-history-only]
+//!                  [--log-destination <redis://...|file:<path>>]...
+//!                  [--fetch-timeout-ms <ms>] [--fetch-connect-timeout-ms <ms>]
+//!                  [--fetch-max-redirects <n>] [--fetch-retries <n>]
+//!                  [--fetch-max-response-bytes <bytes>] [--fetch-max-header-bytes <bytes>]
+//!                  [--fetch-allow-private] [--fetch-proxy <url>] [--fetch-client-certificate <secret>]
+//!                  [--fetch-cache <memory|redis>] [--cache-dir <path>] [--audit-log <path>] [--kv-memory]
+//!                  [--sqlite-dir <path> | --sqlite-memory] [--sqlite-max-bytes <bytes>] [--strict-sql]
+//!                  [--postgres-url <url>] [--postgres-max-connections <n>]
+//!                  [--postgres-max-connections-per-function <n>] [--postgres-acquire-timeout-ms <ms>]
+//!                  [--secret <name>]... [--sandbox] [--v8-flags <flags>] [--temporal] [--stream-output]
 //!
 //! Options:
+//!   --manifest <path>    Configure the function from a vortex.json manifest (flags take precedence)
+//!   --listen <addr>      Serve the control protocol on unix:<path> or stdio instead of running a file
+//!   --processes <n>      With --listen, execute in N pre-forked child processes
+//!   --serve <host:port>  Serve the function over HTTP/1.1 and HTTP/2 instead of running it once
+//!   --max-concurrent-requests <n>  With --serve, handle at most N requests at once (default 256)
+//!   --max-body-bytes <bytes>       With --serve, reject larger request bodies (default 10 MiB)
+//!   --max-form-part-bytes <bytes>  With --serve, largest part formData() accepts (default 4 MiB)
+//!   --tls-cert <path>    With --serve, serve HTTPS with this PEM certificate chain
+//!   --tls-key <path>     Private key (PEM) for --tls-cert
+//!   --max-isolates <n>   With --listen or --serve, keep at most N isolates alive at once, idle
+//!                        ones included; with --processes, in each child process
+//!   --max-heap-bytes <bytes>  With --listen or --serve, admit no more executions while the
+//!                        isolates' heaps add up to this many bytes
+//!   --capacity-wait-ms <ms>   How long an execution over those limits waits before failing
+//!                        with a capacity error (default 0: fail at once; 503 with --serve)
 //!   --redis-url <url>    Redis URL for real-time log streaming (e.g., redis://localhost:6379)
 //!   --function-id <id>   Function ID for Redis channel name (logs:<function_id>)
+//!   --log-channel <template>    Redis channel for logs instead of logs:<function_id>; may use
+//!                        {function} and {tenant}, e.g. tenant:{tenant}:logs:{function}
+//!   --alert-channel <template>  Also publish error-level log entries to this channel
+//!   --events-channel <template> Redis channel for invocation events instead of
+//!                        events:<function_id>
+//!   --runtime-channel <template> Redis channel for the runtime's own warnings instead of
+//!                        runtime:<function_id>
+//!   --heartbeat-ms <ms>  Publish a heartbeat event this often while an invocation runs
+//!   --log-history <n>    Also keep each invocation's last n log entries in a Redis list,
+//!                        logs:<function_id>:<invocation_id>, for an hour
+//!   --log-history-key <template>  Key of that list; may use {function}, {tenant} and
+//!                        {invocation}
+//!   --log-history-only   Keep the log history without publishing entries on the log channel
+//!   --log-destination <url>  Also write every log entry to redis://<host>[:<port>] (on the
+//!                        log channel) or file:<path> (as NDJSON); repeatable
+//!   --tenant <id>        Value of {tenant} in --log-channel and --alert-channel
+//!   --invocation-id <id> Correlation ID attached to logs and the result (default: random UUID)
+//!   --node-compat        Enable the Node.js compatibility shims (process, node:*)
+//!   --commonjs           Provide require() for CommonJS files and node_modules packages
+//!                        bundled with the function
+//!   --env KEY=VALUE      Expose a variable to user code via process.env (repeatable)
+//!   --env-file <path>    Load process.env variables from a dotenv-style file
+//!   --harden             Freeze JavaScript intrinsics after bootstrap (SES lockdown)
+//!   --disable-eval       Disallow eval() and new Function() in user code
+//!   --strict-mode        Run the script in strict mode ('use strict')
+//!   --verify-sandbox     Check the isolate's sandbox before running anything, as `selftest`
+//!                        does, and refuse to run if a check fails
+//!   --bootstrap-script <path>  Run this script before the function's own code, e.g. to
+//!                        install a helper SDK (repeatable, run in order)
+//!   --max-script-size <bytes>   Reject scripts larger than this many bytes
+//!   --compile-timeout-ms <ms>   Abort if compiling the script takes longer than this
+//!   --timeout-ms <ms>           Abort if the invocation takes longer than this; the error
+//!                               lists the async ops it was still waiting for
+//!   --cpu-budget-ms <ms>        Abort if the invocation uses more CPU time than this, even in
+//!                               a loop that never yields
+//!   --max-pending-ops <n>       Limit simultaneously pending async ops and timers
+//!   --max-fs-bytes <bytes>      Capacity of the in-memory vortex.fs (default 16 MiB)
+//!   --max-output-bytes <bytes>  Truncate or drop return values larger than this (see below)
+//!   --output-storage <url>      Write oversized return values to s3://<bucket>[/<prefix>] instead
+//!   --log-keep-first <n>        Keep the first n log entries, then only sample later ones
+//!   --log-sample-one-in <n>     Past --log-keep-first, keep one entry in n (default 0: none)
+//!   --log-max-per-second <n>    Keep at most n log entries in any one second
+//!   --coalesce-logs             Count a log entry repeating the previous one instead of keeping it
+//!   --fetch-timeout-ms <ms>     Fail fetch() requests that take longer, body included (default none)
+//!   --fetch-connect-timeout-ms <ms>  Time fetch() allows to open a connection (default 10000)
+//!   --fetch-max-redirects <n>   Redirects fetch() follows before failing (default 20)
+//!   --fetch-retries <n>         Retry idempotent fetch() requests after a 5xx or failed connection
+//!   --fetch-max-response-bytes <bytes>  Largest response body fetch() reads (default 64 MiB)
+//!   --fetch-max-header-bytes <bytes>    Largest response headers fetch() accepts (default 64 KiB)
+//!   --fetch-allow-private    Let fetch() reach loopback, private and link-local addresses
+//!   --fetch-proxy <url>      Send fetch() requests through this HTTP(S) proxy; credentials
+//!                            in the URL are sent as Proxy-Authorization
+//!   --fetch-client-certificate <secret>  Present the PEM certificate and key in secret <secret>
+//!                            to servers that require mutual TLS (see --secret)
+//!   --fetch-cache <memory|redis>  Answer repeated GETs from fresh cached responses, kept in
+//!                        this process or in Redis (see --redis-url)
+//!   --cache-dir <path>   Keep Cache API (caches.default) entries in this directory instead
+//!                        of Redis; the function needs the storage capability
+//!   --audit-log <path>   Append vortex.audit.record() events to this file as a hash chain
+//!                        (JSON lines); not supported with --processes
+//!   --kv-memory          Keep vortex.kv data in this process instead of Redis; it is lost
+//!                        on exit and not supported with --processes
+//!   --sqlite-dir <path>  Keep each function's vortex.sqlite database in this directory;
+//!                        not supported with --sandbox
+//!   --sqlite-memory      Keep each function's vortex.sqlite database in memory instead; it
+//!                        is lost on exit and not supported with --processes
+//!   --sqlite-max-bytes <bytes>  Largest a vortex.sqlite database may grow (default 64 MiB)
+//!   --strict-sql         Reject SQL statements with inline string literals; values must be
+//!                        passed as parameters
+//!   --postgres-url <url> Run vortex.sql on this Postgres database, through a connection pool
+//!                        shared by every worker of the process (with --processes, one pool
+//!                        per child process)
+//!   --postgres-max-connections <n>  Connections the pool opens at most (default 20)
+//!   --postgres-max-connections-per-function <n>  Connections one function holds at most
+//!                        at a time (default 5)
+//!   --postgres-acquire-timeout-ms <ms>  How long a statement waits for a free connection
+//!                        before failing (default 5000)
+//!   --secret <name>      Let platform APIs use the host environment variable <name> as a
+//!                        secret, e.g. for vortex.crypto.verifyHmac (repeatable)
+//!   --sandbox            Apply seccomp and Landlock restrictions after reading the script (Linux).
+//!                        Sockets stay allowed for Redis, Postgres, --output-storage and the
+//!                        manifest's `net` hosts; --cache-dir stays writable
+//!   --v8-flags <flags>   Comma-separated V8 flags, e.g. --v8-flags=--jitless,--max-old-space-size=128
+//!   --temporal           Expose the Temporal API
+//!   --stream-output      Print vortex.write() chunks as they are written (see below)
+//!   --jsx-factory <name>            JSX element factory for .jsx/.tsx (default React.createElement)
+//!   --jsx-fragment-factory <name>   JSX fragment factory (default React.Fragment)
+//!   --jsx-import-source <module>    Use the automatic JSX runtime from <module>/jsx-runtime
+//!
+//! Archives contain a multi-file module graph plus an optional `vortex.json`;
+//! they are unpacked in memory and the manifest's entrypoint (default
+//! `index.js`) is imported. Its default export is called if it is a function.
+//!
+//! `.ts`, `.tsx` and `.jsx` files are transpiled and run as ES modules: the
+//! default export is called, like an archive entrypoint.
+//!
+//! `bundle` flattens a local multi-file project into a single ES module plus
+//! source map (see `vortex_runtime::bundle`) instead of running it.
+//!
+//! `snapshot` takes a V8 startup snapshot the way `build.rs` does, running the
+//! built-in warmup script or the one given, and writes it with its SHA-256 in
+//! `<path>.sha256`. It is for trying out a warmup script before building it
+//! in with `VORTEX_SNAPSHOT_WARMUP`.
+//!
+//! `--listen` keeps the runtime resident and speaks the newline-delimited JSON
+//! control protocol (`load`, `invoke`, `stats`, `shutdown`; see
+//! `vortex_runtime::protocol`) on the given socket or on stdin/stdout.
+//!
+//! `--serve` keeps the runtime resident and turns each HTTP request into an
+//! invocation of the function, whose return value becomes the response (see
+//! `vortex_runtime::http`). With `--tls-cert` and `--tls-key` it serves HTTPS,
+//! offering HTTP/2 to clients via ALPN.
+//!
+//! `worker-process` runs the binary as a pre-forked child of a `Supervisor`:
+//! it reads newline-delimited JSON invocations from stdin and writes results
+//! to stdout (see `vortex_runtime::supervisor`). All options except the file
+//! path apply.
 //!
 //! Output (JSON to stdout):
 //!   {
 //!     "output": <any>,
-//!     "logs": [{"timestamp": "...", "message": "..."}],
-//!     "execution_time_ms": <number>
+//!     "logs": [{"invocation_id": "...", "seq": 0, "timestamp": "...", "elapsed_ms": 0, "message": "..."}],
+//!     "execution_time_ms": <number>,
+//!     "invocation_id": "...",
+//!     "metrics": {"egress": {"total": {...}, "hosts": {...}}, "timing": {...}},
+//!     "process": {"peak_rss_bytes": <number>, "open_fds": <number>, "peak_open_fds": <number>}
 //!   }
 //!
-//! Errors are written to stderr and exit code 1 is returned.
+//! Errors are written to stderr and exit code 1 is returned. The JSON result
+//! is still printed, with an `error` object (`message`, plus `exception` when
+//! user code threw and `pending_ops` when it timed out) and the logs captured
+//! before the failure.
+//!
+//! With `--stream-output`, each chunk written with `vortex.write()` is printed
+//! on its own line as `{"chunk": {"invocation_id": "...", "seq": 0, "data":
+//! "...", "end": false}}` while the function runs; the result object is the
+//! last line. Otherwise chunks are returned in the result's `chunks` array.
+//!
+//! With `--max-output-bytes`, a larger return value is cut down (strings) or
+//! replaced with `null` (anything else), and the result gains
+//! `"output_truncated": {"original_bytes": <n>, "limit": <n>}`. With
+//! `--output-storage` as well, the value is uploaded instead and the result
+//! gains `"output_ref": {"url": "s3://...", "size": <n>}`.
+//!
+//! A return value with no JSON representation (a function, a symbol, a
+//! `BigInt`, an object with a cycle, or one nested too deeply to parse back)
+//! gives `"output": null` and
+//! `"output_dropped": {"value_type": "...", "reason": "..."}` saying why.
+//!
+//! When the `--log-*` flags drop log entries, the last entry of `logs` says
+//! how many, e.g. `[vortex] Suppressed 950 log entries (900 sampled out, 50
+//! over the rate limit)`. With `--coalesce-logs`, an entry that repeats the
+//! previous one is counted by that entry's `"repeated"` field instead.
+//!
+//! `metrics.egress` counts the requests made with `fetch()` and their body
+//! bytes in each direction (`requests`, `bytes_sent`, `bytes_received`), in
+//! `total` and by host. A failed run reports what it used before failing.
+//!
+//! `metrics.timing` splits the run into microseconds spent creating the
+//! isolate (`init_us`, always a cold start for the CLI), compiling
+//! (`compile_us`), running the script until it first waits (`execute_us`),
+//! in the event loop waiting for timers and ops and running what follows
+//! them (`event_loop_us`), and turning the return value into JSON
+//! (`serialize_us`).
+//!
+//! `process` describes the whole process on Linux: its peak resident set
+//! size, which unlike V8's heap statistics includes memory that ops allocate
+//! natively, and its open file descriptors at the end and at most (sampled
+//! every 10ms).
+//!
+//! `--secret` values are read from the runtime's own environment, never from
+//! the command line, and are not visible to user code through `process.env`:
+//! functions can only refer to them by name (see `vortex_runtime::secrets`).
 
+use std::collections::HashMap;
 use std::env;
 use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use serde::Serialize;
-use vortex_runtime::{LogEntry, VortexWorker};
+use tokio::sync::mpsc;
+use vortex_runtime::archive;
+use vortex_runtime::bundle::{self, BUNDLE_SUBCOMMAND};
+use vortex_runtime::http::{self, Handler, HttpFunction, HttpOptions};
+use vortex_runtime::manifest::Manifest;
+use vortex_runtime::resources::{ProcessSampler, ProcessUsage};
+use vortex_runtime::sandbox::{self, SandboxPolicy, SandboxStatus};
+use vortex_runtime::selftest::{self, SELFTEST_SUBCOMMAND};
+use vortex_runtime::server::{self, Backend, ListenAddr};
+use vortex_runtime::snapshot::{self, DEFAULT_WARMUP_JS, SNAPSHOT_SUBCOMMAND};
+use vortex_runtime::spill::{OutputRef, OutputStorage};
+use vortex_runtime::sqlite::DEFAULT_SQLITE_MAX_BYTES;
+use vortex_runtime::supervisor::{self, WORKER_PROCESS_SUBCOMMAND};
+use vortex_runtime::transpile::{self, JsxOptions};
+use vortex_runtime::{
+    LogEntry, OutputChunk, OutputDropped, OutputTruncated, Permissions, Scheduler, SchedulerOptions, Supervisor, SupervisorOptions,
+    AuditLog, BootstrapScript, CacheOptions, ExecutionError, ExecutionMetrics, FetchCache, FetchOptions, FetchProxy, FileAuditSink, FileLogSink, Governor, GovernorOptions, KvBackend, LogChannels, LogHistory, LogLevel, LogSampling, LogSink, MemoryFetchCache, MemoryKv, PendingOp, PoolOptions, PostgresOptions, PostgresPool, RedisLogSink, RuntimeLog, ScriptError, SecretsProvider, SqliteDatabases, SqliteOptions, StaticSecrets, VortexWorker, WorkerOptions, OUTPUT_CHANNEL_CAPACITY,
+};
 
 /// CLI output structure matching what the Go API expects.
 #[derive(Serialize)]
@@ -34,29 +265,260 @@ struct CliOutput {
     output: Option<serde_json::Value>,
     logs: Vec<LogEntryOutput>,
     execution_time_ms: u64,
+    invocation_id: String,
+    /// Set when the function failed; `logs` then holds what it printed first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<CliError>,
+    /// Output written with `vortex.write()`, unless it was streamed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    chunks: Vec<String>,
+    /// Set when `output` was cut down by `--max-output-bytes`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_truncated: Option<OutputTruncated>,
+    /// Where an oversized output was uploaded, when `--output-storage` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_ref: Option<OutputRef>,
+    /// Why `output` is null although the function returned something
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_dropped: Option<OutputDropped>,
+    /// Resources the run used, e.g. `fetch()` traffic
+    metrics: ExecutionMetrics,
+    /// Warnings of the runtime itself, e.g. that Redis was unreachable
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    runtime_logs: Vec<RuntimeLog>,
+    /// Memory and file descriptors of the whole process, which V8's own
+    /// numbers miss
+    #[serde(skip_serializing_if = "Option::is_none")]
+    process: Option<ProcessUsage>,
+}
+
+/// A line printed with `--stream-output` for each `vortex.write()` chunk.
+#[derive(Serialize)]
+struct ChunkFrame<'a> {
+    chunk: &'a OutputChunk,
+}
+
+/// Why an invocation failed.
+#[derive(Serialize)]
+struct CliError {
+    message: String,
+    /// The thrown value, including `cause` chains and custom properties
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exception: Option<ScriptError>,
+    /// Async ops the invocation was still waiting for when it timed out
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pending_ops: Vec<PendingOp>,
+}
+
+impl CliOutput {
+    /// Output for a failed run, keeping whatever the function logged first.
+    fn failure(error: &anyhow::Error) -> Self {
+        let (logs, execution_time_ms, invocation_id, metrics) =
+            match error.downcast_ref::<ExecutionError>() {
+                Some(execution) => (
+                    execution.logs.clone(),
+                    execution.execution_time_ms,
+                    execution.invocation_id.clone(),
+                    execution.metrics.clone(),
+                ),
+                None => (Vec::new(), 0, String::new(), ExecutionMetrics::default()),
+            };
+        Self {
+            output: None,
+            logs: logs.into_iter().map(LogEntryOutput::from).collect(),
+            execution_time_ms,
+            invocation_id,
+            error: Some(CliError {
+                message: error.to_string(),
+                exception: ExecutionError::script_error(error).cloned(),
+                pending_ops: ExecutionError::pending_ops(error).to_vec(),
+            }),
+            chunks: Vec::new(),
+            output_truncated: None,
+            output_ref: None,
+            output_dropped: None,
+            metrics,
+            runtime_logs: ExecutionError::runtime_logs(error).to_vec(),
+            process: None,
+        }
+    }
 }
 
 /// Log entry for CLI output (simpler format without chrono serialization issues).
 #[derive(Serialize)]
 struct LogEntryOutput {
+    invocation_id: String,
+    seq: u64,
     timestamp: String,
+    elapsed_ms: u64,
+    level: LogLevel,
     message: String,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    fields: serde_json::Map<String, serde_json::Value>,
+    #[serde(skip_serializing_if = "is_zero")]
+    repeated: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
 }
 
 impl From<LogEntry> for LogEntryOutput {
     fn from(entry: LogEntry) -> Self {
         Self {
+            invocation_id: entry.invocation_id,
+            seq: entry.seq,
             timestamp: entry.timestamp.to_rfc3339(),
+            elapsed_ms: entry.elapsed_ms,
+            level: entry.level,
             message: entry.message,
+            fields: entry.fields,
+            repeated: entry.repeated,
         }
     }
 }
 
 /// Parsed CLI arguments
 struct CliArgs {
-    file_path: String,
+    /// Absent in `--listen` and `worker-process` modes, which receive code per invocation
+    file_path: Option<String>,
+    worker_process: bool,
+    listen: Option<ListenAddr>,
+    processes: Option<usize>,
+    /// Serve the function over HTTP on this address
+    serve: Option<SocketAddr>,
+    http_options: HttpOptions,
+    /// Process-wide isolate and heap limits for `--listen` and `--serve`
+    governor: GovernorOptions,
+    /// PEM certificate chain and private key for HTTPS in `--serve` mode
+    tls_cert: Option<String>,
+    tls_key: Option<String>,
     redis_url: Option<String>,
     function_id: Option<String>,
+    /// Correlation ID for the run; a random UUID when absent
+    invocation_id: Option<String>,
+    node_compat: bool,
+    commonjs: bool,
+    env: HashMap<String, String>,
+    harden: bool,
+    disable_code_generation: bool,
+    strict_mode: bool,
+    /// Run the sandbox self-verification when creating each worker
+    verify_sandbox: bool,
+    /// Scripts from `--bootstrap-script`, run before the function's code
+    bootstrap_scripts: Vec<BootstrapScript>,
+    max_script_size: Option<usize>,
+    compile_timeout_ms: Option<u64>,
+    timeout_ms: Option<u64>,
+    cpu_budget_ms: Option<u64>,
+    max_pending_ops: Option<usize>,
+    max_fs_bytes: Option<usize>,
+    max_output_bytes: Option<usize>,
+    /// `s3://` location for outputs over `max_output_bytes`
+    output_storage: Option<String>,
+    /// Sampling and rate limiting of log entries from the `--log-*` flags
+    log_sampling: LogSampling,
+    coalesce_logs: bool,
+    /// Redis channel names from `--log-channel`, `--alert-channel`,
+    /// `--events-channel`, `--runtime-channel` and `--tenant`, and the
+    /// `--log-history*` flags
+    log_channels: LogChannels,
+    /// `--log-destination` URLs, and the sinks opened for them
+    log_destinations: Vec<String>,
+    log_sinks: Vec<Arc<dyn LogSink>>,
+    heartbeat_ms: Option<u64>,
+    /// Defaults for `fetch()` from the `--fetch-*` flags
+    fetch: FetchOptions,
+    /// Where the Cache API keeps responses (`--cache-dir`)
+    cache: CacheOptions,
+    /// Chain that `vortex.audit.record()` appends to (`--audit-log`)
+    audit: Option<AuditLog>,
+    /// Store behind `vortex.kv` when not Redis (`--kv-memory`)
+    kv: Option<Arc<dyn KvBackend>>,
+    /// Where `vortex.sqlite` keeps databases (`--sqlite-*`), if anywhere
+    sqlite: Option<SqliteOptions>,
+    strict_sql: bool,
+    /// Database behind `vortex.sql` (`--postgres-*`), if not SQLite
+    postgres: Option<PostgresOptions>,
+    /// Secrets from `--secret`, read from the host environment
+    secrets: StaticSecrets,
+    sandbox: bool,
+    v8_flags: Vec<String>,
+    temporal: bool,
+    /// Print `vortex.write()` chunks to stdout as they are written
+    stream_output: bool,
+    /// Capabilities from the manifest's `allowedHosts`, `bindings` and `apis`
+    permissions: Permissions,
+    /// Files unpacked from a function archive
+    modules: HashMap<String, String>,
+    jsx_factory: Option<String>,
+    jsx_fragment_factory: Option<String>,
+    jsx_import_source: Option<String>,
+}
+
+impl CliArgs {
+    /// JSX configuration from the flags, defaulting to React's factory.
+    fn jsx(&self) -> JsxOptions {
+        let defaults = JsxOptions::default();
+        JsxOptions {
+            factory: self.jsx_factory.clone().unwrap_or(defaults.factory),
+            fragment_factory: self
+                .jsx_fragment_factory
+                .clone()
+                .unwrap_or(defaults.fragment_factory),
+            import_source: self.jsx_import_source.clone(),
+        }
+    }
+}
+
+/// What to execute in single-shot mode.
+enum Program {
+    /// A classic script read from a `.js` file
+    Script(String),
+    /// The entrypoint of an unpacked archive (see `CliArgs::modules`)
+    Module(String),
+}
+
+/// Parse a single `KEY=VALUE` environment assignment.
+fn parse_env_assignment(assignment: &str) -> Result<(String, String)> {
+    match assignment.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(anyhow!("Invalid environment assignment '{}', expected KEY=VALUE", assignment)),
+    }
+}
+
+/// Parse a dotenv-style file: one `KEY=VALUE` per line, `#` comments and
+/// blank lines ignored, optional surrounding quotes stripped from values.
+fn parse_env_file(contents: &str) -> Result<HashMap<String, String>> {
+    let mut vars = HashMap::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = parse_env_assignment(line)?;
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+            .unwrap_or(value);
+        vars.insert(key, value.to_string());
+    }
+    Ok(vars)
+}
+
+/// Split a comma-separated `--v8-flags` value into individual flags.
+fn parse_v8_flags(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|flag| !flag.is_empty())
+        .map(String::from)
+        .collect()
 }
 
 /// Parse command line arguments
@@ -65,21 +527,174 @@ fn parse_args() -> Result<CliArgs> {
 
     if args.len() < 2 {
         return Err(anyhow!(
-            "Usage: {} <path-to-js-file> [--redis-url <url>] [--function-id <id>]\n\n\
+            "Usage: {0} <path-to-js-file> [--redis-url <url>] [--function-id <id>] [--node-compat]\n\
+             [--commonjs] [--env KEY=VALUE]... [--env-file <path>] [--harden] [--disable-eval]\n\
+             [--strict-mode] [--verify-sandbox] [--bootstrap-script <path>]...\n\
+             [--max-script-size <bytes>] [--compile-timeout-ms <ms>] [--timeout-ms <ms>]\n\
+             [--cpu-budget-ms <ms>] [--max-pending-ops <n>] [--max-fs-bytes <bytes>]\n\
+             [--max-output-bytes <bytes>] [--output-storage <url>]\n\
+             [--log-keep-first <n>] [--log-sample-one-in <n>] [--log-max-per-second <n>]\n\
+             [--coalesce-logs] [--log-channel <template>] [--alert-channel <template>]\n\
+             [--events-channel <template>] [--runtime-channel <template>] [--tenant <id>]\n\
+             [--heartbeat-ms <ms>]\n\
+             [--log-history <n>] [--log-history-key <template>] [--log-history-only]\n\
+             [--log-destination <redis://...|file:<path>>]...\n\
+             [--fetch-timeout-ms <ms>] [--fetch-connect-timeout-ms <ms>]\n\
+             [--fetch-max-redirects <n>] [--fetch-retries <n>]\n\
+             [--fetch-max-response-bytes <bytes>] [--fetch-max-header-bytes <bytes>]\n\
+             [--fetch-allow-private] [--fetch-proxy <url>] [--fetch-client-certificate <secret>]\n\
+             [--fetch-cache <memory|redis>] [--cache-dir <path>] [--audit-log <path>] [--kv-memory]\n\
+             [--sqlite-dir <path> | --sqlite-memory] [--sqlite-max-bytes <bytes>] [--strict-sql]\n\
+             [--postgres-url <url>] [--postgres-max-connections <n>]\n\
+             [--postgres-max-connections-per-function <n>] [--postgres-acquire-timeout-ms <ms>]\n\
+             [--secret <name>]... [--sandbox] [--v8-flags <flags>] [--temporal] [--stream-output]\n\
+       {0} <function.tar.gz|.tgz|.tar|.zip> [options]\n\
+       {0} --listen <unix:<path>|stdio> [--processes <n>] [--max-isolates <n>]\n\
+             [--max-heap-bytes <bytes>] [--capacity-wait-ms <ms>] [options]\n\
+       {0} <function> --serve <host:port> [--max-concurrent-requests <n>] [--max-body-bytes <bytes>]\n\
+             [--max-form-part-bytes <bytes>] [--tls-cert <path> --tls-key <path>]\n\
+       {0} worker-process [options]\n\
+       {0} bundle <entry.js> [--out <path>]\n\
+       {0} snapshot --out <path> [--warmup <path> | --no-warmup]\n\
+       {0} selftest\n\n\
              Executes JavaScript from a file and outputs JSON result to stdout.\n\n\
              Options:\n  \
+               --manifest <path>    Configure the function from a vortex.json manifest\n  \
+               --listen <addr>      Serve the control protocol on unix:<path> or stdio\n  \
+               --processes <n>      With --listen, run N pre-forked child processes\n  \
+               --serve <host:port>  Serve the function over HTTP instead of running it once\n  \
+               --max-concurrent-requests <n>  With --serve, handle at most N requests at once\n  \
+               --max-body-bytes <bytes>       With --serve, reject larger request bodies\n  \
+               --max-form-part-bytes <bytes>  With --serve, largest part formData() accepts\n  \
+               --tls-cert <path>    With --serve, serve HTTPS with this PEM certificate chain\n  \
+               --tls-key <path>     Private key (PEM) for --tls-cert\n  \
+               --max-isolates <n>   With --listen or --serve, keep at most N isolates alive\n  \
+               --max-heap-bytes <bytes>  With --listen or --serve, cap the isolates' total heap\n  \
+               --capacity-wait-ms <ms>   How long an execution waits for capacity\n  \
                --redis-url <url>    Redis URL for real-time log streaming\n  \
-               --function-id <id>   Function ID for Redis channel name",
+               --function-id <id>   Function ID for Redis channel name\n  \
+               --log-channel <template>    Redis channel for logs, e.g. tenant:{{tenant}}:logs:{{function}}\n  \
+               --alert-channel <template>  Also publish error-level logs to this channel\n  \
+               --events-channel <template> Redis channel for invocation events\n  \
+               --runtime-channel <template> Redis channel for the runtime's own warnings\n  \
+               --heartbeat-ms <ms>  Publish a heartbeat event this often while running\n  \
+               --log-history <n>    Also keep each invocation's last n log entries in Redis\n  \
+               --log-history-key <template>  Key of that list, e.g. logs:{{function}}:{{invocation}}\n  \
+               --log-history-only   Keep the log history instead of publishing entries\n  \
+               --log-destination <url>  Also write logs to redis://... or file:<path> (repeatable)\n  \
+               --tenant <id>        Value of {{tenant}} in channel templates\n  \
+               --invocation-id <id> Correlation ID for logs and the result\n  \
+               --node-compat        Enable the Node.js compatibility shims\n  \
+               --commonjs           Provide require() for bundled CommonJS packages\n  \
+               --env KEY=VALUE      Expose a variable via process.env (repeatable)\n  \
+               --env-file <path>    Load process.env variables from a dotenv file\n  \
+               --harden             Freeze JavaScript intrinsics after bootstrap\n  \
+               --disable-eval       Disallow eval() and new Function() in user code\n  \
+               --strict-mode        Run the script in strict mode ('use strict')\n  \
+               --verify-sandbox     Refuse to run if the sandbox self-check fails\n  \
+               --bootstrap-script <path>  Run before the function's code (repeatable)\n  \
+               --max-script-size <bytes>   Reject scripts larger than this many bytes\n  \
+               --compile-timeout-ms <ms>   Abort if compiling the script takes longer\n  \
+               --timeout-ms <ms>           Abort if the invocation takes longer\n  \
+               --cpu-budget-ms <ms>        Abort if the invocation uses more CPU time\n  \
+               --max-pending-ops <n>       Limit simultaneously pending async ops and timers\n  \
+               --max-fs-bytes <bytes>      Capacity of the in-memory vortex.fs\n  \
+               --max-output-bytes <bytes>  Truncate or drop larger return values\n  \
+               --output-storage <url>      Upload oversized return values to s3://<bucket>\n  \
+               --log-keep-first <n>        Keep the first n log entries, then sample\n  \
+               --log-sample-one-in <n>     Past --log-keep-first, keep one entry in n\n  \
+               --log-max-per-second <n>    Keep at most n log entries a second\n  \
+               --coalesce-logs             Count repeats of a log entry instead of keeping them\n  \
+               --fetch-timeout-ms <ms>     Fail fetch() requests that take longer\n  \
+               --fetch-connect-timeout-ms <ms>  Time fetch() allows to open a connection\n  \
+               --fetch-max-redirects <n>   Redirects fetch() follows before failing\n  \
+               --fetch-retries <n>         Retry idempotent fetch() requests after a 5xx\n  \
+               --fetch-max-response-bytes <bytes>  Largest response body fetch() reads\n  \
+               --fetch-max-header-bytes <bytes>    Largest response headers fetch() accepts\n  \
+               --fetch-allow-private       Let fetch() reach private and loopback addresses\n  \
+               --fetch-proxy <url>         Send fetch() requests through this HTTP(S) proxy\n  \
+               --fetch-client-certificate <secret>  mTLS certificate and key (PEM) from a secret\n  \
+               --fetch-cache <memory|redis>  Cache fresh fetch() GET responses\n  \
+               --cache-dir <path>   Keep Cache API entries in this directory instead of Redis\n  \
+               --audit-log <path>   Append vortex.audit.record() events to this file\n  \
+               --kv-memory          Keep vortex.kv data in this process instead of Redis\n  \
+               --sqlite-dir <path>  Keep vortex.sqlite databases in this directory\n  \
+               --sqlite-memory      Keep vortex.sqlite databases in memory\n  \
+               --sqlite-max-bytes <bytes>  Largest a vortex.sqlite database may grow\n  \
+               --strict-sql         Reject SQL with inline string literals\n  \
+               --postgres-url <url> Run vortex.sql on this Postgres database\n  \
+               --postgres-max-connections <n>  Connections the Postgres pool opens at most\n  \
+               --postgres-max-connections-per-function <n>  Connections one function holds\n  \
+               --postgres-acquire-timeout-ms <ms>  Wait for a free connection this long\n  \
+               --secret <name>      Expose host env variable <name> as a secret (repeatable)\n  \
+               --sandbox            Apply seccomp and Landlock restrictions (Linux)\n  \
+               --v8-flags <flags>   Comma-separated V8 flags (e.g. --jitless)\n  \
+               --temporal           Expose the Temporal API\n  \
+               --stream-output      Print vortex.write() chunks as they are written\n  \
+               --jsx-factory <name>            JSX element factory for .jsx/.tsx\n  \
+               --jsx-fragment-factory <name>   JSX fragment factory\n  \
+               --jsx-import-source <module>    Use the automatic JSX runtime",
             args.first().map(|s| s.as_str()).unwrap_or("vortex-runtime")
         ));
     }
 
-    let file_path = args[1].clone();
+    let worker_process = args[1] == WORKER_PROCESS_SUBCOMMAND;
+    let mut file_path: Option<String> = None;
+    let mut listen: Option<ListenAddr> = None;
+    let mut processes: Option<usize> = None;
+    let mut serve: Option<SocketAddr> = None;
+    let mut http_options = HttpOptions::default();
+    let mut governor = GovernorOptions::default();
+    let mut tls_cert: Option<String> = None;
+    let mut tls_key: Option<String> = None;
+    let mut manifest_path: Option<String> = None;
     let mut redis_url: Option<String> = None;
     let mut function_id: Option<String> = None;
+    let mut invocation_id: Option<String> = None;
+    let mut node_compat = false;
+    let mut commonjs = false;
+    let mut env_vars: HashMap<String, String> = HashMap::new();
+    let mut harden = false;
+    let mut disable_code_generation = false;
+    let mut strict_mode = false;
+    let mut verify_sandbox = false;
+    let mut bootstrap_scripts = Vec::new();
+    let mut max_script_size: Option<usize> = None;
+    let mut compile_timeout_ms: Option<u64> = None;
+    let mut timeout_ms: Option<u64> = None;
+    let mut cpu_budget_ms: Option<u64> = None;
+    let mut max_pending_ops: Option<usize> = None;
+    let mut max_fs_bytes: Option<usize> = None;
+    let mut max_output_bytes: Option<usize> = None;
+    let mut output_storage: Option<String> = None;
+    let mut log_sampling = LogSampling::default();
+    let mut coalesce_logs = false;
+    let mut log_channels = LogChannels::default();
+    let mut log_destinations: Vec<String> = Vec::new();
+    let mut heartbeat_ms: Option<u64> = None;
+    let mut fetch = FetchOptions::default();
+    let mut cache = CacheOptions::default();
+    let mut audit: Option<AuditLog> = None;
+    let mut kv: Option<Arc<dyn KvBackend>> = None;
+    let mut sqlite_dir: Option<PathBuf> = None;
+    let mut sqlite_memory = false;
+    let mut sqlite_max_bytes: Option<u64> = None;
+    let mut strict_sql = false;
+    let mut postgres_url: Option<String> = None;
+    let mut postgres_max_connections: Option<usize> = None;
+    let mut postgres_max_connections_per_function: Option<usize> = None;
+    let mut postgres_acquire_timeout: Option<Duration> = None;
+    let mut secrets = StaticSecrets::new();
+    let mut sandbox = false;
+    let mut v8_flags: Vec<String> = Vec::new();
+    let mut temporal = false;
+    let mut stream_output = false;
+    let mut jsx_factory: Option<String> = None;
+    let mut jsx_fragment_factory: Option<String> = None;
+    let mut jsx_import_source: Option<String> = None;
 
-    // Parse optional arguments
-    let mut i = 2;
+    // Parse the script path and optional arguments
+    let mut i = if worker_process { 2 } else { 1 };
     while i < args.len() {
         match args[i].as_str() {
             "--redis-url" => {
@@ -90,6 +705,14 @@ fn parse_args() -> Result<CliArgs> {
                     return Err(anyhow!("--redis-url requires a value"));
                 }
             }
+            "--invocation-id" => {
+                if i + 1 < args.len() {
+                    invocation_id = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--invocation-id requires a value"));
+                }
+            }
             "--function-id" => {
                 if i + 1 < args.len() {
                     function_id = Some(args[i + 1].clone());
@@ -98,35 +721,1251 @@ fn parse_args() -> Result<CliArgs> {
                     return Err(anyhow!("--function-id requires a value"));
                 }
             }
+            "--log-channel" => {
+                if i + 1 < args.len() {
+                    log_channels.template = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--log-channel requires a value"));
+                }
+            }
+            "--alert-channel" => {
+                if i + 1 < args.len() {
+                    log_channels.alert_template = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--alert-channel requires a value"));
+                }
+            }
+            "--events-channel" => {
+                if i + 1 < args.len() {
+                    log_channels.events_template = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--events-channel requires a value"));
+                }
+            }
+            "--runtime-channel" => {
+                if i + 1 < args.len() {
+                    log_channels.runtime_template = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--runtime-channel requires a value"));
+                }
+            }
+            "--heartbeat-ms" => {
+                if i + 1 < args.len() {
+                    heartbeat_ms = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!(
+                            "--heartbeat-ms expects a number of milliseconds, got '{}'",
+                            args[i + 1]
+                        )
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--heartbeat-ms requires a value"));
+                }
+            }
+            "--log-history" => {
+                if i + 1 < args.len() {
+                    let history = log_channels.history.get_or_insert_with(LogHistory::default);
+                    history.max_entries = args[i + 1].parse().map_err(|_| {
+                        anyhow!(
+                            "--log-history expects a number of entries, got '{}'",
+                            args[i + 1]
+                        )
+                    })?;
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--log-history requires a value"));
+                }
+            }
+            "--log-history-key" => {
+                if i + 1 < args.len() {
+                    let history = log_channels.history.get_or_insert_with(LogHistory::default);
+                    history.key_template = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--log-history-key requires a value"));
+                }
+            }
+            "--log-history-only" => {
+                let history = log_channels.history.get_or_insert_with(LogHistory::default);
+                history.exclusive = true;
+                i += 1;
+            }
+            "--log-destination" => {
+                if i + 1 < args.len() {
+                    log_destinations.push(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--log-destination requires a value"));
+                }
+            }
+            "--tenant" => {
+                if i + 1 < args.len() {
+                    log_channels.tenant = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--tenant requires a value"));
+                }
+            }
+            "--node-compat" => {
+                node_compat = true;
+                i += 1;
+            }
+            "--commonjs" => {
+                commonjs = true;
+                i += 1;
+            }
+            "--harden" => {
+                harden = true;
+                i += 1;
+            }
+            "--disable-eval" => {
+                disable_code_generation = true;
+                i += 1;
+            }
+            "--strict-mode" => {
+                strict_mode = true;
+                i += 1;
+            }
+            "--verify-sandbox" => {
+                verify_sandbox = true;
+                i += 1;
+            }
+            "--sandbox" => {
+                sandbox = true;
+                i += 1;
+            }
+            "--temporal" => {
+                temporal = true;
+                i += 1;
+            }
+            "--stream-output" => {
+                stream_output = true;
+                i += 1;
+            }
+            "--fetch-allow-private" => {
+                fetch.allow_private_addresses = true;
+                i += 1;
+            }
+            "--max-script-size" => {
+                if i + 1 < args.len() {
+                    max_script_size = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!("--max-script-size expects a number of bytes, got '{}'", args[i + 1])
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--max-script-size requires a value"));
+                }
+            }
+            "--compile-timeout-ms" => {
+                if i + 1 < args.len() {
+                    compile_timeout_ms = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!("--compile-timeout-ms expects a number of milliseconds, got '{}'", args[i + 1])
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--compile-timeout-ms requires a value"));
+                }
+            }
+            "--timeout-ms" => {
+                if i + 1 < args.len() {
+                    timeout_ms = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!("--timeout-ms expects a number of milliseconds, got '{}'", args[i + 1])
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--timeout-ms requires a value"));
+                }
+            }
+            "--cpu-budget-ms" => {
+                if i + 1 < args.len() {
+                    cpu_budget_ms = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!("--cpu-budget-ms expects a number of milliseconds, got '{}'", args[i + 1])
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--cpu-budget-ms requires a value"));
+                }
+            }
+            "--max-pending-ops" => {
+                if i + 1 < args.len() {
+                    max_pending_ops = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!("--max-pending-ops expects a number, got '{}'", args[i + 1])
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--max-pending-ops requires a value"));
+                }
+            }
+            "--max-fs-bytes" => {
+                if i + 1 < args.len() {
+                    max_fs_bytes = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!("--max-fs-bytes expects a number of bytes, got '{}'", args[i + 1])
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--max-fs-bytes requires a value"));
+                }
+            }
+            "--max-output-bytes" => {
+                if i + 1 < args.len() {
+                    max_output_bytes = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!("--max-output-bytes expects a number of bytes, got '{}'", args[i + 1])
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--max-output-bytes requires a value"));
+                }
+            }
+            "--log-keep-first" => {
+                if i + 1 < args.len() {
+                    log_sampling.keep_first = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!(
+                            "--log-keep-first expects a number of entries, got '{}'",
+                            args[i + 1]
+                        )
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--log-keep-first requires a value"));
+                }
+            }
+            "--log-sample-one-in" => {
+                if i + 1 < args.len() {
+                    log_sampling.one_in = args[i + 1].parse().map_err(|_| {
+                        anyhow!(
+                            "--log-sample-one-in expects a number, got '{}'",
+                            args[i + 1]
+                        )
+                    })?;
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--log-sample-one-in requires a value"));
+                }
+            }
+            "--log-max-per-second" => {
+                if i + 1 < args.len() {
+                    log_sampling.max_per_second = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!(
+                            "--log-max-per-second expects a number of entries, got '{}'",
+                            args[i + 1]
+                        )
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--log-max-per-second requires a value"));
+                }
+            }
+            "--coalesce-logs" => {
+                coalesce_logs = true;
+                i += 1;
+            }
+            "--fetch-timeout-ms" => {
+                if i + 1 < args.len() {
+                    let ms = args[i + 1].parse().map_err(|_| {
+                        anyhow!("--fetch-timeout-ms expects a number of milliseconds, got '{}'", args[i + 1])
+                    })?;
+                    fetch.timeout = Some(Duration::from_millis(ms));
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--fetch-timeout-ms requires a value"));
+                }
+            }
+            "--fetch-connect-timeout-ms" => {
+                if i + 1 < args.len() {
+                    let ms = args[i + 1].parse().map_err(|_| {
+                        anyhow!("--fetch-connect-timeout-ms expects a number of milliseconds, got '{}'", args[i + 1])
+                    })?;
+                    fetch.connect_timeout = Duration::from_millis(ms);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--fetch-connect-timeout-ms requires a value"));
+                }
+            }
+            "--fetch-max-redirects" => {
+                if i + 1 < args.len() {
+                    fetch.max_redirects = args[i + 1].parse().map_err(|_| {
+                        anyhow!("--fetch-max-redirects expects a number, got '{}'", args[i + 1])
+                    })?;
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--fetch-max-redirects requires a value"));
+                }
+            }
+            "--fetch-retries" => {
+                if i + 1 < args.len() {
+                    fetch.retries = args[i + 1].parse().map_err(|_| {
+                        anyhow!("--fetch-retries expects a number, got '{}'", args[i + 1])
+                    })?;
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--fetch-retries requires a value"));
+                }
+            }
+            "--fetch-proxy" => {
+                if i + 1 < args.len() {
+                    fetch.proxy = Some(FetchProxy::new(args[i + 1].clone()));
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--fetch-proxy requires a value"));
+                }
+            }
+            "--fetch-client-certificate" => {
+                if i + 1 < args.len() {
+                    fetch.client_certificate = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--fetch-client-certificate requires a value"));
+                }
+            }
+            "--fetch-max-response-bytes" => {
+                if i + 1 < args.len() {
+                    fetch.max_response_bytes = args[i + 1].parse().map_err(|_| {
+                        anyhow!("--fetch-max-response-bytes expects a number of bytes, got '{}'", args[i + 1])
+                    })?;
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--fetch-max-response-bytes requires a value"));
+                }
+            }
+            "--fetch-max-header-bytes" => {
+                if i + 1 < args.len() {
+                    fetch.max_header_bytes = args[i + 1].parse().map_err(|_| {
+                        anyhow!("--fetch-max-header-bytes expects a number of bytes, got '{}'", args[i + 1])
+                    })?;
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--fetch-max-header-bytes requires a value"));
+                }
+            }
+            "--fetch-cache" => {
+                if i + 1 < args.len() {
+                    fetch.cache = Some(match args[i + 1].as_str() {
+                        "memory" => FetchCache::Memory(MemoryFetchCache::default()),
+                        "redis" => FetchCache::Redis,
+                        other => {
+                            return Err(anyhow!(
+                                "--fetch-cache expects 'memory' or 'redis', got '{}'",
+                                other
+                            ))
+                        }
+                    });
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--fetch-cache requires a value"));
+                }
+            }
+            "--cache-dir" => {
+                if i + 1 < args.len() {
+                    cache.dir = Some(PathBuf::from(&args[i + 1]));
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--cache-dir requires a value"));
+                }
+            }
+            "--audit-log" => {
+                if i + 1 < args.len() {
+                    // Opened now, before --sandbox restricts the filesystem
+                    let path = &args[i + 1];
+                    let log = FileAuditSink::open(path)
+                        .and_then(AuditLog::new)
+                        .map_err(|e| anyhow!("--audit-log {}: {}", path, e))?;
+                    audit = Some(log);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--audit-log requires a value"));
+                }
+            }
+            "--kv-memory" => {
+                kv = Some(Arc::new(MemoryKv::new()));
+                i += 1;
+            }
+            "--sqlite-dir" => {
+                if i + 1 < args.len() {
+                    sqlite_dir = Some(PathBuf::from(&args[i + 1]));
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--sqlite-dir requires a value"));
+                }
+            }
+            "--sqlite-memory" => {
+                sqlite_memory = true;
+                i += 1;
+            }
+            "--strict-sql" => {
+                strict_sql = true;
+                i += 1;
+            }
+            "--postgres-url" => {
+                if i + 1 < args.len() {
+                    postgres_url = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--postgres-url requires a value"));
+                }
+            }
+            "--postgres-max-connections" => {
+                if i + 1 < args.len() {
+                    postgres_max_connections = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!("--postgres-max-connections expects a number, got '{}'", args[i + 1])
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--postgres-max-connections requires a value"));
+                }
+            }
+            "--postgres-max-connections-per-function" => {
+                if i + 1 < args.len() {
+                    postgres_max_connections_per_function = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!(
+                            "--postgres-max-connections-per-function expects a number, got '{}'",
+                            args[i + 1]
+                        )
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--postgres-max-connections-per-function requires a value"));
+                }
+            }
+            "--postgres-acquire-timeout-ms" => {
+                if i + 1 < args.len() {
+                    let ms = args[i + 1].parse().map_err(|_| {
+                        anyhow!("--postgres-acquire-timeout-ms expects a number of milliseconds, got '{}'", args[i + 1])
+                    })?;
+                    postgres_acquire_timeout = Some(Duration::from_millis(ms));
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--postgres-acquire-timeout-ms requires a value"));
+                }
+            }
+            "--sqlite-max-bytes" => {
+                if i + 1 < args.len() {
+                    sqlite_max_bytes = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!("--sqlite-max-bytes expects a number of bytes, got '{}'", args[i + 1])
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--sqlite-max-bytes requires a value"));
+                }
+            }
+            "--output-storage" => {
+                if i + 1 < args.len() {
+                    output_storage = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--output-storage requires a value"));
+                }
+            }
+            "--secret" => {
+                if i + 1 < args.len() {
+                    let name = &args[i + 1];
+                    let value = env::var(name).map_err(|_| {
+                        anyhow!("--secret {}: environment variable {} is not set", name, name)
+                    })?;
+                    secrets.insert(name.clone(), value);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--secret requires a value"));
+                }
+            }
+            "--v8-flags" => {
+                if i + 1 < args.len() {
+                    v8_flags.extend(parse_v8_flags(&args[i + 1]));
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--v8-flags requires a value"));
+                }
+            }
+            arg if arg.starts_with("--v8-flags=") => {
+                v8_flags.extend(parse_v8_flags(&arg["--v8-flags=".len()..]));
+                i += 1;
+            }
+            "--env" => {
+                if i + 1 < args.len() {
+                    let (key, value) = parse_env_assignment(&args[i + 1])?;
+                    env_vars.insert(key, value);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--env requires a value"));
+                }
+            }
+            "--env-file" => {
+                if i + 1 < args.len() {
+                    let contents = fs::read_to_string(&args[i + 1])
+                        .map_err(|e| anyhow!("Failed to read env file '{}': {}", args[i + 1], e))?;
+                    env_vars.extend(parse_env_file(&contents)?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--env-file requires a value"));
+                }
+            }
+            "--bootstrap-script" => {
+                if i + 1 < args.len() {
+                    let source = fs::read_to_string(&args[i + 1]).map_err(|e| {
+                        anyhow!("Failed to read bootstrap script '{}': {}", args[i + 1], e)
+                    })?;
+                    bootstrap_scripts.push(BootstrapScript::new(args[i + 1].clone(), source));
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--bootstrap-script requires a value"));
+                }
+            }
+            "--jsx-factory" => {
+                if i + 1 < args.len() {
+                    jsx_factory = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--jsx-factory requires a value"));
+                }
+            }
+            "--jsx-fragment-factory" => {
+                if i + 1 < args.len() {
+                    jsx_fragment_factory = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--jsx-fragment-factory requires a value"));
+                }
+            }
+            "--jsx-import-source" => {
+                if i + 1 < args.len() {
+                    jsx_import_source = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--jsx-import-source requires a value"));
+                }
+            }
+            "--manifest" => {
+                if i + 1 < args.len() {
+                    manifest_path = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--manifest requires a value"));
+                }
+            }
+            "--listen" => {
+                if i + 1 < args.len() {
+                    listen = Some(args[i + 1].parse()?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--listen requires a value"));
+                }
+            }
+            "--processes" => {
+                if i + 1 < args.len() {
+                    processes = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!("--processes expects a number, got '{}'", args[i + 1])
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--processes requires a value"));
+                }
+            }
+            "--serve" => {
+                if i + 1 < args.len() {
+                    serve = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!("--serve expects <host:port>, got '{}'", args[i + 1])
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--serve requires a value"));
+                }
+            }
+            "--max-concurrent-requests" => {
+                if i + 1 < args.len() {
+                    http_options.max_concurrent_requests = args[i + 1].parse().map_err(|_| {
+                        anyhow!("--max-concurrent-requests expects a number, got '{}'", args[i + 1])
+                    })?;
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--max-concurrent-requests requires a value"));
+                }
+            }
+            "--max-body-bytes" => {
+                if i + 1 < args.len() {
+                    http_options.max_body_bytes = args[i + 1].parse().map_err(|_| {
+                        anyhow!("--max-body-bytes expects a number of bytes, got '{}'", args[i + 1])
+                    })?;
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--max-body-bytes requires a value"));
+                }
+            }
+            "--max-form-part-bytes" => {
+                if i + 1 < args.len() {
+                    http_options.max_form_part_bytes = args[i + 1].parse().map_err(|_| {
+                        anyhow!("--max-form-part-bytes expects a number of bytes, got '{}'", args[i + 1])
+                    })?;
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--max-form-part-bytes requires a value"));
+                }
+            }
+            "--max-isolates" => {
+                if i + 1 < args.len() {
+                    governor.max_isolates = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!("--max-isolates expects a number, got '{}'", args[i + 1])
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--max-isolates requires a value"));
+                }
+            }
+            "--max-heap-bytes" => {
+                if i + 1 < args.len() {
+                    governor.max_heap_bytes = Some(args[i + 1].parse().map_err(|_| {
+                        anyhow!("--max-heap-bytes expects a number of bytes, got '{}'", args[i + 1])
+                    })?);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--max-heap-bytes requires a value"));
+                }
+            }
+            "--capacity-wait-ms" => {
+                if i + 1 < args.len() {
+                    let ms: u64 = args[i + 1].parse().map_err(|_| {
+                        anyhow!("--capacity-wait-ms expects a number of milliseconds, got '{}'", args[i + 1])
+                    })?;
+                    governor.queue_timeout = Duration::from_millis(ms);
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--capacity-wait-ms requires a value"));
+                }
+            }
+            "--tls-cert" => {
+                if i + 1 < args.len() {
+                    tls_cert = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--tls-cert requires a value"));
+                }
+            }
+            "--tls-key" => {
+                if i + 1 < args.len() {
+                    tls_key = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    return Err(anyhow!("--tls-key requires a value"));
+                }
+            }
+            arg if !arg.starts_with("--") && !worker_process && file_path.is_none() => {
+                file_path = Some(arg.to_string());
+                i += 1;
+            }
             _ => {
                 return Err(anyhow!("Unknown argument: {}", args[i]));
             }
         }
     }
 
-    Ok(CliArgs {
+    let manifest = manifest_path
+        .map(|path| Manifest::load(Path::new(&path)))
+        .transpose()?;
+    if let Some(manifest) = &manifest {
+        if file_path.is_none() && listen.is_none() && !worker_process {
+            file_path = manifest
+                .entrypoint_path()
+                .map(|path| path.to_string_lossy().into_owned());
+        }
+    }
+
+    if !worker_process {
+        match (&file_path, &listen) {
+            (None, None) => {
+                return Err(anyhow!("Missing <path-to-js-file> (or --listen <addr>)"));
+            }
+            (Some(_), Some(_)) => {
+                return Err(anyhow!("A script path cannot be combined with --listen"));
+            }
+            _ => {}
+        }
+    }
+    if processes.is_some() && listen.is_none() {
+        return Err(anyhow!("--processes requires --listen"));
+    }
+    if processes.is_some() && audit.is_some() {
+        return Err(anyhow!(
+            "--audit-log cannot be combined with --processes: every child process would start \
+             its own chain in the same file"
+        ));
+    }
+    if processes.is_some() && kv.is_some() {
+        return Err(anyhow!(
+            "--kv-memory cannot be combined with --processes: every child process would keep \
+             its own copy of the data"
+        ));
+    }
+    if sqlite_dir.is_some() && sqlite_memory {
+        return Err(anyhow!("--sqlite-dir and --sqlite-memory cannot be combined"));
+    }
+    if sqlite_max_bytes.is_some() && sqlite_dir.is_none() && !sqlite_memory {
+        return Err(anyhow!("--sqlite-max-bytes requires --sqlite-dir or --sqlite-memory"));
+    }
+    if processes.is_some() && sqlite_memory {
+        return Err(anyhow!(
+            "--sqlite-memory cannot be combined with --processes: every child process would keep \
+             its own copy of the databases"
+        ));
+    }
+    if sandbox && sqlite_dir.is_some() {
+        return Err(anyhow!(
+            "--sqlite-dir cannot be combined with --sandbox: SQLite creates journal files next to \
+             each database, which the sandbox doesn't allow"
+        ));
+    }
+    let sqlite = (sqlite_dir.is_some() || sqlite_memory).then(|| SqliteOptions {
+        dir: sqlite_dir,
+        max_bytes: sqlite_max_bytes.unwrap_or(DEFAULT_SQLITE_MAX_BYTES),
+        ..Default::default()
+    });
+    let postgres_tuned = postgres_max_connections.is_some()
+        || postgres_max_connections_per_function.is_some()
+        || postgres_acquire_timeout.is_some();
+    if postgres_tuned && postgres_url.is_none() {
+        return Err(anyhow!(
+            "--postgres-max-connections, --postgres-max-connections-per-function and \
+             --postgres-acquire-timeout-ms require --postgres-url"
+        ));
+    }
+    let postgres = postgres_url.map(|url| {
+        let mut options = PostgresOptions::new(url);
+        if let Some(max) = postgres_max_connections {
+            options.max_connections = max;
+        }
+        if let Some(max) = postgres_max_connections_per_function {
+            options.max_connections_per_function = max;
+        }
+        if let Some(timeout) = postgres_acquire_timeout {
+            options.acquire_timeout = timeout;
+        }
+        options
+    });
+    // Opened now, before --sandbox restricts the filesystem
+    let log_sinks = log_destinations
+        .iter()
+        .map(|destination| log_sink(destination, function_id.as_deref(), &log_channels))
+        .collect::<Result<Vec<_>>>()?;
+    if serve.is_some() && (listen.is_some() || worker_process) {
+        return Err(anyhow!("--serve cannot be combined with --listen or worker-process"));
+    }
+    if tls_cert.is_some() != tls_key.is_some() {
+        return Err(anyhow!("--tls-cert and --tls-key must be given together"));
+    }
+    if tls_cert.is_some() && serve.is_none() {
+        return Err(anyhow!("--tls-cert requires --serve"));
+    }
+
+    let mut cli_args = CliArgs {
         file_path,
+        worker_process,
+        listen,
+        processes,
+        serve,
+        http_options,
+        governor,
+        tls_cert,
+        tls_key,
         redis_url,
         function_id,
-    })
+        invocation_id,
+        node_compat,
+        commonjs,
+        env: env_vars,
+        harden,
+        disable_code_generation,
+        strict_mode,
+        verify_sandbox,
+        bootstrap_scripts,
+        max_script_size,
+        compile_timeout_ms,
+        timeout_ms,
+        cpu_budget_ms,
+        max_pending_ops,
+        max_fs_bytes,
+        max_output_bytes,
+        output_storage,
+        log_sampling,
+        coalesce_logs,
+        log_channels,
+        log_destinations,
+        log_sinks,
+        heartbeat_ms,
+        fetch,
+        cache,
+        audit,
+        kv,
+        sqlite,
+        strict_sql,
+        postgres,
+        secrets,
+        sandbox,
+        v8_flags,
+        temporal,
+        stream_output,
+        permissions: Permissions::default(),
+        modules: HashMap::new(),
+        jsx_factory,
+        jsx_fragment_factory,
+        jsx_import_source,
+    };
+    if let Some(manifest) = &manifest {
+        apply_manifest(&mut cli_args, manifest);
+    }
+    Ok(cli_args)
+}
+
+/// Open the sink for a `--log-destination`: `redis://` (or `rediss://`)
+/// publishes on the function's log channel, `file:` appends NDJSON.
+fn log_sink(
+    destination: &str,
+    function_id: Option<&str>,
+    log_channels: &LogChannels,
+) -> Result<Arc<dyn LogSink>> {
+    if destination.starts_with("redis://") || destination.starts_with("rediss://") {
+        let function_id = function_id
+            .ok_or_else(|| anyhow!("--log-destination {} requires --function-id", destination))?;
+        let (channel, _) = log_channels.resolve(function_id)?;
+        let client = redis::Client::open(destination)
+            .map_err(|e| anyhow!("--log-destination {}: {}", destination, e))?;
+        return Ok(Arc::new(RedisLogSink::new(client, channel)));
+    }
+    if let Some(path) = destination.strip_prefix("file:") {
+        // Both file:/var/log/fn.ndjson and file:///var/log/fn.ndjson
+        let path = path.strip_prefix("//").unwrap_or(path);
+        let sink = FileLogSink::open(path)
+            .map_err(|e| anyhow!("--log-destination {}: {}", destination, e))?;
+        return Ok(Arc::new(sink));
+    }
+    Err(anyhow!(
+        "--log-destination expects redis://... or file:<path>, got '{}'",
+        destination
+    ))
+}
+
+/// Fill in anything not given on the command line from a manifest.
+fn apply_manifest(cli_args: &mut CliArgs, manifest: &Manifest) {
+    cli_args.node_compat |= manifest.node_compat;
+    cli_args.commonjs |= manifest.commonjs;
+    cli_args.harden |= manifest.harden;
+    cli_args.disable_code_generation |= manifest.disable_eval;
+    cli_args.strict_mode |= manifest.strict_mode;
+    cli_args.strict_sql |= manifest.strict_sql;
+    for (key, value) in &manifest.env {
+        cli_args.env.entry(key.clone()).or_insert_with(|| value.clone());
+    }
+    cli_args.max_script_size = cli_args.max_script_size.or(manifest.limits.max_script_size);
+    cli_args.compile_timeout_ms = cli_args.compile_timeout_ms.or(manifest.limits.compile_timeout_ms);
+    cli_args.timeout_ms = cli_args.timeout_ms.or(manifest.limits.timeout_ms);
+    cli_args.cpu_budget_ms = cli_args.cpu_budget_ms.or(manifest.limits.cpu_budget_ms);
+    cli_args.max_pending_ops = cli_args.max_pending_ops.or(manifest.limits.max_pending_ops);
+    cli_args.max_fs_bytes = cli_args.max_fs_bytes.or(manifest.limits.max_fs_bytes);
+    cli_args.max_output_bytes = cli_args.max_output_bytes.or(manifest.limits.max_output_bytes);
+    cli_args.permissions = manifest.permissions();
+    if let Some(jsx) = &manifest.jsx {
+        cli_args.jsx_factory.get_or_insert_with(|| jsx.factory.clone());
+        cli_args
+            .jsx_fragment_factory
+            .get_or_insert_with(|| jsx.fragment_factory.clone());
+        if cli_args.jsx_import_source.is_none() {
+            cli_args.jsx_import_source = jsx.import_source.clone();
+        }
+    }
 }
 
-#[tokio::main]
-async fn main() {
-    if let Err(e) = run().await {
+fn main() {
+    if let Err(e) = start() {
         eprintln!("Error: {}", e);
         process::exit(1);
     }
 }
 
-async fn run() -> Result<()> {
+/// Read the script and apply the optional OS sandbox before any runtime
+/// threads exist, then run the worker on a tokio runtime.
+///
+/// The sandbox only applies to threads created after it is installed, which
+/// is why this cannot happen inside an async `main`.
+fn start() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some(BUNDLE_SUBCOMMAND) {
+        return bundle_command(&args[2..]);
+    }
+    if args.get(1).map(String::as_str) == Some(SELFTEST_SUBCOMMAND) {
+        return block_on_runtime(selftest_command());
+    }
+    if args.get(1).map(String::as_str) == Some(SNAPSHOT_SUBCOMMAND) {
+        return snapshot_command(&args[2..]);
+    }
+
     // Parse command line arguments
-    let cli_args = parse_args()?;
+    let mut cli_args = parse_args()?;
+
+    if cli_args.worker_process {
+        apply_sandbox(&cli_args)?;
+        let pool = pool_options(&cli_args);
+        let options = worker_options(cli_args)?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| anyhow!("Failed to start async runtime: {}", e))?;
+        let local = tokio::task::LocalSet::new();
+        return local.block_on(&runtime, supervisor::serve_worker_process(options, pool));
+    }
+
+    if let Some(addr) = cli_args.listen.clone() {
+        return serve(addr, cli_args);
+    }
+
+    let program = load_program(&mut cli_args)?;
+    if let Some(addr) = cli_args.serve {
+        return serve_http(addr, cli_args, program);
+    }
+
+    apply_sandbox(&cli_args)?;
+    block_on_runtime(run(cli_args, program))
+}
+
+/// Read the function given on the command line.
+fn load_program(cli_args: &mut CliArgs) -> Result<Program> {
+    let file_path = cli_args.file_path.clone().unwrap_or_default();
+
+    // Multi-file functions are unpacked into memory; their manifest applies
+    // like --manifest, with flags taking precedence
+    if archive::is_archive(&file_path) {
+        let bundle = archive::load_archive(&file_path)?;
+        if let Some(manifest) = &bundle.manifest {
+            apply_manifest(cli_args, manifest);
+        }
+        let entrypoint = bundle.entrypoint().to_string();
+        cli_args.modules = bundle.files;
+        return Ok(Program::Module(entrypoint));
+    }
+
+    // Check the file size before reading so oversized payloads never get loaded into memory
+    if let Some(max) = cli_args.max_script_size {
+        let size = fs::metadata(&file_path)
+            .map_err(|e| anyhow!("Failed to read file '{}': {}", file_path, e))?
+            .len();
+        if size > max as u64 {
+            return Err(anyhow!(
+                "Script size of {} bytes exceeds the limit of {} bytes",
+                size,
+                max
+            ));
+        }
+    }
 
     // Read JavaScript code from file
-    let code = fs::read_to_string(&cli_args.file_path)
-        .map_err(|e| anyhow!("Failed to read file '{}': {}", cli_args.file_path, e))?;
+    let code = fs::read_to_string(&file_path)
+        .map_err(|e| anyhow!("Failed to read file '{}': {}", file_path, e))?;
 
+    // TypeScript and JSX files are ES modules, so they run like an archive entrypoint
+    if transpile::needs_transpile(&file_path) {
+        let name = Path::new(&file_path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        cli_args.modules.insert(name.clone(), code);
+        return Ok(Program::Module(name));
+    }
+
+    Ok(Program::Script(code))
+}
+
+/// Run a future to completion on a fresh multi-threaded tokio runtime.
+fn block_on_runtime(future: impl std::future::Future<Output = Result<()>>) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| anyhow!("Failed to start async runtime: {}", e))?;
+    runtime.block_on(future)
+}
+
+/// `vortex-runtime selftest`: verify the sandbox of freshly created workers,
+/// printing one line per configuration, and fail if any check fails.
+async fn selftest_command() -> Result<()> {
+    let mut failed = 0;
+    for (name, violations) in selftest::run()? {
+        if violations.is_empty() {
+            println!("ok    {}", name);
+        }
+        for violation in &violations {
+            println!("FAIL  {}: {}", name, violation);
+        }
+        failed += violations.len();
+    }
+    if failed > 0 {
+        return Err(anyhow!("{} sandbox check(s) failed", failed));
+    }
+    Ok(())
+}
+
+/// `vortex-runtime bundle <entry> [--out <path>] [--jsx-* <value>]`.
+///
+/// With `--out`, writes the bundle and `<path>.map` next to it. Otherwise
+/// prints `{"code": ..., "source_map": ...}` to stdout for the Go API.
+fn bundle_command(args: &[String]) -> Result<()> {
+    let mut entrypoint: Option<&str> = None;
+    let mut out: Option<&str> = None;
+    let mut jsx = JsxOptions::default();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--out" => {
+                let value = args.get(i + 1).ok_or_else(|| anyhow!("--out requires a value"))?;
+                out = Some(value.as_str());
+                i += 2;
+            }
+            flag @ ("--jsx-factory" | "--jsx-fragment-factory" | "--jsx-import-source") => {
+                let value = args
+                    .get(i + 1)
+                    .ok_or_else(|| anyhow!("{} requires a value", flag))?
+                    .clone();
+                match flag {
+                    "--jsx-factory" => jsx.factory = value,
+                    "--jsx-fragment-factory" => jsx.fragment_factory = value,
+                    _ => jsx.import_source = Some(value),
+                }
+                i += 2;
+            }
+            arg if !arg.starts_with("--") && entrypoint.is_none() => {
+                entrypoint = Some(arg);
+                i += 1;
+            }
+            _ => return Err(anyhow!("Unknown argument: {}", args[i])),
+        }
+    }
+    let entrypoint = entrypoint
+        .ok_or_else(|| anyhow!("Usage: vortex-runtime bundle <entry> [--out <path>]"))?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| anyhow!("Failed to start async runtime: {}", e))?;
+    let bundle = runtime.block_on(bundle::bundle(Path::new(entrypoint), &jsx))?;
+
+    match out {
+        Some(out) => {
+            let mut code = bundle.code;
+            if let Some(map) = &bundle.source_map {
+                let map_path = format!("{}.map", out);
+                fs::write(&map_path, map)
+                    .map_err(|e| anyhow!("Failed to write '{}': {}", map_path, e))?;
+                let map_name = Path::new(&map_path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or(map_path);
+                code.push_str(&format!("\n//# sourceMappingURL={}\n", map_name));
+            }
+            fs::write(out, code).map_err(|e| anyhow!("Failed to write '{}': {}", out, e))?;
+        }
+        None => {
+            let json = serde_json::to_string(&bundle)
+                .map_err(|e| anyhow!("Failed to serialize bundle: {}", e))?;
+            println!("{}", json);
+        }
+    }
+    Ok(())
+}
+
+/// `vortex-runtime snapshot --out <path> [--warmup <path> | --no-warmup]`.
+///
+/// Writes the snapshot to `<path>` and its hex-encoded SHA-256 to
+/// `<path>.sha256`, like the pair `build.rs` embeds.
+fn snapshot_command(args: &[String]) -> Result<()> {
+    let mut out: Option<&str> = None;
+    let mut warmup = Some(DEFAULT_WARMUP_JS.to_string());
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--out" => {
+                let value = args
+                    .get(i + 1)
+                    .ok_or_else(|| anyhow!("--out requires a value"))?;
+                out = Some(value.as_str());
+                i += 2;
+            }
+            "--warmup" => {
+                let path = args
+                    .get(i + 1)
+                    .ok_or_else(|| anyhow!("--warmup requires a value"))?;
+                let source = fs::read_to_string(path)
+                    .map_err(|e| anyhow!("Failed to read warmup script '{}': {}", path, e))?;
+                warmup = Some(source);
+                i += 2;
+            }
+            "--no-warmup" => {
+                warmup = None;
+                i += 1;
+            }
+            _ => return Err(anyhow!("Unknown argument: {}", args[i])),
+        }
+    }
+    let out = out.ok_or_else(|| {
+        anyhow!("Usage: vortex-runtime snapshot --out <path> [--warmup <path> | --no-warmup]")
+    })?;
+
+    let bytes = snapshot::create_snapshot(warmup)?;
+    let hash_path = format!("{}.sha256", out);
+    fs::write(&hash_path, snapshot::snapshot_sha256(&bytes))
+        .map_err(|e| anyhow!("Failed to write '{}': {}", hash_path, e))?;
+    fs::write(out, bytes).map_err(|e| anyhow!("Failed to write '{}': {}", out, e))?;
+    Ok(())
+}
+
+/// Run the resident socket server for `--listen`.
+///
+/// With `--processes`, invocations go to pre-forked children started with the
+/// same flags (including `--sandbox`); otherwise they run on in-process threads.
+fn serve(addr: ListenAddr, cli_args: CliArgs) -> Result<()> {
+    if cli_args.processes.is_none() {
+        if addr == ListenAddr::Stdio {
+            apply_sandbox(&cli_args)?;
+        } else if cli_args.sandbox {
+            return Err(anyhow!(
+                "--sandbox with --listen unix: requires --processes: the server process must keep \
+                 accepting connections, so only the child processes are sandboxed"
+            ));
+        }
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| anyhow!("Failed to start async runtime: {}", e))?;
+    runtime.block_on(async move {
+        let backend = match cli_args.processes {
+            Some(processes) => Backend::Processes(Supervisor::new(SupervisorOptions {
+                processes,
+                args: child_args(&env::args().skip(1).collect::<Vec<_>>()),
+                ..Default::default()
+            })?),
+            None => Backend::InProcess {
+                scheduler: Scheduler::new(SchedulerOptions {
+                    pool: pool_options(&cli_args),
+                    ..Default::default()
+                })?,
+                options: worker_options(cli_args)?,
+            },
+        };
+        eprintln!("Listening on {}", addr);
+        server::serve(&addr, backend).await
+    })
+}
+
+/// Serve the function over HTTP for `--serve`.
+///
+/// Requests run on in-process threads, so `--sandbox` would restrict the
+/// listener as well and is not supported here.
+fn serve_http(addr: SocketAddr, cli_args: CliArgs, program: Program) -> Result<()> {
+    if cli_args.sandbox {
+        return Err(anyhow!(
+            "--sandbox cannot be combined with --serve: the server process must keep accepting \
+             connections"
+        ));
+    }
+
+    let function_id = cli_args
+        .function_id
+        .clone()
+        .unwrap_or_else(|| "default".to_string());
+    let handler = match program {
+        Program::Script(code) => Handler::Script(code),
+        Program::Module(entrypoint) => Handler::Module(entrypoint),
+    };
+    let mut http_options = cli_args.http_options.clone();
+    if let (Some(cert), Some(key)) = (&cli_args.tls_cert, &cli_args.tls_key) {
+        http_options.tls = Some(http::tls_config(Path::new(cert), Path::new(key))?);
+    }
+
+    block_on_runtime(async move {
+        let scheduler = Scheduler::new(SchedulerOptions {
+            pool: pool_options(&cli_args),
+            ..Default::default()
+        })?;
+        let function = HttpFunction {
+            function_id,
+            handler,
+            options: worker_options(cli_args)?,
+        };
+        let scheme = if http_options.tls.is_some() { "HTTPS" } else { "HTTP" };
+        eprintln!("Serving {} on {}", scheme, addr);
+        http::serve(addr, scheduler, function, http_options).await
+    })
+}
+
+/// Pool options for the long-running modes, with a governor if
+/// `--max-isolates` or `--max-heap-bytes` was given.
+fn pool_options(cli_args: &CliArgs) -> PoolOptions {
+    let governor = &cli_args.governor;
+    let limited = governor.max_isolates.is_some() || governor.max_heap_bytes.is_some();
+    PoolOptions {
+        governor: limited.then(|| Governor::new(governor.clone())),
+        ..Default::default()
+    }
+}
+
+/// Forward the worker flags to child processes, minus the server-only ones.
+fn child_args(args: &[String]) -> Vec<String> {
+    let mut forwarded = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--listen" | "--processes" => i += 2,
+            _ => {
+                forwarded.push(args[i].clone());
+                i += 1;
+            }
+        }
+    }
+    forwarded
+}
+
+/// Apply the OS sandbox if `--sandbox` was given.
+fn apply_sandbox(cli_args: &CliArgs) -> Result<()> {
+    if !cli_args.sandbox {
+        return Ok(());
+    }
+    let policy = sandbox_policy(cli_args);
+    // Landlock can only allow writes beneath directories that exist
+    for dir in &policy.write_paths {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| anyhow!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let status = sandbox::apply(&policy)
+        .map_err(|e| anyhow!("Failed to apply sandbox: {}", e))?;
+    if status == SandboxStatus::PartiallyEnforced {
+        eprintln!("Warning: Landlock is unavailable, filesystem access is not restricted");
+    }
+    Ok(())
+}
+
+/// What the sandbox has to leave open for the configured backends.
+///
+/// The `--audit-log` and file `--log-destination` files are opened before the
+/// sandbox is applied and need no rule; `--sqlite-dir` is rejected with
+/// `--sandbox` when the flags are parsed.
+fn sandbox_policy(cli_args: &CliArgs) -> SandboxPolicy {
+    let allow_network = cli_args.redis_url.is_some()
+        || cli_args.postgres.is_some()
+        || cli_args.output_storage.is_some()
+        // Only a manifest grants fetch() any hosts
+        || !cli_args.permissions.net.is_empty()
+        || cli_args
+            .log_destinations
+            .iter()
+            .any(|destination| destination.starts_with("redis"));
+    SandboxPolicy {
+        allow_network,
+        // Counted for the result's `process` report
+        read_paths: vec![PathBuf::from("/proc/self/fd")],
+        write_paths: cli_args.cache.dir.iter().cloned().collect(),
+    }
+}
+
+/// Build the worker configuration from the parsed CLI flags.
+fn worker_options(cli_args: CliArgs) -> Result<WorkerOptions> {
     // Create Redis client if URL is provided
     let redis_client = if let Some(ref url) = cli_args.redis_url {
         Some(redis::Client::open(url.as_str())
@@ -135,23 +1974,140 @@ async fn run() -> Result<()> {
         None
     };
 
+    Ok(WorkerOptions {
+        redis_client,
+        function_id: cli_args.function_id,
+        node_compat: cli_args.node_compat,
+        commonjs: cli_args.commonjs,
+        env: cli_args.env,
+        harden: cli_args.harden,
+        disable_code_generation: cli_args.disable_code_generation,
+        strict_mode: cli_args.strict_mode,
+        verify_sandbox: cli_args.verify_sandbox,
+        bootstrap_scripts: cli_args.bootstrap_scripts,
+        max_script_size: cli_args.max_script_size,
+        compile_timeout: cli_args.compile_timeout_ms.map(Duration::from_millis),
+        timeout: cli_args.timeout_ms.map(Duration::from_millis),
+        cpu_budget: cli_args.cpu_budget_ms.map(Duration::from_millis),
+        max_pending_ops: cli_args.max_pending_ops,
+        v8_flags: cli_args.v8_flags,
+        temporal: cli_args.temporal,
+        permissions: cli_args.permissions,
+        jsx: cli_args.jsx(),
+        modules: cli_args.modules,
+        max_fs_bytes: cli_args.max_fs_bytes,
+        max_output_bytes: cli_args.max_output_bytes,
+        output_storage: cli_args
+            .output_storage
+            .as_deref()
+            .map(OutputStorage::s3)
+            .transpose()?,
+        log_sampling: cli_args.log_sampling,
+        coalesce_logs: cli_args.coalesce_logs,
+        log_channels: cli_args.log_channels,
+        log_sinks: cli_args.log_sinks,
+        heartbeat_interval: cli_args.heartbeat_ms.map(Duration::from_millis),
+        secrets: (!cli_args.secrets.is_empty())
+            .then(|| Arc::new(cli_args.secrets) as Arc<dyn SecretsProvider>),
+        fetch: cli_args.fetch,
+        cache: cli_args.cache,
+        audit: cli_args.audit,
+        kv: cli_args.kv,
+        sqlite: cli_args.sqlite.map(SqliteDatabases::new),
+        strict_sql: cli_args.strict_sql,
+        postgres: cli_args.postgres.map(PostgresPool::new).transpose()?,
+        ..Default::default()
+    })
+}
+
+/// How often the run counts the process's open file descriptors.
+const PROCESS_SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Run the program and print its result object to stdout.
+///
+/// Failures are still reported on stderr with exit status 1, but a result
+/// object is printed as well, carrying the error and any logs captured up to
+/// the failure point.
+async fn run(cli_args: CliArgs, program: Program) -> Result<()> {
+    let invocation_id = cli_args.invocation_id.clone();
+    let stream_output = cli_args.stream_output;
+    let sampler = ProcessSampler::start(PROCESS_SAMPLE_INTERVAL);
     // Create worker with optional Redis support
-    let mut worker = VortexWorker::new_with_redis(redis_client, cli_args.function_id)
-        .map_err(|e| anyhow!("Failed to initialize runtime: {}", e))?;
+    let mut worker = match VortexWorker::with_options(worker_options(cli_args)?) {
+        Ok(worker) => worker,
+        Err(e) => {
+            let e = anyhow!("Failed to initialize runtime: {}", e);
+            print_output(&CliOutput {
+                process: Some(sampler.finish()),
+                ..CliOutput::failure(&e)
+            })?;
+            return Err(e);
+        }
+    };
 
-    let result = worker
-        .run(&code)
-        .await
-        .map_err(|e| anyhow!("Execution failed: {}", e))?;
+    // Chunks are printed by a separate task so a slow stdout reader only
+    // holds back the body of a returned Response, never the isolate itself
+    let printer = stream_output.then(|| {
+        let (tx, mut rx) = mpsc::channel::<OutputChunk>(OUTPUT_CHANNEL_CAPACITY);
+        worker.set_output_sink(Some(tx));
+        tokio::spawn(async move {
+            while let Some(chunk) = rx.recv().await {
+                if let Ok(json) = serde_json::to_string(&ChunkFrame { chunk: &chunk }) {
+                    println!("{}", json);
+                }
+            }
+        })
+    });
+
+    let result = match program {
+        Program::Script(code) => {
+            worker
+                .run_invocation(&code, &serde_json::Value::Null, invocation_id.as_deref())
+                .await
+        }
+        Program::Module(entrypoint) => {
+            worker
+                .run_module(&entrypoint, &serde_json::Value::Null, invocation_id.as_deref())
+                .await
+        }
+    };
+    // Dropping the sink lets the printer drain and exit, so every chunk is
+    // printed before the result line
+    worker.set_output_sink(None);
+    if let Some(printer) = printer {
+        let _ = printer.await;
+    }
+    let process = Some(sampler.finish());
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            print_output(&CliOutput {
+                process,
+                ..CliOutput::failure(&e)
+            })?;
+            return Err(anyhow!("Execution failed: {}", e));
+        }
+    };
 
     // Convert to CLI output format
-    let output = CliOutput {
+    print_output(&CliOutput {
         output: result.output,
         logs: result.logs.into_iter().map(LogEntryOutput::from).collect(),
         execution_time_ms: result.execution_time_ms,
-    };
+        invocation_id: result.invocation_id,
+        error: None,
+        chunks: result.chunks,
+        output_truncated: result.output_truncated,
+        output_ref: result.output_ref,
+        output_dropped: result.output_dropped,
+        metrics: result.metrics,
+        runtime_logs: result.runtime_logs,
+        process,
+    })
+}
 
-    // Output JSON to stdout
+/// Output JSON to stdout
+fn print_output(output: &CliOutput) -> Result<()> {
     let json = serde_json::to_string(&output)
         .map_err(|e| anyhow!("Failed to serialize output: {}", e))?;
 
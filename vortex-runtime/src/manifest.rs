@@ -0,0 +1,177 @@
+//! Per-function manifest (`vortex.json`).
+//!
+//! Instead of passing a dozen discrete CLI flags, a function can ship a
+//! manifest describing how it should run:
+//!
+//! ```json
+//! {
+//!   "entrypoint": "index.js",
+//!   "nodeCompat": true,
+//!   "env": { "API_URL": "https://api.example.com" },
+//!   "limits": {
+//!     "maxScriptSize": 1048576, "compileTimeoutMs": 500, "timeoutMs": 10000,
+//!     "cpuBudgetMs": 2000, "maxPendingOps": 100
+//!   },
+//!   "allowedHosts": ["api.example.com", "*.internal.dev"],
+//!   "bindings": { "kv": true },
+//!   "apis": ["kv", "crypto", "jwt"],
+//!   "jsx": { "factory": "h", "fragmentFactory": "Fragment" }
+//! }
+//! ```
+//!
+//! Unknown keys are rejected so that typos fail loudly instead of silently
+//! running a function without the limits its author intended.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::permissions::Permissions;
+use crate::transpile::JsxOptions;
+
+/// Conventional manifest file name.
+pub const MANIFEST_FILE_NAME: &str = "vortex.json";
+
+/// Resource limits declared in a manifest.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ManifestLimits {
+    pub max_script_size: Option<usize>,
+    pub compile_timeout_ms: Option<u64>,
+    pub timeout_ms: Option<u64>,
+    pub cpu_budget_ms: Option<u64>,
+    pub max_pending_ops: Option<usize>,
+    pub max_fs_bytes: Option<usize>,
+    pub max_output_bytes: Option<usize>,
+}
+
+/// Platform resources the function is bound to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestBindings {
+    #[serde(default)]
+    pub kv: bool,
+    #[serde(default)]
+    pub sql: bool,
+    #[serde(default)]
+    pub storage: bool,
+}
+
+/// A parsed `vortex.json`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Manifest {
+    /// Script to execute, relative to the manifest's directory
+    pub entrypoint: Option<String>,
+    #[serde(default)]
+    pub node_compat: bool,
+    /// Provide `require()` for bundled CommonJS packages (see `WorkerOptions::commonjs`)
+    #[serde(default)]
+    pub commonjs: bool,
+    #[serde(default)]
+    pub harden: bool,
+    #[serde(default)]
+    pub disable_eval: bool,
+    /// Run the script in strict mode (see `WorkerOptions::strict_mode`)
+    #[serde(default)]
+    pub strict_mode: bool,
+    /// Reject SQL with inline string literals (see `WorkerOptions::strict_sql`)
+    #[serde(default)]
+    pub strict_sql: bool,
+    /// Variables exposed through `process.env`
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub limits: ManifestLimits,
+    /// Hosts outbound requests may reach (same syntax as [`Permissions::net`])
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    #[serde(default)]
+    pub bindings: ManifestBindings,
+    /// `vortex.*` namespaces the function may use (see [`Permissions::apis`]);
+    /// all of them when absent
+    pub apis: Option<Vec<String>>,
+    /// JSX factory configuration for `.jsx`/`.tsx` modules
+    pub jsx: Option<JsxOptions>,
+    /// Directory the manifest was loaded from; relative paths resolve against it
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+impl Manifest {
+    /// Parse a manifest from JSON text.
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(|e| anyhow!("Invalid manifest: {}", e))
+    }
+
+    /// Read and parse a manifest file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read manifest '{}': {}", path.display(), e))?;
+        let mut manifest = Self::from_json(&contents)
+            .map_err(|e| anyhow!("{} ({})", e, path.display()))?;
+        manifest.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(manifest)
+    }
+
+    /// Absolute (or manifest-relative) path of the entrypoint, if declared.
+    pub fn entrypoint_path(&self) -> Option<PathBuf> {
+        self.entrypoint.as_ref().map(|entry| self.base_dir.join(entry))
+    }
+
+    /// Capabilities granted by `allowedHosts`, `bindings` and `apis`.
+    pub fn permissions(&self) -> Permissions {
+        Permissions {
+            net: self.allowed_hosts.clone(),
+            kv: self.bindings.kv,
+            sql: self.bindings.sql,
+            storage: self.bindings.storage,
+            apis: self.apis.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = Manifest::from_json(
+            r#"{
+                "entrypoint": "index.js",
+                "nodeCompat": true,
+                "env": { "MODE": "prod" },
+                "limits": { "maxPendingOps": 10 },
+                "allowedHosts": ["api.example.com"],
+                "bindings": { "kv": true },
+                "apis": ["kv", "crypto"],
+                "strictSql": true
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.entrypoint.as_deref(), Some("index.js"));
+        assert!(manifest.node_compat);
+        assert_eq!(manifest.env["MODE"], "prod");
+        assert_eq!(manifest.limits.max_pending_ops, Some(10));
+        assert!(manifest.strict_sql);
+
+        let permissions = manifest.permissions();
+        assert!(permissions.kv);
+        assert!(!permissions.sql);
+        assert_eq!(
+            permissions.apis,
+            Some(vec!["kv".to_string(), "crypto".to_string()])
+        );
+        assert!(permissions.allows_host("api.example.com"));
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let error = Manifest::from_json(r#"{ "limits": { "maxScriptSise": 10 } }"#).unwrap_err();
+        assert!(error.to_string().contains("maxScriptSise"));
+    }
+}
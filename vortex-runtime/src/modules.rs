@@ -0,0 +1,185 @@
+//! In-memory ES module loader.
+//!
+//! Multi-file functions are unpacked into memory (see [`crate::archive`]) and
+//! served to V8 from a `HashMap`, never from the host filesystem or network.
+//! Files are addressed as `file:///<path>`, so relative imports such as
+//! `import { x } from './lib/util.js'` resolve the usual way. TypeScript and
+//! JSX files are transpiled on load (see [`crate::transpile`]).
+//!
+//! With CommonJS interop enabled, bare specifiers resolve in `node_modules`
+//! and CommonJS files are wrapped as ES modules (see [`crate::commonjs`]).
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use deno_core::error::{type_error, AnyError};
+use deno_core::{
+    resolve_import, ModuleLoadResponse, ModuleLoader, ModuleSource, ModuleSourceCode,
+    ModuleSpecifier, ModuleType, RequestedModuleType, ResolutionKind,
+};
+
+use crate::commonjs::{self, ModuleFormat, IMPORT_CONDITIONS};
+use crate::transpile::{needs_transpile, transpile, JsxOptions};
+
+/// URL prefix under which bundled files are exposed.
+pub const MODULE_ROOT: &str = "file:///";
+
+/// Specifier for a bundled file path such as `lib/util.js`.
+pub fn module_url(path: &str) -> String {
+    format!("{}{}", MODULE_ROOT, path.trim_start_matches("./").trim_start_matches('/'))
+}
+
+/// Serves modules from an in-memory file map.
+pub struct MemoryModuleLoader {
+    files: Rc<HashMap<String, String>>,
+    jsx: JsxOptions,
+    commonjs: bool,
+}
+
+impl MemoryModuleLoader {
+    /// `files` maps bundle-relative paths (`index.js`, `lib/util.js`) to source.
+    pub fn new(files: HashMap<String, String>, jsx: JsxOptions) -> Self {
+        Self {
+            files: Rc::new(files),
+            jsx,
+            commonjs: false,
+        }
+    }
+
+    /// Resolve bare specifiers in `node_modules` and serve CommonJS files as
+    /// ES modules whose default export is their `module.exports`. Relies on
+    /// the global `require()` installed for `WorkerOptions::commonjs`.
+    pub fn with_commonjs(mut self, commonjs: bool) -> Self {
+        self.commonjs = commonjs;
+        self
+    }
+
+    /// The bundled files, shared with the ops behind `require()`.
+    pub fn files(&self) -> Rc<HashMap<String, String>> {
+        self.files.clone()
+    }
+}
+
+impl ModuleLoader for MemoryModuleLoader {
+    fn resolve(
+        &self,
+        specifier: &str,
+        referrer: &str,
+        _kind: ResolutionKind,
+    ) -> Result<ModuleSpecifier, AnyError> {
+        if self.commonjs && commonjs::is_bare(specifier) {
+            let referrer = referrer.strip_prefix(MODULE_ROOT).unwrap_or_default();
+            let path = commonjs::resolve(&self.files, specifier, referrer, IMPORT_CONDITIONS)
+                .map_err(|e| type_error(e.to_string()))?;
+            return Ok(ModuleSpecifier::parse(&module_url(&path))?);
+        }
+        let resolved = resolve_import(specifier, referrer)?;
+        if !resolved.as_str().starts_with(MODULE_ROOT) {
+            return Err(type_error(format!(
+                "Cannot import '{}': only modules bundled with the function can be imported",
+                specifier
+            )));
+        }
+        Ok(resolved)
+    }
+
+    fn load(
+        &self,
+        module_specifier: &ModuleSpecifier,
+        _maybe_referrer: Option<&ModuleSpecifier>,
+        _is_dyn_import: bool,
+        _requested_module_type: RequestedModuleType,
+    ) -> ModuleLoadResponse {
+        let path = &module_specifier.as_str()[MODULE_ROOT.len()..];
+        let Some(source) = self.files.get(path) else {
+            return ModuleLoadResponse::Sync(Err(type_error(format!(
+                "Module not found: '{}'",
+                path
+            ))));
+        };
+
+        if self.commonjs && commonjs::format(&self.files, path) == ModuleFormat::CommonJs {
+            let code = match serde_json::to_string(&format!("/{}", path)) {
+                Ok(path) => format!("export default require({});", path),
+                Err(e) => return ModuleLoadResponse::Sync(Err(e.into())),
+            };
+            return ModuleLoadResponse::Sync(Ok(ModuleSource::new(
+                ModuleType::JavaScript,
+                ModuleSourceCode::String(code.into()),
+                module_specifier,
+                None,
+            )));
+        }
+
+        let module_type = if path.ends_with(".json") {
+            ModuleType::Json
+        } else {
+            ModuleType::JavaScript
+        };
+        let code = if needs_transpile(path) {
+            match transpile(module_specifier, source, &self.jsx) {
+                Ok(code) => code,
+                Err(e) => return ModuleLoadResponse::Sync(Err(e)),
+            }
+        } else {
+            source.clone()
+        };
+        ModuleLoadResponse::Sync(Ok(ModuleSource::new(
+            module_type,
+            ModuleSourceCode::String(code.into()),
+            module_specifier,
+            None,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_relative_and_reject_remote() {
+        let loader = MemoryModuleLoader::new(HashMap::new(), JsxOptions::default());
+        let resolved = loader
+            .resolve("./util.js", "file:///lib/main.js", ResolutionKind::Import)
+            .unwrap();
+        assert_eq!(resolved.as_str(), "file:///lib/util.js");
+        assert!(loader
+            .resolve("https://example.com/x.js", "file:///main.js", ResolutionKind::Import)
+            .is_err());
+    }
+
+    #[test]
+    fn test_commonjs_imports() {
+        let files = HashMap::from([
+            (
+                "node_modules/ms/index.js".to_string(),
+                "module.exports = 1;".to_string(),
+            ),
+            ("util.js".to_string(), "export const x = 1;".to_string()),
+        ]);
+        let loader = MemoryModuleLoader::new(files.clone(), JsxOptions::default());
+        assert!(loader
+            .resolve("ms", "file:///main.js", ResolutionKind::Import)
+            .is_err());
+
+        let loader = MemoryModuleLoader::new(files, JsxOptions::default()).with_commonjs(true);
+        let resolved = loader
+            .resolve("ms", "file:///main.js", ResolutionKind::Import)
+            .unwrap();
+        assert_eq!(resolved.as_str(), "file:///node_modules/ms/index.js");
+        let error = loader
+            .resolve("left-pad", "file:///main.js", ResolutionKind::Import)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cannot find module 'left-pad' from 'main.js'"
+        );
+    }
+
+    #[test]
+    fn test_module_url() {
+        assert_eq!(module_url("./index.js"), "file:///index.js");
+        assert_eq!(module_url("lib/a.js"), "file:///lib/a.js");
+    }
+}
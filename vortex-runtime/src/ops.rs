@@ -2,9 +2,8 @@
 //!
 //! # Snapshot Compatibility
 //!
-//! These ops are designed to work in TWO contexts:
-//! 1. **Snapshot generation** (build.rs) - State is NOT present
-//! 2. **Runtime execution** (worker.rs) - State IS present
+//! The V8 snapshot built by build.rs doesn't include these ops: they are
+//! registered, with their state, when a worker is created (worker.rs).
 //!
 //! The ops use `OpState::try_borrow()` patterns to gracefully handle missing state.
 //!
@@ -18,28 +17,101 @@
 //! - Logs are still captured locally even if Redis is unavailable
 
 use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Utc};
-use deno_core::{op2, OpState};
+use deno_core::error::{custom_error, range_error, type_error, AnyError};
+use deno_core::url::{quirks, Url};
+use deno_core::{op2, CancelFuture, CancelHandle, JsBuffer, OpState, ResourceId};
+use flate2::write::{
+    DeflateDecoder, DeflateEncoder, GzDecoder, GzEncoder, ZlibDecoder, ZlibEncoder,
+};
+use flate2::Compression;
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use md5::Md5;
+use redis::aio::MultiplexedConnection;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
 use tokio::sync::mpsc;
 
+use crate::audit::{AuditLog, MAX_AUDIT_EVENT_BYTES};
+use crate::cache::{CachedResponse, ResponseCache};
+use crate::commonjs::{self, BundleFiles, ModuleFormat, REQUIRE_CONDITIONS};
+use crate::fetch::{
+    cache_mode, fetch_cache_ttl, served_headers, vary_headers, vary_matches, BlockedAddress,
+    CacheMode, EgressCounts, EgressMetrics, FetchCache, FetchClient, FetchOptions,
+};
+use crate::kv::{KvBackend, KvEntry, KvError, KvListPage, KvPut};
+use crate::logging::{history_key, LogFilterState, LogLevel, LogSink, RuntimeLogger};
+use crate::permissions::{check_net, check_permission, Capability, PERMISSION_DENIED_CLASS};
+use crate::postgres::{PooledConnection, PostgresChanges, PostgresError, PostgresPool};
+use crate::secrets::SecretsState;
+use crate::sql::{
+    check_migrations, find_literal, Migration, MigrationReport, DEFAULT_SQL_TRANSACTION_TIMEOUT,
+    MAX_SQL_TRANSACTION_TIMEOUT,
+};
+use crate::sqlite::{
+    SqliteChanges, SqliteDatabase, SqliteDatabases, SqliteError, SqliteTransaction,
+};
+
 /// A single log entry captured from JavaScript console methods.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogEntry {
+    /// Invocation that produced this entry; matches
+    /// `ExecutionResult::invocation_id`
+    #[serde(default)]
+    pub invocation_id: String,
+    /// Position of this entry within its invocation, starting at 0. Lets
+    /// consumers of the Redis stream detect gaps and restore ordering when
+    /// timestamps collide.
+    #[serde(default)]
+    pub seq: u64,
     /// UTC timestamp when the log was captured
     pub timestamp: DateTime<Utc>,
+    /// Milliseconds since the invocation started, from a monotonic clock.
+    /// Unlike `timestamp` this is unaffected by clock adjustments.
+    #[serde(default)]
+    pub elapsed_ms: u64,
+    /// Severity, from the console method that wrote the entry
+    #[serde(default)]
+    pub level: LogLevel,
     /// The log message content
     pub message: String,
+    /// Structured fields from `vortex.log(message, { fields })` or a trailing
+    /// object passed to `console.log`
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub fields: serde_json::Map<String, serde_json::Value>,
+    /// How many identical entries directly following this one were folded
+    /// into it by [`crate::WorkerOptions::coalesce_logs`]. Redis and log
+    /// listeners get the entry when it is first logged, so they see 0.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub repeated: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
 }
 
 impl LogEntry {
     /// Create a new log entry with the current timestamp
-    pub fn new(message: String) -> Self {
+    pub fn new(seq: u64, message: String) -> Self {
         Self {
+            invocation_id: String::new(),
+            seq,
             timestamp: Utc::now(),
+            elapsed_ms: 0,
+            level: LogLevel::Info,
             message,
+            fields: serde_json::Map::new(),
+            repeated: 0,
         }
     }
 }
@@ -47,19 +119,487 @@ impl LogEntry {
 /// Type alias for the log storage used in OpState
 pub type LogStorage = Rc<RefCell<Vec<LogEntry>>>;
 
+/// Per-invocation bookkeeping, reset by the worker at the start of each run.
+#[derive(Debug, Clone)]
+pub struct Invocation {
+    /// Correlates logs, Redis messages and the result of one run
+    pub id: String,
+    /// When the current invocation started
+    pub started_at: Instant,
+    /// Output set with `vortex.setResult()`, as JSON text. Takes the place
+    /// of the function's return value.
+    pub result: Option<String>,
+}
+
+impl Default for Invocation {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            started_at: Instant::now(),
+            result: None,
+        }
+    }
+}
+
+/// Type alias for the invocation state shared between the worker and ops
+pub type InvocationState = Rc<RefCell<Invocation>>;
+
+/// A piece of output streamed with `vortex.write()`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputChunk {
+    /// Invocation that wrote this chunk
+    #[serde(default)]
+    pub invocation_id: String,
+    /// Position of this chunk within its invocation, starting at 0
+    pub seq: u64,
+    /// The chunk contents; empty for the chunk that ends the stream
+    pub data: String,
+    /// Set on the last chunk, written by `vortex.end()` or when the
+    /// invocation finishes
+    #[serde(default)]
+    pub end: bool,
+    /// Status and headers of a `Response` the function returned, on the
+    /// chunk that starts its body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub head: Option<ResponseHead>,
+    /// The raw bytes of a chunk of a returned `Response`'s body, whose
+    /// `data` is empty. Only in-process sinks see them.
+    #[serde(skip)]
+    pub bytes: Option<Vec<u8>>,
+}
+
+/// Status and headers of a `Response` returned by the function, which is
+/// streamed out as output instead of being serialized.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseHead {
+    pub status: u16,
+    /// Header name and value pairs in order
+    pub headers: Vec<(String, String)>,
+}
+
+/// Chunks an output sink should hold before the body of a returned
+/// `Response` waits for it to catch up.
+pub const OUTPUT_CHANNEL_CAPACITY: usize = 16;
+
+/// Destination of streamed output, reset by the worker for each invocation.
+#[derive(Debug, Default)]
+pub struct OutputStream {
+    /// Receives chunks as they are written. When `None`, chunks are kept in
+    /// `buffered` and returned with the result instead.
+    pub sender: Option<mpsc::Sender<OutputChunk>>,
+    /// Chunks the sender had no room for yet. `vortex.write()` doesn't wait,
+    /// so they are held here, in order, until [`OutputStream::flush`].
+    backlog: VecDeque<OutputChunk>,
+    /// Chunks written without a sender
+    pub buffered: Vec<String>,
+    /// Number of chunks written so far
+    pub seq: u64,
+    /// Whether `vortex.end()` has been called
+    pub ended: bool,
+}
+
+impl OutputStream {
+    /// Prepare for a new invocation, keeping the sender.
+    pub fn reset(&mut self) {
+        self.backlog.clear();
+        self.buffered.clear();
+        self.seq = 0;
+        self.ended = false;
+    }
+
+    fn push(&mut self, invocation_id: String, data: String, end: bool) {
+        self.send(OutputChunk {
+            invocation_id,
+            seq: self.seq,
+            data,
+            end,
+            head: None,
+            bytes: None,
+        });
+    }
+
+    fn send(&mut self, chunk: OutputChunk) {
+        match &self.sender {
+            Some(_) if !self.backlog.is_empty() => self.backlog.push_back(chunk),
+            // A dropped receiver means nobody is listening any more
+            Some(sender) => {
+                if let Err(mpsc::error::TrySendError::Full(chunk)) = sender.try_send(chunk) {
+                    self.backlog.push_back(chunk);
+                }
+            }
+            None if !chunk.end && chunk.head.is_none() => self.buffered.push(match chunk.bytes {
+                Some(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                None => chunk.data,
+            }),
+            None => {}
+        }
+        self.seq += 1;
+    }
+
+    /// Wait until the sender has taken every chunk written so far.
+    pub async fn flush(output: &OutputStreamState) {
+        loop {
+            let sender = {
+                let output = output.borrow();
+                match &output.sender {
+                    Some(sender) if !output.backlog.is_empty() => sender.clone(),
+                    _ => return,
+                }
+            };
+            // Chunks are taken off the backlog only once there is room, so
+            // ones written meanwhile still queue up behind them
+            let Ok(permit) = sender.reserve_owned().await else {
+                output.borrow_mut().backlog.clear();
+                return;
+            };
+            if let Some(chunk) = output.borrow_mut().backlog.pop_front() {
+                permit.send(chunk);
+            }
+        }
+    }
+
+    /// Close the stream if the invocation wrote to it without calling
+    /// `vortex.end()`, so consumers always see a final chunk.
+    pub fn finish(&mut self, invocation_id: &str) {
+        if self.seq > 0 && !self.ended {
+            self.ended = true;
+            self.push(invocation_id.to_string(), String::new(), true);
+        }
+    }
+}
+
+/// Type alias for the output stream state shared between the worker and ops
+pub type OutputStreamState = Rc<RefCell<OutputStream>>;
+
 /// Redis publisher for real-time log streaming.
 /// Uses an unbounded mpsc channel for fire-and-forget publishing.
 pub struct RedisPublisher {
-    /// Sender channel to the background Redis publishing task
-    pub sender: mpsc::UnboundedSender<String>,
+    /// Sender channel to the background Redis publishing task; `None` when
+    /// entries only go to the log history
+    pub sender: Option<mpsc::UnboundedSender<String>>,
+    /// Function whose logs are published, included in every message
+    pub function_id: String,
+    /// Publishes error-level entries to the alert channel, if there is one
+    pub alert_sender: Option<mpsc::UnboundedSender<String>>,
+    /// Appends entries to the invocation's log history, if it is kept
+    pub history: Option<LogHistoryWriter>,
+}
+
+/// Sends log entries to the background task appending them to log history
+/// lists; see [`crate::logging::LogHistory`].
+pub struct LogHistoryWriter {
+    /// Key of an invocation's list, with `{invocation}` still to be filled in
+    pub key_template: String,
+    /// Pairs of list key and entry JSON
+    pub sender: mpsc::UnboundedSender<(String, String)>,
+}
+
+/// A log entry as published to Redis: the entry itself plus the function
+/// that wrote it, so stream consumers need no out-of-band correlation.
+#[derive(Serialize)]
+struct PublishedLogEntry<'a> {
+    function_id: &'a str,
+    #[serde(flatten)]
+    entry: &'a LogEntry,
 }
 
 /// Type alias for optional Redis publisher state
 pub type RedisPublisherState = Rc<RefCell<Option<RedisPublisher>>>;
 
+/// Called with every log entry as it is recorded.
+pub type LogHook = Arc<dyn Fn(&LogEntry) + Send + Sync>;
+
+/// Who hears about log entries as they are recorded, besides Redis.
+#[derive(Default)]
+pub struct LogListeners {
+    pub hooks: Vec<LogHook>,
+    /// Destinations from `WorkerOptions::log_sinks`
+    pub sinks: Vec<Arc<dyn LogSink>>,
+    /// Function the sinks are told wrote each entry
+    pub function_id: Option<String>,
+    /// Where sink errors are reported
+    pub runtime_logs: RuntimeLogger,
+    /// Channels handed out by `VortexWorker::log_receiver`; dropped once
+    /// their receiver is
+    pub senders: Vec<mpsc::Sender<LogEntry>>,
+}
+
+/// Type alias for the log listeners shared between the worker and ops
+pub type LogListenersState = Rc<RefCell<LogListeners>>;
+
+/// Worker configuration that the bootstrap JavaScript needs to see.
+///
+/// Stored in OpState by the worker and read once by bootstrap via
+/// `op_bootstrap_config`. When it is absent the defaults are used.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapConfig {
+    /// Install the Node.js compatibility shims (`process`, `node:*` builtins)
+    pub node_compat: bool,
+    /// Install the CommonJS `require()`; see [`crate::commonjs`]
+    pub commonjs: bool,
+    /// Environment variables exposed to user code as `process.env`
+    pub env: HashMap<String, String>,
+    /// Freeze all intrinsics once bootstrap has finished
+    pub harden: bool,
+    /// Replace `eval` with a stub that explains code generation is disabled
+    pub disable_code_generation: bool,
+    /// Maximum number of simultaneously active timers
+    pub max_pending_ops: Option<usize>,
+    /// Object ID of the actor this isolate is pinned to, if any; exposes
+    /// `vortex.actor`
+    pub actor_id: Option<String>,
+    /// `vortex.*` namespaces to expose, all of them if `None`; see
+    /// [`crate::Permissions::apis`]
+    pub apis: Option<Vec<String>>,
+    /// Leave the `Temporal` global in place
+    pub temporal: bool,
+}
+
+/// Return the bootstrap configuration for this worker.
+#[op2]
+#[serde]
+pub fn op_bootstrap_config(state: &OpState) -> BootstrapConfig {
+    state
+        .try_borrow::<BootstrapConfig>()
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// A bundled file loaded by `require()`.
+#[derive(Debug, Serialize)]
+pub struct RequiredSource {
+    pub format: ModuleFormat,
+    pub source: String,
+}
+
+/// Resolve a `require()` specifier from the bundled file `referrer` (empty
+/// for the function's own script) to a bundle path.
+#[op2]
+#[string]
+pub fn op_require_resolve(
+    state: &OpState,
+    #[string] specifier: String,
+    #[string] referrer: String,
+) -> Result<String, AnyError> {
+    let BundleFiles(files) = state
+        .try_borrow::<BundleFiles>()
+        .ok_or_else(|| type_error("require is not available"))?;
+    commonjs::resolve(files, &specifier, &referrer, REQUIRE_CONDITIONS)
+        .map_err(|e| type_error(e.to_string()))
+}
+
+/// Source and format of a bundle path returned by `op_require_resolve`.
+#[op2]
+#[serde]
+pub fn op_require_source(
+    state: &OpState,
+    #[string] path: String,
+) -> Result<RequiredSource, AnyError> {
+    let BundleFiles(files) = state
+        .try_borrow::<BundleFiles>()
+        .ok_or_else(|| type_error("require is not available"))?;
+    let source = files
+        .get(&path)
+        .ok_or_else(|| type_error(format!("Module not found: '{}'", path)))?;
+    Ok(RequiredSource {
+        format: commonjs::format(files, &path),
+        source: source.clone(),
+    })
+}
+
+/// Bookkeeping for in-flight async ops, stored in OpState.
+///
+/// Every async op takes a [`PendingOpGuard`] for its lifetime so that code like
+/// `for (;;) setTimeout(...)` fails fast instead of flooding the event loop.
+/// The guards also record what each op is doing, so an invocation that
+/// times out can say what it was still waiting for. Ops are labelled
+/// `vortex.<api>.<operation>`, web APIs included (`vortex.fetch.body`).
+#[derive(Debug, Default)]
+pub struct PendingOps {
+    /// Number of async ops currently in flight
+    pub count: usize,
+    /// Maximum number of async ops allowed in flight at once
+    pub limit: Option<usize>,
+    /// The ops in flight, by guard
+    in_flight: HashMap<u64, InFlightOp>,
+    next_id: u64,
+}
+
+/// An async op that hasn't finished yet.
+#[derive(Debug)]
+struct InFlightOp {
+    api: &'static str,
+    detail: Option<String>,
+    started_at: Instant,
+    /// Cancels the op when a new invocation starts (timers)
+    cancel: Option<Rc<CancelHandle>>,
+}
+
+/// Async ops of one kind that were still in flight, e.g. when an
+/// invocation timed out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingOp {
+    /// The op that started them, e.g. `vortex.fetch.request` or
+    /// `vortex.timers.sleep`
+    pub api: String,
+    /// What they were doing, e.g. the host a `fetch()` went to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// How many there were
+    pub count: usize,
+    /// How long the oldest of them had been running
+    pub oldest_ms: u64,
+}
+
+impl std::fmt::Display for PendingOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.api)?;
+        if let Some(detail) = &self.detail {
+            write!(f, " ({})", detail)?;
+        }
+        if self.count > 1 {
+            write!(f, " x{}, oldest {}ms", self.count, self.oldest_ms)
+        } else {
+            write!(f, " for {}ms", self.oldest_ms)
+        }
+    }
+}
+
+impl PendingOps {
+    /// The ops in flight, grouped by API and detail, longest-running first.
+    pub fn report(&self) -> Vec<PendingOp> {
+        let mut groups: HashMap<(&str, Option<&str>), PendingOp> = HashMap::new();
+        for op in self.in_flight.values() {
+            let age = op.started_at.elapsed().as_millis() as u64;
+            groups
+                .entry((op.api, op.detail.as_deref()))
+                .and_modify(|group| {
+                    group.count += 1;
+                    group.oldest_ms = group.oldest_ms.max(age);
+                })
+                .or_insert_with(|| PendingOp {
+                    api: op.api.to_string(),
+                    detail: op.detail.clone(),
+                    count: 1,
+                    oldest_ms: age,
+                });
+        }
+        let mut report: Vec<PendingOp> = groups.into_values().collect();
+        report.sort_by(|a, b| {
+            b.oldest_ms
+                .cmp(&a.oldest_ms)
+                .then_with(|| a.api.cmp(&b.api))
+                .then_with(|| a.detail.cmp(&b.detail))
+        });
+        report
+    }
+
+    /// Ops in flight that can still settle, i.e. not counting timers that
+    /// were cleared but haven't been polled since.
+    pub fn live(&self) -> usize {
+        self.in_flight
+            .values()
+            .filter(|op| {
+                !op.cancel
+                    .as_ref()
+                    .is_some_and(|cancel| cancel.is_canceled())
+            })
+            .count()
+    }
+
+    /// Start over for a new invocation on the same worker: cancel the timers
+    /// an earlier invocation left behind and stop counting its other ops,
+    /// which nothing awaits any more.
+    pub fn reset(&mut self) {
+        for op in self.in_flight.values() {
+            if let Some(cancel) = &op.cancel {
+                cancel.cancel();
+            }
+        }
+        self.in_flight.clear();
+        self.count = 0;
+    }
+}
+
+/// RAII guard that counts one in-flight async op against [`PendingOps`].
+pub struct PendingOpGuard {
+    state: Rc<RefCell<OpState>>,
+    id: u64,
+}
+
+impl PendingOpGuard {
+    /// Register a new in-flight op of `api`, failing if the limit is already
+    /// reached.
+    ///
+    /// Returns `Ok(None)` when no `PendingOps` is present (snapshot generation).
+    pub fn acquire(
+        state: &Rc<RefCell<OpState>>,
+        api: &'static str,
+    ) -> Result<Option<Self>, AnyError> {
+        let mut op_state = state.borrow_mut();
+        let Some(pending) = op_state.try_borrow_mut::<PendingOps>() else {
+            return Ok(None);
+        };
+        if let Some(limit) = pending.limit {
+            if pending.count >= limit {
+                return Err(range_error(format!(
+                    "Too many pending async operations (limit: {})",
+                    limit
+                )));
+            }
+        }
+        pending.count += 1;
+        pending.next_id += 1;
+        let id = pending.next_id;
+        pending.in_flight.insert(
+            id,
+            InFlightOp {
+                api,
+                detail: None,
+                started_at: Instant::now(),
+                cancel: None,
+            },
+        );
+        Ok(Some(Self {
+            state: state.clone(),
+            id,
+        }))
+    }
+
+    /// Say what the op is doing, for [`PendingOps::report`].
+    pub fn describe(&self, detail: impl Into<String>) {
+        if let Some(pending) = self.state.borrow_mut().try_borrow_mut::<PendingOps>() {
+            if let Some(op) = pending.in_flight.get_mut(&self.id) {
+                op.detail = Some(detail.into());
+            }
+        }
+    }
+
+    /// Cancel the op through `cancel` when [`PendingOps::reset`] runs.
+    pub fn cancel_on_reset(&self, cancel: Rc<CancelHandle>) {
+        if let Some(pending) = self.state.borrow_mut().try_borrow_mut::<PendingOps>() {
+            if let Some(op) = pending.in_flight.get_mut(&self.id) {
+                op.cancel = Some(cancel);
+            }
+        }
+    }
+}
+
+impl Drop for PendingOpGuard {
+    fn drop(&mut self) {
+        if let Some(pending) = self.state.borrow_mut().try_borrow_mut::<PendingOps>() {
+            // Ops from before a reset are no longer counted
+            if pending.in_flight.remove(&self.id).is_some() {
+                pending.count = pending.count.saturating_sub(1);
+            }
+        }
+    }
+}
+
 /// Custom operation to capture console.log messages.
 ///
-/// This op is called from JavaScript via `Deno.core.ops.op_log(message)`.
+/// This op is called from JavaScript via `Deno.core.ops.op_log(level, message)`.
 /// Instead of printing to stdout, it stores the message in our log buffer
 /// so it can be returned as part of the ExecutionResult.
 ///
@@ -71,12 +611,86 @@ pub type RedisPublisherState = Rc<RefCell<Option<RedisPublisher>>>;
 ///
 /// # Arguments
 /// * `state` - The operation state (may or may not contain our storage)
+/// * `level` - `debug`, `info`, `warn` or `error`; see [`LogLevel`]
 /// * `message` - The log message from JavaScript
 #[op2(fast)]
-pub fn op_log(state: &OpState, #[string] message: String) {
+pub fn op_log(state: &OpState, #[string] level: String, #[string] message: String) {
+    record_log(
+        state,
+        LogEntry {
+            level: LogLevel::parse(&level),
+            ..LogEntry::new(0, message)
+        },
+    );
+}
+
+/// Like [`op_log`], with structured fields passed as a JSON object string.
+///
+/// Fields that are not a JSON object are dropped rather than failing the log call.
+#[op2(fast)]
+pub fn op_log_structured(
+    state: &OpState,
+    #[string] level: String,
+    #[string] message: String,
+    #[string] fields: String,
+) {
+    let fields = match serde_json::from_str(&fields) {
+        Ok(serde_json::Value::Object(fields)) => fields,
+        _ => serde_json::Map::new(),
+    };
+    record_log(
+        state,
+        LogEntry {
+            level: LogLevel::parse(&level),
+            fields,
+            ..LogEntry::new(0, message)
+        },
+    );
+}
+
+/// Pass an entry through [`LogFilterState`] before emitting it.
+fn record_log(state: &OpState, entry: LogEntry) {
+    if let Some(filter) = state.try_borrow::<LogFilterState>() {
+        let mut filter = filter.borrow_mut();
+        if let Some(log_storage) = state.try_borrow::<LogStorage>() {
+            if let Some(last) = log_storage.borrow_mut().last_mut() {
+                if filter.coalesce(last, &entry) {
+                    return;
+                }
+            }
+        }
+        let (_, elapsed_ms) = invocation_clock(state);
+        if !filter.admit(elapsed_ms) {
+            return;
+        }
+    }
+    emit_log(state, entry);
+}
+
+/// ID of the current invocation and the milliseconds since it started.
+fn invocation_clock(state: &OpState) -> (String, u64) {
+    state
+        .try_borrow::<InvocationState>()
+        .map(|invocation| {
+            let invocation = invocation.borrow();
+            (
+                invocation.id.clone(),
+                invocation.started_at.elapsed().as_millis() as u64,
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Store a log entry and pass it on to Redis and log listeners, bypassing
+/// [`LogFilterState`]. Used for entries the runtime itself writes.
+///
+/// The entry's `seq`, `invocation_id` and `elapsed_ms` are filled in here.
+pub(crate) fn emit_log(state: &OpState, mut entry: LogEntry) {
     // Try to get LogStorage - may not exist during snapshot generation
     if let Some(log_storage) = state.try_borrow::<LogStorage>() {
-        let entry = LogEntry::new(message.clone());
+        // Storage is cleared per invocation, so its length is the next seq
+        entry.seq = log_storage.borrow().len() as u64;
+        (entry.invocation_id, entry.elapsed_ms) = invocation_clock(state);
         
         // Store locally for the ExecutionResult
         log_storage.borrow_mut().push(entry.clone());
@@ -85,16 +699,157 @@ pub fn op_log(state: &OpState, #[string] message: String) {
         if let Some(redis_pub) = state.try_borrow::<RedisPublisherState>() {
             // Fire-and-forget publish to Redis if configured
             if let Some(publisher) = redis_pub.borrow().as_ref() {
-                if let Ok(json) = serde_json::to_string(&entry) {
+                let published = PublishedLogEntry {
+                    function_id: &publisher.function_id,
+                    entry: &entry,
+                };
+                if let Ok(json) = serde_json::to_string(&published) {
                     // Ignore send errors - Redis publishing is best-effort
-                    let _ = publisher.sender.send(json);
+                    if let Some(alert_sender) = &publisher.alert_sender {
+                        if entry.level == LogLevel::Error {
+                            let _ = alert_sender.send(json.clone());
+                        }
+                    }
+                    if let Some(history) = &publisher.history {
+                        let key = history_key(&history.key_template, &entry.invocation_id);
+                        let _ = history.sender.send((key, json.clone()));
+                    }
+                    if let Some(sender) = &publisher.sender {
+                        let _ = sender.send(json);
+                    }
+                }
+            }
+        }
+
+        if let Some(listeners) = state.try_borrow::<LogListenersState>() {
+            let mut listeners = listeners.borrow_mut();
+            for hook in &listeners.hooks {
+                hook(&entry);
+            }
+            for sink in &listeners.sinks {
+                if let Err(e) = sink.write(listeners.function_id.as_deref(), &entry) {
+                    let message = format!("Log sink error (non-fatal): {}", e);
+                    listeners.runtime_logs.warn(message);
                 }
             }
+            // A receiver that falls behind misses entries (its consumer can
+            // tell from `seq`) rather than stalling the isolate
+            listeners.senders.retain(|sender| {
+                !matches!(
+                    sender.try_send(entry.clone()),
+                    Err(mpsc::error::TrySendError::Closed(_))
+                )
+            });
         }
     }
     // If no state, silently ignore (we're in snapshot generation)
 }
 
+/// Write a chunk of streamed output (`vortex.write(chunk)`).
+#[op2]
+pub fn op_write(state: &OpState, #[string] chunk: String) -> Result<(), AnyError> {
+    let Some(output) = state.try_borrow::<OutputStreamState>() else {
+        return Ok(());
+    };
+    let mut output = output.borrow_mut();
+    if output.ended {
+        return Err(type_error("vortex.write() called after vortex.end()"));
+    }
+    output.push(invocation_id(state), chunk, false);
+    Ok(())
+}
+
+/// End the streamed output (`vortex.end()`). Calling it again is a no-op.
+#[op2(fast)]
+pub fn op_end(state: &OpState) {
+    if let Some(output) = state.try_borrow::<OutputStreamState>() {
+        let mut output = output.borrow_mut();
+        if !output.ended {
+            output.ended = true;
+            output.push(invocation_id(state), String::new(), true);
+        }
+    }
+}
+
+/// Start streaming a `Response` the function returned with its status and
+/// headers. It has to be the first output of the invocation.
+#[op2]
+pub fn op_response_head(
+    state: &OpState,
+    #[smi] status: u32,
+    #[serde] headers: Vec<(String, String)>,
+) -> Result<(), AnyError> {
+    let Some(output) = state.try_borrow::<OutputStreamState>() else {
+        return Ok(());
+    };
+    let mut output = output.borrow_mut();
+    if output.seq > 0 {
+        return Err(type_error(
+            "A Response can't be returned after output was written with vortex.write()",
+        ));
+    }
+    let status = u16::try_from(status)
+        .map_err(|_| type_error(format!("Invalid response status: {}", status)))?;
+    let chunk = OutputChunk {
+        invocation_id: invocation_id(state),
+        seq: output.seq,
+        data: String::new(),
+        end: false,
+        head: Some(ResponseHead { status, headers }),
+        bytes: None,
+    };
+    output.send(chunk);
+    Ok(())
+}
+
+/// Write a chunk of a returned `Response`'s body, resolving once the sink
+/// has room for it so a slow client holds the body back.
+#[op2(async)]
+pub async fn op_response_write(
+    state: Rc<RefCell<OpState>>,
+    #[buffer] chunk: JsBuffer,
+) -> Result<(), AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "Response.body")?;
+    let output = {
+        let state = state.borrow();
+        let Some(output) = state.try_borrow::<OutputStreamState>() else {
+            return Ok(());
+        };
+        let mut stream = output.borrow_mut();
+        if stream.ended {
+            return Err(type_error("Response body written after the output ended"));
+        }
+        let chunk = OutputChunk {
+            invocation_id: invocation_id(&state),
+            seq: stream.seq,
+            data: String::new(),
+            end: false,
+            head: None,
+            bytes: Some(chunk.to_vec()),
+        };
+        stream.send(chunk);
+        output.clone()
+    };
+    OutputStream::flush(&output).await;
+    Ok(())
+}
+
+/// Set the invocation's output (`vortex.setResult()`), already serialized to
+/// JSON. The last call wins.
+#[op2(fast)]
+pub fn op_set_result(state: &OpState, #[string] json: String) {
+    if let Some(invocation) = state.try_borrow::<InvocationState>() {
+        invocation.borrow_mut().result = Some(json);
+    }
+}
+
+fn invocation_id(state: &OpState) -> String {
+    state
+        .try_borrow::<InvocationState>()
+        .map(|invocation| invocation.borrow().id.clone())
+        .unwrap_or_default()
+}
+
 /// Get the current time in milliseconds since Unix epoch.
 ///
 /// This op supports timing operations in JavaScript.
@@ -113,51 +868,3829 @@ pub fn op_get_time_ms() -> f64 {
 /// The tokio runtime will properly yield the thread during the sleep,
 /// allowing thousands of concurrent tenants without burning CPU cycles.
 ///
+/// Each timer owns a cancel handle resource; `clearTimeout()` closes it,
+/// which ends the sleep and releases its pending op right away. Resolves to
+/// whether the full delay elapsed, so a cancelled timer never rejects.
+///
 /// # Arguments
+/// * `state` - The operation state, used to enforce the pending op limit
 /// * `delay_ms` - The number of milliseconds to sleep
+/// * `cancel_rid` - The timer's cancel handle, from `core.createCancelHandle()`
 #[op2(async)]
-pub async fn op_sleep(#[bigint] delay_ms: u64) {
-    tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+pub async fn op_sleep(
+    state: Rc<RefCell<OpState>>,
+    #[bigint] delay_ms: u64,
+    #[smi] cancel_rid: ResourceId,
+) -> Result<bool, AnyError> {
+    // Already closed: the timer was cleared before it started
+    let Ok(cancel) = state
+        .borrow()
+        .resource_table
+        .get::<CancelHandle>(cancel_rid)
+    else {
+        return Ok(false);
+    };
+    let guard = PendingOpGuard::acquire(&state, "vortex.timers.sleep")?;
+    if let Some(guard) = &guard {
+        guard.describe(format!("{}ms", delay_ms));
+        guard.cancel_on_reset(cancel.clone());
+    }
+    let slept = tokio::time::sleep(std::time::Duration::from_millis(delay_ms))
+        .or_cancel(cancel)
+        .await;
+    Ok(slept.is_ok())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Fill a buffer with cryptographically secure random bytes.
+///
+/// Backs `crypto.getRandomValues()` and `crypto.randomUUID()` in bootstrap.
+/// The JavaScript side enforces the 65536-byte quota from the Web Crypto spec.
+#[op2]
+pub fn op_get_random_values(#[buffer] buf: &mut [u8]) -> Result<(), AnyError> {
+    rand::rngs::OsRng.try_fill_bytes(buf)?;
+    Ok(())
+}
 
-    #[test]
-    fn test_log_entry_creation() {
-        let entry = LogEntry::new("test message".to_string());
-        assert_eq!(entry.message, "test message");
-        // Timestamp should be recent (within last second)
-        let now = Utc::now();
-        let diff = now.signed_duration_since(entry.timestamp);
-        assert!(diff.num_seconds() < 1);
+/// Compute a message digest for the `node:crypto` `createHash()` shim.
+///
+/// # Arguments
+/// * `algorithm` - One of `md5`, `sha1`, `sha256`, `sha384`, `sha512`
+/// * `data` - The bytes to hash
+#[op2]
+#[buffer]
+pub fn op_crypto_hash(#[string] algorithm: String, #[buffer] data: &[u8]) -> Result<Vec<u8>, AnyError> {
+    let digest = match algorithm.to_ascii_lowercase().as_str() {
+        "md5" => Md5::digest(data).to_vec(),
+        "sha1" => Sha1::digest(data).to_vec(),
+        "sha256" => Sha256::digest(data).to_vec(),
+        "sha384" => Sha384::digest(data).to_vec(),
+        "sha512" => Sha512::digest(data).to_vec(),
+        other => return Err(type_error(format!("Digest method not supported: '{}'", other))),
+    };
+    Ok(digest)
+}
+
+/// Resolve a secret by name through the worker's secrets provider.
+fn secret(state: &OpState, name: &str) -> Result<Vec<u8>, AnyError> {
+    state
+        .try_borrow::<SecretsState>()
+        .and_then(Option::as_ref)
+        .and_then(|secrets| secrets.get(name))
+        .ok_or_else(|| type_error(format!("Unknown secret: '{}'", name)))
+}
+
+/// Compute `HMAC(secret, payload)` and compare it with `signature` in constant time.
+fn hmac_matches<M: Mac + KeyInit>(secret: &[u8], payload: &[u8], signature: &[u8]) -> bool {
+    let Ok(mut mac) = <M as Mac>::new_from_slice(secret) else {
+        return false;
+    };
+    Mac::update(&mut mac, payload);
+    mac.verify_slice(signature).is_ok()
+}
+
+/// Verify a webhook-style HMAC signature against a host-held secret.
+///
+/// The secret is looked up by name in the worker's secrets provider, so its
+/// value never reaches JavaScript; only the verdict does. The comparison is
+/// constant-time.
+///
+/// # Arguments
+/// * `secret_ref` - Name of the secret in the worker's [`SecretsProvider`](crate::secrets::SecretsProvider)
+/// * `payload` - The signed bytes, e.g. the raw request body
+/// * `signature` - The decoded signature (the JavaScript side accepts hex or base64)
+/// * `algorithm` - One of `sha1`, `sha256`, `sha384`, `sha512`
+#[op2(fast)]
+pub fn op_crypto_verify_hmac(
+    state: &OpState,
+    #[string] secret_ref: String,
+    #[buffer] payload: &[u8],
+    #[buffer] signature: &[u8],
+    #[string] algorithm: String,
+) -> Result<bool, AnyError> {
+    let secret = secret(state, &secret_ref)?;
+    let valid = match algorithm.to_ascii_lowercase().as_str() {
+        "sha1" => hmac_matches::<Hmac<Sha1>>(&secret, payload, signature),
+        "sha256" => hmac_matches::<Hmac<Sha256>>(&secret, payload, signature),
+        "sha384" => hmac_matches::<Hmac<Sha384>>(&secret, payload, signature),
+        "sha512" => hmac_matches::<Hmac<Sha512>>(&secret, payload, signature),
+        other => {
+            return Err(type_error(format!(
+                "HMAC algorithm not supported: '{}'",
+                other
+            )))
+        }
+    };
+    Ok(valid)
+}
+
+/// Compute `HMAC(secret, payload)`.
+fn hmac_sign<M: Mac + KeyInit>(secret: &[u8], payload: &[u8]) -> Vec<u8> {
+    // HMAC accepts keys of any length
+    let mut mac = <M as Mac>::new_from_slice(secret).expect("HMAC takes any key size");
+    Mac::update(&mut mac, payload);
+    Mac::finalize(mac).into_bytes().to_vec()
+}
+
+/// Sign `payload` with a host-held secret, e.g. for `vortex.cookies.sign()`.
+///
+/// Like [`op_crypto_verify_hmac`], only the result of the computation is
+/// returned to JavaScript, never the secret.
+#[op2]
+#[buffer]
+pub fn op_crypto_sign_hmac(
+    state: &OpState,
+    #[string] secret_ref: String,
+    #[buffer] payload: &[u8],
+    #[string] algorithm: String,
+) -> Result<Vec<u8>, AnyError> {
+    let secret = secret(state, &secret_ref)?;
+    match algorithm.to_ascii_lowercase().as_str() {
+        "sha1" => Ok(hmac_sign::<Hmac<Sha1>>(&secret, payload)),
+        "sha256" => Ok(hmac_sign::<Hmac<Sha256>>(&secret, payload)),
+        "sha384" => Ok(hmac_sign::<Hmac<Sha384>>(&secret, payload)),
+        "sha512" => Ok(hmac_sign::<Hmac<Sha512>>(&secret, payload)),
+        other => Err(type_error(format!("HMAC algorithm not supported: '{}'", other))),
     }
+}
 
-    #[test]
-    fn test_get_time_ms_logic() {
-        // Test the underlying time logic (can't call op-decorated function directly)
-        let get_time = || {
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map(|d| d.as_millis() as f64)
-                .unwrap_or(0.0)
-        };
-        
-        let time1 = get_time();
-        std::thread::sleep(std::time::Duration::from_millis(10));
-        let time2 = get_time();
-        assert!(time2 > time1);
-        assert!(time2 - time1 >= 10.0);
+/// JavaScript error class for tokens that fail `vortex.jwt.verify()`.
+pub const JWT_ERROR_CLASS: &str = "JwtError";
+
+/// Options for `vortex.jwt.sign()`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JwtSignOptions {
+    /// Name of the secret holding the HMAC key or PEM private key
+    pub key: String,
+    pub algorithm: String,
+    /// Written to the header's `kid`
+    #[serde(default)]
+    pub key_id: Option<String>,
+}
+
+/// Options for `vortex.jwt.verify()`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JwtVerifyOptions {
+    /// Name of the secret holding the HMAC key or PEM public key
+    pub key: String,
+    /// Accepted `alg` values; HMAC and public-key algorithms cannot be mixed
+    pub algorithms: Vec<String>,
+    /// Accepted `aud` values; the claim is not checked when empty
+    #[serde(default)]
+    pub audience: Vec<String>,
+    /// Accepted `iss` values; the claim is not checked when empty
+    #[serde(default)]
+    pub issuer: Vec<String>,
+    /// Clock skew tolerated for `exp` and `nbf`, in seconds
+    #[serde(default)]
+    pub leeway: u64,
+    /// Reject tokens without an `exp` claim
+    pub require_exp: bool,
+}
+
+/// The algorithms `vortex.jwt` supports.
+fn jwt_algorithm(name: &str) -> Result<Algorithm, AnyError> {
+    match name {
+        "HS256" => Ok(Algorithm::HS256),
+        "RS256" => Ok(Algorithm::RS256),
+        "ES256" => Ok(Algorithm::ES256),
+        other => Err(type_error(format!(
+            "JWT algorithm not supported: '{}'",
+            other
+        ))),
     }
+}
 
-    #[tokio::test]
-    async fn test_op_sleep_logic() {
-        // Test the underlying sleep logic
-        let start = std::time::Instant::now();
-        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
-        let elapsed = start.elapsed().as_millis();
-        assert!(elapsed >= 50);
-        assert!(elapsed < 100); // Should be reasonably close
+/// Sign `claims` as a compact JWS with a key from the secrets provider.
+#[op2]
+#[string]
+pub fn op_jwt_sign(
+    state: &OpState,
+    #[serde] claims: serde_json::Value,
+    #[serde] options: JwtSignOptions,
+) -> Result<String, AnyError> {
+    let algorithm = jwt_algorithm(&options.algorithm)?;
+    let key = secret(state, &options.key)?;
+    let invalid_key = |e: jsonwebtoken::errors::Error| {
+        type_error(format!(
+            "Secret '{}' is not a valid {:?} key: {}",
+            options.key, algorithm, e
+        ))
+    };
+    let key = match algorithm {
+        Algorithm::RS256 => EncodingKey::from_rsa_pem(&key).map_err(invalid_key)?,
+        Algorithm::ES256 => EncodingKey::from_ec_pem(&key).map_err(invalid_key)?,
+        _ => EncodingKey::from_secret(&key),
+    };
+    let header = Header {
+        kid: options.key_id,
+        ..Header::new(algorithm)
+    };
+    jsonwebtoken::encode(&header, &claims, &key).map_err(|e| type_error(e.to_string()))
+}
+
+/// Verify a compact JWS and return its claims, throwing a `JwtError` if the
+/// signature, algorithm or registered claims don't check out.
+#[op2]
+#[serde]
+pub fn op_jwt_verify(
+    state: &OpState,
+    #[string] token: String,
+    #[serde] options: JwtVerifyOptions,
+) -> Result<serde_json::Value, AnyError> {
+    let algorithms = options
+        .algorithms
+        .iter()
+        .map(|name| jwt_algorithm(name))
+        .collect::<Result<Vec<_>, _>>()?;
+    if algorithms.is_empty() {
+        return Err(type_error("vortex.jwt.verify: no algorithms allowed"));
+    }
+    // With one key for several families, an RS256 public key would double as
+    // an HS256 secret, letting anyone who knows it forge tokens
+    if algorithms.contains(&Algorithm::HS256) && algorithms.len() > 1 {
+        return Err(type_error(
+            "vortex.jwt.verify: HS256 cannot be combined with public-key algorithms",
+        ));
+    }
+
+    let invalid = |e: jsonwebtoken::errors::Error| {
+        custom_error(JWT_ERROR_CLASS, format!("Invalid token: {}", e))
+    };
+    let header = jsonwebtoken::decode_header(&token).map_err(invalid)?;
+    if !algorithms.contains(&header.alg) {
+        return Err(custom_error(
+            JWT_ERROR_CLASS,
+            format!("Invalid token: algorithm {:?} is not allowed", header.alg),
+        ));
+    }
+
+    let key = secret(state, &options.key)?;
+    let invalid_key = |e: jsonwebtoken::errors::Error| {
+        type_error(format!(
+            "Secret '{}' is not a valid {:?} key: {}",
+            options.key, header.alg, e
+        ))
+    };
+    let key = match header.alg {
+        Algorithm::RS256 => DecodingKey::from_rsa_pem(&key).map_err(invalid_key)?,
+        Algorithm::ES256 => DecodingKey::from_ec_pem(&key).map_err(invalid_key)?,
+        _ => DecodingKey::from_secret(&key),
+    };
+
+    // `alg` was checked against the allowed list above
+    let mut validation = Validation::new(header.alg);
+    validation.leeway = options.leeway;
+    validation.required_spec_claims.clear();
+    if options.require_exp {
+        validation.set_required_spec_claims(&["exp"]);
+    }
+    if options.audience.is_empty() {
+        validation.validate_aud = false;
+    } else {
+        validation.set_audience(&options.audience);
+    }
+    if !options.issuer.is_empty() {
+        validation.set_issuer(&options.issuer);
+    }
+
+    jsonwebtoken::decode::<serde_json::Value>(&token, &key, &validation)
+        .map(|data| data.claims)
+        .map_err(invalid)
+}
+
+/// Redis connection for ops that coordinate across invocations and hosts
+/// (`vortex.ratelimit`, `vortex.lock`).
+///
+/// Keys are namespaced by function ID so tenants sharing a Redis instance
+/// can't see, exhaust or hold each other's limits and locks.
+pub struct RedisStore {
+    client: redis::Client,
+    /// Opened on first use and shared by all ops of the worker
+    connection: Option<MultiplexedConnection>,
+    namespace: String,
+}
+
+impl RedisStore {
+    pub fn new(client: redis::Client, namespace: impl Into<String>) -> Self {
+        Self {
+            client,
+            connection: None,
+            namespace: namespace.into(),
+        }
+    }
+
+    /// Redis key for the user-supplied `key` of `kind` (e.g. `ratelimit`).
+    fn key(&self, kind: &str, key: &str) -> String {
+        format!("vortex:{}:{}:{}", kind, self.namespace, key)
+    }
+}
+
+/// Type alias for the optional Redis store shared between the worker and ops
+pub type RedisStoreState = Rc<RefCell<Option<RedisStore>>>;
+
+/// Get a connection to the worker's Redis store and the namespaced key for
+/// `key`, connecting on first use.
+///
+/// Every op that reaches Redis comes through here, so this is where its
+/// `capability` is checked.
+async fn redis_key(
+    state: &Rc<RefCell<OpState>>,
+    capability: Capability,
+    api: &str,
+    kind: &str,
+    key: &str,
+) -> Result<(MultiplexedConnection, String), AnyError> {
+    let store = {
+        let state = state.borrow();
+        check_permission(&state, capability)?;
+        state
+            .try_borrow::<RedisStoreState>()
+            .cloned()
+            .ok_or_else(|| redis_unavailable(api))?
+    };
+    let (client, cached, key) = match store.borrow().as_ref() {
+        Some(store) => (
+            store.client.clone(),
+            store.connection.clone(),
+            store.key(kind, key),
+        ),
+        None => return Err(redis_unavailable(api)),
+    };
+    if let Some(connection) = cached {
+        return Ok((connection, key));
+    }
+
+    let connection = client
+        .get_multiplexed_async_connection()
+        .await
+        .map_err(|e| AnyError::msg(format!("{}: failed to connect to Redis: {}", api, e)))?;
+    if let Some(store) = store.borrow_mut().as_mut() {
+        store.connection = Some(connection.clone());
+    }
+    Ok((connection, key))
+}
+
+fn redis_unavailable(api: &str) -> AnyError {
+    type_error(format!(
+        "{} requires Redis, which is not configured for this function",
+        api
+    ))
+}
+
+/// Outcome of `vortex.ratelimit.check()`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    /// Requests left in the current window after this one
+    pub remaining: u64,
+    /// When the next request will be allowed, if this one was not
+    pub retry_after_ms: u64,
+}
+
+/// Sliding-window log: one sorted-set member per allowed request, scored by
+/// Redis server time so hosts with skewed clocks agree on the window.
+const RATE_LIMIT_SCRIPT: &str = r#"
+local key = KEYS[1]
+local limit = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+redis.call('ZREMRANGEBYSCORE', key, '-inf', now - window)
+local count = redis.call('ZCARD', key)
+if count < limit then
+    redis.call('ZADD', key, now, now .. ':' .. ARGV[3])
+    redis.call('PEXPIRE', key, window)
+    return {1, limit - count - 1, 0}
+end
+local oldest = redis.call('ZRANGE', key, 0, 0, 'WITHSCORES')
+if oldest[2] == nil then
+    return {0, 0, window}
+end
+return {0, 0, math.max(tonumber(oldest[2]) + window - now, 0)}
+"#;
+
+/// Count a request against `key`, allowing at most `limit` per `window_ms`.
+#[op2(async)]
+#[serde]
+pub async fn op_ratelimit_check(
+    state: Rc<RefCell<OpState>>,
+    #[string] key: String,
+    #[bigint] limit: u64,
+    #[bigint] window_ms: u64,
+) -> Result<RateLimitDecision, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.ratelimit.check")?;
+    if window_ms == 0 {
+        return Err(range_error(
+            "vortex.ratelimit.check: window must be positive",
+        ));
+    }
+    let (mut connection, key) = redis_key(
+        &state,
+        Capability::Kv,
+        "vortex.ratelimit",
+        "ratelimit",
+        &key,
+    )
+    .await?;
+    let (allowed, remaining, retry_after_ms): (u8, u64, u64) =
+        redis::Script::new(RATE_LIMIT_SCRIPT)
+            .key(key)
+            .arg(limit)
+            .arg(window_ms)
+            .arg(rand::random::<u64>())
+            .invoke_async(&mut connection)
+            .await
+            .map_err(|e| AnyError::msg(format!("vortex.ratelimit: {}", e)))?;
+    Ok(RateLimitDecision {
+        allowed: allowed == 1,
+        remaining,
+        retry_after_ms,
+    })
+}
+
+/// Take the lock `name` for `ttl_ms` unless someone else holds it.
+///
+/// Returns the token that proves ownership on release, or `None` if the lock
+/// is taken. The TTL bounds how long a crashed holder can block others.
+#[op2(async)]
+#[string]
+pub async fn op_lock_acquire(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+    #[bigint] ttl_ms: u64,
+) -> Result<Option<String>, AnyError> {
+    let guard = PendingOpGuard::acquire(&state, "vortex.lock.acquire")?;
+    if let Some(guard) = &guard {
+        guard.describe(name.as_str());
+    }
+    if ttl_ms == 0 {
+        return Err(range_error("vortex.lock.acquire: ttl must be positive"));
+    }
+    let (mut connection, key) =
+        redis_key(&state, Capability::Kv, "vortex.lock", "lock", &name).await?;
+    let mut token = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut token);
+    let token: String = token.iter().map(|b| format!("{:02x}", b)).collect();
+
+    let acquired: Option<String> = redis::cmd("SET")
+        .arg(&key)
+        .arg(&token)
+        .arg("NX")
+        .arg("PX")
+        .arg(ttl_ms)
+        .query_async(&mut connection)
+        .await
+        .map_err(|e| AnyError::msg(format!("vortex.lock: {}", e)))?;
+    Ok(acquired.map(|_| token))
+}
+
+/// Delete the lock only if it still holds our token, so a holder whose TTL
+/// ran out can't release a lock someone else has since acquired.
+const LOCK_RELEASE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+
+/// Release the lock `name` acquired with `token`. Returns `false` if it had
+/// already expired or been taken over.
+#[op2(async)]
+pub async fn op_lock_release(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+    #[string] token: String,
+) -> Result<bool, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.lock.release")?;
+    let (mut connection, key) =
+        redis_key(&state, Capability::Kv, "vortex.lock", "lock", &name).await?;
+    let released: u8 = redis::Script::new(LOCK_RELEASE_SCRIPT)
+        .key(key)
+        .arg(token)
+        .invoke_async(&mut connection)
+        .await
+        .map_err(|e| AnyError::msg(format!("vortex.lock: {}", e)))?;
+    Ok(released == 1)
+}
+
+/// Largest key `vortex.kv` accepts, in bytes.
+pub const MAX_KV_KEY_BYTES: usize = 512;
+
+/// Largest value `vortex.kv.put()` accepts, as JSON, in bytes.
+pub const MAX_KV_VALUE_BYTES: usize = 1024 * 1024;
+
+/// Most keys one page of `vortex.kv.list()` returns.
+pub const MAX_KV_LIST_LIMIT: u32 = 1000;
+
+/// The KV backend of a worker and the namespace (function ID) its keys
+/// live in.
+#[derive(Debug, Clone, Default)]
+pub struct KvStore {
+    pub backend: Option<Arc<dyn KvBackend>>,
+    pub namespace: String,
+}
+
+/// The worker's KV backend and namespace.
+fn kv_store(state: &OpState) -> Result<(Arc<dyn KvBackend>, String), AnyError> {
+    check_permission(state, Capability::Kv)?;
+    match state.try_borrow::<KvStore>() {
+        Some(KvStore {
+            backend: Some(backend),
+            namespace,
+        }) => Ok((backend.clone(), namespace.clone())),
+        _ => Err(type_error(
+            "vortex.kv requires Redis or a KV backend, which is not configured for this function",
+        )),
+    }
+}
+
+fn check_kv_key(key: &str) -> Result<(), AnyError> {
+    if key.is_empty() || key.len() > MAX_KV_KEY_BYTES {
+        return Err(range_error(format!(
+            "vortex.kv: keys must be 1 to {} bytes long",
+            MAX_KV_KEY_BYTES
+        )));
+    }
+    Ok(())
+}
+
+fn kv_error(e: KvError) -> AnyError {
+    match e {
+        KvError::NotAnInteger => type_error(format!("vortex.kv.incr: {}", e)),
+        KvError::Overflow => range_error(format!("vortex.kv.incr: {}", e)),
+        KvError::Backend(_) => AnyError::msg(format!("vortex.kv: {}", e)),
+    }
+}
+
+/// `vortex.kv.get()` and `getWithRevision()`: the entry stored under `key`,
+/// if any.
+#[op2(async)]
+#[serde]
+pub async fn op_kv_get(
+    state: Rc<RefCell<OpState>>,
+    #[string] key: String,
+) -> Result<Option<KvEntry>, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.kv.get")?;
+    check_kv_key(&key)?;
+    let (backend, namespace) = kv_store(&state.borrow())?;
+    backend.get(&namespace, &key).await.map_err(kv_error)
+}
+
+/// `vortex.kv.put()` and `putIfMatch()`: store `value` (JSON text) under
+/// `key` for `ttl_ms` (0: until deleted). With `expected`, only if the key
+/// is still at that revision (0: doesn't exist). Returns the new revision,
+/// or `None` if the key had moved on.
+#[op2(async)]
+#[serde]
+pub async fn op_kv_put(
+    state: Rc<RefCell<OpState>>,
+    #[string] key: String,
+    #[string] value: String,
+    #[bigint] ttl_ms: u64,
+    #[serde] expected: Option<u64>,
+) -> Result<Option<u64>, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.kv.put")?;
+    check_kv_key(&key)?;
+    if value.len() > MAX_KV_VALUE_BYTES {
+        return Err(range_error(format!(
+            "vortex.kv.put: value is larger than {} bytes",
+            MAX_KV_VALUE_BYTES
+        )));
+    }
+    let (backend, namespace) = kv_store(&state.borrow())?;
+    let options = KvPut {
+        ttl: (ttl_ms > 0).then(|| Duration::from_millis(ttl_ms)),
+        expected,
+    };
+    backend
+        .put(&namespace, &key, value, options)
+        .await
+        .map_err(kv_error)
+}
+
+/// `vortex.kv.incr()`: atomically add `delta` to the integer under `key`
+/// and return the result.
+#[op2(async)]
+#[serde]
+pub async fn op_kv_incr(
+    state: Rc<RefCell<OpState>>,
+    #[string] key: String,
+    #[bigint] delta: i64,
+) -> Result<i64, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.kv.incr")?;
+    check_kv_key(&key)?;
+    let (backend, namespace) = kv_store(&state.borrow())?;
+    backend
+        .incr(&namespace, &key, delta)
+        .await
+        .map_err(kv_error)
+}
+
+/// `vortex.kv.delete()`: returns whether `key` existed.
+#[op2(async)]
+pub async fn op_kv_delete(
+    state: Rc<RefCell<OpState>>,
+    #[string] key: String,
+) -> Result<bool, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.kv.delete")?;
+    check_kv_key(&key)?;
+    let (backend, namespace) = kv_store(&state.borrow())?;
+    backend.delete(&namespace, &key).await.map_err(kv_error)
+}
+
+/// `vortex.kv.list()`: up to `limit` keys starting with `prefix`, in order,
+/// after `cursor` (the last key of the previous page) if given.
+#[op2(async)]
+#[serde]
+pub async fn op_kv_list(
+    state: Rc<RefCell<OpState>>,
+    #[string] prefix: String,
+    limit: u32,
+    #[serde] cursor: Option<String>,
+) -> Result<KvListPage, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.kv.list")?;
+    let limit = limit.clamp(1, MAX_KV_LIST_LIMIT) as usize;
+    let (backend, namespace) = kv_store(&state.borrow())?;
+    backend
+        .list(&namespace, &prefix, cursor.as_deref(), limit)
+        .await
+        .map_err(kv_error)
+}
+
+/// The host's SQL databases and the function whose database a worker uses.
+#[derive(Debug, Clone, Default)]
+pub struct SqlStore {
+    /// Behind `vortex.sqlite`, and `vortex.sql` when there is no Postgres
+    pub sqlite: Option<SqliteDatabases>,
+    /// Behind `vortex.sql`
+    pub postgres: Option<PostgresPool>,
+    pub function_id: String,
+    /// Reject statements with inline string literals; see [`find_literal`]
+    pub strict: bool,
+}
+
+/// The worker's SQLite database, opened on first use. `api` is the API
+/// that needs it, for errors.
+async fn sqlite_database(
+    state: &Rc<RefCell<OpState>>,
+    api: &str,
+) -> Result<SqliteDatabase, AnyError> {
+    let (databases, function_id) = {
+        let state = state.borrow();
+        check_permission(&state, Capability::Sql)?;
+        let held = state
+            .try_borrow::<SqlTransactionsState>()
+            .is_some_and(|transactions| transactions.borrow().holds_sqlite());
+        if held {
+            // Waiting for the database would wait for ourselves
+            return Err(type_error(format!(
+                "{} can't be used while a transaction holds the database; use the transaction's tx.query() and tx.execute()",
+                api
+            )));
+        }
+        match state.try_borrow::<SqlStore>() {
+            Some(SqlStore {
+                sqlite: Some(databases),
+                function_id,
+                ..
+            }) => (databases.clone(), function_id.clone()),
+            _ => {
+                return Err(type_error(format!(
+                    "{} requires a database directory or in-memory databases, which are not configured for this function",
+                    api
+                )))
+            }
+        }
+    };
+    databases
+        .open(&function_id)
+        .await
+        .map_err(|e| sqlite_error(api, e))
+}
+
+/// The database behind `vortex.sql`: a pooled Postgres connection when the
+/// host has a pool, the function's SQLite database otherwise.
+enum SqlDatabase {
+    Postgres(PooledConnection),
+    Sqlite(SqliteDatabase),
+}
+
+/// Rows inserted, updated or deleted by `vortex.sql.execute()`, plus the
+/// last rowid on SQLite.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum SqlChanges {
+    Postgres(PostgresChanges),
+    Sqlite(SqliteChanges),
+}
+
+async fn sql_database(state: &Rc<RefCell<OpState>>, api: &str) -> Result<SqlDatabase, AnyError> {
+    let postgres = {
+        let state = state.borrow();
+        check_permission(&state, Capability::Sql)?;
+        state
+            .try_borrow::<SqlStore>()
+            .and_then(|store| Some((store.postgres.clone()?, store.function_id.clone())))
+    };
+    match postgres {
+        Some((pool, function_id)) => pool
+            .connection(&function_id)
+            .await
+            .map(SqlDatabase::Postgres)
+            .map_err(|e| postgres_error(api, e)),
+        None => sqlite_database(state, api).await.map(SqlDatabase::Sqlite),
+    }
+}
+
+/// In strict mode, reject `sql` if it has values written into it.
+fn check_strict_sql(state: &OpState, api: &str, sql: &str) -> Result<(), AnyError> {
+    let strict = state
+        .try_borrow::<SqlStore>()
+        .is_some_and(|store| store.strict);
+    match find_literal(sql) {
+        Some(offset) if strict => Err(type_error(format!(
+            "{}: strict SQL mode rejects the inline literal at offset {}; pass values as parameters instead",
+            api, offset
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn sqlite_error(api: &str, e: SqliteError) -> AnyError {
+    // A failed migration throws what its statement would have
+    let cause = match &e {
+        SqliteError::Migration { error, .. } => &**error,
+        e => e,
+    };
+    match cause {
+        SqliteError::InvalidParameter(_) | SqliteError::NotAuthorized => {
+            type_error(format!("{}: {}", api, e))
+        }
+        SqliteError::TooManyRows | SqliteError::Full => range_error(format!("{}: {}", api, e)),
+        _ => AnyError::msg(format!("{}: {}", api, e)),
+    }
+}
+
+fn postgres_error(api: &str, e: PostgresError) -> AnyError {
+    let cause = match &e {
+        PostgresError::Migration { error, .. } => &**error,
+        e => e,
+    };
+    match cause {
+        PostgresError::InvalidParameter { .. }
+        | PostgresError::ParameterCount { .. }
+        | PostgresError::UnsupportedType { .. } => type_error(format!("{}: {}", api, e)),
+        PostgresError::TooManyRows => range_error(format!("{}: {}", api, e)),
+        _ => AnyError::msg(format!("{}: {}", api, e)),
+    }
+}
+
+/// `vortex.sqlite.query()`: run `sql` with `params` bound to its `?`
+/// placeholders and return the rows as objects.
+#[op2(async)]
+#[serde]
+pub async fn op_sqlite_query(
+    state: Rc<RefCell<OpState>>,
+    #[string] sql: String,
+    #[serde] params: Vec<serde_json::Value>,
+) -> Result<Vec<serde_json::Map<String, serde_json::Value>>, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.sqlite.query")?;
+    let database = sqlite_database(&state, "vortex.sqlite").await?;
+    check_strict_sql(&state.borrow(), "vortex.sqlite", &sql)?;
+    database
+        .query(&sql, params)
+        .await
+        .map_err(|e| sqlite_error("vortex.sqlite", e))
+}
+
+/// `vortex.sqlite.execute()`: run `sql`, which doesn't return rows, with
+/// `params` bound to its `?` placeholders.
+#[op2(async)]
+#[serde]
+pub async fn op_sqlite_execute(
+    state: Rc<RefCell<OpState>>,
+    #[string] sql: String,
+    #[serde] params: Vec<serde_json::Value>,
+) -> Result<SqliteChanges, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.sqlite.execute")?;
+    let database = sqlite_database(&state, "vortex.sqlite").await?;
+    check_strict_sql(&state.borrow(), "vortex.sqlite", &sql)?;
+    database
+        .execute(&sql, params)
+        .await
+        .map_err(|e| sqlite_error("vortex.sqlite", e))
+}
+
+/// `vortex.sql.query()`: run `sql` on the function's SQL database and
+/// return the rows as objects.
+#[op2(async)]
+#[serde]
+pub async fn op_sql_query(
+    state: Rc<RefCell<OpState>>,
+    #[string] sql: String,
+    #[serde] params: Vec<serde_json::Value>,
+) -> Result<Vec<serde_json::Map<String, serde_json::Value>>, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.sql.query")?;
+    let database = sql_database(&state, "vortex.sql").await?;
+    check_strict_sql(&state.borrow(), "vortex.sql", &sql)?;
+    match database {
+        SqlDatabase::Postgres(mut connection) => connection
+            .query(&sql, params)
+            .await
+            .map_err(|e| postgres_error("vortex.sql", e)),
+        SqlDatabase::Sqlite(database) => database
+            .query(&sql, params)
+            .await
+            .map_err(|e| sqlite_error("vortex.sql", e)),
+    }
+}
+
+/// `vortex.sql.execute()`: run `sql`, which needn't return rows, on the
+/// function's SQL database.
+#[op2(async)]
+#[serde]
+pub async fn op_sql_execute(
+    state: Rc<RefCell<OpState>>,
+    #[string] sql: String,
+    #[serde] params: Vec<serde_json::Value>,
+) -> Result<SqlChanges, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.sql.execute")?;
+    let database = sql_database(&state, "vortex.sql").await?;
+    check_strict_sql(&state.borrow(), "vortex.sql", &sql)?;
+    match database {
+        SqlDatabase::Postgres(mut connection) => connection
+            .execute(&sql, params)
+            .await
+            .map(SqlChanges::Postgres)
+            .map_err(|e| postgres_error("vortex.sql", e)),
+        SqlDatabase::Sqlite(database) => database
+            .execute(&sql, params)
+            .await
+            .map(SqlChanges::Sqlite)
+            .map_err(|e| sqlite_error("vortex.sql", e)),
+    }
+}
+
+/// `vortex.sql.migrate()`: apply the `migrations` the function's database
+/// doesn't have yet.
+#[op2(async)]
+#[serde]
+pub async fn op_sql_migrate(
+    state: Rc<RefCell<OpState>>,
+    #[serde] migrations: Vec<Migration>,
+) -> Result<MigrationReport, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.sql.migrate")?;
+    check_migrations(&migrations).map_err(|e| type_error(format!("vortex.sql.migrate: {}", e)))?;
+    match sql_database(&state, "vortex.sql").await? {
+        SqlDatabase::Postgres(mut connection) => connection
+            .migrate(migrations)
+            .await
+            .map_err(|e| postgres_error("vortex.sql.migrate", e)),
+        SqlDatabase::Sqlite(database) => database
+            .migrate(migrations)
+            .await
+            .map_err(|e| sqlite_error("vortex.sql.migrate", e)),
+    }
+}
+
+const TRANSACTION_API: &str = "vortex.sql.transaction";
+
+/// A `vortex.sql.transaction()` in progress.
+#[derive(Clone)]
+struct SqlTransaction {
+    /// `None` once the transaction has ended, including when its deadline
+    /// rolled it back
+    connection: Arc<tokio::sync::Mutex<Option<TransactionConnection>>>,
+    /// Holds the function's SQLite database, which no other statement of the
+    /// invocation can use meanwhile
+    sqlite: bool,
+    deadline: Instant,
+    timeout: Duration,
+}
+
+enum TransactionConnection {
+    Postgres(PooledConnection),
+    Sqlite(SqliteTransaction),
+}
+
+impl TransactionConnection {
+    async fn query(
+        &mut self,
+        sql: &str,
+        params: Vec<serde_json::Value>,
+    ) -> Result<Vec<serde_json::Map<String, serde_json::Value>>, AnyError> {
+        match self {
+            Self::Postgres(connection) => connection
+                .query(sql, params)
+                .await
+                .map_err(|e| postgres_error(TRANSACTION_API, e)),
+            Self::Sqlite(transaction) => transaction
+                .query(sql, params)
+                .await
+                .map_err(|e| sqlite_error(TRANSACTION_API, e)),
+        }
+    }
+
+    async fn execute(
+        &mut self,
+        sql: &str,
+        params: Vec<serde_json::Value>,
+    ) -> Result<SqlChanges, AnyError> {
+        match self {
+            Self::Postgres(connection) => connection
+                .execute(sql, params)
+                .await
+                .map(SqlChanges::Postgres)
+                .map_err(|e| postgres_error(TRANSACTION_API, e)),
+            Self::Sqlite(transaction) => transaction
+                .execute(sql, params)
+                .await
+                .map(SqlChanges::Sqlite)
+                .map_err(|e| sqlite_error(TRANSACTION_API, e)),
+        }
+    }
+
+    async fn end(self, commit: bool) -> Result<(), AnyError> {
+        match self {
+            Self::Postgres(mut connection) => {
+                let result = if commit {
+                    connection.commit().await
+                } else {
+                    connection.rollback().await
+                };
+                result.map_err(|e| postgres_error(TRANSACTION_API, e))
+            }
+            Self::Sqlite(transaction) => {
+                let result = if commit {
+                    transaction.commit().await
+                } else {
+                    transaction.rollback().await
+                };
+                result.map_err(|e| sqlite_error(TRANSACTION_API, e))
+            }
+        }
+    }
+}
+
+impl SqlTransaction {
+    /// The connection, unless the transaction has ended.
+    async fn lock(
+        &self,
+    ) -> Result<tokio::sync::MutexGuard<'_, Option<TransactionConnection>>, AnyError> {
+        let connection = self.connection.lock().await;
+        match *connection {
+            Some(_) => Ok(connection),
+            None => Err(self.ended()),
+        }
+    }
+
+    fn expired(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// Why the transaction can't be used any more.
+    fn ended(&self) -> AnyError {
+        if self.expired() {
+            range_error(format!(
+                "{}: transaction ran past its time limit of {}ms and was rolled back",
+                TRANSACTION_API,
+                self.timeout.as_millis()
+            ))
+        } else {
+            type_error(format!(
+                "{}: transaction has already ended",
+                TRANSACTION_API
+            ))
+        }
+    }
+
+    /// Roll back once the deadline passes, even if the function is busy
+    /// elsewhere and doesn't use the transaction again.
+    fn watch(&self) {
+        let connection = Arc::downgrade(&self.connection);
+        let deadline = self.deadline;
+        tokio::spawn(async move {
+            tokio::time::sleep_until(deadline.into()).await;
+            if let Some(connection) = connection.upgrade() {
+                // Dropping the connection rolls back
+                drop(connection.lock().await.take());
+            }
+        });
+    }
+}
+
+/// Transactions the worker's invocation has open, by ID.
+#[derive(Default)]
+pub struct SqlTransactions {
+    open: HashMap<u32, SqlTransaction>,
+    next_id: u32,
+}
+
+impl SqlTransactions {
+    /// Forget every transaction, which rolls back the ones still open.
+    pub fn clear(&mut self) {
+        self.open.clear();
+    }
+
+    /// Whether a transaction holds the function's SQLite database.
+    fn holds_sqlite(&self) -> bool {
+        self.open.values().any(|transaction| {
+            transaction.sqlite
+                && transaction
+                    .connection
+                    .try_lock()
+                    .map_or(true, |connection| connection.is_some())
+        })
+    }
+}
+
+/// Type alias for the open transactions shared between the worker and ops
+pub type SqlTransactionsState = Rc<RefCell<SqlTransactions>>;
+
+fn sql_transaction(state: &OpState, id: u32) -> Result<SqlTransaction, AnyError> {
+    let transaction = state
+        .try_borrow::<SqlTransactionsState>()
+        .and_then(|transactions| transactions.borrow().open.get(&id).cloned());
+    transaction.ok_or_else(|| {
+        type_error(format!(
+            "{}: transaction has already ended",
+            TRANSACTION_API
+        ))
+    })
+}
+
+/// `vortex.sql.transaction()`: begin a transaction that is rolled back
+/// unless it ends within `timeout_ms`, and return its ID.
+#[op2(async)]
+pub async fn op_sql_transaction_begin(
+    state: Rc<RefCell<OpState>>,
+    #[serde] timeout_ms: Option<u32>,
+) -> Result<u32, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.sql.transaction")?;
+    let timeout = timeout_ms
+        .map_or(DEFAULT_SQL_TRANSACTION_TIMEOUT, |ms| {
+            Duration::from_millis(ms.into())
+        })
+        .min(MAX_SQL_TRANSACTION_TIMEOUT);
+    let deadline = Instant::now() + timeout;
+    let connection = match sql_database(&state, TRANSACTION_API).await? {
+        SqlDatabase::Postgres(mut connection) => {
+            connection
+                .begin()
+                .await
+                .map_err(|e| postgres_error(TRANSACTION_API, e))?;
+            TransactionConnection::Postgres(connection)
+        }
+        SqlDatabase::Sqlite(database) => TransactionConnection::Sqlite(
+            database
+                .begin(deadline)
+                .await
+                .map_err(|e| sqlite_error(TRANSACTION_API, e))?,
+        ),
+    };
+    let transaction = SqlTransaction {
+        sqlite: matches!(connection, TransactionConnection::Sqlite(_)),
+        connection: Arc::new(tokio::sync::Mutex::new(Some(connection))),
+        deadline,
+        timeout,
+    };
+    transaction.watch();
+
+    let state = state.borrow();
+    let mut transactions = state
+        .try_borrow::<SqlTransactionsState>()
+        .ok_or_else(|| type_error(format!("{} is not available", TRANSACTION_API)))?
+        .borrow_mut();
+    transactions.next_id = transactions.next_id.wrapping_add(1);
+    let id = transactions.next_id;
+    transactions.open.insert(id, transaction);
+    Ok(id)
+}
+
+/// `tx.query()`: like `vortex.sql.query()`, inside transaction `id`.
+#[op2(async)]
+#[serde]
+pub async fn op_sql_transaction_query(
+    state: Rc<RefCell<OpState>>,
+    id: u32,
+    #[string] sql: String,
+    #[serde] params: Vec<serde_json::Value>,
+) -> Result<Vec<serde_json::Map<String, serde_json::Value>>, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.sql.transaction.query")?;
+    check_strict_sql(&state.borrow(), TRANSACTION_API, &sql)?;
+    let transaction = sql_transaction(&state.borrow(), id)?;
+    let mut connection = transaction.lock().await?;
+    let statement = connection
+        .as_mut()
+        .expect("lock() checked the transaction is open")
+        .query(&sql, params);
+    match tokio::time::timeout_at(transaction.deadline.into(), statement).await {
+        Ok(result) => result,
+        Err(_) => {
+            drop(connection.take());
+            Err(transaction.ended())
+        }
+    }
+}
+
+/// `tx.execute()`: like `vortex.sql.execute()`, inside transaction `id`.
+#[op2(async)]
+#[serde]
+pub async fn op_sql_transaction_execute(
+    state: Rc<RefCell<OpState>>,
+    id: u32,
+    #[string] sql: String,
+    #[serde] params: Vec<serde_json::Value>,
+) -> Result<SqlChanges, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.sql.transaction.execute")?;
+    check_strict_sql(&state.borrow(), TRANSACTION_API, &sql)?;
+    let transaction = sql_transaction(&state.borrow(), id)?;
+    let mut connection = transaction.lock().await?;
+    let statement = connection
+        .as_mut()
+        .expect("lock() checked the transaction is open")
+        .execute(&sql, params);
+    match tokio::time::timeout_at(transaction.deadline.into(), statement).await {
+        Ok(result) => result,
+        Err(_) => {
+            drop(connection.take());
+            Err(transaction.ended())
+        }
+    }
+}
+
+/// Commit or roll back transaction `id`. Rolling back one that has already
+/// ended does nothing; committing one fails.
+#[op2(async)]
+pub async fn op_sql_transaction_end(
+    state: Rc<RefCell<OpState>>,
+    id: u32,
+    commit: bool,
+) -> Result<(), AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.sql.transaction")?;
+    let transaction = sql_transaction(&state.borrow(), id)?;
+    if let Some(transactions) = state.borrow().try_borrow::<SqlTransactionsState>() {
+        transactions.borrow_mut().open.remove(&id);
+    }
+    let connection = match transaction.lock().await {
+        Ok(mut connection) => connection.take(),
+        Err(_) if !commit => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let connection = connection.expect("lock() checked the transaction is open");
+    if commit && transaction.expired() {
+        drop(connection);
+        return Err(transaction.ended());
+    }
+    connection.end(commit).await
+}
+
+/// The host's audit log, and the function a worker's entries are
+/// attributed to.
+#[derive(Debug, Clone, Default)]
+pub struct AuditTrail {
+    pub log: Option<AuditLog>,
+    pub function_id: Option<String>,
+}
+
+/// Where `vortex.audit.record()` put an event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditReceipt {
+    pub seq: u64,
+    pub hash: String,
+}
+
+/// Append `event` (JSON text) to the audit log. Resolves once the sink has
+/// stored it, so a function that awaits it can't finish without its record.
+#[op2(async)]
+#[serde]
+pub async fn op_audit_record(
+    state: Rc<RefCell<OpState>>,
+    #[string] event: String,
+) -> Result<AuditReceipt, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.audit.record")?;
+    if event.len() > MAX_AUDIT_EVENT_BYTES {
+        return Err(range_error(format!(
+            "vortex.audit.record: event is larger than {} bytes",
+            MAX_AUDIT_EVENT_BYTES
+        )));
+    }
+    let (trail, invocation_id) = {
+        let state = state.borrow();
+        let trail = state
+            .try_borrow::<AuditTrail>()
+            .cloned()
+            .unwrap_or_default();
+        (trail, invocation_id(&state))
+    };
+    let log = trail.log.ok_or_else(|| {
+        type_error("vortex.audit requires an audit log, which is not configured for this function")
+    })?;
+    let function_id = trail.function_id;
+    // Sinks may sync to disk; keep that off the event loop
+    let entry = tokio::task::spawn_blocking(move || {
+        log.record(function_id.as_deref(), &invocation_id, event, Utc::now())
+    })
+    .await?
+    .map_err(|e| AnyError::msg(format!("vortex.audit.record: {}", e)))?;
+    Ok(AuditReceipt {
+        seq: entry.seq,
+        hash: entry.hash,
+    })
+}
+
+/// Contents of an actor's `state.storage`, held in the isolate.
+///
+/// The actor registry (see `actors.rs`) loads the entries when it pins an
+/// isolate to an object and saves them again after every invocation that
+/// changed them and succeeded.
+#[derive(Debug, Default)]
+pub struct ActorStorage {
+    pub entries: std::collections::BTreeMap<String, serde_json::Value>,
+    /// Set by every write, cleared when the registry saves the entries
+    pub dirty: bool,
+}
+
+/// Type alias for the actor storage shared between the worker and ops
+pub type ActorStorageState = Rc<RefCell<ActorStorage>>;
+
+fn actor_storage(state: &OpState) -> Result<ActorStorageState, AnyError> {
+    state
+        .try_borrow::<ActorStorageState>()
+        .cloned()
+        .ok_or_else(|| type_error("state.storage is only available to actors"))
+}
+
+/// `state.storage.get()`. Returns `[]` for a missing key and `[value]`
+/// otherwise, so a stored `null` is not mistaken for a missing key.
+#[op2]
+#[serde]
+pub fn op_actor_storage_get(
+    state: &OpState,
+    #[string] key: String,
+) -> Result<Vec<serde_json::Value>, AnyError> {
+    let storage = actor_storage(state)?;
+    let storage = storage.borrow();
+    Ok(storage.entries.get(&key).cloned().into_iter().collect())
+}
+
+/// `state.storage.put()`.
+#[op2]
+pub fn op_actor_storage_put(
+    state: &OpState,
+    #[string] key: String,
+    #[serde] value: serde_json::Value,
+) -> Result<(), AnyError> {
+    let storage = actor_storage(state)?;
+    let mut storage = storage.borrow_mut();
+    storage.entries.insert(key, value);
+    storage.dirty = true;
+    Ok(())
+}
+
+/// `state.storage.delete()`, returning whether the key existed.
+#[op2]
+pub fn op_actor_storage_delete(state: &OpState, #[string] key: String) -> Result<bool, AnyError> {
+    let storage = actor_storage(state)?;
+    let mut storage = storage.borrow_mut();
+    let existed = storage.entries.remove(&key).is_some();
+    storage.dirty |= existed;
+    Ok(existed)
+}
+
+/// `state.storage.list()`: entries whose key starts with `prefix`, in key
+/// order. A `limit` of 0 means no limit.
+#[op2]
+#[serde]
+pub fn op_actor_storage_list(
+    state: &OpState,
+    #[string] prefix: String,
+    limit: u32,
+) -> Result<Vec<(String, serde_json::Value)>, AnyError> {
+    let storage = actor_storage(state)?;
+    let storage = storage.borrow();
+    let limit = if limit == 0 { usize::MAX } else { limit as usize };
+    Ok(storage
+        .entries
+        .range(prefix.clone()..)
+        .take_while(|(key, _)| key.starts_with(&prefix))
+        .take(limit)
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect())
+}
+
+/// Default limit on the size of a single `multipart/form-data` part.
+pub const DEFAULT_FORM_PART_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+/// Maximum number of parts `vortex.request.formData()` accepts.
+const MAX_FORM_PARTS: usize = 1000;
+
+/// An HTTP request body streamed into an invocation as it arrives.
+#[derive(Debug)]
+pub struct RequestBodyStream {
+    /// The chunks of the body. An `Err` aborts it, e.g. when the client
+    /// disconnects or the body grows past the size limit.
+    pub chunks: mpsc::Receiver<Result<Vec<u8>, String>>,
+    /// The request's `Content-Type`, needed to parse it as form data
+    pub content_type: Option<String>,
+    /// Largest part `vortex.request.formData()` accepts, in bytes
+    pub max_form_part_bytes: usize,
+}
+
+/// The request body of the current invocation, behind `vortex.request`.
+///
+/// Set by the HTTP serve mode (see `http.rs`) before each invocation. The
+/// body is pulled one chunk at a time, so it never has to be held in the
+/// isolate heap all at once.
+#[derive(Debug, Default)]
+pub struct RequestBody {
+    /// `None` once the body has been read to the end (or if there is none)
+    pub stream: Option<RequestBodyStream>,
+    /// Whether the invocation received a body at all
+    pub present: bool,
+    /// Set when user code starts reading; a body can only be read once
+    pub used: bool,
+}
+
+impl RequestBody {
+    pub fn new(stream: Option<RequestBodyStream>) -> Self {
+        Self {
+            present: stream.is_some(),
+            stream,
+            used: false,
+        }
+    }
+}
+
+/// Type alias for the request body shared between the worker and ops
+pub type RequestBodyState = Rc<RefCell<RequestBody>>;
+
+fn request_body(state: &OpState) -> Result<RequestBodyState, AnyError> {
+    state
+        .try_borrow::<RequestBodyState>()
+        .cloned()
+        .ok_or_else(|| type_error("vortex.request is not available"))
+}
+
+/// Whether the current invocation has a request body.
+#[op2(fast)]
+pub fn op_request_body_present(state: &OpState) -> bool {
+    state
+        .try_borrow::<RequestBodyState>()
+        .is_some_and(|body| body.borrow().present)
+}
+
+/// Start reading the request body; it can only be read once.
+#[op2]
+pub fn op_request_body_claim(state: &OpState) -> Result<(), AnyError> {
+    let body = request_body(state)?;
+    let mut body = body.borrow_mut();
+    if body.used {
+        return Err(type_error("Request body has already been read"));
+    }
+    body.used = true;
+    Ok(())
+}
+
+/// The next chunk of the request body, or an empty buffer at the end.
+#[op2(async)]
+#[buffer]
+pub async fn op_request_body_read(state: Rc<RefCell<OpState>>) -> Result<Vec<u8>, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.request.body")?;
+    let body = request_body(&state.borrow())?;
+    // Held outside the RefCell across the await; reads are serialized in JS
+    let Some(mut stream) = body.borrow_mut().stream.take() else {
+        return Ok(Vec::new());
+    };
+    loop {
+        match stream.chunks.recv().await {
+            Some(Ok(chunk)) if chunk.is_empty() => continue,
+            Some(Ok(chunk)) => {
+                body.borrow_mut().stream = Some(stream);
+                return Ok(chunk);
+            }
+            Some(Err(e)) => return Err(type_error(format!("Failed to read request body: {}", e))),
+            None => return Ok(Vec::new()),
+        }
+    }
+}
+
+/// One field of a `multipart/form-data` body.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormPart {
+    pub name: String,
+    /// Set for file uploads, possibly to an empty string
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub data: deno_core::ToJsBuffer,
+}
+
+/// The `boundary` parameter of a `multipart/form-data` content type.
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    let essence = params.next()?.trim();
+    if !essence.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| unquote(value.trim()))
+        .filter(|boundary| !boundary.is_empty() && boundary.len() <= 70)
+}
+
+fn unquote(value: &str) -> String {
+    match value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+        Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+        None => value.to_string(),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Split a `multipart/form-data` body (RFC 7578) into its parts.
+///
+/// Parts larger than `max_part_bytes` and bodies with more than
+/// `MAX_FORM_PARTS` parts are rejected rather than truncated.
+fn parse_multipart(
+    body: &[u8],
+    boundary: &str,
+    max_part_bytes: usize,
+) -> Result<Vec<(FormPartHeaders, Vec<u8>)>, String> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut close = b"\r\n".to_vec();
+    close.extend_from_slice(&delimiter);
+
+    // Anything before the first delimiter is a preamble and ignored
+    let mut rest = if body.starts_with(&delimiter) {
+        &body[delimiter.len()..]
+    } else {
+        let start = find(body, &close).ok_or("no boundary found in the body")?;
+        &body[start + close.len()..]
+    };
+
+    let mut parts = Vec::new();
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        // Transport padding may follow the delimiter
+        let line_end = find(rest, b"\r\n").ok_or("unexpected end of body")?;
+        if rest[..line_end].iter().any(|b| *b != b' ' && *b != b'\t') {
+            return Err("malformed boundary line".to_string());
+        }
+        rest = &rest[line_end + 2..];
+
+        let headers_end = if rest.starts_with(b"\r\n") {
+            0
+        } else {
+            find(rest, b"\r\n\r\n").ok_or("unexpected end of part headers")? + 2
+        };
+        let headers = FormPartHeaders::parse(&String::from_utf8_lossy(&rest[..headers_end]))?;
+        rest = &rest[headers_end + 2..];
+
+        let content_end = find(rest, &close).ok_or("unexpected end of body")?;
+        if content_end > max_part_bytes {
+            return Err(format!(
+                "part '{}' exceeds the limit of {} bytes",
+                headers.name, max_part_bytes
+            ));
+        }
+        if parts.len() == MAX_FORM_PARTS {
+            return Err(format!("more than {} parts", MAX_FORM_PARTS));
+        }
+        parts.push((headers, rest[..content_end].to_vec()));
+        rest = &rest[content_end + close.len()..];
+    }
+}
+
+/// The headers of a form part that matter to the handler.
+#[derive(Debug, Default, PartialEq)]
+struct FormPartHeaders {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+}
+
+impl FormPartHeaders {
+    fn parse(headers: &str) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut disposition = false;
+        for line in headers.split("\r\n").filter(|line| !line.is_empty()) {
+            let (name, value) = line.split_once(':').ok_or("malformed part header")?;
+            let value = value.trim();
+            if name.trim().eq_ignore_ascii_case("content-type") {
+                parsed.content_type = Some(value.to_string());
+            } else if name.trim().eq_ignore_ascii_case("content-disposition") {
+                let mut params = value.split(';');
+                if !params.next().unwrap_or("").trim().eq_ignore_ascii_case("form-data") {
+                    return Err("part is not form-data".to_string());
+                }
+                for param in params {
+                    match param.split_once('=') {
+                        Some((key, value)) if key.trim().eq_ignore_ascii_case("name") => {
+                            parsed.name = unquote(value.trim());
+                            disposition = true;
+                        }
+                        Some((key, value)) if key.trim().eq_ignore_ascii_case("filename") => {
+                            parsed.filename = Some(unquote(value.trim()));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        if !disposition {
+            return Err("part without a Content-Disposition name".to_string());
+        }
+        Ok(parsed)
+    }
+}
+
+/// `vortex.request.formData()`, for `multipart/form-data` and
+/// `application/x-www-form-urlencoded` bodies.
+///
+/// The body is collected and split outside the isolate; only the fields
+/// themselves are handed to JavaScript.
+#[op2(async)]
+#[serde]
+pub async fn op_request_body_form_data(
+    state: Rc<RefCell<OpState>>,
+) -> Result<Vec<FormPart>, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.request.formData")?;
+    let body = request_body(&state.borrow())?;
+    let (mut stream, boundary) = {
+        let mut body = body.borrow_mut();
+        if body.used {
+            return Err(type_error("Request body has already been read"));
+        }
+        let content_type = body
+            .stream
+            .as_ref()
+            .and_then(|stream| stream.content_type.as_deref())
+            .unwrap_or("");
+        let boundary = multipart_boundary(content_type);
+        let essence = content_type.split(';').next().unwrap_or("").trim();
+        let urlencoded = essence.eq_ignore_ascii_case("application/x-www-form-urlencoded");
+        if boundary.is_none() && !urlencoded {
+            return Err(type_error(
+                "formData: expected a multipart/form-data or application/x-www-form-urlencoded body",
+            ));
+        }
+        body.used = true;
+        match body.stream.take() {
+            Some(stream) => (stream, boundary),
+            None => return Ok(Vec::new()),
+        }
+    };
+
+    let mut data = Vec::new();
+    while let Some(chunk) = stream.chunks.recv().await {
+        let chunk =
+            chunk.map_err(|e| type_error(format!("Failed to read request body: {}", e)))?;
+        data.extend_from_slice(&chunk);
+    }
+    let Some(boundary) = boundary else {
+        return Ok(deno_core::url::form_urlencoded::parse(&data)
+            .map(|(name, value)| FormPart {
+                name: name.into_owned(),
+                filename: None,
+                content_type: None,
+                data: value.into_owned().into_bytes().into(),
+            })
+            .collect());
+    };
+    let parts = parse_multipart(&data, &boundary, stream.max_form_part_bytes)
+        .map_err(|e| type_error(format!("formData: {}", e)))?;
+    Ok(parts
+        .into_iter()
+        .map(|(headers, data)| FormPart {
+            name: headers.name,
+            filename: headers.filename,
+            content_type: headers.content_type,
+            data: data.into(),
+        })
+        .collect())
+}
+
+/// A WebSocket message relayed between the client and the handler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebSocketMessage {
+    Text(String),
+    Binary(Vec<u8>),
+    /// The closing handshake, with its status code and reason
+    Close(u16, String),
+}
+
+/// The handler's end of the WebSocket connection an HTTP request can be
+/// upgraded to.
+#[derive(Debug)]
+pub struct WebSocketChannel {
+    /// Messages from the client, once the connection has been upgraded
+    pub incoming: mpsc::Receiver<WebSocketMessage>,
+    /// Messages for the client
+    pub outgoing: mpsc::Sender<WebSocketMessage>,
+}
+
+/// The WebSocket upgrade offered to the current invocation, behind
+/// `WebSocketPair`.
+///
+/// Set by the HTTP serve mode (see `http.rs`) for requests that ask for an
+/// upgrade. The handler accepts it by calling `accept()` on the server side
+/// of a pair and returning a `101` response with the client side.
+#[derive(Debug, Default)]
+pub struct WebSocketUpgrade {
+    /// `None` while a message is being received, or if there is no upgrade
+    pub incoming: Option<mpsc::Receiver<WebSocketMessage>>,
+    /// `None` if the request didn't ask for an upgrade
+    pub outgoing: Option<mpsc::Sender<WebSocketMessage>>,
+    /// Set once user code has accepted the connection
+    pub accepted: bool,
+}
+
+impl WebSocketUpgrade {
+    pub fn new(channel: Option<WebSocketChannel>) -> Self {
+        let (incoming, outgoing) = channel
+            .map(|channel| (channel.incoming, channel.outgoing))
+            .unzip();
+        Self {
+            incoming,
+            outgoing,
+            accepted: false,
+        }
+    }
+}
+
+/// Type alias for the WebSocket upgrade shared between the worker and ops
+pub type WebSocketState = Rc<RefCell<WebSocketUpgrade>>;
+
+/// A message for the server side of a `WebSocketPair`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum WebSocketEvent {
+    Text { data: String },
+    Binary { data: deno_core::ToJsBuffer },
+    Close { code: u16, reason: String },
+}
+
+fn websocket(state: &OpState) -> Result<WebSocketState, AnyError> {
+    state
+        .try_borrow::<WebSocketState>()
+        .cloned()
+        .ok_or_else(|| type_error("WebSocketPair is not available"))
+}
+
+/// The outgoing half of an accepted WebSocket connection.
+fn websocket_outgoing(state: &OpState) -> Result<mpsc::Sender<WebSocketMessage>, AnyError> {
+    let websocket = websocket(state)?;
+    let websocket = websocket.borrow();
+    websocket
+        .outgoing
+        .clone()
+        .filter(|_| websocket.accepted)
+        .ok_or_else(|| type_error("WebSocket is not accepted"))
+}
+
+/// Accept the WebSocket upgrade of the current request.
+#[op2(fast)]
+pub fn op_websocket_accept(state: &OpState) -> Result<(), AnyError> {
+    let websocket = websocket(state)?;
+    let mut websocket = websocket.borrow_mut();
+    if websocket.outgoing.is_none() {
+        return Err(type_error("The request is not a WebSocket upgrade"));
+    }
+    if websocket.accepted {
+        return Err(type_error(
+            "The WebSocket upgrade has already been accepted",
+        ));
+    }
+    websocket.accepted = true;
+    Ok(())
+}
+
+/// The next message from the client. A connection that went away without a
+/// closing handshake ends with code `1006`.
+#[op2(async)]
+#[serde]
+pub async fn op_websocket_receive(state: Rc<RefCell<OpState>>) -> Result<WebSocketEvent, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "WebSocket")?;
+    let websocket = websocket(&state.borrow())?;
+    // Held outside the RefCell across the await; receives are serialized in JS
+    let Some(mut incoming) = websocket.borrow_mut().incoming.take() else {
+        return Err(type_error("WebSocket is not accepted"));
+    };
+    let message = incoming.recv().await;
+    // Unless the invocation is over and the upgrade was reset meanwhile
+    let mut upgrade = websocket.borrow_mut();
+    if upgrade.accepted && upgrade.incoming.is_none() {
+        upgrade.incoming = Some(incoming);
+    }
+    Ok(match message {
+        Some(WebSocketMessage::Text(data)) => WebSocketEvent::Text { data },
+        Some(WebSocketMessage::Binary(data)) => WebSocketEvent::Binary { data: data.into() },
+        Some(WebSocketMessage::Close(code, reason)) => WebSocketEvent::Close { code, reason },
+        None => WebSocketEvent::Close {
+            code: 1006,
+            reason: String::new(),
+        },
+    })
+}
+
+/// Send a text message to the client, resolving once the connection has
+/// room for it.
+#[op2(async)]
+pub async fn op_websocket_send(
+    state: Rc<RefCell<OpState>>,
+    #[string] text: String,
+) -> Result<(), AnyError> {
+    websocket_send(&state, WebSocketMessage::Text(text)).await
+}
+
+/// Send a binary message to the client.
+#[op2(async)]
+pub async fn op_websocket_send_binary(
+    state: Rc<RefCell<OpState>>,
+    #[buffer] data: JsBuffer,
+) -> Result<(), AnyError> {
+    websocket_send(&state, WebSocketMessage::Binary(data.to_vec())).await
+}
+
+/// Start the closing handshake, after the messages sent before it.
+#[op2(async)]
+pub async fn op_websocket_close(
+    state: Rc<RefCell<OpState>>,
+    #[smi] code: u32,
+    #[string] reason: String,
+) -> Result<(), AnyError> {
+    let code = u16::try_from(code).map_err(|_| range_error("Invalid close code"))?;
+    websocket_send(&state, WebSocketMessage::Close(code, reason)).await
+}
+
+/// Queue `message` for the client. Sends wait their turn in the order they
+/// were made, so a handler that sends faster than the client reads is held
+/// back rather than buffering without limit.
+async fn websocket_send(
+    state: &Rc<RefCell<OpState>>,
+    message: WebSocketMessage,
+) -> Result<(), AnyError> {
+    let _guard = PendingOpGuard::acquire(state, "WebSocket")?;
+    let outgoing = websocket_outgoing(&state.borrow())?;
+    // The connection may be gone already; the close event tells user code
+    let _ = outgoing.send(message).await;
+    Ok(())
+}
+
+/// Error class for a `fetch()` that runs past its timeout; bootstrap.js
+/// turns it into a `DOMException` named `TimeoutError`.
+pub const FETCH_TIMEOUT_CLASS: &str = "TimeoutError";
+
+/// The HTTP client for `fetch()`, created on first use unless the worker was
+/// given a shared one.
+pub type FetchClientState = Rc<RefCell<Option<FetchClient>>>;
+
+/// A response whose body is still being read, with the deadline of the
+/// request it answers and how much of the body it may still return.
+#[derive(Debug)]
+struct FetchBody {
+    source: FetchBodySource,
+    /// Where the body comes from, for egress metering
+    host: String,
+    deadline: Option<tokio::time::Instant>,
+    received: usize,
+    max_bytes: usize,
+    /// Set while the response is being collected for the fetch cache
+    store: Option<FetchCacheWrite>,
+}
+
+#[derive(Debug)]
+enum FetchBodySource {
+    Network(reqwest::Response),
+    /// A response answered from the fetch cache, returned in one chunk
+    Cached(Vec<u8>),
+}
+
+/// A response that is stored in the fetch cache once its whole body has
+/// been read, unless the body grows past `max_bytes`.
+#[derive(Debug)]
+struct FetchCacheWrite {
+    cache: FetchCache,
+    key: String,
+    response: CachedResponse,
+    max_bytes: usize,
+}
+
+/// Bodies of `fetch()` responses that are still being read, cleared for each
+/// invocation.
+#[derive(Debug, Default)]
+pub struct FetchBodies {
+    responses: HashMap<u32, FetchBody>,
+    next_id: u32,
+}
+
+impl FetchBodies {
+    pub fn clear(&mut self) {
+        self.responses.clear();
+    }
+
+    fn insert(&mut self, body: FetchBody) -> u32 {
+        self.next_id = self.next_id.wrapping_add(1);
+        self.responses.insert(self.next_id, body);
+        self.next_id
+    }
+}
+
+/// Type alias for the response bodies shared between the worker and ops
+pub type FetchBodiesState = Rc<RefCell<FetchBodies>>;
+
+fn fetch_bodies(state: &OpState) -> Result<FetchBodiesState, AnyError> {
+    state
+        .try_borrow::<FetchBodiesState>()
+        .cloned()
+        .ok_or_else(|| type_error("fetch is not available"))
+}
+
+/// Type alias for the invocation's egress metrics shared between the worker
+/// and ops
+pub type EgressState = Rc<RefCell<EgressMetrics>>;
+
+fn fetch_egress(state: &OpState) -> Result<EgressState, AnyError> {
+    state
+        .try_borrow::<EgressState>()
+        .cloned()
+        .ok_or_else(|| type_error("fetch is not available"))
+}
+
+fn fetch_client(state: &OpState) -> Result<FetchClient, AnyError> {
+    let client = state
+        .try_borrow::<FetchClientState>()
+        .ok_or_else(|| type_error("fetch is not available"))?;
+    let mut client = client.borrow_mut();
+    if let Some(client) = client.as_ref() {
+        return Ok(client.clone());
+    }
+    let options = state
+        .try_borrow::<FetchOptions>()
+        .cloned()
+        .unwrap_or_default();
+    let secrets = state.try_borrow::<SecretsState>().cloned().flatten();
+    Ok(client
+        .insert(FetchClient::new(&options, secrets.as_deref())?)
+        .clone())
+}
+
+/// The private address a request was refused for, if that's why it failed.
+fn blocked_address(error: &reqwest::Error) -> Option<&BlockedAddress> {
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        if let Some(blocked) = cause.downcast_ref::<BlockedAddress>() {
+            return Some(blocked);
+        }
+        source = cause.source();
+    }
+    None
+}
+
+/// A request to a private address, refused as `PermissionDenied`.
+fn blocked_address_error(blocked: &BlockedAddress) -> AnyError {
+    custom_error(
+        PERMISSION_DENIED_CLASS,
+        format!("fetch failed: {}", blocked),
+    )
+}
+
+/// A network error, as the `TypeError` `fetch()` rejects with. reqwest's own
+/// message is terse, so the underlying causes are appended.
+fn fetch_error(error: reqwest::Error) -> AnyError {
+    if let Some(blocked) = blocked_address(&error) {
+        return blocked_address_error(blocked);
+    }
+    let error = error.without_url();
+    let mut message = format!("fetch failed: {}", error);
+    let mut source = std::error::Error::source(&error);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    type_error(message)
+}
+
+/// Await `future`, failing with a `TimeoutError` once `deadline` has passed.
+async fn before_deadline<T>(
+    deadline: Option<tokio::time::Instant>,
+    future: impl std::future::Future<Output = T>,
+) -> Result<T, AnyError> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future)
+            .await
+            .map_err(|_| custom_error(FETCH_TIMEOUT_CLASS, "fetch failed: request timed out")),
+        None => Ok(future.await),
+    }
+}
+
+/// Fail a response whose headers or declared body exceed the worker's
+/// limits, before any of the body is read.
+fn check_fetch_response(
+    response: &reqwest::Response,
+    options: &FetchOptions,
+) -> Result<(), AnyError> {
+    let header_bytes: usize = response
+        .headers()
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum();
+    if header_bytes > options.max_header_bytes {
+        return Err(type_error(format!(
+            "fetch failed: response headers are {} bytes, over the {} byte limit",
+            header_bytes, options.max_header_bytes
+        )));
+    }
+    if let Some(length) = response.content_length() {
+        if length > options.max_response_bytes as u64 {
+            return Err(type_error(format!(
+                "fetch failed: response body is {} bytes, over the {} byte limit",
+                length, options.max_response_bytes
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Methods that are safe to send again after a failed attempt.
+const IDEMPOTENT_METHODS: [reqwest::Method; 5] = [
+    reqwest::Method::GET,
+    reqwest::Method::HEAD,
+    reqwest::Method::OPTIONS,
+    reqwest::Method::PUT,
+    reqwest::Method::DELETE,
+];
+
+/// Send one hop of a request, calling `on_attempt` before each attempt. An
+/// idempotent request is retried after a 5xx response or a failed
+/// connection, backing off exponentially, until `retries` run out or the
+/// next attempt would start past the deadline.
+async fn fetch_send(
+    mut builder: reqwest::RequestBuilder,
+    idempotent: bool,
+    retries: u32,
+    backoff: std::time::Duration,
+    deadline: Option<tokio::time::Instant>,
+    on_attempt: impl Fn(),
+) -> Result<reqwest::Response, AnyError> {
+    let mut attempt = 0;
+    loop {
+        // Bodies are buffered, so the request can always be cloned
+        let retry = builder
+            .try_clone()
+            .filter(|_| idempotent && attempt < retries);
+        on_attempt();
+        let result = before_deadline(deadline, builder.send()).await?;
+        let retryable = match &result {
+            Ok(response) => response.status().is_server_error(),
+            Err(error) => error.is_connect() && blocked_address(error).is_none(),
+        };
+        let delay = backoff.saturating_mul(1 << attempt.min(16));
+        let too_late =
+            deadline.is_some_and(|deadline| tokio::time::Instant::now() + delay >= deadline);
+        match retry {
+            Some(retry) if retryable && !too_late => builder = retry,
+            _ => return result.map_err(fetch_error),
+        }
+        drop(result);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// A request built by `fetch()` in bootstrap.js.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Option<JsBuffer>,
+    /// `follow`, `manual` or `error`, as in `RequestInit.redirect`
+    redirect: String,
+    /// Overrides of the worker's [`FetchOptions`] from `init.vortex`
+    timeout_ms: Option<u64>,
+    max_redirects: Option<usize>,
+    retries: Option<u32>,
+    retry_backoff_ms: Option<u64>,
+}
+
+/// Status and headers of a `fetch()` response; the body is read with
+/// `op_fetch_body_read(rid)`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchResponse {
+    rid: u32,
+    status: u16,
+    status_text: String,
+    headers: Vec<(String, String)>,
+    url: String,
+    redirected: bool,
+}
+
+/// Send a request for `fetch()`, following redirects unless told otherwise.
+///
+/// Every hop is checked against the worker's net allowlist, and against
+/// private addresses, before it is sent, so a redirect can't lead a request
+/// to a host the function may not reach. The timeout, redirect limit and
+/// retries come from the worker's [`FetchOptions`] unless the request
+/// overrides them. With a fetch cache, a fresh stored response answers a
+/// `GET` without contacting the origin; see [`crate::fetch`].
+#[op2(async)]
+#[serde]
+pub async fn op_fetch(
+    state: Rc<RefCell<OpState>>,
+    #[serde] request: FetchRequest,
+) -> Result<FetchResponse, AnyError> {
+    let guard = PendingOpGuard::acquire(&state, "vortex.fetch.request")?;
+    let client = fetch_client(&state.borrow())?;
+    let egress = fetch_egress(&state.borrow())?;
+    let options = state
+        .borrow()
+        .try_borrow::<FetchOptions>()
+        .cloned()
+        .unwrap_or_default();
+    let timeout = request
+        .timeout_ms
+        .map(std::time::Duration::from_millis)
+        .or(options.timeout);
+    let deadline = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+    let max_redirects = request.max_redirects.unwrap_or(options.max_redirects);
+    let retries = request.retries.unwrap_or(options.retries);
+    let retry_backoff = request
+        .retry_backoff_ms
+        .map(std::time::Duration::from_millis)
+        .unwrap_or(options.retry_backoff);
+    let mode = match options.cache {
+        Some(_) => cache_mode(&request.method, &request.headers),
+        None => CacheMode::Bypass,
+    };
+
+    let mut method = reqwest::Method::from_bytes(request.method.as_bytes())
+        .map_err(|_| type_error(format!("Invalid method: '{}'", request.method)))?;
+    let mut url = reqwest::Url::parse(&request.url)
+        .map_err(|_| type_error(format!("Invalid URL: '{}'", request.url)))?;
+    let mut headers = reqwest::header::HeaderMap::new();
+    for (name, value) in &request.headers {
+        let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| type_error(format!("Invalid header name: '{}'", name)))?;
+        let value = reqwest::header::HeaderValue::from_str(value)
+            .map_err(|_| type_error(format!("Invalid header value: '{}'", value)))?;
+        headers.append(name, value);
+    }
+    let mut body = request.body.map(|body| body.to_vec());
+
+    let mut redirects = 0;
+    loop {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(type_error(format!(
+                "fetch failed: unsupported URL scheme '{}'",
+                url.scheme()
+            )));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| type_error(format!("fetch failed: '{}' has no host", url)))?;
+        if let Some(guard) = &guard {
+            guard.describe(host);
+        }
+        check_net(&state.borrow(), host)?;
+        client
+            .check_url(&url)
+            .map_err(|blocked| blocked_address_error(&blocked))?;
+
+        if let (Some(cache), CacheMode::Use, 0) = (&options.cache, mode, redirects) {
+            let now = Utc::now();
+            let hit = fetch_cache_lookup(&state, cache, url.as_str(), now)
+                .await
+                .filter(|stored| vary_matches(stored, &request.headers));
+            if let Some(stored) = hit {
+                let headers = served_headers(&stored, now);
+                let rid = fetch_bodies(&state.borrow())?
+                    .borrow_mut()
+                    .insert(FetchBody {
+                        source: FetchBodySource::Cached(stored.body),
+                        host: host.to_string(),
+                        deadline: None,
+                        received: 0,
+                        max_bytes: options.max_response_bytes,
+                        store: None,
+                    });
+                return Ok(FetchResponse {
+                    rid,
+                    status: stored.status,
+                    status_text: stored.status_text,
+                    headers,
+                    url: url.to_string(),
+                    redirected: false,
+                });
+            }
+        }
+
+        let mut builder = client
+            .client()
+            .request(method.clone(), url.clone())
+            .headers(headers.clone());
+        if let Some(body) = &body {
+            builder = builder.body(body.clone());
+        }
+        let idempotent = IDEMPOTENT_METHODS.contains(&method);
+        let sent = EgressCounts {
+            requests: 1,
+            bytes_sent: body.as_ref().map_or(0, |body| body.len() as u64),
+            bytes_received: 0,
+        };
+        let record = || egress.borrow_mut().record(host, sent);
+        let response = fetch_send(
+            builder,
+            idempotent,
+            retries,
+            retry_backoff,
+            deadline,
+            record,
+        )
+        .await?;
+        check_fetch_response(&response, &options)?;
+
+        let status = response.status();
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .filter(|_| status.is_redirection() && request.redirect != "manual")
+            .map(str::to_string);
+        if let Some(location) = location {
+            if request.redirect == "error" {
+                return Err(type_error(format!(
+                    "fetch failed: redirected to '{}' with redirect mode 'error'",
+                    location
+                )));
+            }
+            redirects += 1;
+            if redirects > max_redirects {
+                return Err(type_error(format!(
+                    "fetch failed: more than {} redirects",
+                    max_redirects
+                )));
+            }
+            let next = url.join(&location).map_err(|_| {
+                type_error(format!("fetch failed: invalid redirect to '{}'", location))
+            })?;
+
+            // 303 (and 301/302 after a POST) turn the request into a body-less GET
+            let to_get = match status.as_u16() {
+                303 => method != reqwest::Method::HEAD,
+                301 | 302 => method == reqwest::Method::POST,
+                _ => false,
+            };
+            if to_get {
+                method = reqwest::Method::GET;
+                body = None;
+                for name in [
+                    "content-type",
+                    "content-length",
+                    "content-encoding",
+                    "content-language",
+                    "content-location",
+                ] {
+                    headers.remove(name);
+                }
+            }
+            // Credentials are not sent on to another origin
+            if next.origin() != url.origin() {
+                headers.remove(reqwest::header::AUTHORIZATION);
+                headers.remove(reqwest::header::PROXY_AUTHORIZATION);
+                headers.remove(reqwest::header::COOKIE);
+            }
+            url = next;
+            continue;
+        }
+
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.as_str().to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+        let status_text = status.canonical_reason().unwrap_or_default().to_string();
+        // Only first-hop responses are stored, under the URL that was asked
+        // for; a redirect is followed again each time
+        let store = match &options.cache {
+            Some(cache)
+                if mode != CacheMode::Bypass && redirects == 0 && !status.is_redirection() =>
+            {
+                let now = Utc::now();
+                let max_bytes = cache.max_entry_bytes();
+                fetch_cache_ttl(status.as_u16(), &headers, now)
+                    .filter(|_| response.content_length().unwrap_or(0) <= max_bytes as u64)
+                    .map(|ttl| FetchCacheWrite {
+                        cache: cache.clone(),
+                        key: url.to_string(),
+                        response: CachedResponse {
+                            status: status.as_u16(),
+                            status_text: status_text.clone(),
+                            headers: headers.clone(),
+                            stored_at_ms: now.timestamp_millis(),
+                            expires_at_ms: now.timestamp_millis() + ttl.as_millis() as i64,
+                            vary: vary_headers(&headers, &request.headers),
+                            body: Vec::new(),
+                        },
+                        max_bytes,
+                    })
+            }
+            _ => None,
+        };
+        let mut fetched = FetchResponse {
+            rid: 0,
+            status: status.as_u16(),
+            status_text,
+            headers,
+            url: url.to_string(),
+            redirected: redirects > 0,
+        };
+        fetched.rid = fetch_bodies(&state.borrow())?
+            .borrow_mut()
+            .insert(FetchBody {
+                source: FetchBodySource::Network(response),
+                host: url.host_str().unwrap_or_default().to_string(),
+                deadline,
+                received: 0,
+                max_bytes: options.max_response_bytes,
+                store,
+            });
+        return Ok(fetched);
+    }
+}
+
+/// The next chunk of a response body, or an empty buffer at the end.
+#[op2(async)]
+#[buffer]
+pub async fn op_fetch_body_read(
+    state: Rc<RefCell<OpState>>,
+    rid: u32,
+) -> Result<Vec<u8>, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.fetch.body")?;
+    let bodies = fetch_bodies(&state.borrow())?;
+    let egress = fetch_egress(&state.borrow())?;
+    // Held outside the RefCell across the await; reads are serialized in JS
+    let Some(mut body) = bodies.borrow_mut().responses.remove(&rid) else {
+        return Ok(Vec::new());
+    };
+    let response = match &mut body.source {
+        FetchBodySource::Network(response) => response,
+        // Not put back, so the next read returns the end of the body
+        FetchBodySource::Cached(cached) => return Ok(std::mem::take(cached)),
+    };
+    loop {
+        let chunk = before_deadline(body.deadline, response.chunk()).await?;
+        match chunk.map_err(fetch_error)? {
+            Some(chunk) if chunk.is_empty() => continue,
+            Some(chunk) => {
+                let received = EgressCounts {
+                    bytes_received: chunk.len() as u64,
+                    ..Default::default()
+                };
+                egress.borrow_mut().record(&body.host, received);
+                body.received += chunk.len();
+                if body.received > body.max_bytes {
+                    return Err(type_error(format!(
+                        "fetch failed: response body is over the {} byte limit",
+                        body.max_bytes
+                    )));
+                }
+                let too_large = body.store.as_mut().is_some_and(|write| {
+                    write.response.body.extend_from_slice(&chunk);
+                    write.response.body.len() > write.max_bytes
+                });
+                if too_large {
+                    body.store = None;
+                }
+                bodies.borrow_mut().responses.insert(rid, body);
+                return Ok(chunk.to_vec());
+            }
+            None => {
+                if let Some(write) = body.store {
+                    fetch_cache_store(&state, write).await;
+                }
+                return Ok(Vec::new());
+            }
+        }
+    }
+}
+
+/// Redis key kind for fetch cache entries, distinct from the Cache API's.
+const FETCH_CACHE_KIND: &str = "fetch-cache";
+
+/// A fresh response stored for `key`. Cache failures only cost a miss.
+async fn fetch_cache_lookup(
+    state: &Rc<RefCell<OpState>>,
+    cache: &FetchCache,
+    key: &str,
+    now: DateTime<Utc>,
+) -> Option<CachedResponse> {
+    match cache {
+        FetchCache::Memory(cache) => cache.get(key, now),
+        FetchCache::Redis => {
+            let (mut connection, key) =
+                redis_key(state, Capability::Net, "fetch", FETCH_CACHE_KIND, key)
+                    .await
+                    .ok()?;
+            let bytes: Option<Vec<u8>> = redis::cmd("GET")
+                .arg(&key)
+                .query_async(&mut connection)
+                .await
+                .ok()?;
+            bytes
+                .and_then(|bytes| CachedResponse::decode(&bytes))
+                .filter(|response| !response.is_expired(now))
+        }
+    }
+}
+
+/// Store a response whose body has been read in full.
+async fn fetch_cache_store(state: &Rc<RefCell<OpState>>, write: FetchCacheWrite) {
+    let now = Utc::now();
+    match write.cache {
+        FetchCache::Memory(cache) => cache.insert(&write.key, write.response, now),
+        FetchCache::Redis => {
+            let ttl_ms = write.response.expires_at_ms - now.timestamp_millis();
+            let Ok((mut connection, key)) = redis_key(
+                state,
+                Capability::Net,
+                "fetch",
+                FETCH_CACHE_KIND,
+                &write.key,
+            )
+            .await
+            else {
+                return;
+            };
+            if ttl_ms > 0 {
+                let _: Result<(), _> = redis::cmd("SET")
+                    .arg(&key)
+                    .arg(write.response.encode())
+                    .arg("PX")
+                    .arg(ttl_ms)
+                    .query_async(&mut connection)
+                    .await;
+            }
+        }
+    }
+}
+
+/// Drop the rest of a response body (it was cancelled, or never read).
+#[op2(fast)]
+pub fn op_fetch_body_close(state: &OpState, rid: u32) {
+    if let Some(bodies) = state.try_borrow::<FetchBodiesState>() {
+        bodies.borrow_mut().responses.remove(&rid);
+    }
+}
+
+/// Where the Cache API keeps one entry.
+enum CacheLocation {
+    Disk(std::path::PathBuf),
+    Redis(MultiplexedConnection, String),
+}
+
+fn response_cache(state: &OpState) -> Result<ResponseCache, AnyError> {
+    check_permission(state, Capability::Storage)?;
+    let cache = state
+        .try_borrow::<ResponseCache>()
+        .cloned()
+        .ok_or_else(|| type_error("caches is not available"))?;
+    let redis = state
+        .try_borrow::<RedisStoreState>()
+        .is_some_and(|store| store.borrow().is_some());
+    if cache.options.dir.is_none() && !redis {
+        return Err(type_error(
+            "caches requires Redis or a cache directory, which is not configured for this function",
+        ));
+    }
+    Ok(cache)
+}
+
+async fn cache_location(
+    state: &Rc<RefCell<OpState>>,
+    cache: &ResponseCache,
+    name: &str,
+    url: &str,
+) -> Result<CacheLocation, AnyError> {
+    let key = ResponseCache::key(name, url);
+    if let Some(path) = cache.path(&key) {
+        return Ok(CacheLocation::Disk(path));
+    }
+    let (connection, key) = redis_key(state, Capability::Storage, "caches", "cache", &key).await?;
+    Ok(CacheLocation::Redis(connection, key))
+}
+
+fn cache_error(error: impl std::fmt::Display) -> AnyError {
+    AnyError::msg(format!("caches: {}", error))
+}
+
+/// A response found by `cache.match()`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheMatch {
+    status: u16,
+    status_text: String,
+    headers: Vec<(String, String)>,
+    body: deno_core::ToJsBuffer,
+}
+
+/// Status and headers of a response given to `cache.put()`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachePut {
+    status: u16,
+    status_text: String,
+    headers: Vec<(String, String)>,
+}
+
+/// Look up the fresh response stored for `url` in the cache `name`.
+#[op2(async)]
+#[serde]
+pub async fn op_cache_match(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+    #[string] url: String,
+) -> Result<Option<CacheMatch>, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.cache.match")?;
+    let cache = response_cache(&state.borrow())?;
+    let now = Utc::now();
+    let response = match cache_location(&state, &cache, &name, &url).await? {
+        CacheLocation::Disk(path) => crate::cache::read_entry(&path, now)
+            .await
+            .map_err(cache_error)?,
+        CacheLocation::Redis(mut connection, key) => {
+            let bytes: Option<Vec<u8>> = redis::cmd("GET")
+                .arg(&key)
+                .query_async(&mut connection)
+                .await
+                .map_err(cache_error)?;
+            bytes
+                .and_then(|bytes| CachedResponse::decode(&bytes))
+                .filter(|response| !response.is_expired(now))
+        }
+    };
+    Ok(response.map(|response| CacheMatch {
+        status: response.status,
+        status_text: response.status_text,
+        headers: response.headers,
+        body: response.body.into(),
+    }))
+}
+
+/// Store a response for `url` in the cache `name` for as long as its headers
+/// allow. Responses that must not be cached are dropped.
+#[op2(async)]
+pub async fn op_cache_put(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+    #[string] url: String,
+    #[serde] response: CachePut,
+    #[buffer] body: JsBuffer,
+) -> Result<(), AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.cache.put")?;
+    let cache = response_cache(&state.borrow())?;
+    if body.len() > cache.options.max_entry_bytes {
+        return Err(range_error(format!(
+            "caches: response body is {} bytes, over the {} byte limit",
+            body.len(),
+            cache.options.max_entry_bytes
+        )));
+    }
+    let now = Utc::now();
+    let Some(ttl) = crate::cache::cache_ttl(&response.headers, cache.options.default_ttl, now)
+    else {
+        return Ok(());
+    };
+    let entry = CachedResponse {
+        status: response.status,
+        status_text: response.status_text,
+        headers: response.headers,
+        stored_at_ms: now.timestamp_millis(),
+        expires_at_ms: now.timestamp_millis() + ttl.as_millis() as i64,
+        vary: Vec::new(),
+        body: body.to_vec(),
+    };
+    match cache_location(&state, &cache, &name, &url).await? {
+        CacheLocation::Disk(path) => crate::cache::write_entry(&path, &entry)
+            .await
+            .map_err(cache_error)?,
+        CacheLocation::Redis(mut connection, key) => redis::cmd("SET")
+            .arg(&key)
+            .arg(entry.encode())
+            .arg("PX")
+            .arg(ttl.as_millis() as u64)
+            .query_async(&mut connection)
+            .await
+            .map_err(cache_error)?,
+    }
+    Ok(())
+}
+
+/// Remove the response stored for `url` in the cache `name`, returning
+/// whether there was one.
+#[op2(async)]
+pub async fn op_cache_delete(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+    #[string] url: String,
+) -> Result<bool, AnyError> {
+    let _guard = PendingOpGuard::acquire(&state, "vortex.cache.delete")?;
+    let cache = response_cache(&state.borrow())?;
+    match cache_location(&state, &cache, &name, &url).await? {
+        CacheLocation::Disk(path) => crate::cache::remove_entry(&path).await.map_err(cache_error),
+        CacheLocation::Redis(mut connection, key) => {
+            let removed: u32 = redis::cmd("DEL")
+                .arg(&key)
+                .query_async(&mut connection)
+                .await
+                .map_err(cache_error)?;
+            Ok(removed > 0)
+        }
+    }
+}
+
+/// Default capacity of the in-memory filesystem behind `vortex.fs`.
+pub const DEFAULT_FS_MAX_BYTES: usize = 16 * 1024 * 1024;
+
+/// In-memory filesystem backing `vortex.fs`, cleared for each invocation.
+///
+/// Only files are stored; directories exist implicitly while they contain a
+/// file. Paths are normalized against a virtual root, so `..` can never reach
+/// anything outside it (and there is nothing outside it to reach).
+#[derive(Debug)]
+pub struct MemFs {
+    files: std::collections::BTreeMap<String, Vec<u8>>,
+    /// Total size of all file contents
+    used: usize,
+    /// Maximum value of `used`
+    pub max_bytes: usize,
+}
+
+impl Default for MemFs {
+    fn default() -> Self {
+        Self::new(DEFAULT_FS_MAX_BYTES)
+    }
+}
+
+/// Metadata returned by `vortex.fs.stat()`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileStat {
+    pub size: usize,
+    pub is_directory: bool,
+}
+
+impl MemFs {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            files: Default::default(),
+            used: 0,
+            max_bytes,
+        }
+    }
+
+    /// Remove every file.
+    pub fn clear(&mut self) {
+        self.files.clear();
+        self.used = 0;
+    }
+
+    /// Resolve `path` against the root, returning e.g. `/tmp/out.csv`.
+    pub fn normalize(path: &str) -> String {
+        let mut parts: Vec<&str> = Vec::new();
+        for part in path.split('/') {
+            match part {
+                "" | "." => {}
+                ".." => {
+                    parts.pop();
+                }
+                part => parts.push(part),
+            }
+        }
+        format!("/{}", parts.join("/"))
+    }
+
+    fn is_directory(&self, path: &str) -> bool {
+        if path == "/" {
+            return true;
+        }
+        let prefix = format!("{}/", path);
+        self.files
+            .range(prefix.clone()..)
+            .next()
+            .is_some_and(|(key, _)| key.starts_with(&prefix))
+    }
+
+    pub fn stat(&self, path: &str) -> Option<FileStat> {
+        let path = Self::normalize(path);
+        if let Some(data) = self.files.get(&path) {
+            return Some(FileStat {
+                size: data.len(),
+                is_directory: false,
+            });
+        }
+        self.is_directory(&path).then_some(FileStat {
+            size: 0,
+            is_directory: true,
+        })
+    }
+
+    pub fn read(&self, path: &str) -> Option<&[u8]> {
+        self.files.get(&Self::normalize(path)).map(Vec::as_slice)
+    }
+
+    /// Create or replace (or, with `append`, extend) the file at `path`.
+    pub fn write(&mut self, path: &str, data: &[u8], append: bool) -> Result<(), AnyError> {
+        let path = Self::normalize(path);
+        if self.is_directory(&path) {
+            return Err(type_error(format!("'{}' is a directory", path)));
+        }
+        // Every ancestor must be a directory, not a file
+        let mut ancestor = path.as_str();
+        while let Some(index) = ancestor.rfind('/').filter(|&index| index > 0) {
+            ancestor = &ancestor[..index];
+            if self.files.contains_key(ancestor) {
+                return Err(type_error(format!("'{}' is not a directory", ancestor)));
+            }
+        }
+
+        let existing = self.files.get(&path).map_or(0, Vec::len);
+        let new_size = if append { existing + data.len() } else { data.len() };
+        let used = self.used - existing + new_size;
+        if used > self.max_bytes {
+            return Err(range_error(format!(
+                "vortex.fs is full (limit: {} bytes)",
+                self.max_bytes
+            )));
+        }
+        self.used = used;
+        let file = self.files.entry(path).or_default();
+        if !append {
+            file.clear();
+        }
+        file.extend_from_slice(data);
+        Ok(())
+    }
+
+    /// Names of the files and directories directly inside `path`.
+    pub fn list(&self, path: &str) -> Vec<String> {
+        let path = Self::normalize(path);
+        let prefix = if path == "/" { path } else { format!("{}/", path) };
+        let mut names: Vec<String> = self
+            .files
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .filter_map(|(key, _)| key[prefix.len()..].split('/').next())
+            .map(String::from)
+            .collect();
+        names.dedup();
+        names
+    }
+
+    /// Remove a file, returning whether it existed.
+    pub fn remove(&mut self, path: &str) -> bool {
+        match self.files.remove(&Self::normalize(path)) {
+            Some(data) => {
+                self.used -= data.len();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Type alias for the filesystem state shared between the worker and ops
+pub type MemFsState = Rc<RefCell<MemFs>>;
+
+fn mem_fs(state: &OpState) -> Result<MemFsState, AnyError> {
+    state
+        .try_borrow::<MemFsState>()
+        .cloned()
+        .ok_or_else(|| type_error("vortex.fs is not available"))
+}
+
+/// `vortex.fs.stat()`; `null` when nothing exists at `path`.
+#[op2]
+#[serde]
+pub fn op_fs_stat(state: &OpState, #[string] path: String) -> Result<Option<FileStat>, AnyError> {
+    Ok(mem_fs(state)?.borrow().stat(&path))
+}
+
+/// Read a whole file from `vortex.fs`.
+#[op2]
+#[buffer]
+pub fn op_fs_read(state: &OpState, #[string] path: String) -> Result<Vec<u8>, AnyError> {
+    let fs = mem_fs(state)?;
+    let fs = fs.borrow();
+    fs.read(&path)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| type_error(format!("No such file: '{}'", MemFs::normalize(&path))))
+}
+
+/// Write (or append to) a file in `vortex.fs`.
+#[op2]
+pub fn op_fs_write(
+    state: &OpState,
+    #[string] path: String,
+    #[buffer] data: &[u8],
+    append: bool,
+) -> Result<(), AnyError> {
+    mem_fs(state)?.borrow_mut().write(&path, data, append)
+}
+
+/// List a directory in `vortex.fs`.
+#[op2]
+#[serde]
+pub fn op_fs_list(state: &OpState, #[string] path: String) -> Result<Vec<String>, AnyError> {
+    Ok(mem_fs(state)?.borrow().list(&path))
+}
+
+/// Delete a file from `vortex.fs`, returning whether it existed.
+#[op2]
+pub fn op_fs_remove(state: &OpState, #[string] path: String) -> Result<bool, AnyError> {
+    Ok(mem_fs(state)?.borrow_mut().remove(&path))
+}
+
+/// Upper bound on the bytes one `DecompressionStream` may produce, so a small
+/// compressed payload cannot inflate into gigabytes of host memory.
+pub const MAX_DECOMPRESSED_BYTES: usize = 256 * 1024 * 1024;
+
+/// Input is fed to decoders in slices of this size so the output limit is
+/// enforced before a single write can blow past it.
+const DECOMPRESS_SLICE: usize = 64 * 1024;
+
+/// One `CompressionStream` or `DecompressionStream`, buffering its output.
+enum Codec {
+    GzipEncoder(GzEncoder<Vec<u8>>),
+    GzipDecoder(GzDecoder<Vec<u8>>),
+    /// `deflate` is the zlib format (RFC 1950)
+    DeflateEncoder(ZlibEncoder<Vec<u8>>),
+    DeflateDecoder(ZlibDecoder<Vec<u8>>),
+    /// `deflate-raw` is bare DEFLATE (RFC 1951)
+    DeflateRawEncoder(DeflateEncoder<Vec<u8>>),
+    DeflateRawDecoder(DeflateDecoder<Vec<u8>>),
+}
+
+/// Run `$body` with `$inner` bound to whichever flate2 writer `$codec` holds.
+macro_rules! with_codec {
+    ($codec:expr, $inner:ident => $body:expr) => {
+        match $codec {
+            Codec::GzipEncoder($inner) => $body,
+            Codec::GzipDecoder($inner) => $body,
+            Codec::DeflateEncoder($inner) => $body,
+            Codec::DeflateDecoder($inner) => $body,
+            Codec::DeflateRawEncoder($inner) => $body,
+            Codec::DeflateRawDecoder($inner) => $body,
+        }
+    };
+}
+
+impl Codec {
+    fn new(format: &str, decompress: bool) -> Result<Self, AnyError> {
+        let level = Compression::default();
+        let codec = match (format, decompress) {
+            ("gzip", false) => Codec::GzipEncoder(GzEncoder::new(Vec::new(), level)),
+            ("gzip", true) => Codec::GzipDecoder(GzDecoder::new(Vec::new())),
+            ("deflate", false) => Codec::DeflateEncoder(ZlibEncoder::new(Vec::new(), level)),
+            ("deflate", true) => Codec::DeflateDecoder(ZlibDecoder::new(Vec::new())),
+            ("deflate-raw", false) => {
+                Codec::DeflateRawEncoder(DeflateEncoder::new(Vec::new(), level))
+            }
+            ("deflate-raw", true) => Codec::DeflateRawDecoder(DeflateDecoder::new(Vec::new())),
+            (other, _) => {
+                return Err(type_error(format!(
+                    "Unsupported compression format: '{}'",
+                    other
+                )))
+            }
+        };
+        Ok(codec)
+    }
+
+    fn is_decoder(&self) -> bool {
+        matches!(
+            self,
+            Codec::GzipDecoder(_) | Codec::DeflateDecoder(_) | Codec::DeflateRawDecoder(_)
+        )
+    }
+
+    /// Feed `data` through the codec and take whatever output is ready.
+    fn write(&mut self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        with_codec!(self, inner => {
+            inner.write_all(data)?;
+            Ok(std::mem::take(inner.get_mut()))
+        })
+    }
+
+    /// Flush the remaining output. Gzip decoders also verify the trailer here.
+    fn finish(self) -> std::io::Result<Vec<u8>> {
+        with_codec!(self, inner => inner.finish())
+    }
+}
+
+/// Open compression streams, cleared for each invocation.
+#[derive(Default)]
+pub struct Compressors {
+    streams: HashMap<u32, Codec>,
+    /// Bytes produced so far by each decompression stream
+    inflated: HashMap<u32, usize>,
+    next_id: u32,
+}
+
+impl Compressors {
+    pub fn clear(&mut self) {
+        self.streams.clear();
+        self.inflated.clear();
+    }
+
+    fn open(&mut self, format: &str, decompress: bool) -> Result<u32, AnyError> {
+        let codec = Codec::new(format, decompress)?;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.streams.insert(self.next_id, codec);
+        Ok(self.next_id)
+    }
+
+    fn write(&mut self, id: u32, data: &[u8]) -> Result<Vec<u8>, AnyError> {
+        let codec = self
+            .streams
+            .get_mut(&id)
+            .ok_or_else(|| type_error("Compression stream is closed"))?;
+        if !codec.is_decoder() {
+            return codec.write(data).map_err(|e| type_error(e.to_string()));
+        }
+
+        let mut output = Vec::new();
+        for slice in data.chunks(DECOMPRESS_SLICE) {
+            let chunk = match codec.write(slice) {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    self.close(id);
+                    return Err(type_error(format!("Failed to decompress data: {}", e)));
+                }
+            };
+            let inflated = self.inflated.entry(id).or_default();
+            *inflated += chunk.len();
+            if *inflated > MAX_DECOMPRESSED_BYTES {
+                self.close(id);
+                return Err(range_error(format!(
+                    "Decompressed data exceeds the limit of {} bytes",
+                    MAX_DECOMPRESSED_BYTES
+                )));
+            }
+            output.extend_from_slice(&chunk);
+        }
+        Ok(output)
+    }
+
+    fn finish(&mut self, id: u32) -> Result<Vec<u8>, AnyError> {
+        let codec = self
+            .streams
+            .remove(&id)
+            .ok_or_else(|| type_error("Compression stream is closed"))?;
+        self.inflated.remove(&id);
+        let decoder = codec.is_decoder();
+        codec.finish().map_err(|e| {
+            if decoder {
+                type_error(format!("Failed to decompress data: {}", e))
+            } else {
+                type_error(e.to_string())
+            }
+        })
+    }
+
+    fn close(&mut self, id: u32) {
+        self.streams.remove(&id);
+        self.inflated.remove(&id);
+    }
+}
+
+/// Type alias for the compression state shared between the worker and ops
+pub type CompressionState = Rc<RefCell<Compressors>>;
+
+fn compressors(state: &OpState) -> Result<CompressionState, AnyError> {
+    state
+        .try_borrow::<CompressionState>()
+        .cloned()
+        .ok_or_else(|| type_error("Compression streams are not available"))
+}
+
+/// Open a `CompressionStream` (or, with `decompress`, a
+/// `DecompressionStream`) for `gzip`, `deflate` or `deflate-raw`.
+#[op2]
+pub fn op_compression_new(
+    state: &OpState,
+    #[string] format: String,
+    decompress: bool,
+) -> Result<u32, AnyError> {
+    compressors(state)?.borrow_mut().open(&format, decompress)
+}
+
+/// Push a chunk through a stream, returning the output it produced (possibly empty).
+#[op2]
+#[buffer]
+pub fn op_compression_write(
+    state: &OpState,
+    id: u32,
+    #[buffer] data: &[u8],
+) -> Result<Vec<u8>, AnyError> {
+    compressors(state)?.borrow_mut().write(id, data)
+}
+
+/// Close a stream, returning its final output.
+#[op2]
+#[buffer]
+pub fn op_compression_finish(state: &OpState, id: u32) -> Result<Vec<u8>, AnyError> {
+    compressors(state)?.borrow_mut().finish(id)
+}
+
+/// Discard a stream without flushing it (the stream was cancelled or aborted).
+#[op2(fast)]
+pub fn op_compression_close(state: &OpState, id: u32) {
+    if let Some(compressors) = state.try_borrow::<CompressionState>() {
+        compressors.borrow_mut().close(id);
+    }
+}
+
+/// Elements whose content is raw text that runs up to their end tag.
+const RAW_TEXT_ELEMENTS: &[&str] = &[
+    "script",
+    "style",
+    "textarea",
+    "title",
+    "xmp",
+    "iframe",
+    "noembed",
+    "noframes",
+    "plaintext",
+];
+
+/// Most input an `HTMLRewriter` holds back while waiting for the end of a
+/// tag or comment.
+const MAX_HTML_BUFFERED_BYTES: usize = 1024 * 1024;
+
+/// A token produced by [`HtmlTokenizer`] for `HTMLRewriter`.
+///
+/// `raw` is the exact source text, so tokens nobody changed are written back
+/// byte for byte. Attribute values are not entity-decoded.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum HtmlToken {
+    #[serde(rename_all = "camelCase")]
+    StartTag {
+        raw: String,
+        name: String,
+        attributes: Vec<(String, String)>,
+        self_closing: bool,
+    },
+    EndTag {
+        raw: String,
+        name: String,
+    },
+    /// Text arrives in chunks; `last` is set on the final chunk of a run
+    Text {
+        raw: String,
+        last: bool,
+    },
+    Comment {
+        raw: String,
+    },
+    Doctype {
+        raw: String,
+    },
+}
+
+/// Splits a stream of HTML into tokens as chunks arrive.
+///
+/// This is a tokenizer, not a tree builder: it knows about raw text elements
+/// such as `<script>`, but leaves matching end tags (and elements closed
+/// implicitly) to the JavaScript side.
+#[derive(Debug, Default)]
+pub struct HtmlTokenizer {
+    /// Decoded input not yet turned into tokens
+    buffer: String,
+    /// Trailing bytes of an incomplete UTF-8 sequence
+    pending: Vec<u8>,
+    /// Set inside a raw text element, to the name of its end tag
+    raw_text: Option<String>,
+    /// Set while a run of text has been emitted without its last chunk
+    in_text: bool,
+}
+
+enum Markup {
+    Token(HtmlToken, usize),
+    /// Needs more input to decide
+    Incomplete,
+    /// A `<` that does not start markup
+    Text,
+}
+
+impl HtmlTokenizer {
+    pub fn write(&mut self, data: &[u8]) -> Result<Vec<HtmlToken>, String> {
+        self.pending.extend_from_slice(data);
+        let complete = self.pending.len() - incomplete_utf8_tail(&self.pending);
+        let rest = self.pending.split_off(complete);
+        self.buffer
+            .push_str(&String::from_utf8_lossy(&std::mem::replace(
+                &mut self.pending,
+                rest,
+            )));
+        let tokens = self.tokens(false);
+        if self.buffer.len() > MAX_HTML_BUFFERED_BYTES {
+            return Err(format!(
+                "markup exceeds the limit of {} bytes",
+                MAX_HTML_BUFFERED_BYTES
+            ));
+        }
+        Ok(tokens)
+    }
+
+    /// Tokenize whatever is left; an unterminated tag or comment becomes text.
+    pub fn finish(&mut self) -> Vec<HtmlToken> {
+        let pending = std::mem::take(&mut self.pending);
+        self.buffer.push_str(&String::from_utf8_lossy(&pending));
+        let mut tokens = self.tokens(true);
+        if self.in_text {
+            self.text(&mut tokens, "", true);
+        }
+        tokens
+    }
+
+    fn text(&mut self, tokens: &mut Vec<HtmlToken>, raw: &str, last: bool) {
+        if raw.is_empty() && !(last && self.in_text) {
+            return;
+        }
+        tokens.push(HtmlToken::Text {
+            raw: raw.to_string(),
+            last,
+        });
+        self.in_text = !last;
+    }
+
+    fn tokens(&mut self, eof: bool) -> Vec<HtmlToken> {
+        let input = std::mem::take(&mut self.buffer);
+        let mut tokens = Vec::new();
+        // Text in `input[pos..scan]` has been seen but not emitted yet
+        let mut pos = 0;
+        let mut scan = 0;
+        while pos < input.len() {
+            if let Some(element) = &self.raw_text {
+                let rest = &input[pos..];
+                match find_end_tag(rest, element) {
+                    Some(end) => {
+                        self.text(&mut tokens, &rest[..end], true);
+                        self.raw_text = None;
+                        pos += end;
+                    }
+                    None if eof => {
+                        self.text(&mut tokens, rest, true);
+                        pos = input.len();
+                    }
+                    None => {
+                        // Hold back what could be the start of the end tag
+                        let mut cut = rest.len().saturating_sub(element.len() + 2);
+                        while !rest.is_char_boundary(cut) {
+                            cut -= 1;
+                        }
+                        self.text(&mut tokens, &rest[..cut], false);
+                        pos += cut;
+                        break;
+                    }
+                }
+                scan = pos;
+                continue;
+            }
+
+            let Some(at) = input[scan..].find('<').map(|i| scan + i) else {
+                // More text may follow in the next chunk
+                self.text(&mut tokens, &input[pos..], eof);
+                pos = input.len();
+                break;
+            };
+            match markup(&input[at..], eof) {
+                Markup::Token(token, len) => {
+                    self.text(&mut tokens, &input[pos..at], true);
+                    if let HtmlToken::StartTag {
+                        name, self_closing, ..
+                    } = &token
+                    {
+                        if !self_closing && RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                            self.raw_text = Some(name.clone());
+                        }
+                    }
+                    tokens.push(token);
+                    pos = at + len;
+                    scan = pos;
+                }
+                Markup::Text => scan = at + 1,
+                // An unterminated tag or comment at the very end is text
+                Markup::Incomplete if eof => {
+                    self.text(&mut tokens, &input[pos..], true);
+                    pos = input.len();
+                }
+                Markup::Incomplete => {
+                    self.text(&mut tokens, &input[pos..at], false);
+                    pos = at;
+                    break;
+                }
+            }
+        }
+        self.buffer = input[pos..].to_string();
+        tokens
+    }
+}
+
+/// Length of an incomplete UTF-8 sequence at the end of `bytes`.
+fn incomplete_utf8_tail(bytes: &[u8]) -> usize {
+    for len in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - len];
+        if byte & 0xc0 != 0x80 {
+            let needed = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            return if needed > len { len } else { 0 };
+        }
+    }
+    0
+}
+
+fn is_space(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\n' | b'\r' | b'\x0c')
+}
+
+/// Offset of the `</element` that ends a raw text element, if it is in `text`.
+fn find_end_tag(text: &str, element: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    text.match_indices("</").map(|(i, _)| i).find(|&i| {
+        let name = i + 2..i + 2 + element.len();
+        bytes
+            .get(name.clone())
+            .is_some_and(|name| name.eq_ignore_ascii_case(element.as_bytes()))
+            && bytes
+                .get(name.end)
+                .is_some_and(|&b| is_space(b) || b == b'/' || b == b'>')
+    })
+}
+
+/// Parse the markup at the start of `rest`, which begins with `<`.
+fn markup(rest: &str, eof: bool) -> Markup {
+    let bytes = rest.as_bytes();
+    let until = |from: usize, terminator: &str| {
+        rest.get(from..)
+            .and_then(|tail| tail.find(terminator))
+            .map(|i| from + i + terminator.len())
+    };
+    let Some(&next) = bytes.get(1) else {
+        return if eof {
+            Markup::Text
+        } else {
+            Markup::Incomplete
+        };
+    };
+    let token = match next {
+        b'!' if rest.starts_with("<!--") => until(4, "-->").map(|len| {
+            (
+                HtmlToken::Comment {
+                    raw: rest[..len].to_string(),
+                },
+                len,
+            )
+        }),
+        // Could still become `<!--`
+        b'!' if "<!--".starts_with(rest) => None,
+        b'!' | b'?' => until(2, ">").map(|len| {
+            let raw = rest[..len].to_string();
+            let doctype = raw.len() >= 9 && raw[..9].eq_ignore_ascii_case("<!doctype");
+            let token = if doctype {
+                HtmlToken::Doctype { raw }
+            } else {
+                HtmlToken::Comment { raw }
+            };
+            (token, len)
+        }),
+        b'/' => match bytes.get(2) {
+            None => None,
+            Some(b) if b.is_ascii_alphabetic() => until(2, ">").map(|len| {
+                let name_end = rest[2..]
+                    .find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+                    .map_or(len, |i| i + 2);
+                let token = HtmlToken::EndTag {
+                    raw: rest[..len].to_string(),
+                    name: rest[2..name_end].to_ascii_lowercase(),
+                };
+                (token, len)
+            }),
+            Some(b'>') => return Markup::Text,
+            // A bogus comment, e.g. `</3>`
+            Some(_) => until(2, ">").map(|len| {
+                (
+                    HtmlToken::Comment {
+                        raw: rest[..len].to_string(),
+                    },
+                    len,
+                )
+            }),
+        },
+        b if b.is_ascii_alphabetic() => start_tag(rest),
+        _ => return Markup::Text,
+    };
+    match token {
+        Some((token, len)) => Markup::Token(token, len),
+        None => Markup::Incomplete,
+    }
+}
+
+/// Parse a start tag; `None` if it continues past the end of `rest`.
+fn start_tag(rest: &str) -> Option<(HtmlToken, usize)> {
+    let bytes = rest.as_bytes();
+    let skip = |mut i: usize, stop: &dyn Fn(u8) -> bool| {
+        while i < bytes.len() && !stop(bytes[i]) {
+            i += 1;
+        }
+        i
+    };
+    let mut i = skip(1, &|b| is_space(b) || b == b'/' || b == b'>');
+    let name = rest[1..i].to_ascii_lowercase();
+    let mut attributes: Vec<(String, String)> = Vec::new();
+    let mut self_closing = false;
+    loop {
+        i = skip(i, &|b| !is_space(b));
+        match *bytes.get(i)? {
+            b'>' => {
+                i += 1;
+                break;
+            }
+            b'/' => {
+                if *bytes.get(i + 1)? == b'>' {
+                    self_closing = true;
+                    i += 2;
+                    break;
+                }
+                i += 1;
+            }
+            _ => {
+                let start = i;
+                i = skip(i + 1, &|b| is_space(b) || matches!(b, b'/' | b'>' | b'='));
+                let attribute = rest[start..i].to_ascii_lowercase();
+                let mut value = "";
+                let after_name = skip(i, &|b| !is_space(b));
+                if *bytes.get(after_name)? == b'=' {
+                    let start = skip(after_name + 1, &|b| !is_space(b));
+                    match *bytes.get(start)? {
+                        quote @ (b'"' | b'\'') => {
+                            let len = rest[start + 1..].find(quote as char)?;
+                            value = &rest[start + 1..start + 1 + len];
+                            i = start + len + 2;
+                        }
+                        _ => {
+                            i = skip(start, &|b| is_space(b) || b == b'>');
+                            bytes.get(i)?;
+                            value = &rest[start..i];
+                        }
+                    }
+                }
+                // The first of duplicate attributes wins
+                if !attributes.iter().any(|(name, _)| *name == attribute) {
+                    attributes.push((attribute, value.to_string()));
+                }
+            }
+        }
+    }
+    let token = HtmlToken::StartTag {
+        raw: rest[..i].to_string(),
+        name,
+        attributes,
+        self_closing,
+    };
+    Some((token, i))
+}
+
+/// Open `HTMLRewriter` tokenizers, cleared for each invocation.
+#[derive(Debug, Default)]
+pub struct HtmlTokenizers {
+    tokenizers: HashMap<u32, HtmlTokenizer>,
+    next_id: u32,
+}
+
+impl HtmlTokenizers {
+    pub fn clear(&mut self) {
+        self.tokenizers.clear();
+    }
+
+    fn open(&mut self) -> u32 {
+        self.next_id = self.next_id.wrapping_add(1);
+        self.tokenizers
+            .insert(self.next_id, HtmlTokenizer::default());
+        self.next_id
+    }
+
+    fn get(&mut self, id: u32) -> Result<&mut HtmlTokenizer, AnyError> {
+        self.tokenizers
+            .get_mut(&id)
+            .ok_or_else(|| type_error("HTMLRewriter stream is closed"))
+    }
+}
+
+/// Type alias for the HTML tokenizers shared between the worker and ops
+pub type HtmlState = Rc<RefCell<HtmlTokenizers>>;
+
+fn html_tokenizers(state: &OpState) -> Result<HtmlState, AnyError> {
+    state
+        .try_borrow::<HtmlState>()
+        .cloned()
+        .ok_or_else(|| type_error("HTMLRewriter is not available"))
+}
+
+/// Start tokenizing a document for `HTMLRewriter.transform()`.
+#[op2(fast)]
+pub fn op_html_tokenizer_new(state: &OpState) -> Result<u32, AnyError> {
+    Ok(html_tokenizers(state)?.borrow_mut().open())
+}
+
+/// Feed a chunk of the document, returning the tokens it completed.
+#[op2]
+#[serde]
+pub fn op_html_tokenizer_write(
+    state: &OpState,
+    id: u32,
+    #[buffer] data: &[u8],
+) -> Result<Vec<HtmlToken>, AnyError> {
+    let tokenizers = html_tokenizers(state)?;
+    let mut tokenizers = tokenizers.borrow_mut();
+    let tokens = tokenizers.get(id)?.write(data);
+    tokens.map_err(|e| {
+        tokenizers.tokenizers.remove(&id);
+        range_error(format!("HTMLRewriter: {}", e))
+    })
+}
+
+/// End the document, returning its remaining tokens.
+#[op2]
+#[serde]
+pub fn op_html_tokenizer_finish(state: &OpState, id: u32) -> Result<Vec<HtmlToken>, AnyError> {
+    let tokenizers = html_tokenizers(state)?;
+    let mut tokenizers = tokenizers.borrow_mut();
+    let tokens = tokenizers.get(id)?.finish();
+    tokenizers.tokenizers.remove(&id);
+    Ok(tokens)
+}
+
+/// Discard a tokenizer (the transform was cancelled or a handler threw).
+#[op2(fast)]
+pub fn op_html_tokenizer_close(state: &OpState, id: u32) {
+    if let Some(tokenizers) = state.try_borrow::<HtmlState>() {
+        tokenizers.borrow_mut().tokenizers.remove(&id);
+    }
+}
+
+/// The components of a parsed URL, as exposed by the JavaScript `URL` class.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UrlParts {
+    pub href: String,
+    pub origin: String,
+    pub protocol: String,
+    pub username: String,
+    pub password: String,
+    pub host: String,
+    pub hostname: String,
+    pub port: String,
+    pub pathname: String,
+    pub search: String,
+    pub hash: String,
+}
+
+impl From<&Url> for UrlParts {
+    fn from(url: &Url) -> Self {
+        Self {
+            href: quirks::href(url).to_string(),
+            origin: quirks::origin(url),
+            protocol: quirks::protocol(url).to_string(),
+            username: quirks::username(url).to_string(),
+            password: quirks::password(url).to_string(),
+            host: quirks::host(url).to_string(),
+            hostname: quirks::hostname(url).to_string(),
+            port: quirks::port(url).to_string(),
+            pathname: quirks::pathname(url).to_string(),
+            search: quirks::search(url).to_string(),
+            hash: quirks::hash(url).to_string(),
+        }
+    }
+}
+
+/// Parse a URL (optionally relative to a base) following the WHATWG URL spec.
+///
+/// # Arguments
+/// * `href` - The URL string to parse
+/// * `base` - Optional base URL for resolving relative references
+#[op2]
+#[serde]
+pub fn op_url_parse(
+    #[string] href: String,
+    #[serde] base: Option<String>,
+) -> Result<UrlParts, AnyError> {
+    let parsed = match base {
+        Some(base) => Url::parse(&base).and_then(|base| base.join(&href)),
+        None => Url::parse(&href),
+    };
+    parsed
+        .map(|url| UrlParts::from(&url))
+        .map_err(|_| type_error(format!("Invalid URL: '{}'", href)))
+}
+
+/// Update a single component of a URL using the WHATWG setter semantics.
+///
+/// Invalid values are ignored (as the spec requires for URL setters), in which
+/// case the original components are returned unchanged.
+#[op2]
+#[serde]
+pub fn op_url_set(
+    #[string] href: String,
+    #[string] component: String,
+    #[string] value: String,
+) -> Result<UrlParts, AnyError> {
+    let mut url = Url::parse(&href).map_err(|_| type_error(format!("Invalid URL: '{}'", href)))?;
+    let _ = match component.as_str() {
+        "protocol" => quirks::set_protocol(&mut url, &value),
+        "username" => quirks::set_username(&mut url, &value),
+        "password" => quirks::set_password(&mut url, &value),
+        "host" => quirks::set_host(&mut url, &value),
+        "hostname" => quirks::set_hostname(&mut url, &value),
+        "port" => quirks::set_port(&mut url, &value),
+        "pathname" => {
+            quirks::set_pathname(&mut url, &value);
+            Ok(())
+        }
+        "search" => {
+            quirks::set_search(&mut url, &value);
+            Ok(())
+        }
+        "hash" => {
+            quirks::set_hash(&mut url, &value);
+            Ok(())
+        }
+        other => return Err(type_error(format!("Unknown URL component: '{}'", other))),
+    };
+    Ok(UrlParts::from(&url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_entry_creation() {
+        let entry = LogEntry::new(3, "test message".to_string());
+        assert_eq!(entry.seq, 3);
+        assert_eq!(entry.message, "test message");
+        // Timestamp should be recent (within last second)
+        let now = Utc::now();
+        let diff = now.signed_duration_since(entry.timestamp);
+        assert!(diff.num_seconds() < 1);
+    }
+
+    #[test]
+    fn test_published_log_entry() {
+        let entry = LogEntry {
+            invocation_id: "inv-1".to_string(),
+            level: LogLevel::Error,
+            ..LogEntry::new(4, "boom".to_string())
+        };
+        let published = PublishedLogEntry {
+            function_id: "fn-1",
+            entry: &entry,
+        };
+        let json = serde_json::to_value(&published).unwrap();
+        assert_eq!(json["function_id"], "fn-1");
+        assert_eq!(json["invocation_id"], "inv-1");
+        assert_eq!(json["level"], "error");
+        assert_eq!(json["seq"], 4);
+        assert_eq!(json["message"], "boom");
+    }
+
+    #[test]
+    fn test_get_time_ms_logic() {
+        // Test the underlying time logic (can't call op-decorated function directly)
+        let get_time = || {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as f64)
+                .unwrap_or(0.0)
+        };
+        
+        let time1 = get_time();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let time2 = get_time();
+        assert!(time2 > time1);
+        assert!(time2 - time1 >= 10.0);
+    }
+
+    #[tokio::test]
+    async fn test_op_sleep_logic() {
+        // Test the underlying sleep logic
+        let start = std::time::Instant::now();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let elapsed = start.elapsed().as_millis();
+        assert!(elapsed >= 50);
+        assert!(elapsed < 100); // Should be reasonably close
+    }
+
+    #[test]
+    fn test_bootstrap_config_serializes_camel_case() {
+        let config = BootstrapConfig {
+            node_compat: true,
+            commonjs: false,
+            env: HashMap::from([("KEY".to_string(), "value".to_string())]),
+            harden: false,
+            disable_code_generation: false,
+            max_pending_ops: None,
+            actor_id: None,
+            apis: None,
+            temporal: false,
+        };
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["nodeCompat"], serde_json::json!(true));
+        assert_eq!(json["env"]["KEY"], serde_json::json!("value"));
+    }
+
+    #[test]
+    fn test_pending_op_guard_enforces_limit() {
+        let mut op_state = OpState::new(None);
+        op_state.put(PendingOps {
+            limit: Some(2),
+            ..Default::default()
+        });
+        let state = Rc::new(RefCell::new(op_state));
+
+        let first = PendingOpGuard::acquire(&state, "vortex.fetch.request").unwrap();
+        let second = PendingOpGuard::acquire(&state, "vortex.fetch.request").unwrap();
+        assert!(PendingOpGuard::acquire(&state, "vortex.fetch.request").is_err());
+
+        drop(first);
+        assert!(PendingOpGuard::acquire(&state, "vortex.fetch.request").is_ok());
+        drop(second);
+        assert_eq!(state.borrow().borrow::<PendingOps>().count, 0);
+    }
+
+    #[test]
+    fn test_pending_ops_report() {
+        let mut op_state = OpState::new(None);
+        op_state.put(PendingOps::default());
+        let state = Rc::new(RefCell::new(op_state));
+
+        let first = PendingOpGuard::acquire(&state, "vortex.fetch.request")
+            .unwrap()
+            .unwrap();
+        first.describe("api.example.com");
+        std::thread::sleep(Duration::from_millis(20));
+        let second = PendingOpGuard::acquire(&state, "vortex.fetch.request")
+            .unwrap()
+            .unwrap();
+        second.describe("api.example.com");
+        let _timer = PendingOpGuard::acquire(&state, "vortex.timers.sleep").unwrap();
+
+        let report = state.borrow().borrow::<PendingOps>().report();
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].api, "vortex.fetch.request");
+        assert_eq!(report[0].detail.as_deref(), Some("api.example.com"));
+        assert_eq!(report[0].count, 2);
+        assert!(report[0].oldest_ms >= 20);
+        assert_eq!(report[1].api, "vortex.timers.sleep");
+
+        drop((first, second));
+        let report = state.borrow().borrow::<PendingOps>().report();
+        assert_eq!(report.len(), 1);
+    }
+
+    #[test]
+    fn test_pending_ops_reset() {
+        let mut op_state = OpState::new(None);
+        op_state.put(PendingOps {
+            limit: Some(1),
+            ..Default::default()
+        });
+        let state = Rc::new(RefCell::new(op_state));
+
+        let timer = PendingOpGuard::acquire(&state, "vortex.timers.sleep")
+            .unwrap()
+            .unwrap();
+        let cancel = Rc::new(CancelHandle::new());
+        timer.cancel_on_reset(cancel.clone());
+        assert!(PendingOpGuard::acquire(&state, "vortex.timers.sleep").is_err());
+
+        // The next invocation starts with nothing pending, and the leftover
+        // timer is cancelled rather than left to fire
+        state.borrow_mut().borrow_mut::<PendingOps>().reset();
+        assert!(cancel.is_canceled());
+        let fresh = PendingOpGuard::acquire(&state, "vortex.timers.sleep").unwrap();
+        assert!(fresh.is_some());
+
+        // Dropping the leftover op doesn't release the new one's slot
+        drop(timer);
+        assert_eq!(state.borrow().borrow::<PendingOps>().count, 1);
+        assert!(PendingOpGuard::acquire(&state, "vortex.timers.sleep").is_err());
+        drop(fresh);
+        assert_eq!(state.borrow().borrow::<PendingOps>().count, 0);
+    }
+
+    #[test]
+    fn test_url_parts_from_url() {
+        let url = Url::parse("https://user:pw@example.com:8080/a/b?x=1#frag").unwrap();
+        let parts = UrlParts::from(&url);
+        assert_eq!(parts.protocol, "https:");
+        assert_eq!(parts.username, "user");
+        assert_eq!(parts.host, "example.com:8080");
+        assert_eq!(parts.hostname, "example.com");
+        assert_eq!(parts.port, "8080");
+        assert_eq!(parts.pathname, "/a/b");
+        assert_eq!(parts.search, "?x=1");
+        assert_eq!(parts.hash, "#frag");
+        assert_eq!(parts.origin, "https://example.com:8080");
+    }
+
+    #[test]
+    fn test_mem_fs() {
+        let mut fs = MemFs::new(8);
+        assert_eq!(MemFs::normalize("../../etc/./passwd"), "/etc/passwd");
+
+        fs.write("tmp/a.txt", b"abc", false).unwrap();
+        fs.write("/tmp/a.txt", b"de", true).unwrap();
+        fs.write("/tmp/sub/b", b"x", false).unwrap();
+        assert_eq!(fs.read("/tmp/a.txt"), Some(&b"abcde"[..]));
+        assert_eq!(fs.list("/tmp"), ["a.txt", "sub"]);
+        assert_eq!(fs.list("/"), ["tmp"]);
+        assert!(fs.stat("/tmp/sub").unwrap().is_directory);
+        assert!(fs.write("/tmp/a.txt/c", b"", false).is_err());
+        assert!(fs.write("/tmp", b"", false).is_err());
+
+        // 6 of 8 bytes used
+        assert!(fs.write("/big", b"xyz", false).is_err());
+        assert!(fs.remove("/tmp/a.txt"));
+        fs.write("/big", b"xyz", false).unwrap();
+        assert!(fs.stat("/tmp/a.txt").is_none());
+    }
+
+    #[test]
+    fn test_compressors() {
+        let mut compressors = Compressors::default();
+        let payload = b"webhook payload ".repeat(100);
+
+        for format in ["gzip", "deflate", "deflate-raw"] {
+            let id = compressors.open(format, false).unwrap();
+            let mut compressed = compressors.write(id, &payload).unwrap();
+            compressed.extend(compressors.finish(id).unwrap());
+            assert!(compressed.len() < payload.len());
+
+            let id = compressors.open(format, true).unwrap();
+            let mut inflated = Vec::new();
+            for chunk in compressed.chunks(7) {
+                inflated.extend(compressors.write(id, chunk).unwrap());
+            }
+            inflated.extend(compressors.finish(id).unwrap());
+            assert_eq!(inflated, payload, "{} round trip", format);
+        }
+
+        assert!(compressors.open("brotli", false).is_err());
+        let id = compressors.open("gzip", true).unwrap();
+        assert!(compressors.write(id, b"not gzip at all").is_err());
+        // A failed stream is closed
+        assert!(compressors.finish(id).is_err());
+    }
+
+    #[test]
+    fn test_parse_multipart() {
+        let boundary = multipart_boundary("multipart/form-data; boundary=\"----xyz\"").unwrap();
+        assert_eq!(boundary, "----xyz");
+        assert!(multipart_boundary("application/json").is_none());
+        assert!(multipart_boundary("multipart/form-data").is_none());
+
+        let body = b"preamble\r\n------xyz\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            hello\r\n------xyz\r\n\
+            Content-Disposition: form-data; name=\"upload\"; filename=\"a \\\"b\\\".txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            line 1\r\nline 2\r\n------xyz--\r\n";
+        let parts = parse_multipart(body, &boundary, 64).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].0.name, "title");
+        assert_eq!(parts[0].0.filename, None);
+        assert_eq!(parts[0].1, b"hello");
+        assert_eq!(
+            parts[1].0,
+            FormPartHeaders {
+                name: "upload".to_string(),
+                filename: Some("a \"b\".txt".to_string()),
+                content_type: Some("text/plain".to_string()),
+            }
+        );
+        assert_eq!(parts[1].1, b"line 1\r\nline 2");
+
+        let error = parse_multipart(body, &boundary, 8).unwrap_err();
+        assert!(error.contains("'upload' exceeds the limit of 8 bytes"), "{}", error);
+        assert!(parse_multipart(&body[..60], &boundary, 64).is_err());
+        assert!(parse_multipart(b"------xyz--", &boundary, 64).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_html_tokenizer() {
+        let html = "<!DOCTYPE html><title>a < b</title><script>if (a<b) x('</p>')</script>\
+            <p id=intro data-x='1>2' ID=dup>Héllo <b>wörld</b><br/><!-- note --></p>";
+        let tokenize = |chunk_size: usize| {
+            let mut tokenizer = HtmlTokenizer::default();
+            let mut tokens = Vec::new();
+            for chunk in html.as_bytes().chunks(chunk_size) {
+                tokens.extend(tokenizer.write(chunk).unwrap());
+            }
+            tokens.extend(tokenizer.finish());
+            // Join the chunks of each run of text
+            let mut merged: Vec<HtmlToken> = Vec::new();
+            for token in tokens {
+                if let (
+                    HtmlToken::Text { raw, last },
+                    Some(HtmlToken::Text {
+                        raw: run,
+                        last: run_last,
+                    }),
+                ) = (&token, merged.last_mut())
+                {
+                    if !*run_last {
+                        run.push_str(raw);
+                        *run_last = *last;
+                        continue;
+                    }
+                }
+                merged.push(token);
+            }
+            merged
+        };
+
+        let tokens = tokenize(html.len());
+        // Splitting mid-tag and mid-character gives the same tokens
+        assert_eq!(tokenize(1), tokens);
+        assert_eq!(tokenize(3), tokens);
+
+        let text = |raw: &str| HtmlToken::Text {
+            raw: raw.to_string(),
+            last: true,
+        };
+        assert_eq!(
+            tokens[0],
+            HtmlToken::Doctype {
+                raw: "<!DOCTYPE html>".to_string()
+            }
+        );
+        assert_eq!(tokens[2], text("a < b"));
+        assert_eq!(tokens[5], text("if (a<b) x('</p>')"));
+        assert_eq!(
+            tokens[7],
+            HtmlToken::StartTag {
+                raw: "<p id=intro data-x='1>2' ID=dup>".to_string(),
+                name: "p".to_string(),
+                attributes: vec![
+                    ("id".to_string(), "intro".to_string()),
+                    ("data-x".to_string(), "1>2".to_string()),
+                ],
+                self_closing: false,
+            }
+        );
+        assert_eq!(tokens[8], text("Héllo "));
+        assert!(
+            matches!(&tokens[12], HtmlToken::StartTag { name, self_closing: true, .. } if name == "br")
+        );
+        assert_eq!(
+            tokens[13],
+            HtmlToken::Comment {
+                raw: "<!-- note -->".to_string()
+            }
+        );
+        assert_eq!(tokens.len(), 15);
+
+        // An unterminated tag at the end is text
+        let mut tokenizer = HtmlTokenizer::default();
+        assert_eq!(
+            tokenizer.write(b"a<div class=\"x").unwrap(),
+            vec![HtmlToken::Text {
+                raw: "a".to_string(),
+                last: false,
+            }]
+        );
+        assert_eq!(tokenizer.finish(), vec![text("<div class=\"x")]);
     }
 }
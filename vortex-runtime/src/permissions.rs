@@ -0,0 +1,203 @@
+//! Capability-based permission policy for privileged ops.
+//!
+//! Every op that reaches outside the isolate (network, KV, SQL, storage) must
+//! call [`check_permission`] (or [`check_net`]) before doing any work. Ops on
+//! the worker's Redis store (`vortex.ratelimit`, `vortex.lock`, `caches`, the
+//! fetch cache) get the check from the helper in ops.rs that hands out Redis
+//! keys, so they can't reach Redis without it. The host decides per function
+//! / plan tier which capabilities are granted by passing a [`Permissions`]
+//! value in `WorkerOptions`.
+//!
+//! `vortex.audit` and an actor's `state.storage` are not capabilities: they
+//! only exist when the host configures an audit log for the function or pins
+//! the isolate to an actor, and are otherwise unavailable.
+//!
+//! The default policy denies everything, so an op added without a matching
+//! grant is unavailable to tenants rather than implicitly allowed.
+//!
+//! A plan tier can also hide whole `vortex.*` namespaces with
+//! [`Permissions::apis`]: code that touches one gets a
+//! `VortexCapabilityError` naming it, instead of a failing op.
+
+use std::fmt;
+
+use deno_core::error::{custom_error, AnyError};
+use deno_core::OpState;
+use serde::{Deserialize, Serialize};
+
+/// JavaScript error class thrown when an op is denied.
+/// Registered with `Deno.core.registerErrorClass` in bootstrap.js.
+pub const PERMISSION_DENIED_CLASS: &str = "PermissionDenied";
+
+/// A privileged capability that can be granted to user code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Outbound network access (fetch)
+    Net,
+    /// Key-value storage (`vortex.kv`), and the Redis-backed
+    /// `vortex.ratelimit` and `vortex.lock`
+    Kv,
+    /// SQL databases (`vortex.sql`, `vortex.sqlite`)
+    Sql,
+    /// Blob/object storage and the response cache (`caches`)
+    Storage,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Capability::Net => "net",
+            Capability::Kv => "kv",
+            Capability::Sql => "sql",
+            Capability::Storage => "storage",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The set of capabilities granted to a worker.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Permissions {
+    /// Hosts that outbound requests may reach. Empty denies all network access;
+    /// an entry of `"*"` allows any host. Entries may use a leading wildcard
+    /// (`*.example.com`) to match subdomains.
+    #[serde(default)]
+    pub net: Vec<String>,
+    /// Allow access to the KV store
+    #[serde(default)]
+    pub kv: bool,
+    /// Allow access to SQL databases
+    #[serde(default)]
+    pub sql: bool,
+    /// Allow access to blob/object storage and the response cache
+    #[serde(default)]
+    pub storage: bool,
+    /// `vortex.*` namespaces that exist for user code, e.g. `["kv",
+    /// "crypto"]`; `None` exposes all of them. Accessing any other namespace
+    /// throws a `VortexCapabilityError`. `vortex.log`, `write`, `end`,
+    /// `setResult` and `sse` are always available.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apis: Option<Vec<String>>,
+}
+
+impl Permissions {
+    /// A policy that grants every capability. Intended for trusted code and tests.
+    pub fn allow_all() -> Self {
+        Self {
+            net: vec!["*".to_string()],
+            kv: true,
+            sql: true,
+            storage: true,
+            apis: None,
+        }
+    }
+
+    /// Whether the given capability is granted at all.
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Net => !self.net.is_empty(),
+            Capability::Kv => self.kv,
+            Capability::Sql => self.sql,
+            Capability::Storage => self.storage,
+        }
+    }
+
+    /// Whether outbound requests to `host` are allowed.
+    pub fn allows_host(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.net.iter().any(|pattern| {
+            let pattern = pattern.to_ascii_lowercase();
+            if pattern == "*" {
+                true
+            } else if let Some(suffix) = pattern.strip_prefix("*.") {
+                host.ends_with(&format!(".{}", suffix))
+            } else {
+                host == pattern
+            }
+        })
+    }
+
+    /// Fail with a `PermissionDenied` error unless `capability` is granted.
+    pub fn check(&self, capability: Capability) -> Result<(), AnyError> {
+        if self.allows(capability) {
+            Ok(())
+        } else {
+            Err(permission_denied(format!(
+                "Requires {} access, which is not granted to this function",
+                capability
+            )))
+        }
+    }
+
+    /// Fail with a `PermissionDenied` error unless `host` is on the net allowlist.
+    pub fn check_host(&self, host: &str) -> Result<(), AnyError> {
+        if self.allows_host(host) {
+            Ok(())
+        } else {
+            Err(permission_denied(format!(
+                "Requires net access to \"{}\", which is not granted to this function",
+                host
+            )))
+        }
+    }
+}
+
+fn permission_denied(message: String) -> AnyError {
+    custom_error(PERMISSION_DENIED_CLASS, message)
+}
+
+/// Check a capability against the worker's `Permissions` in OpState.
+///
+/// Fails closed: if no policy is present, the capability is denied.
+pub fn check_permission(state: &OpState, capability: Capability) -> Result<(), AnyError> {
+    match state.try_borrow::<Permissions>() {
+        Some(permissions) => permissions.check(capability),
+        None => Permissions::default().check(capability),
+    }
+}
+
+/// Check outbound network access to `host` against the worker's `Permissions`.
+pub fn check_net(state: &OpState, host: &str) -> Result<(), AnyError> {
+    match state.try_borrow::<Permissions>() {
+        Some(permissions) => permissions.check_host(host),
+        None => Permissions::default().check_host(host),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_denies_everything() {
+        let permissions = Permissions::default();
+        for capability in [Capability::Net, Capability::Kv, Capability::Sql, Capability::Storage] {
+            assert!(permissions.check(capability).is_err());
+        }
+        assert!(permissions.check_host("example.com").is_err());
+    }
+
+    #[test]
+    fn test_net_allowlist() {
+        let permissions = Permissions {
+            net: vec!["api.example.com".to_string(), "*.internal.dev".to_string()],
+            ..Default::default()
+        };
+        assert!(permissions.allows_host("api.example.com"));
+        assert!(permissions.allows_host("API.EXAMPLE.COM"));
+        assert!(permissions.allows_host("db.internal.dev"));
+        assert!(!permissions.allows_host("internal.dev"));
+        assert!(!permissions.allows_host("example.com"));
+        assert!(Permissions::allow_all().allows_host("anything.test"));
+    }
+
+    #[test]
+    fn test_denied_error_class() {
+        let error = Permissions::default().check(Capability::Kv).unwrap_err();
+        assert_eq!(
+            deno_core::error::get_custom_error_class(&error),
+            Some(PERMISSION_DENIED_CLASS)
+        );
+        assert!(error.to_string().contains("kv access"));
+    }
+}
@@ -0,0 +1,647 @@
+//! PoolManager - warm worker reuse with per-tenant limits.
+//!
+//! Creating a V8 isolate and running the bootstrap is the dominant cost of a
+//! cold start. The pool keeps workers warm after an invocation, keyed by
+//! function id, so the next invocation of the same function can skip it.
+//!
+//! - **Warm reuse**: idle workers are stored per function id and handed out LIFO
+//! - **Per-tenant concurrency**: each tenant gets a semaphore; `acquire` waits
+//!   when the tenant already has `max_concurrency_per_tenant` workers checked out
+//! - **Idle TTL**: workers idle longer than `idle_ttl` are dropped
+//! - **Recycling**: workers are retired after `max_invocations_per_worker`
+//!   invocations or `max_worker_lifetime`, so long-lived isolates with
+//!   fragmented heaps are replaced instead of creeping up in memory
+//! - **Isolation between invocations**: a worker returned with timers or async
+//!   ops still pending is dropped, so they can't run during whichever
+//!   invocation gets the worker next
+//! - **GC on release**: optionally run a compacting GC when a worker is
+//!   returned, so memory retained by one invocation is freed before the next
+//! - **Shared fetch client**: workers created by a pool share one
+//!   [`FetchClient`] per egress proxy (and per function, for functions with
+//!   a client certificate), so keep-alive connections outlive the workers
+//!   that opened them
+//! - **Process-wide limits**: pools that share a [`Governor`] stay under its
+//!   isolate and heap limits together, dropping their own idle workers
+//!   before making an execution wait for capacity
+//! - **Metrics**: cold/warm starts, evictions and current worker counts
+//!
+//! `VortexWorker` wraps a `JsRuntime`, which is `!Send`, so a `PoolManager`
+//! lives on a single thread (e.g. inside a `LocalSet`).
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::fetch::{FetchClient, FetchProxy};
+use crate::governor::{CapacityError, Governor, IsolateSlot};
+use crate::worker::{VortexWorker, WorkerOptions};
+
+/// Limits applied by a [`PoolManager`].
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    /// Maximum number of workers a single tenant may have checked out at once
+    pub max_concurrency_per_tenant: usize,
+    /// Maximum number of idle workers kept per function id
+    pub max_idle_per_function: usize,
+    /// How long an idle worker is kept before it is dropped
+    pub idle_ttl: Duration,
+    /// Run a compacting GC on each worker as it is returned to the pool
+    pub gc_on_release: bool,
+    /// Retire a worker after it has served this many invocations
+    pub max_invocations_per_worker: Option<u64>,
+    /// Retire a worker once it is older than this, regardless of use
+    pub max_worker_lifetime: Option<Duration>,
+    /// Limits shared with other pools, e.g. those of the scheduler's other
+    /// threads
+    pub governor: Option<Governor>,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            max_concurrency_per_tenant: 10,
+            max_idle_per_function: 4,
+            idle_ttl: Duration::from_secs(300),
+            gc_on_release: false,
+            max_invocations_per_worker: None,
+            max_worker_lifetime: None,
+            governor: None,
+        }
+    }
+}
+
+/// Point-in-time counters describing pool behaviour.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PoolMetrics {
+    /// Invocations that had to create a new worker
+    pub cold_starts: u64,
+    /// Invocations served by an idle worker
+    pub warm_starts: u64,
+    /// Idle workers dropped because of the idle TTL or the idle cap
+    pub evictions: u64,
+    /// Workers retired for reaching their invocation or lifetime limit
+    pub recycled: u64,
+    /// Workers dropped on release because timers or async ops were still
+    /// pending, which would otherwise settle in the next invocation
+    pub unsettled: u64,
+    /// Workers currently checked out
+    pub active_workers: usize,
+    /// Workers currently idle in the pool
+    pub idle_workers: usize,
+}
+
+/// A worker together with the bookkeeping used for recycling.
+struct PoolEntry {
+    worker: VortexWorker,
+    created_at: Instant,
+    invocations: u64,
+    /// The worker's share of the governor's limits
+    slot: Option<IsolateSlot>,
+}
+
+impl PoolEntry {
+    /// Tell the governor how big the worker's heap is now.
+    fn measure_heap(&mut self) {
+        if let Some(slot) = &mut self.slot {
+            slot.set_heap_bytes(self.worker.heap_bytes());
+        }
+    }
+}
+
+struct IdleWorker {
+    entry: PoolEntry,
+    idle_since: Instant,
+}
+
+struct PoolState {
+    options: PoolOptions,
+    idle: HashMap<String, Vec<IdleWorker>>,
+    tenants: HashMap<String, Arc<Semaphore>>,
+    metrics: PoolMetrics,
+    /// Clients by egress proxy and, for functions with a client certificate,
+    /// function ID. Each is created with the first worker that needs it and
+    /// isn't given a client of its own.
+    fetch_clients: HashMap<(Option<FetchProxy>, Option<String>), FetchClient>,
+}
+
+impl PoolState {
+    /// Whether a worker has reached its invocation count or lifetime limit.
+    fn should_recycle(&self, entry: &PoolEntry) -> bool {
+        let worn_out = self
+            .options
+            .max_invocations_per_worker
+            .is_some_and(|max| entry.invocations >= max);
+        let too_old = self
+            .options
+            .max_worker_lifetime
+            .is_some_and(|max| entry.created_at.elapsed() >= max);
+        worn_out || too_old
+    }
+
+    /// Drop idle workers that have exceeded the idle TTL or their lifetime.
+    fn evict_expired(&mut self) {
+        let ttl = self.options.idle_ttl;
+        let max_lifetime = self.options.max_worker_lifetime;
+        let mut evicted = 0;
+        let mut recycled = 0;
+        for workers in self.idle.values_mut() {
+            workers.retain(|idle| {
+                if idle.idle_since.elapsed() >= ttl {
+                    evicted += 1;
+                    false
+                } else if max_lifetime.is_some_and(|max| idle.entry.created_at.elapsed() >= max) {
+                    recycled += 1;
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+        self.idle.retain(|_, workers| !workers.is_empty());
+        self.metrics.evictions += evicted;
+        self.metrics.recycled += recycled;
+    }
+
+    /// Drop the idle worker that has been idle longest, if there is one.
+    fn evict_oldest_idle(&mut self) -> bool {
+        let oldest = self
+            .idle
+            .iter()
+            .flat_map(|(function_id, workers)| {
+                workers
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, idle)| (idle.idle_since, function_id, index))
+            })
+            .min()
+            .map(|(_, function_id, index)| (function_id.clone(), index));
+        let Some((function_id, index)) = oldest else {
+            return false;
+        };
+        if let Some(workers) = self.idle.get_mut(&function_id) {
+            workers.remove(index);
+            if workers.is_empty() {
+                self.idle.remove(&function_id);
+            }
+        }
+        self.metrics.evictions += 1;
+        true
+    }
+
+    /// Return a worker to the idle list, or drop it if it is due for recycling,
+    /// still has work pending or the function is at its idle cap.
+    fn release(&mut self, function_id: &str, mut entry: PoolEntry) {
+        self.metrics.active_workers -= 1;
+        if self.should_recycle(&entry) {
+            self.metrics.recycled += 1;
+            return;
+        }
+        if entry.worker.pending_op_count() > 0 {
+            self.metrics.unsettled += 1;
+            return;
+        }
+        let max_idle = self.options.max_idle_per_function;
+        let gc_on_release = self.options.gc_on_release;
+        let workers = self.idle.entry(function_id.to_string()).or_default();
+        if workers.len() < max_idle {
+            if gc_on_release {
+                entry.worker.collect_garbage();
+            }
+            entry.measure_heap();
+            workers.push(IdleWorker {
+                entry,
+                idle_since: Instant::now(),
+            });
+        } else {
+            self.metrics.evictions += 1;
+        }
+    }
+}
+
+/// A pool of warm workers keyed by function id.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vortex_runtime::{PoolManager, PoolOptions, WorkerOptions};
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() -> anyhow::Result<()> {
+///     let pool = PoolManager::new(PoolOptions::default());
+///     let mut worker = pool.acquire("tenant-a", "fn-1", WorkerOptions::default()).await?;
+///     let result = worker.run("return 42").await?;
+///     println!("Output: {:?}", result.output);
+///     // Dropping the handle returns the worker to the pool
+///     Ok(())
+/// }
+/// ```
+pub struct PoolManager {
+    state: Rc<RefCell<PoolState>>,
+}
+
+impl PoolManager {
+    /// Create an empty pool.
+    pub fn new(options: PoolOptions) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(PoolState {
+                options,
+                idle: HashMap::new(),
+                tenants: HashMap::new(),
+                metrics: PoolMetrics::default(),
+                fetch_clients: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Check out a worker for `function_id` on behalf of `tenant_id`.
+    ///
+    /// Waits while the tenant is at its concurrency limit. Reuses an idle
+    /// worker for the function when one is available; otherwise a new worker
+    /// is created from `options`, with the pool's [`FetchClient`] for its
+    /// proxy and client certificate unless `options` has one, and with
+    /// `tenant_id` as its [`LogChannels::tenant`](crate::LogChannels::tenant)
+    /// unless that is set. Fails with a [`CapacityError`] if the pool's
+    /// governor doesn't admit the execution.
+    pub async fn acquire(
+        &self,
+        tenant_id: &str,
+        function_id: &str,
+        mut options: WorkerOptions,
+    ) -> Result<PooledWorker> {
+        let semaphore = {
+            let mut state = self.state.borrow_mut();
+            let limit = state.options.max_concurrency_per_tenant;
+            state
+                .tenants
+                .entry(tenant_id.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+                .clone()
+        };
+        let permit = semaphore
+            .acquire_owned()
+            .await
+            .map_err(|_| anyhow!("Worker pool for tenant '{}' is closed", tenant_id))?;
+
+        let reused = {
+            let mut state = self.state.borrow_mut();
+            state.evict_expired();
+            state.idle.get_mut(function_id).and_then(Vec::pop)
+        };
+
+        let mut entry = match reused {
+            Some(idle) => {
+                if let Err(error) = self.admit(false).await {
+                    let mut state = self.state.borrow_mut();
+                    state
+                        .idle
+                        .entry(function_id.to_string())
+                        .or_default()
+                        .push(idle);
+                    return Err(error.into());
+                }
+                self.state.borrow_mut().metrics.warm_starts += 1;
+                idle.entry
+            }
+            None => {
+                let slot = self.admit(true).await?;
+                if options.fetch_client.is_none() {
+                    options.fetch_client = Some(self.fetch_client(function_id, &options)?);
+                }
+                options
+                    .log_channels
+                    .tenant
+                    .get_or_insert_with(|| tenant_id.to_string());
+                let worker = VortexWorker::with_options(options)?;
+                self.state.borrow_mut().metrics.cold_starts += 1;
+                let mut entry = PoolEntry {
+                    worker,
+                    created_at: Instant::now(),
+                    invocations: 0,
+                    slot,
+                };
+                entry.measure_heap();
+                entry
+            }
+        };
+        entry.invocations += 1;
+        self.state.borrow_mut().metrics.active_workers += 1;
+
+        Ok(PooledWorker {
+            entry: Some(entry),
+            function_id: function_id.to_string(),
+            pool: Rc::clone(&self.state),
+            _permit: permit,
+        })
+    }
+
+    /// Wait for the pool's governor, if it has one, to admit an execution,
+    /// reserving an isolate for it if it needs a new one. Idle workers are
+    /// dropped first when that makes room.
+    pub(crate) async fn admit(
+        &self,
+        new_isolate: bool,
+    ) -> Result<Option<IsolateSlot>, CapacityError> {
+        let Some(governor) = self.state.borrow().options.governor.clone() else {
+            return Ok(None);
+        };
+        loop {
+            match governor.try_admit(new_isolate) {
+                Ok(slot) => return Ok(slot),
+                Err(_) if self.state.borrow_mut().evict_oldest_idle() => continue,
+                Err(_) => return governor.admit(new_isolate).await,
+            }
+        }
+    }
+
+    /// The pool's client for the proxy and client certificate in `options`.
+    /// A certificate is resolved through the function's own secrets, so
+    /// those clients aren't shared between functions. The other client
+    /// settings, such as the connect timeout, come from the first worker to
+    /// need it.
+    fn fetch_client(&self, function_id: &str, options: &WorkerOptions) -> Result<FetchClient> {
+        let fetch = &options.fetch;
+        let key = (
+            fetch.proxy.clone(),
+            fetch
+                .client_certificate
+                .as_ref()
+                .map(|_| function_id.to_string()),
+        );
+        let mut state = self.state.borrow_mut();
+        if let Some(client) = state.fetch_clients.get(&key) {
+            return Ok(client.clone());
+        }
+        let client = FetchClient::new(fetch, options.secrets.as_deref())?;
+        state.fetch_clients.insert(key, client.clone());
+        Ok(client)
+    }
+
+    /// Drop idle workers that have exceeded the idle TTL or their lifetime.
+    ///
+    /// Expired workers are also evicted lazily on `acquire`; call this
+    /// periodically to release memory when traffic is low.
+    pub fn evict_idle(&self) {
+        self.state.borrow_mut().evict_expired();
+    }
+
+    /// Drop the idle workers of `function_id`, e.g. when it names a function
+    /// version that has been replaced. Workers that are checked out return
+    /// to the pool as usual.
+    pub fn evict_function(&self, function_id: &str) {
+        let mut state = self.state.borrow_mut();
+        if let Some(workers) = state.idle.remove(function_id) {
+            state.metrics.evictions += workers.len() as u64;
+        }
+    }
+
+    /// Current pool counters.
+    pub fn metrics(&self) -> PoolMetrics {
+        let state = self.state.borrow();
+        PoolMetrics {
+            idle_workers: state.idle.values().map(Vec::len).sum(),
+            ..state.metrics.clone()
+        }
+    }
+}
+
+/// A worker checked out of a [`PoolManager`].
+///
+/// Dereferences to [`VortexWorker`]. Dropping it returns the worker to the
+/// pool; call [`PooledWorker::discard`] instead if the worker should not be
+/// reused (e.g. after it was terminated).
+pub struct PooledWorker {
+    entry: Option<PoolEntry>,
+    function_id: String,
+    pool: Rc<RefCell<PoolState>>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledWorker {
+    /// Drop the worker instead of returning it to the pool.
+    pub fn discard(mut self) {
+        self.entry.take();
+        self.pool.borrow_mut().metrics.active_workers -= 1;
+    }
+}
+
+impl Deref for PooledWorker {
+    type Target = VortexWorker;
+
+    fn deref(&self) -> &VortexWorker {
+        &self.entry.as_ref().expect("worker already released").worker
+    }
+}
+
+impl DerefMut for PooledWorker {
+    fn deref_mut(&mut self) -> &mut VortexWorker {
+        &mut self.entry.as_mut().expect("worker already released").worker
+    }
+}
+
+impl Drop for PooledWorker {
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            self.pool.borrow_mut().release(&self.function_id, entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::governor::{CapacityResource, GovernorOptions};
+
+    #[tokio::test]
+    async fn test_warm_reuse_per_function() {
+        let pool = PoolManager::new(PoolOptions::default());
+
+        let mut worker = pool.acquire("t1", "fn-a", WorkerOptions::default()).await.unwrap();
+        let result = worker.run("globalThis.counter = 1").await.unwrap();
+        assert!(result.metrics.cold_start);
+        drop(worker);
+
+        let mut worker = pool.acquire("t1", "fn-a", WorkerOptions::default()).await.unwrap();
+        let result = worker.run("return globalThis.counter").await.unwrap();
+        assert_eq!(result.output, Some(serde_json::json!(1)));
+        assert!(!result.metrics.cold_start);
+        drop(worker);
+
+        let _other = pool.acquire("t1", "fn-b", WorkerOptions::default()).await.unwrap();
+        let metrics = pool.metrics();
+        assert_eq!(metrics.cold_starts, 2);
+        assert_eq!(metrics.warm_starts, 1);
+        assert_eq!(metrics.active_workers, 1);
+        assert_eq!(metrics.idle_workers, 1);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_concurrency_limit() {
+        let pool = PoolManager::new(PoolOptions {
+            max_concurrency_per_tenant: 1,
+            ..Default::default()
+        });
+
+        let first = pool.acquire("t1", "fn-a", WorkerOptions::default()).await.unwrap();
+        let blocked = tokio::time::timeout(
+            Duration::from_millis(50),
+            pool.acquire("t1", "fn-a", WorkerOptions::default()),
+        )
+        .await;
+        assert!(blocked.is_err(), "second acquire should wait for the tenant limit");
+
+        // Other tenants are unaffected
+        let _other = pool.acquire("t2", "fn-a", WorkerOptions::default()).await.unwrap();
+
+        drop(first);
+        assert!(pool.acquire("t1", "fn-a", WorkerOptions::default()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_gc_on_release_keeps_worker_usable() {
+        let pool = PoolManager::new(PoolOptions {
+            gc_on_release: true,
+            ..Default::default()
+        });
+
+        let mut worker = pool.acquire("t1", "fn-a", WorkerOptions::default()).await.unwrap();
+        worker
+            .run("globalThis.kept = 'yes'; let garbage = new Array(100000).fill({});")
+            .await
+            .unwrap();
+        drop(worker);
+
+        let mut worker = pool.acquire("t1", "fn-a", WorkerOptions::default()).await.unwrap();
+        let result = worker.run("return globalThis.kept").await.unwrap();
+        assert_eq!(result.output, Some(serde_json::json!("yes")));
+    }
+
+    #[tokio::test]
+    async fn test_max_invocations_recycles_worker() {
+        let pool = PoolManager::new(PoolOptions {
+            max_invocations_per_worker: Some(2),
+            ..Default::default()
+        });
+
+        for _ in 0..3 {
+            let mut worker = pool.acquire("t1", "fn-a", WorkerOptions::default()).await.unwrap();
+            worker.run("return 1").await.unwrap();
+        }
+
+        let metrics = pool.metrics();
+        assert_eq!(metrics.cold_starts, 2);
+        assert_eq!(metrics.warm_starts, 1);
+        assert_eq!(metrics.recycled, 1);
+    }
+
+    #[tokio::test]
+    async fn test_workers_with_pending_timers_are_not_reused() {
+        let pool = PoolManager::new(PoolOptions::default());
+
+        let mut worker = pool.acquire("t1", "fn-a", WorkerOptions::default()).await.unwrap();
+        worker
+            .run("globalThis.first = true; setTimeout(() => console.log('late'), 10);")
+            .await
+            .unwrap();
+        drop(worker);
+        let metrics = pool.metrics();
+        assert_eq!(metrics.unsettled, 1);
+        assert_eq!(metrics.idle_workers, 0);
+
+        let mut worker = pool.acquire("t1", "fn-a", WorkerOptions::default()).await.unwrap();
+        let result = worker
+            .run(
+                "await new Promise((resolve) => setTimeout(resolve, 50)); \
+                 return globalThis.first ?? false;",
+            )
+            .await
+            .unwrap();
+        assert!(result.metrics.cold_start);
+        assert_eq!(result.output, Some(serde_json::json!(false)));
+        assert!(result.logs.is_empty());
+        drop(worker);
+
+        // Timers that were cleared don't keep a worker out of the pool
+        let mut worker = pool.acquire("t1", "fn-a", WorkerOptions::default()).await.unwrap();
+        worker
+            .run("clearTimeout(setTimeout(() => {}, 60000));")
+            .await
+            .unwrap();
+        drop(worker);
+        let metrics = pool.metrics();
+        assert_eq!(metrics.unsettled, 1);
+        assert_eq!(metrics.idle_workers, 1);
+    }
+
+    #[tokio::test]
+    async fn test_idle_ttl_evicts_workers() {
+        let pool = PoolManager::new(PoolOptions {
+            idle_ttl: Duration::ZERO,
+            ..Default::default()
+        });
+
+        drop(pool.acquire("t1", "fn-a", WorkerOptions::default()).await.unwrap());
+        pool.evict_idle();
+
+        let metrics = pool.metrics();
+        assert_eq!(metrics.idle_workers, 0);
+        assert_eq!(metrics.evictions, 1);
+    }
+
+    #[tokio::test]
+    async fn test_evict_function() {
+        let pool = PoolManager::new(PoolOptions::default());
+        let first = pool.acquire("t1", "fn-a@1", WorkerOptions::default()).await.unwrap();
+        let second = pool.acquire("t1", "fn-a@1", WorkerOptions::default()).await.unwrap();
+        drop(pool.acquire("t1", "fn-a@2", WorkerOptions::default()).await.unwrap());
+        drop(first);
+
+        pool.evict_function("fn-a@1");
+        let metrics = pool.metrics();
+        assert_eq!(metrics.evictions, 1);
+        assert_eq!(metrics.idle_workers, 1);
+
+        // A worker checked out at the time still goes back to the pool
+        drop(second);
+        assert_eq!(pool.metrics().idle_workers, 2);
+    }
+
+    #[tokio::test]
+    async fn test_governor_limits_isolates() {
+        let governor = Governor::new(GovernorOptions {
+            max_isolates: Some(1),
+            ..Default::default()
+        });
+        let pool = PoolManager::new(PoolOptions {
+            governor: Some(governor.clone()),
+            ..Default::default()
+        });
+
+        drop(pool.acquire("t1", "fn-a", WorkerOptions::default()).await.unwrap());
+        assert_eq!(governor.metrics().isolates, 1);
+        assert!(governor.metrics().heap_bytes > 0);
+
+        // The idle worker makes way for another function's
+        let worker = pool.acquire("t1", "fn-b", WorkerOptions::default()).await.unwrap();
+        assert_eq!(pool.metrics().evictions, 1);
+        assert_eq!(governor.metrics().isolates, 1);
+
+        let error = pool
+            .acquire("t2", "fn-c", WorkerOptions::default())
+            .await
+            .err()
+            .unwrap();
+        let capacity = error.downcast_ref::<CapacityError>().unwrap();
+        assert_eq!(capacity.resource, CapacityResource::Isolates);
+
+        worker.discard();
+        assert_eq!(governor.metrics().isolates, 0);
+        assert!(pool.acquire("t2", "fn-c", WorkerOptions::default()).await.is_ok());
+    }
+}
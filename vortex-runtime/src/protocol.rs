@@ -0,0 +1,713 @@
+//! Control-plane protocol spoken by the resident server (`--listen`).
+//!
+//! Every frame is one line of JSON. Requests carry a protocol version `v`, a
+//! caller-chosen `id` that is echoed in the response, and a `type`:
+//!
+//! ```text
+//! {"v":1,"id":1,"type":"load","function_id":"fn-1","code":"return input * 2"}
+//! {"v":1,"id":2,"type":"invoke","function_id":"fn-1","tenant_id":"t1","input":21}
+//! {"v":1,"id":3,"type":"stats"}
+//! {"v":1,"id":4,"type":"shutdown"}
+//! ```
+//!
+//! Responses have either `result` or `error`:
+//!
+//! ```text
+//! {"v":1,"id":2,"result":{"output":42,"logs":[],"execution_time_ms":1}}
+//! {"v":1,"id":9,"error":"Function 'fn-2' is not loaded"}
+//! ```
+//!
+//! When user code throws, `exception` carries the thrown error as a
+//! structured object, including its `cause` chain (see [`ScriptError`]).
+//! A result has a `metrics` object with the invocation's `fetch()` traffic and
+//! time breakdown (see [`ExecutionMetrics`]); an invocation that failed reports
+//! it next to `error` instead.
+//!
+//! # Multiplexing
+//!
+//! A connection (including `--listen stdio`) may have any number of
+//! invocations in flight; responses are written as each one finishes, so
+//! they can arrive in a different order than the requests. Every `invoke`
+//! response carries the invocation's `invocation_id` (the caller's, or a
+//! generated one) next to `id`, so a client sharing one runtime process
+//! between overlapping requests can match responses, streamed logs and
+//! Redis messages to the right invocation:
+//!
+//! ```text
+//! {"v":1,"id":7,"type":"invoke","code":"return 1","invocation_id":"req-a"}
+//! {"v":1,"id":8,"type":"invoke","code":"return 2","invocation_id":"req-b"}
+//! {"v":1,"id":8,"invocation_id":"req-b","result":{"output":2,...}}
+//! {"v":1,"id":7,"invocation_id":"req-a","result":{"output":1,...}}
+//! ```
+//!
+//! Code is uploaded once with `load` and invocations only reference the
+//! function id, so the per-invocation payload is just the input. `invoke` may
+//! also carry `code` inline for one-off executions without a `load`.
+//!
+//! # Hot reload
+//!
+//! Sending `load` for a function id that is already loaded swaps its code
+//! atomically: invocations that start afterwards use the new code, while the
+//! ones already running finish on the old version. The `load` response is
+//! only sent once those in-flight invocations have drained, so a deploy can
+//! treat it as "the old version is gone". Each version gets its own warm
+//! workers, and once the previous version has drained its idle workers are
+//! evicted rather than left to the pool's idle TTL. An `invoke` with both
+//! `code` and `function_id` shares the warm workers of the loaded version
+//! (or of the unloaded function until the first `load`), so it goes through
+//! the same reload and eviction as the loaded code.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Notify;
+
+use crate::error::{ExecutionError, ScriptError};
+use crate::logging::RuntimeLog;
+use crate::ops::{LogEntry, PendingOp};
+use crate::server::Backend;
+use crate::supervisor::ProcessRequest;
+use crate::worker::{new_invocation_id, ExecutionMetrics};
+
+/// Version of the control-plane protocol implemented by this build.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+fn default_version() -> u32 {
+    PROTOCOL_VERSION
+}
+
+/// A request frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlRequest {
+    /// Protocol version the client speaks
+    #[serde(default = "default_version")]
+    pub v: u32,
+    /// Echoed in the response
+    #[serde(default)]
+    pub id: u64,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+/// The verbs understood by the control plane.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Command {
+    /// Register (or replace) the code for a function id
+    Load { function_id: String, code: String },
+    /// Run a loaded function, or inline `code` when given
+    Invoke {
+        #[serde(default)]
+        function_id: Option<String>,
+        #[serde(default)]
+        tenant_id: String,
+        #[serde(default)]
+        code: Option<String>,
+        #[serde(default)]
+        input: Value,
+        /// Correlation ID for logs and result; generated when absent
+        #[serde(default)]
+        invocation_id: Option<String>,
+    },
+    /// Report runtime counters
+    Stats,
+    /// Stop accepting work and exit once in-flight invocations finish
+    Shutdown,
+}
+
+/// A response frame. Exactly one of `result` / `error` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlResponse {
+    pub v: u32,
+    pub id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Structured form of `error` when user code threw (see [`ScriptError`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exception: Option<ScriptError>,
+    /// Logs an invocation produced before failing
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub logs: Vec<LogEntry>,
+    /// The invocation this response belongs to (`invoke` only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invocation_id: Option<String>,
+    /// Resources a failed invocation used (a result carries its own)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<ExecutionMetrics>,
+    /// Async ops a timed-out invocation was still waiting for
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending_ops: Vec<PendingOp>,
+    /// Runtime warnings a failed invocation raised (a result carries its own)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub runtime_logs: Vec<RuntimeLog>,
+}
+
+impl ControlResponse {
+    fn new(id: u64, result: Result<Value>) -> Self {
+        match result {
+            Ok(value) => Self {
+                v: PROTOCOL_VERSION,
+                id,
+                result: Some(value),
+                error: None,
+                exception: None,
+                logs: Vec::new(),
+                invocation_id: None,
+                metrics: None,
+                pending_ops: Vec::new(),
+                runtime_logs: Vec::new(),
+            },
+            Err(e) => Self {
+                v: PROTOCOL_VERSION,
+                id,
+                result: None,
+                error: Some(e.to_string()),
+                exception: ExecutionError::script_error(&e).cloned(),
+                logs: ExecutionError::partial_logs(&e).to_vec(),
+                invocation_id: ExecutionError::invocation_id(&e).map(String::from),
+                metrics: ExecutionError::metrics(&e).cloned(),
+                pending_ops: ExecutionError::pending_ops(&e).to_vec(),
+                runtime_logs: ExecutionError::runtime_logs(&e).to_vec(),
+            },
+        }
+    }
+}
+
+/// Counters returned by the `stats` verb.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeStats {
+    pub protocol_version: u32,
+    pub uptime_ms: u64,
+    pub functions_loaded: usize,
+    pub invocations: u64,
+    pub errors: u64,
+    pub in_flight: usize,
+    /// Executions queued or running per isolate thread (in-process backend only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_load: Option<Vec<usize>>,
+}
+
+/// Invocations currently running against one version of a function.
+#[derive(Default)]
+struct InFlight {
+    count: AtomicUsize,
+    drained: Notify,
+}
+
+impl InFlight {
+    /// Wait until no invocation of this version is running.
+    async fn drained(&self) {
+        loop {
+            // Register interest before checking, so a decrement in between isn't missed
+            let notified = self.drained.notified();
+            if self.count.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Marks one invocation as running for as long as it is alive.
+struct InFlightGuard(Arc<InFlight>);
+
+impl InFlightGuard {
+    fn new(in_flight: Arc<InFlight>) -> Self {
+        in_flight.count.fetch_add(1, Ordering::SeqCst);
+        Self(in_flight)
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.drained.notify_waiters();
+        }
+    }
+}
+
+/// Key under which workers of one version of a function are pooled. Version
+/// 0 is a function id that hasn't been loaded yet.
+fn pool_key(function_id: &str, version: u64) -> String {
+    format!("{}@{}", function_id, version)
+}
+
+/// The currently deployed version of a function. A function id that is only
+/// invoked with inline `code` has no code of its own and stays at version 0.
+#[derive(Clone, Default)]
+struct LoadedFunction {
+    code: Option<Arc<str>>,
+    version: u64,
+    in_flight: Arc<InFlight>,
+}
+
+impl LoadedFunction {
+    /// Code and pool key for one invocation, counted in flight from here on.
+    fn start(
+        &self,
+        function_id: &str,
+        code: Option<String>,
+    ) -> Result<(String, String, InFlightGuard)> {
+        let code = code
+            .or_else(|| self.code.as_deref().map(String::from))
+            .ok_or_else(|| anyhow!("Function '{}' is not loaded", function_id))?;
+        let guard = InFlightGuard::new(Arc::clone(&self.in_flight));
+        Ok((code, pool_key(function_id, self.version), guard))
+    }
+}
+
+/// Shared state behind every connection of a resident server.
+pub struct ControlPlane {
+    backend: Backend,
+    functions: RwLock<HashMap<String, LoadedFunction>>,
+    started_at: Instant,
+    invocations: AtomicU64,
+    errors: AtomicU64,
+    in_flight: AtomicUsize,
+    next_id: AtomicU64,
+    shutdown: Notify,
+}
+
+impl ControlPlane {
+    pub fn new(backend: Backend) -> Self {
+        Self {
+            backend,
+            functions: RwLock::new(HashMap::new()),
+            started_at: Instant::now(),
+            invocations: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            in_flight: AtomicUsize::new(0),
+            next_id: AtomicU64::new(1),
+            shutdown: Notify::new(),
+        }
+    }
+
+    /// Parse and handle one request line.
+    pub async fn handle_line(&self, line: &str) -> ControlResponse {
+        match serde_json::from_str::<ControlRequest>(line) {
+            Ok(request) => self.handle(request).await,
+            Err(e) => ControlResponse::new(0, Err(anyhow!("Invalid request: {}", e))),
+        }
+    }
+
+    /// Handle a request.
+    pub async fn handle(&self, request: ControlRequest) -> ControlResponse {
+        let id = request.id;
+        if request.v != PROTOCOL_VERSION {
+            return ControlResponse::new(
+                id,
+                Err(anyhow!(
+                    "Unsupported protocol version {} (this runtime speaks {})",
+                    request.v,
+                    PROTOCOL_VERSION
+                )),
+            );
+        }
+
+        let result = match request.command {
+            Command::Load { function_id, code } => Ok(self.load(function_id, code).await),
+            Command::Invoke {
+                function_id,
+                tenant_id,
+                code,
+                input,
+                invocation_id,
+            } => {
+                // Assigned here rather than by the worker so that even a
+                // request rejected before it runs is tagged
+                let invocation_id = invocation_id.unwrap_or_else(new_invocation_id);
+                let result = self
+                    .invoke(function_id, tenant_id, code, input, invocation_id.clone())
+                    .await;
+                return ControlResponse {
+                    invocation_id: Some(invocation_id),
+                    ..ControlResponse::new(id, result)
+                };
+            }
+            Command::Stats => Ok(serde_json::to_value(self.stats())?),
+            Command::Shutdown => {
+                self.shutdown.notify_one();
+                Ok(Value::Null)
+            }
+        };
+        ControlResponse::new(id, result)
+    }
+
+    /// Deploy `code` for `function_id`, draining invocations of the previous version.
+    async fn load(&self, function_id: String, code: String) -> Value {
+        let previous = {
+            let mut functions = self.functions.write().unwrap();
+            let previous = functions.get(&function_id).cloned();
+            let version = previous.as_ref().map_or(1, |f| f.version + 1);
+            functions.insert(
+                function_id.clone(),
+                LoadedFunction {
+                    code: Some(Arc::from(code)),
+                    version,
+                    in_flight: Arc::default(),
+                },
+            );
+            previous
+        };
+
+        if let Some(previous) = &previous {
+            previous.in_flight.drained().await;
+        }
+        // Nothing can check out a worker of the previous version any more
+        let stale = previous.as_ref().map_or(0, |f| f.version);
+        self.backend.evict(&pool_key(&function_id, stale)).await;
+        serde_json::json!({
+            "function_id": function_id,
+            "version": previous.map_or(1, |f| f.version + 1),
+        })
+    }
+
+    async fn invoke(
+        &self,
+        function_id: Option<String>,
+        tenant_id: String,
+        code: Option<String>,
+        input: Value,
+        invocation_id: String,
+    ) -> Result<Value> {
+        let (code, function_id, _guard) = match (code, function_id) {
+            (Some(code), None) => (code, None, None),
+            (code, Some(function_id)) => {
+                let (code, key, guard) = self.start(&function_id, code)?;
+                (code, Some(key), Some(guard))
+            }
+            (None, None) => return Err(anyhow!("invoke requires a function_id or code")),
+        };
+
+        self.invocations.fetch_add(1, Ordering::Relaxed);
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        let result = self
+            .backend
+            .execute(ProcessRequest {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                tenant_id,
+                function_id,
+                code,
+                input,
+                invocation_id: Some(invocation_id),
+            })
+            .await;
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+
+        match result {
+            Ok(result) => Ok(serde_json::to_value(result)?),
+            Err(e) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                Err(e)
+            }
+        }
+    }
+
+    /// Resolve the code and pool key of an invocation of `function_id`.
+    ///
+    /// The invocation is counted in flight before the lock is released, so a
+    /// concurrent `load` either drains it or swapped versions before it
+    /// started. Workers are pooled per version so a reload never runs in a
+    /// stale isolate.
+    fn start(
+        &self,
+        function_id: &str,
+        code: Option<String>,
+    ) -> Result<(String, String, InFlightGuard)> {
+        if let Some(loaded) = self.functions.read().unwrap().get(function_id) {
+            return loaded.start(function_id, code);
+        }
+        if code.is_none() {
+            return Err(anyhow!("Function '{}' is not loaded", function_id));
+        }
+        // Inline code for an unloaded id: track it at version 0 so the first
+        // `load` drains it before evicting its workers
+        let mut functions = self.functions.write().unwrap();
+        let loaded = functions.entry(function_id.to_string()).or_default();
+        loaded.start(function_id, code)
+    }
+
+    /// Current runtime counters.
+    pub fn stats(&self) -> RuntimeStats {
+        RuntimeStats {
+            protocol_version: PROTOCOL_VERSION,
+            uptime_ms: self.started_at.elapsed().as_millis() as u64,
+            functions_loaded: self
+                .functions
+                .read()
+                .unwrap()
+                .values()
+                .filter(|f| f.code.is_some())
+                .count(),
+            invocations: self.invocations.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            thread_load: self.backend.load(),
+        }
+    }
+
+    /// Resolves once a `shutdown` request has been received.
+    pub async fn shutdown_requested(&self) {
+        self.shutdown.notified().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::governor::{Governor, GovernorOptions};
+    use crate::pool::PoolOptions;
+    use crate::scheduler::{Scheduler, SchedulerOptions};
+    use crate::worker::WorkerOptions;
+
+    fn control_plane() -> ControlPlane {
+        ControlPlane::new(Backend::InProcess {
+            scheduler: Scheduler::new(SchedulerOptions {
+                threads: 1,
+                ..Default::default()
+            })
+            .unwrap(),
+            options: WorkerOptions::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_load_then_invoke() {
+        let plane = control_plane();
+        let loaded = plane
+            .handle_line(r#"{"v":1,"id":1,"type":"load","function_id":"double","code":"return input * 2"}"#)
+            .await;
+        assert!(loaded.error.is_none());
+
+        let invoked = plane
+            .handle_line(r#"{"v":1,"id":2,"type":"invoke","function_id":"double","input":21}"#)
+            .await;
+        assert_eq!(invoked.id, 2);
+        assert_eq!(invoked.result.unwrap()["output"], serde_json::json!(42));
+
+        let stats = plane.stats();
+        assert_eq!(stats.functions_loaded, 1);
+        assert_eq!(stats.invocations, 1);
+        assert_eq!(stats.errors, 0);
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_invocations_are_tagged() {
+        let plane = Arc::new(control_plane());
+        let slow = {
+            let plane = Arc::clone(&plane);
+            tokio::spawn(async move {
+                plane
+                    .handle_line(r#"{"id":1,"type":"invoke","code":"await new Promise(r => setTimeout(r, 50)); return 'slow'","invocation_id":"a"}"#)
+                    .await
+            })
+        };
+        let fast = plane
+            .handle_line(r#"{"id":2,"type":"invoke","code":"return 'fast'"}"#)
+            .await;
+        let slow = slow.await.unwrap();
+
+        assert_eq!(slow.invocation_id.as_deref(), Some("a"));
+        assert_eq!(slow.result.unwrap()["invocation_id"], "a");
+        let generated = fast.invocation_id.unwrap();
+        assert_eq!(fast.result.unwrap()["invocation_id"], generated.as_str());
+        assert_ne!(generated, "a");
+    }
+
+    #[tokio::test]
+    async fn test_reload_swaps_code() {
+        let plane = control_plane();
+        plane
+            .handle_line(r#"{"id":1,"type":"load","function_id":"f","code":"return 'v1'"}"#)
+            .await;
+        let reloaded = plane
+            .handle_line(r#"{"id":2,"type":"load","function_id":"f","code":"return 'v2'"}"#)
+            .await;
+        assert_eq!(reloaded.result.unwrap()["version"], serde_json::json!(2));
+
+        let invoked = plane
+            .handle_line(r#"{"id":3,"type":"invoke","function_id":"f"}"#)
+            .await;
+        assert_eq!(invoked.result.unwrap()["output"], serde_json::json!("v2"));
+    }
+
+    #[tokio::test]
+    async fn test_reload_waits_for_in_flight_invocations() {
+        let plane = Arc::new(control_plane());
+        plane
+            .handle_line(
+                r#"{"id":1,"type":"load","function_id":"f",
+                    "code":"await new Promise(r => setTimeout(r, 100)); return 'old'"}"#,
+            )
+            .await;
+
+        let slow = tokio::spawn({
+            let plane = Arc::clone(&plane);
+            async move {
+                plane
+                    .handle_line(r#"{"id":2,"type":"invoke","function_id":"f"}"#)
+                    .await
+            }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        plane
+            .handle_line(r#"{"id":3,"type":"load","function_id":"f","code":"return 'new'"}"#)
+            .await;
+        // The old invocation must have completed before the reload was acknowledged
+        assert!(slow.is_finished());
+        let old = slow.await.unwrap();
+        assert_eq!(old.result.unwrap()["output"], serde_json::json!("old"));
+    }
+
+    #[tokio::test]
+    async fn test_reload_evicts_previous_version() {
+        let governor = Governor::new(GovernorOptions::default());
+        let plane = ControlPlane::new(Backend::InProcess {
+            scheduler: Scheduler::new(SchedulerOptions {
+                threads: 1,
+                pool: PoolOptions {
+                    governor: Some(governor.clone()),
+                    ..Default::default()
+                },
+            })
+            .unwrap(),
+            options: WorkerOptions::default(),
+        });
+
+        plane
+            .handle_line(r#"{"id":1,"type":"load","function_id":"f","code":"return 'v1'"}"#)
+            .await;
+        plane
+            .handle_line(r#"{"id":2,"type":"invoke","function_id":"f"}"#)
+            .await;
+        assert_eq!(governor.metrics().isolates, 1);
+
+        plane
+            .handle_line(r#"{"id":3,"type":"load","function_id":"f","code":"return 'v2'"}"#)
+            .await;
+        assert_eq!(governor.metrics().isolates, 0);
+    }
+
+    #[tokio::test]
+    async fn test_reload_drains_inline_invocations() {
+        let plane = Arc::new(control_plane());
+        let inline = tokio::spawn({
+            let plane = Arc::clone(&plane);
+            async move {
+                plane
+                    .handle_line(
+                        r#"{"id":1,"type":"invoke","function_id":"f",
+                        "code":"await new Promise(r => setTimeout(r, 100)); return 'inline'"}"#,
+                    )
+                    .await
+            }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        plane
+            .handle_line(r#"{"id":2,"type":"load","function_id":"f","code":"return 'v1'"}"#)
+            .await;
+        assert!(inline.is_finished());
+        let inline = inline.await.unwrap();
+        assert_eq!(
+            inline.result.unwrap()["output"],
+            serde_json::json!("inline")
+        );
+        assert_eq!(plane.stats().functions_loaded, 1);
+    }
+
+    #[tokio::test]
+    async fn test_reload_during_invocations_leaves_no_stale_workers() {
+        let governor = Governor::new(GovernorOptions::default());
+        let plane = Arc::new(ControlPlane::new(Backend::InProcess {
+            scheduler: Scheduler::new(SchedulerOptions {
+                threads: 2,
+                pool: PoolOptions {
+                    governor: Some(governor.clone()),
+                    ..Default::default()
+                },
+            })
+            .unwrap(),
+            options: WorkerOptions::default(),
+        }));
+
+        let mut tasks = Vec::new();
+        for i in 0..20 {
+            let plane = Arc::clone(&plane);
+            tasks.push(tokio::spawn(async move {
+                let line = match i % 4 {
+                    0 => format!(
+                        r#"{{"id":{i},"type":"load","function_id":"f","code":"return {i}"}}"#
+                    ),
+                    1 => format!(
+                        r#"{{"id":{i},"type":"invoke","function_id":"f","code":"return {i}"}}"#
+                    ),
+                    _ => format!(r#"{{"id":{i},"type":"invoke","function_id":"f"}}"#),
+                };
+                plane.handle_line(&line).await
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // Every earlier version was drained and evicted by the reload after it,
+        // so once the last reload is acknowledged no worker is left behind
+        plane
+            .handle_line(r#"{"id":20,"type":"load","function_id":"f","code":"return 'last'"}"#)
+            .await;
+        assert_eq!(governor.metrics().isolates, 0);
+    }
+
+    #[tokio::test]
+    async fn test_inline_invoke_shares_loaded_version() {
+        let plane = control_plane();
+        plane
+            .handle_line(r#"{"id":1,"type":"load","function_id":"f","code":"globalThis.n = (globalThis.n ?? 0) + 1; return n"}"#)
+            .await;
+        plane
+            .handle_line(r#"{"id":2,"type":"invoke","function_id":"f"}"#)
+            .await;
+
+        // Inline code with the function id runs in the loaded version's warm worker
+        let inline = plane
+            .handle_line(
+                r#"{"id":3,"type":"invoke","function_id":"f","code":"return globalThis.n"}"#,
+            )
+            .await;
+        assert_eq!(inline.result.unwrap()["output"], serde_json::json!(1));
+
+        // ...and after a reload, in the new version's
+        plane
+            .handle_line(r#"{"id":4,"type":"load","function_id":"f","code":"return 'v2'"}"#)
+            .await;
+        let inline = plane
+            .handle_line(r#"{"id":5,"type":"invoke","function_id":"f","code":"return globalThis.n ?? null"}"#)
+            .await;
+        assert_eq!(inline.result.unwrap()["output"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_invoke_unknown_function() {
+        let plane = control_plane();
+        let response = plane
+            .handle_line(r#"{"id":3,"type":"invoke","function_id":"missing"}"#)
+            .await;
+        assert_eq!(response.error.unwrap(), "Function 'missing' is not loaded");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unknown_version() {
+        let plane = control_plane();
+        let response = plane.handle_line(r#"{"v":99,"id":4,"type":"stats"}"#).await;
+        assert!(response.error.unwrap().contains("Unsupported protocol version 99"));
+    }
+}
@@ -0,0 +1,166 @@
+//! Resources used by the whole process, as opposed to one isolate.
+//!
+//! V8's heap statistics miss memory that ops allocate natively (SQLite page
+//! caches, TLS and compression buffers, response bodies) and say nothing
+//! about file descriptors. The CLI runs one invocation per process, so it
+//! reports the process's own numbers with the result:
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//!
+//! use vortex_runtime::resources::ProcessSampler;
+//!
+//! let sampler = ProcessSampler::start(Duration::from_millis(10));
+//! // ... run the invocation ...
+//! let usage = sampler.finish();
+//! println!("peak RSS: {:?} bytes", usage.peak_rss_bytes);
+//! ```
+//!
+//! The numbers come from `getrusage(2)` and `/proc/self/fd`, so they are only
+//! available on Linux. So is [`thread_cpu_time`], which the worker's CPU
+//! budget is measured with. Under the OS sandbox, `/proc/self/fd` has to stay
+//! readable (see [`crate::sandbox`]).
+
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// What the process used while a [`ProcessSampler`] ran.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessUsage {
+    /// Largest resident set size the process ever had
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_rss_bytes: Option<u64>,
+    /// File descriptors open when the sampler finished
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_fds: Option<u64>,
+    /// Most file descriptors seen open at once by the sampler
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_open_fds: Option<u64>,
+}
+
+/// Counts the process's open file descriptors on a background thread, to
+/// catch the peak of descriptors that are closed again before the end.
+pub struct ProcessSampler {
+    stop: mpsc::Sender<()>,
+    thread: JoinHandle<Option<u64>>,
+}
+
+impl ProcessSampler {
+    /// Start sampling every `interval`.
+    pub fn start(interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            let mut peak = open_fds();
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                peak = peak.max(open_fds());
+            }
+            peak
+        });
+        Self { stop, thread }
+    }
+
+    /// Stop sampling and report what the process used.
+    pub fn finish(self) -> ProcessUsage {
+        let _ = self.stop.send(());
+        let sampled = self.thread.join().unwrap_or(None);
+        let open_fds = open_fds();
+        ProcessUsage {
+            peak_rss_bytes: peak_rss_bytes(),
+            open_fds,
+            peak_open_fds: sampled.max(open_fds),
+        }
+    }
+}
+
+/// Largest resident set size of the process so far.
+#[cfg(target_os = "linux")]
+pub fn peak_rss_bytes() -> Option<u64> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: getrusage only writes to the struct it is given
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: getrusage succeeded, so it filled in the struct
+    let usage = unsafe { usage.assume_init() };
+    // Linux reports kilobytes
+    u64::try_from(usage.ru_maxrss).ok().map(|kb| kb * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn peak_rss_bytes() -> Option<u64> {
+    None
+}
+
+/// Number of file descriptors the process has open.
+#[cfg(target_os = "linux")]
+pub fn open_fds() -> Option<u64> {
+    let entries = std::fs::read_dir("/proc/self/fd").ok()?.count() as u64;
+    // Listing the directory takes a descriptor of its own
+    Some(entries.saturating_sub(1))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn open_fds() -> Option<u64> {
+    None
+}
+
+/// CPU time the calling thread has used so far.
+#[cfg(target_os = "linux")]
+pub fn thread_cpu_time() -> Option<Duration> {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: clock_gettime only writes to the struct it is given
+    if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) } != 0 {
+        return None;
+    }
+    Some(Duration::new(
+        u64::try_from(time.tv_sec).ok()?,
+        u32::try_from(time.tv_nsec).ok()?,
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn thread_cpu_time() -> Option<Duration> {
+    None
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_sampler() {
+        let sampler = ProcessSampler::start(Duration::from_millis(5));
+        let files: Vec<_> = (0..8)
+            .map(|_| std::fs::File::open("/proc/self/status").unwrap())
+            .collect();
+        thread::sleep(Duration::from_millis(50));
+        drop(files);
+
+        let usage = sampler.finish();
+        assert!(usage.peak_rss_bytes.unwrap() > 0);
+        // Other tests open and close files too, so only this test's files
+        // are certain
+        assert!(usage.peak_open_fds.unwrap() >= 8, "{:?}", usage);
+        assert!(usage.open_fds.unwrap() <= usage.peak_open_fds.unwrap());
+    }
+
+    #[test]
+    fn test_thread_cpu_time() {
+        let start = thread_cpu_time().unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_millis(20);
+        while std::time::Instant::now() < deadline {}
+        let spinning = thread_cpu_time().unwrap() - start;
+        assert!(spinning >= Duration::from_millis(10), "{:?}", spinning);
+
+        let start = thread_cpu_time().unwrap();
+        thread::sleep(Duration::from_millis(20));
+        let sleeping = thread_cpu_time().unwrap() - start;
+        assert!(sleeping < Duration::from_millis(10), "{:?}", sleeping);
+    }
+}
@@ -0,0 +1,310 @@
+//! OS-level process sandboxing for CLI execution (Linux only).
+//!
+//! V8 isolates are the primary security boundary, but a multi-tenant FaaS
+//! should not rely on a single layer. After the CLI has read the script file,
+//! [`apply`] locks the process down so that even a V8 escape cannot open
+//! files or sockets beyond what the invocation needs:
+//!
+//! - **Landlock** removes all filesystem access except an explicit read-only
+//!   allowlist (the DNS configuration files when networking is enabled) and
+//!   the directories the runtime writes to (e.g. `--cache-dir`).
+//! - **seccomp** rejects syscalls the runtime never needs (`execve`, `ptrace`,
+//!   `mount`, `bind`, kernel module loading, ...) with `EPERM`, and blocks
+//!   `socket` entirely unless networking is allowed (e.g. for Redis).
+//!
+//! Both restrictions only affect the calling thread and the threads it spawns
+//! afterwards, so [`apply`] must run before the tokio runtime and the V8
+//! platform threads are started.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+/// Files needed for hostname resolution when networking is allowed.
+const DNS_CONFIG_FILES: &[&str] = &[
+    "/etc/hosts",
+    "/etc/resolv.conf",
+    "/etc/nsswitch.conf",
+    "/etc/host.conf",
+    "/etc/gai.conf",
+];
+
+/// What the sandboxed process is still allowed to do.
+#[derive(Debug, Clone, Default)]
+pub struct SandboxPolicy {
+    /// Allow creating sockets (required for Redis log streaming)
+    pub allow_network: bool,
+    /// Additional paths that remain readable after the sandbox is applied
+    pub read_paths: Vec<PathBuf>,
+    /// Directories beneath which files can still be created, written and
+    /// removed. They must exist when the sandbox is applied.
+    pub write_paths: Vec<PathBuf>,
+}
+
+/// How much of the sandbox the kernel was able to enforce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxStatus {
+    /// Both Landlock and seccomp are active
+    FullyEnforced,
+    /// seccomp is active but the kernel lacks (full) Landlock support
+    PartiallyEnforced,
+}
+
+#[cfg(target_os = "linux")]
+pub fn apply(policy: &SandboxPolicy) -> Result<SandboxStatus> {
+    let filesystem_enforced = linux::apply_landlock(policy)?;
+    linux::apply_seccomp(policy)?;
+    Ok(if filesystem_enforced {
+        SandboxStatus::FullyEnforced
+    } else {
+        SandboxStatus::PartiallyEnforced
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply(_policy: &SandboxPolicy) -> Result<SandboxStatus> {
+    Err(anyhow::anyhow!("Process sandboxing is only supported on Linux"))
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    use anyhow::{anyhow, Result};
+    use landlock::{
+        path_beneath_rules, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr,
+        RulesetStatus, ABI,
+    };
+    use seccompiler::{BpfProgram, SeccompAction, SeccompFilter, SeccompRule, TargetArch};
+
+    use super::{SandboxPolicy, DNS_CONFIG_FILES};
+
+    /// Syscalls the runtime never needs once the script has been loaded.
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_execve,
+        libc::SYS_execveat,
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_bind,
+        libc::SYS_listen,
+        libc::SYS_accept,
+        libc::SYS_accept4,
+        libc::SYS_kexec_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_reboot,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_setuid,
+        libc::SYS_setgid,
+        libc::SYS_setreuid,
+        libc::SYS_setregid,
+        libc::SYS_setresuid,
+        libc::SYS_setresgid,
+        libc::SYS_personality,
+    ];
+
+    /// Restrict filesystem access to the policy's read-only allowlist and
+    /// writable directories.
+    ///
+    /// Returns whether the kernel fully enforced the ruleset.
+    pub(super) fn apply_landlock(policy: &SandboxPolicy) -> Result<bool> {
+        let abi = ABI::V2;
+
+        let mut readable = readable_paths(policy);
+        // Landlock rules can only be attached to paths that exist
+        readable.retain(|path| path.exists());
+        if let Some(missing) = policy.write_paths.iter().find(|path| !path.is_dir()) {
+            return Err(anyhow!("{} is not a directory", missing.display()));
+        }
+
+        let status = Ruleset::default()
+            .handle_access(AccessFs::from_all(abi))?
+            .create()?
+            .add_rules(path_beneath_rules(&readable, AccessFs::from_read(abi)))?
+            .add_rules(path_beneath_rules(
+                &policy.write_paths,
+                AccessFs::from_all(abi),
+            ))?
+            .restrict_self()
+            .map_err(|e| anyhow!("Failed to apply Landlock ruleset: {}", e))?;
+
+        Ok(status.ruleset == RulesetStatus::FullyEnforced)
+    }
+
+    /// Paths the policy leaves readable, whether or not they exist.
+    pub(super) fn readable_paths(policy: &SandboxPolicy) -> Vec<PathBuf> {
+        let mut readable: Vec<PathBuf> = policy.read_paths.clone();
+        if policy.allow_network {
+            readable.extend(DNS_CONFIG_FILES.iter().map(PathBuf::from));
+        }
+        readable
+    }
+
+    /// Syscalls the policy denies, each unconditionally.
+    pub(super) fn denied_syscalls(policy: &SandboxPolicy) -> Vec<libc::c_long> {
+        let mut denied = DENIED_SYSCALLS.to_vec();
+        if !policy.allow_network {
+            denied.extend([libc::SYS_socket, libc::SYS_socketpair]);
+        }
+        denied
+    }
+
+    /// The seccomp filter for the policy, compiled for this architecture.
+    pub(super) fn seccomp_program(policy: &SandboxPolicy) -> Result<BpfProgram> {
+        let rules: BTreeMap<i64, Vec<SeccompRule>> = denied_syscalls(policy)
+            .into_iter()
+            .map(|syscall| (syscall, vec![]))
+            .collect();
+        let arch: TargetArch = std::env::consts::ARCH
+            .try_into()
+            .map_err(|e| anyhow!("Unsupported architecture for seccomp: {:?}", e))?;
+        let filter = SeccompFilter::new(
+            rules,
+            SeccompAction::Allow,
+            SeccompAction::Errno(libc::EPERM as u32),
+            arch,
+        )?;
+        Ok(filter.try_into()?)
+    }
+
+    /// Install a seccomp filter that fails denied syscalls with `EPERM`.
+    pub(super) fn apply_seccomp(policy: &SandboxPolicy) -> Result<()> {
+        let program = seccomp_program(policy)?;
+        seccompiler::apply_filter(&program)
+            .map_err(|e| anyhow!("Failed to apply seccomp filter: {}", e))?;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::io::ErrorKind;
+    use std::net::UdpSocket;
+
+    use super::*;
+
+    #[test]
+    fn test_policy_paths() {
+        let policy = SandboxPolicy::default();
+        assert!(!policy.allow_network);
+        assert!(linux::readable_paths(&policy).is_empty());
+
+        let policy = SandboxPolicy {
+            allow_network: true,
+            read_paths: vec![PathBuf::from("/proc/self/fd")],
+            write_paths: vec![],
+        };
+        let readable = linux::readable_paths(&policy);
+        assert_eq!(readable[0], PathBuf::from("/proc/self/fd"));
+        for file in DNS_CONFIG_FILES {
+            assert!(readable.contains(&PathBuf::from(file)), "{}", file);
+        }
+    }
+
+    #[test]
+    fn test_syscall_denylist() {
+        let offline = linux::denied_syscalls(&SandboxPolicy::default());
+        for syscall in [
+            libc::SYS_execve,
+            libc::SYS_ptrace,
+            libc::SYS_mount,
+            libc::SYS_bind,
+            libc::SYS_bpf,
+            libc::SYS_setuid,
+            libc::SYS_socket,
+            libc::SYS_socketpair,
+        ] {
+            assert!(offline.contains(&syscall), "{}", syscall);
+        }
+        // With networking, sockets can be created but still not served on
+        let online = linux::denied_syscalls(&SandboxPolicy {
+            allow_network: true,
+            ..Default::default()
+        });
+        assert!(!online.contains(&libc::SYS_socket));
+        assert!(online.contains(&libc::SYS_bind));
+        assert!(online.contains(&libc::SYS_listen));
+        // Syscalls the runtime itself needs stay allowed
+        for syscall in [
+            libc::SYS_read,
+            libc::SYS_write,
+            libc::SYS_mmap,
+            libc::SYS_futex,
+        ] {
+            assert!(!offline.contains(&syscall), "{}", syscall);
+        }
+
+        assert!(!linux::seccomp_program(&SandboxPolicy::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_apply_restricts_the_calling_thread() {
+        let dir = std::env::temp_dir().join(format!("vortex-sandbox-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let allowed = dir.join("allowed.txt");
+        let denied = dir.join("denied.txt");
+        let writable = dir.join("cache");
+        std::fs::write(&allowed, "ok").unwrap();
+        std::fs::write(&denied, "secret").unwrap();
+        std::fs::create_dir_all(&writable).unwrap();
+
+        // Only the thread that applies the sandbox is restricted, so the
+        // rest of the test process is unaffected
+        let policy = SandboxPolicy {
+            allow_network: false,
+            read_paths: vec![allowed.clone()],
+            write_paths: vec![writable.clone()],
+        };
+        let entry = writable.join("entry");
+        let outside = dir.join("outside.txt");
+        let (status, socket, read_allowed, read_denied, written, written_outside) =
+            std::thread::spawn(move || {
+                let status = apply(&policy).unwrap();
+                (
+                    status,
+                    UdpSocket::bind("127.0.0.1:0").map(drop),
+                    std::fs::read_to_string(&allowed),
+                    std::fs::read_to_string(&denied),
+                    std::fs::write(&entry, "cached").and_then(|_| std::fs::read_to_string(&entry)),
+                    std::fs::write(&outside, "x"),
+                )
+            })
+            .join()
+            .unwrap();
+
+        assert_eq!(
+            socket.unwrap_err().raw_os_error(),
+            Some(libc::EPERM),
+            "socket() must be denied"
+        );
+        if status == SandboxStatus::FullyEnforced {
+            assert_eq!(read_allowed.unwrap(), "ok");
+            assert_eq!(read_denied.unwrap_err().kind(), ErrorKind::PermissionDenied);
+            assert_eq!(
+                written_outside.unwrap_err().kind(),
+                ErrorKind::PermissionDenied
+            );
+        }
+        assert_eq!(written.unwrap(), "cached");
+        assert!(UdpSocket::bind("127.0.0.1:0").is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
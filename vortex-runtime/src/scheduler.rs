@@ -0,0 +1,347 @@
+//! Scheduler - multi-isolate execution across a thread-per-core pool.
+//!
+//! `JsRuntime` is `!Send`, so a single `VortexWorker` is pinned to the thread
+//! that created it. The scheduler owns N OS threads, each running a
+//! current-thread tokio runtime with a `LocalSet` and its own [`PoolManager`].
+//! Executions are submitted through a `Send` front door and routed to the
+//! least-loaded thread, where they run concurrently with the other isolates
+//! on that thread.
+//!
+//! ```text
+//! execute() ──► least-loaded thread ──► LocalSet task ──► PoolManager ──► VortexWorker
+//!                  (mpsc channel)                                           │
+//!          ◄──────────────────────── oneshot reply ◄────────────────────────┘
+//! ```
+
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::LocalSet;
+
+use crate::ops::{OutputChunk, RequestBodyStream, WebSocketChannel};
+use crate::pool::{PoolManager, PoolOptions};
+use crate::worker::{ExecutionResult, VortexWorker, WorkerOptions};
+
+/// Configuration for a [`Scheduler`].
+#[derive(Debug, Clone)]
+pub struct SchedulerOptions {
+    /// Number of worker threads (defaults to the number of available cores)
+    pub threads: usize,
+    /// Pool limits applied independently on each worker thread
+    pub pool: PoolOptions,
+}
+
+impl Default for SchedulerOptions {
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            pool: PoolOptions::default(),
+        }
+    }
+}
+
+/// A single invocation submitted to the [`Scheduler`].
+#[derive(Debug, Default)]
+pub struct ExecutionRequest {
+    /// Tenant the invocation is billed to; used for per-tenant concurrency limits
+    pub tenant_id: String,
+    /// Function being invoked. When set, warm workers for the same function are
+    /// reused; when `None`, a fresh worker is created and discarded afterwards.
+    pub function_id: Option<String>,
+    /// JavaScript source to execute
+    pub code: String,
+    /// Invocation payload, available to user code as `input`
+    pub input: Value,
+    /// Correlation ID for logs and result; generated when `None`
+    pub invocation_id: Option<String>,
+    /// Options used if a new worker has to be created
+    pub options: WorkerOptions,
+    /// HTTP request body streamed to user code as `vortex.request.body`
+    pub body: Option<RequestBodyStream>,
+    /// Receives this invocation's `vortex.write()` chunks as they are
+    /// written, instead of [`ExecutionResult::chunks`]
+    pub output: Option<mpsc::Sender<OutputChunk>>,
+    /// WebSocket connection the invocation may accept through `WebSocketPair`
+    pub websocket: Option<WebSocketChannel>,
+}
+
+/// How often each thread sweeps its pool for expired and worn-out workers.
+const EVICTION_INTERVAL: Duration = Duration::from_secs(10);
+
+struct Job {
+    request: ExecutionRequest,
+    reply: oneshot::Sender<Result<ExecutionResult>>,
+}
+
+/// What a worker thread is asked to do.
+enum Message {
+    Execute(Job),
+    /// Drop the thread's idle workers for a function id, then reply
+    Evict(String, oneshot::Sender<()>),
+}
+
+struct WorkerThread {
+    sender: mpsc::UnboundedSender<Message>,
+    in_flight: Arc<AtomicUsize>,
+    handle: JoinHandle<()>,
+}
+
+/// Load-balances executions across a fixed set of isolate threads.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vortex_runtime::{Scheduler, SchedulerOptions};
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let scheduler = Scheduler::new(SchedulerOptions::default())?;
+///     let result = scheduler
+///         .execute("return input.a + input.b", serde_json::json!({ "a": 1, "b": 2 }))
+///         .await?;
+///     println!("Output: {:?}", result.output);
+///     Ok(())
+/// }
+/// ```
+pub struct Scheduler {
+    threads: Vec<WorkerThread>,
+}
+
+impl Scheduler {
+    /// Spawn the worker threads.
+    pub fn new(options: SchedulerOptions) -> Result<Self> {
+        let threads = (0..options.threads.max(1))
+            .map(|index| spawn_worker_thread(index, options.pool.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { threads })
+    }
+
+    /// Execute anonymous code with an input payload in a fresh worker.
+    pub async fn execute(&self, code: impl Into<String>, input: Value) -> Result<ExecutionResult> {
+        self.submit(ExecutionRequest {
+            code: code.into(),
+            input,
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Route an execution to the least-loaded thread and wait for its result.
+    pub async fn submit(&self, request: ExecutionRequest) -> Result<ExecutionResult> {
+        let thread = self
+            .threads
+            .iter()
+            .min_by_key(|thread| thread.in_flight.load(Ordering::Relaxed))
+            .ok_or_else(|| anyhow!("Scheduler has no worker threads"))?;
+
+        let (reply, response) = oneshot::channel();
+        thread.in_flight.fetch_add(1, Ordering::Relaxed);
+        if thread
+            .sender
+            .send(Message::Execute(Job { request, reply }))
+            .is_err()
+        {
+            thread.in_flight.fetch_sub(1, Ordering::Relaxed);
+            return Err(anyhow!("Scheduler worker thread has stopped"));
+        }
+        response
+            .await
+            .map_err(|_| anyhow!("Scheduler worker thread dropped the execution"))?
+    }
+
+    /// Drop the idle workers for `function_id` on every thread, e.g. those of
+    /// a function version that has been replaced.
+    pub async fn evict(&self, function_id: &str) {
+        let mut evicted = Vec::with_capacity(self.threads.len());
+        for thread in &self.threads {
+            let (reply, done) = oneshot::channel();
+            let message = Message::Evict(function_id.to_string(), reply);
+            if thread.sender.send(message).is_ok() {
+                evicted.push(done);
+            }
+        }
+        for done in evicted {
+            let _ = done.await;
+        }
+    }
+
+    /// Number of executions currently queued or running on each thread.
+    pub fn load(&self) -> Vec<usize> {
+        self.threads
+            .iter()
+            .map(|thread| thread.in_flight.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// Stop accepting work and wait for in-flight executions to finish.
+    pub fn shutdown(self) {
+        let handles: Vec<_> = self
+            .threads
+            .into_iter()
+            .map(|thread| {
+                drop(thread.sender);
+                thread.handle
+            })
+            .collect();
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn spawn_worker_thread(index: usize, pool_options: PoolOptions) -> Result<WorkerThread> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Message>();
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let thread_in_flight = Arc::clone(&in_flight);
+
+    let handle = thread::Builder::new()
+        .name(format!("vortex-isolate-{}", index))
+        .spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to build isolate thread runtime");
+            let local = LocalSet::new();
+            local.block_on(&runtime, async move {
+                let pool = Rc::new(PoolManager::new(pool_options));
+                let sweeper = tokio::task::spawn_local({
+                    let pool = Rc::clone(&pool);
+                    async move {
+                        let mut interval = tokio::time::interval(EVICTION_INTERVAL);
+                        loop {
+                            interval.tick().await;
+                            pool.evict_idle();
+                        }
+                    }
+                });
+                while let Some(message) = receiver.recv().await {
+                    let job = match message {
+                        Message::Execute(job) => job,
+                        Message::Evict(function_id, reply) => {
+                            pool.evict_function(&function_id);
+                            let _ = reply.send(());
+                            continue;
+                        }
+                    };
+                    let pool = Rc::clone(&pool);
+                    let in_flight = Arc::clone(&thread_in_flight);
+                    tokio::task::spawn_local(async move {
+                        let result = run_job(&pool, job.request).await;
+                        in_flight.fetch_sub(1, Ordering::Relaxed);
+                        let _ = job.reply.send(result);
+                    });
+                }
+                sweeper.abort();
+            });
+            // Let executions that were already running finish
+            runtime.block_on(local);
+        })
+        .map_err(|e| anyhow!("Failed to spawn isolate thread: {}", e))?;
+
+    Ok(WorkerThread {
+        sender,
+        in_flight,
+        handle,
+    })
+}
+
+/// Run one execution on this thread, through the pool when it names a function.
+pub(crate) async fn run_job(
+    pool: &PoolManager,
+    request: ExecutionRequest,
+) -> Result<ExecutionResult> {
+    let ExecutionRequest {
+        tenant_id,
+        function_id,
+        code,
+        input,
+        invocation_id,
+        options,
+        body,
+        output,
+        websocket,
+    } = request;
+    let invocation_id = invocation_id.as_deref();
+
+    match function_id {
+        Some(function_id) => {
+            let mut worker = pool.acquire(&tenant_id, &function_id, options).await?;
+            worker.set_request_body(body);
+            worker.set_websocket(websocket);
+            let previous_sink = output.map(|sink| worker.replace_output_sink(Some(sink)));
+            let result = worker.run_invocation(&code, &input, invocation_id).await;
+            if let Some(previous_sink) = previous_sink {
+                worker.replace_output_sink(previous_sink);
+            }
+            if result.is_err() {
+                // A failed invocation may leave the isolate in a bad state
+                worker.discard();
+            }
+            result
+        }
+        None => {
+            // Counted against the governor until the worker is dropped
+            let mut slot = pool.admit(true).await?;
+            let mut worker = VortexWorker::with_options(options)?;
+            if let Some(slot) = &mut slot {
+                slot.set_heap_bytes(worker.heap_bytes());
+            }
+            worker.set_request_body(body);
+            worker.set_websocket(websocket);
+            if output.is_some() {
+                worker.set_output_sink(output);
+            }
+            worker.run_invocation(&code, &input, invocation_id).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_execute_with_input() {
+        let scheduler = Scheduler::new(SchedulerOptions {
+            threads: 2,
+            ..Default::default()
+        })
+        .unwrap();
+        let result = scheduler
+            .execute("return input.a + input.b", serde_json::json!({ "a": 1, "b": 2 }))
+            .await
+            .unwrap();
+        assert_eq!(result.output, Some(serde_json::json!(3)));
+        scheduler.shutdown();
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_submissions() {
+        let scheduler = Scheduler::new(SchedulerOptions {
+            threads: 2,
+            ..Default::default()
+        })
+        .unwrap();
+        let runs = (0..8).map(|i| {
+            scheduler.submit(ExecutionRequest {
+                tenant_id: "tenant".to_string(),
+                function_id: Some(format!("fn-{}", i % 2)),
+                code: "await new Promise(r => setTimeout(r, 10)); return input".to_string(),
+                input: serde_json::json!(i),
+                ..Default::default()
+            })
+        });
+        let results = deno_core::futures::future::join_all(runs).await;
+        for (i, result) in results.into_iter().enumerate() {
+            assert_eq!(result.unwrap().output, Some(serde_json::json!(i)));
+        }
+        assert_eq!(scheduler.load(), vec![0, 0]);
+        scheduler.shutdown();
+    }
+}
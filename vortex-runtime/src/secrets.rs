@@ -0,0 +1,80 @@
+//! Host-held secrets.
+//!
+//! Some platform APIs need key material that user code must never see, such
+//! as a webhook signing secret. Instead of passing the secret through
+//! `process.env`, the host registers it with a [`SecretsProvider`] and user
+//! code refers to it by name:
+//!
+//! ```js
+//! const ok = vortex.crypto.verifyHmac('STRIPE_WEBHOOK_SECRET', body, signature, 'sha256');
+//! ```
+//!
+//! The runtime resolves the name inside the op and only hands the result of
+//! the computation back to JavaScript.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Resolves secret names to their values.
+pub trait SecretsProvider: fmt::Debug + Send + Sync {
+    /// The secret called `name`, or `None` if there is no such secret (or
+    /// this function may not use it).
+    fn get(&self, name: &str) -> Option<Vec<u8>>;
+}
+
+/// Shared handle to the provider a worker resolves secrets through.
+pub type SecretsState = Option<Arc<dyn SecretsProvider>>;
+
+/// A fixed set of secrets, e.g. read from the host environment at startup.
+#[derive(Clone, Default)]
+pub struct StaticSecrets {
+    secrets: HashMap<String, Vec<u8>>,
+}
+
+impl StaticSecrets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add (or replace) the secret `name`.
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<Vec<u8>>) {
+        self.secrets.insert(name.into(), value.into());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+}
+
+impl SecretsProvider for StaticSecrets {
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        self.secrets.get(name).cloned()
+    }
+}
+
+// Only the names: secret values must not end up in logs via `{:?}`
+impl fmt::Debug for StaticSecrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<_> = self.secrets.keys().collect();
+        names.sort();
+        f.debug_struct("StaticSecrets").field("names", &names).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_hides_values() {
+        let mut secrets = StaticSecrets::new();
+        secrets.insert("STRIPE_WEBHOOK_SECRET", "whsec_123");
+        assert_eq!(secrets.get("STRIPE_WEBHOOK_SECRET").unwrap(), b"whsec_123");
+        assert!(secrets.get("OTHER").is_none());
+
+        let debug = format!("{:?}", secrets);
+        assert!(debug.contains("STRIPE_WEBHOOK_SECRET"));
+        assert!(!debug.contains("whsec_123"));
+    }
+}
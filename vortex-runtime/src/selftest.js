@@ -0,0 +1,141 @@
+// Vortex sandbox self-verification probes (see selftest.rs).
+//
+// A function expression called with { harden, platformGlobals } in a freshly
+// bootstrapped isolate, with no more privileges than user code has. Returns
+// the failed checks as a JSON array of { check, detail }. It must leave no
+// trace behind: nothing is defined on the global object, and anything it
+// changes is restored.
+
+(options) => {
+    const failures = [];
+    const fail = (check, detail) => failures.push({ check, detail });
+
+    // Upper bound on the objects walked looking for Deno.core; reaching it
+    // is a failure rather than a pass
+    const MAX_REACHABLE = 200000;
+
+    // =========================================================================
+    // Deno is unreachable
+    // =========================================================================
+
+    if ('Deno' in globalThis || typeof Deno !== 'undefined') {
+        fail('deno-unreachable', 'Deno is a global');
+    }
+
+    // Code that runs in a scope other than this script's must not see Deno
+    // either. With code generation disabled these throw EvalError, which is
+    // just as good.
+    const probe = (name, run) => {
+        try {
+            const type = run();
+            if (type !== 'undefined') {
+                fail('deno-unreachable', `${name} sees typeof Deno === '${type}'`);
+            }
+        } catch (e) {
+            if (!(e instanceof EvalError)) {
+                fail('deno-unreachable', `${name} threw ${e}`);
+            }
+        }
+    };
+    const GeneratorFunction = Object.getPrototypeOf(function* () {}).constructor;
+    probe('Function()', () => Function('return typeof Deno')());
+    probe('(() => {}).constructor()', () => (() => {}).constructor('return typeof this.Deno')());
+    probe('GeneratorFunction()', () => GeneratorFunction('yield typeof Deno')().next().value);
+    probe('indirect eval', () => (0, eval)('typeof Deno'));
+    if (typeof ShadowRealm === 'function') {
+        probe('ShadowRealm', () => new ShadowRealm().evaluate('typeof Deno'));
+    }
+
+    // Sloppy-mode bootstrap frames calling back into user code could expose
+    // their receiver and function through the V8 stack trace API
+    const roots = [globalThis];
+    const prepareStackTrace = Object.getOwnPropertyDescriptor(Error, 'prepareStackTrace');
+    try {
+        Error.prepareStackTrace = (_error, frames) => frames;
+        new Event({
+            toString() {
+                const frames = new Error().stack;
+                if (Array.isArray(frames)) {
+                    for (const frame of frames) roots.push(frame.getThis(), frame.getFunction());
+                }
+                return 'selftest';
+            },
+        });
+    } catch (e) {
+        fail('deno-unreachable', `stack frame probe threw ${e}`);
+    } finally {
+        if (prepareStackTrace) {
+            Object.defineProperty(Error, 'prepareStackTrace', prepareStackTrace);
+        } else {
+            delete Error.prepareStackTrace;
+        }
+    }
+
+    // Walk everything reachable without calling user-visible code (getters
+    // are collected, not invoked) looking for the op table or an op itself
+    const seen = new Set();
+    const queue = roots;
+    while (queue.length > 0) {
+        const value = queue.pop();
+        if (value === null || (typeof value !== 'object' && typeof value !== 'function') || seen.has(value)) {
+            continue;
+        }
+        if (seen.size >= MAX_REACHABLE) {
+            fail('deno-unreachable', `more than ${MAX_REACHABLE} objects reachable from globalThis; gave up`);
+            break;
+        }
+        seen.add(value);
+        try {
+            queue.push(Object.getPrototypeOf(value));
+            for (const key of Reflect.ownKeys(value)) {
+                const desc = Object.getOwnPropertyDescriptor(value, key);
+                if (!desc) continue;
+                if (!('value' in desc)) {
+                    queue.push(desc.get, desc.set);
+                    continue;
+                }
+                const name = typeof key === 'symbol' ? key.description : key;
+                if (typeof desc.value === 'function' && /^op_/.test(name ?? '')) {
+                    fail('deno-unreachable', `op '${name}' is reachable from user code`);
+                    return JSON.stringify(failures);
+                }
+                queue.push(desc.value);
+            }
+        } catch (e) {
+            // Proxies may throw from their traps; what they guard isn't reachable this way
+        }
+    }
+
+    // =========================================================================
+    // Bootstrap globals are frozen
+    // =========================================================================
+
+    for (const name of ['console', 'vortex']) {
+        const desc = Object.getOwnPropertyDescriptor(globalThis, name);
+        if (!desc || desc.writable || desc.configurable || !Object.isFrozen(desc.value)) {
+            fail('frozen-globals', `${name} can be replaced or modified`);
+        }
+    }
+
+    if (options.harden) {
+        const intrinsics = {
+            'Object.prototype': Object.prototype,
+            'Function.prototype': Function.prototype,
+            'Array.prototype': Array.prototype,
+            'Promise.prototype': Promise.prototype,
+            'Error.prototype': Error.prototype,
+            'GeneratorFunction.prototype': GeneratorFunction.prototype,
+            JSON,
+            Math,
+            Reflect,
+        };
+        for (const name of options.platformGlobals) intrinsics[name] = globalThis[name];
+        for (const [name, value] of Object.entries(intrinsics)) {
+            if (!Object.isFrozen(value)) {
+                fail('frozen-globals', `${name} is not frozen`);
+            }
+        }
+    }
+
+    return JSON.stringify(failures);
+}
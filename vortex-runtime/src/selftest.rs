@@ -0,0 +1,229 @@
+//! Sandbox self-verification.
+//!
+//! The isolate is only as tight as the bootstrap leaves it, and a change to
+//! the extension list or to `bootstrap.js` can quietly loosen it. The checks
+//! here look at a live worker the way hostile user code would:
+//!
+//! - **deno-unreachable**: `Deno`, and with it the runtime's ops, can't be
+//!   reached: not as a global, not through the `Function` constructors,
+//!   indirect `eval` or a `ShadowRealm`, and not by walking everything
+//!   reachable from `globalThis` (and from bootstrap stack frames calling back
+//!   into user code) for an op function;
+//! - **host-ops**: no op of a host-access extension (`deno_fs`, `deno_net`,
+//!   processes, environment, FFI) is registered;
+//! - **frozen-globals**: `console` and `vortex` are frozen and can't be
+//!   replaced; with `harden`, so are the intrinsics and platform globals.
+//!
+//! `vortex-runtime selftest` runs them against workers in a few
+//! representative configurations and exits non-zero if any check fails.
+//! [`WorkerOptions::verify_sandbox`](crate::WorkerOptions::verify_sandbox)
+//! runs them on every new worker and fails worker creation instead of handing
+//! out a worker whose sandbox is broken.
+
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::conformance::SUPPORTED_GLOBALS;
+use crate::worker::{VortexWorker, WorkerOptions};
+
+/// Subcommand that verifies the sandbox instead of running a function.
+pub const SELFTEST_SUBCOMMAND: &str = "selftest";
+
+/// Script name the probes are executed under.
+pub(crate) const SELFTEST_SCRIPT_NAME: &str = "[vortex:selftest]";
+
+/// Probes run inside the isolate; see `selftest.js`.
+const PROBE_JS: &str = include_str!("selftest.js");
+
+/// Op name prefixes of the deno extensions that reach the host: `deno_fs`,
+/// `deno_net`, TLS, DNS, subprocesses, the process environment and FFI.
+const HOST_OP_PREFIXES: &[&str] = &[
+    "op_fs_",
+    "op_net_",
+    "op_tls_",
+    "op_dns_",
+    "op_spawn",
+    "op_run",
+    "op_kill",
+    "op_env",
+    "op_set_env",
+    "op_delete_env",
+    "op_exec_path",
+    "op_chdir",
+    "op_ffi_",
+    "op_dlopen",
+];
+
+/// Ops of our own that share a host prefix but never touch the host:
+/// `vortex.fs` is an in-memory filesystem.
+const VIRTUAL_OPS: &[&str] = &[
+    "op_fs_stat",
+    "op_fs_read",
+    "op_fs_write",
+    "op_fs_list",
+    "op_fs_remove",
+];
+
+/// A sandbox check that failed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SandboxViolation {
+    /// Which check: `deno-unreachable`, `host-ops` or `frozen-globals`
+    pub check: String,
+    /// What was found
+    pub detail: String,
+}
+
+impl fmt::Display for SandboxViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.check, self.detail)
+    }
+}
+
+/// Violations among the ops registered in a worker.
+///
+/// No ops at all means they couldn't be enumerated, which fails too.
+pub fn check_ops(op_names: &[&str]) -> Vec<SandboxViolation> {
+    if op_names.is_empty() {
+        return vec![SandboxViolation {
+            check: "host-ops".to_string(),
+            detail: "could not enumerate the registered ops".to_string(),
+        }];
+    }
+    op_names
+        .iter()
+        .copied()
+        .filter(|name| !VIRTUAL_OPS.contains(name))
+        .filter(|name| {
+            HOST_OP_PREFIXES
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        })
+        .map(|name| SandboxViolation {
+            check: "host-ops".to_string(),
+            detail: format!("{} is registered", name),
+        })
+        .collect()
+}
+
+/// The probe script for a worker, evaluating to a JSON array of violations.
+pub(crate) fn probe_script(harden: bool) -> Result<String> {
+    let platform_globals: Vec<&str> = SUPPORTED_GLOBALS
+        .iter()
+        .copied()
+        .filter(|name| !matches!(*name, "globalThis" | "self"))
+        .collect();
+    let options = serde_json::json!({
+        "harden": harden,
+        "platformGlobals": platform_globals,
+    });
+    Ok(format!(
+        "({})({})",
+        PROBE_JS.trim_end(),
+        serde_json::to_string(&options)?
+    ))
+}
+
+/// Fail with every violation if there are any.
+pub fn ensure(violations: &[SandboxViolation]) -> Result<()> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let violations: Vec<String> = violations.iter().map(ToString::to_string).collect();
+    Err(anyhow!(
+        "Sandbox verification failed: {}",
+        violations.join("; ")
+    ))
+}
+
+/// Worker configurations `vortex-runtime selftest` verifies.
+pub fn configurations() -> Vec<(&'static str, WorkerOptions)> {
+    vec![
+        ("default", WorkerOptions::default()),
+        (
+            "harden",
+            WorkerOptions {
+                harden: true,
+                ..Default::default()
+            },
+        ),
+        (
+            "disable-eval",
+            WorkerOptions {
+                disable_code_generation: true,
+                ..Default::default()
+            },
+        ),
+        (
+            "node-compat",
+            WorkerOptions {
+                node_compat: true,
+                commonjs: true,
+                ..Default::default()
+            },
+        ),
+    ]
+}
+
+/// Create a worker for each of [`configurations`] and verify its sandbox.
+pub fn run() -> Result<Vec<(&'static str, Vec<SandboxViolation>)>> {
+    configurations()
+        .into_iter()
+        .map(|(name, options)| {
+            let mut worker = VortexWorker::with_options(options)?;
+            Ok((name, worker.verify_sandbox()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_ops() {
+        assert!(check_ops(&["op_log", "op_fetch", "op_fs_read", "op_fs_stat"]).is_empty());
+
+        let violations = check_ops(&["op_log", "op_fs_read_file_sync", "op_net_connect_tcp"]);
+        let details: Vec<&str> = violations.iter().map(|v| v.detail.as_str()).collect();
+        assert_eq!(
+            details,
+            [
+                "op_fs_read_file_sync is registered",
+                "op_net_connect_tcp is registered"
+            ]
+        );
+
+        // Failing to enumerate the ops must not pass
+        assert_eq!(check_ops(&[]).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_selftest_passes() {
+        for (name, violations) in run().unwrap() {
+            assert!(violations.is_empty(), "{}: {:?}", name, violations);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_sandbox_fails_closed() {
+        let mut worker = VortexWorker::new().unwrap();
+        worker
+            .run("globalThis.leak = { ops: { op_fs_open_sync() {} } };")
+            .await
+            .unwrap();
+        let violations = worker.verify_sandbox().unwrap();
+        assert_eq!(
+            violations,
+            [SandboxViolation {
+                check: "deno-unreachable".to_string(),
+                detail: "op 'op_fs_open_sync' is reachable from user code".to_string(),
+            }]
+        );
+        assert!(ensure(&violations)
+            .unwrap_err()
+            .to_string()
+            .starts_with("Sandbox verification failed: deno-unreachable"));
+    }
+}
@@ -0,0 +1,251 @@
+//! Resident server for same-host invocation.
+//!
+//! `vortex-runtime --listen unix:/path/to.sock` keeps the runtime resident and
+//! accepts invocations over a Unix socket, so the Go API can reuse warm
+//! workers without spawning a process per request or managing TCP ports.
+//! `--listen stdio` serves a single session over stdin/stdout instead.
+//!
+//! Each connection carries newline-delimited JSON frames in the control-plane
+//! protocol (see [`crate::protocol`]). A connection may have many requests in
+//! flight; responses are written as each one finishes and are matched to
+//! requests by `id`.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::UnixListener;
+use tokio::sync::mpsc;
+
+use crate::protocol::ControlPlane;
+use crate::scheduler::{ExecutionRequest, Scheduler};
+use crate::supervisor::{ProcessRequest, Supervisor};
+use crate::worker::{ExecutionResult, WorkerOptions};
+
+/// Where invocations received by the server are executed.
+pub enum Backend {
+    /// Isolates on in-process threads, created from the given options
+    InProcess {
+        scheduler: Scheduler,
+        options: WorkerOptions,
+    },
+    /// Pre-forked child processes
+    Processes(Supervisor),
+}
+
+impl Backend {
+    pub(crate) async fn execute(&self, request: ProcessRequest) -> Result<ExecutionResult> {
+        match self {
+            Backend::InProcess { scheduler, options } => {
+                scheduler
+                    .submit(ExecutionRequest {
+                        tenant_id: request.tenant_id,
+                        function_id: request.function_id,
+                        code: request.code,
+                        input: request.input,
+                        invocation_id: request.invocation_id,
+                        options: options.clone(),
+                        body: None,
+                        output: None,
+                        websocket: None,
+                    })
+                    .await
+            }
+            Backend::Processes(supervisor) => supervisor.dispatch(request).await,
+        }
+    }
+
+    /// Drop the idle workers pooled under `function_id` everywhere.
+    pub(crate) async fn evict(&self, function_id: &str) {
+        match self {
+            Backend::InProcess { scheduler, .. } => scheduler.evict(function_id).await,
+            Backend::Processes(supervisor) => supervisor.evict(function_id).await,
+        }
+    }
+
+    /// Per-thread load for the in-process backend.
+    pub(crate) fn load(&self) -> Option<Vec<usize>> {
+        match self {
+            Backend::InProcess { scheduler, .. } => Some(scheduler.load()),
+            Backend::Processes(_) => None,
+        }
+    }
+}
+
+/// A parsed `--listen` address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Unix(PathBuf),
+    Stdio,
+}
+
+impl std::fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+            ListenAddr::Stdio => f.write_str("stdio"),
+        }
+    }
+}
+
+impl std::str::FromStr for ListenAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(addr: &str) -> Result<Self> {
+        if addr == "stdio" {
+            return Ok(ListenAddr::Stdio);
+        }
+        match addr.strip_prefix("unix:") {
+            Some(path) if !path.is_empty() => Ok(ListenAddr::Unix(PathBuf::from(path))),
+            _ => Err(anyhow!(
+                "Unsupported listen address '{}', expected unix:<path> or stdio",
+                addr
+            )),
+        }
+    }
+}
+
+/// Serve the control plane on `addr` until Ctrl-C or a `shutdown` request,
+/// executing invocations on `backend`.
+pub async fn serve(addr: &ListenAddr, backend: Backend) -> Result<()> {
+    let plane = Arc::new(ControlPlane::new(backend));
+
+    let path = match addr {
+        ListenAddr::Stdio => {
+            let session =
+                handle_connection(tokio::io::stdin(), tokio::io::stdout(), Arc::clone(&plane));
+            tokio::select! {
+                _ = session => {}
+                _ = plane.shutdown_requested() => {}
+                _ = tokio::signal::ctrl_c() => {}
+            }
+            return Ok(());
+        }
+        ListenAddr::Unix(path) => path,
+    };
+
+    let listener = bind_unix(path)?;
+    let result = loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    let (reader, writer) = stream.into_split();
+                    tokio::spawn(handle_connection(reader, writer, Arc::clone(&plane)));
+                }
+                Err(e) => break Err(anyhow!("Failed to accept connection: {}", e)),
+            },
+            _ = plane.shutdown_requested() => break Ok(()),
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+        }
+    };
+
+    let _ = std::fs::remove_file(path);
+    result
+}
+
+/// Bind a Unix socket, replacing a stale socket file left by a previous run.
+fn bind_unix(path: &Path) -> Result<UnixListener> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if metadata.file_type().is_socket() {
+                std::fs::remove_file(path)?;
+            }
+        }
+    }
+    UnixListener::bind(path)
+        .map_err(|e| anyhow!("Failed to listen on unix:{}: {}", path.display(), e))
+}
+
+async fn handle_connection<R, W>(reader: R, mut writer: W, plane: Arc<ControlPlane>)
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    // A single writer keeps concurrent responses from interleaving
+    let (out_tx, mut out_rx) = mpsc::unbounded_channel::<String>();
+    let write_task = tokio::spawn(async move {
+        while let Some(line) = out_rx.recv().await {
+            if writer.write_all(line.as_bytes()).await.is_err() || writer.flush().await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let out_tx = out_tx.clone();
+        let plane = Arc::clone(&plane);
+        tokio::spawn(async move {
+            let response = plane.handle_line(&line).await;
+            if let Ok(mut line) = serde_json::to_string(&response) {
+                line.push('\n');
+                let _ = out_tx.send(line);
+            }
+        });
+    }
+
+    drop(out_tx);
+    let _ = write_task.await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ControlResponse;
+    use crate::scheduler::SchedulerOptions;
+    use tokio::net::UnixStream;
+
+    #[test]
+    fn test_parse_listen_addr() {
+        assert_eq!(
+            "unix:/tmp/vortex.sock".parse::<ListenAddr>().unwrap(),
+            ListenAddr::Unix(PathBuf::from("/tmp/vortex.sock"))
+        );
+        assert_eq!("stdio".parse::<ListenAddr>().unwrap(), ListenAddr::Stdio);
+        assert!("tcp:127.0.0.1:9000".parse::<ListenAddr>().is_err());
+        assert!("unix:".parse::<ListenAddr>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_invocation_over_unix_socket() {
+        let path = std::env::temp_dir().join(format!("vortex-test-{}.sock", std::process::id()));
+        let listener = bind_unix(&path).unwrap();
+        let plane = Arc::new(ControlPlane::new(Backend::InProcess {
+            scheduler: Scheduler::new(SchedulerOptions {
+                threads: 1,
+                ..Default::default()
+            })
+            .unwrap(),
+            options: WorkerOptions::default(),
+        }));
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, writer) = stream.into_split();
+            handle_connection(reader, writer, plane).await;
+        });
+
+        let stream = UnixStream::connect(&path).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        writer
+            .write_all(
+                b"{\"v\":1,\"id\":5,\"type\":\"invoke\",\
+                  \"code\":\"console.log('hi'); return input * 2\",\"input\":21}\n",
+            )
+            .await
+            .unwrap();
+
+        let line = BufReader::new(reader).lines().next_line().await.unwrap().unwrap();
+        let response: ControlResponse = serde_json::from_str(&line).unwrap();
+        let result: ExecutionResult = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!(response.id, 5);
+        assert_eq!(result.output, Some(serde_json::json!(42)));
+        assert_eq!(result.logs[0].message, "hi");
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,155 @@
+//! The V8 startup snapshot generated by `build.rs`.
+//!
+//! `build.rs` writes the snapshot and a SHA-256 of its contents to `OUT_DIR`;
+//! both are embedded here. [`verified_snapshot`] re-hashes the embedded bytes
+//! and compares them against the recorded hash so that a corrupted binary, or
+//! a snapshot left over from a different build, fails fast with a clear error
+//! instead of crashing deep inside V8 deserialization. It is the only way to
+//! get at the bytes, so what V8 is handed is always what was verified.
+//!
+//! [`create_snapshot`] takes a snapshot the way `build.rs` does, for the
+//! `snapshot` subcommand.
+
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+
+use crate::snapshot_builder;
+use crate::worker::init_v8_flags;
+
+pub use crate::snapshot_builder::snapshot_sha256;
+
+/// First CLI argument that takes a snapshot instead of running a function.
+pub const SNAPSHOT_SUBCOMMAND: &str = "snapshot";
+
+/// The warmup script `build.rs` runs unless `VORTEX_SNAPSHOT_WARMUP` says otherwise.
+pub const DEFAULT_WARMUP_JS: &str = include_str!("warmup.js");
+
+/// Raw snapshot bytes produced by `build.rs`.
+///
+/// This is a `'static` slice into the executable's read-only data segment,
+/// which the OS maps lazily and shares between processes. Pass it directly as
+/// `RuntimeOptions::startup_snapshot` (which takes `&'static [u8]`) so that
+/// isolates deserialize from the mapping instead of a per-worker copy; never
+/// `to_vec()` it. Private: [`verified_snapshot`] hands it out.
+static VORTEX_SNAPSHOT: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/VORTEX_SNAPSHOT.bin"));
+
+/// Hex-encoded SHA-256 of the snapshot, recorded at build time.
+pub const VORTEX_SNAPSHOT_SHA256: &str =
+    include_str!(concat!(env!("OUT_DIR"), "/VORTEX_SNAPSHOT.sha256"));
+
+/// Return the embedded snapshot after verifying its integrity.
+///
+/// The hash is only computed once per process; later calls reuse the result.
+pub fn verified_snapshot() -> Result<&'static [u8]> {
+    static VERIFIED: OnceLock<Result<(), String>> = OnceLock::new();
+    VERIFIED
+        .get_or_init(|| verify(VORTEX_SNAPSHOT, VORTEX_SNAPSHOT_SHA256).map_err(|e| e.to_string()))
+        .clone()
+        .map(|()| VORTEX_SNAPSHOT)
+        .map_err(|e| anyhow!(e))
+}
+
+/// Take a snapshot like the embedded one, running `warmup` just before.
+///
+/// Applies the V8 flags workers run with, so it fails if this process has
+/// already created workers with other flags.
+pub fn create_snapshot(warmup: Option<String>) -> Result<Box<[u8]>> {
+    init_v8_flags(&[])?;
+    snapshot_builder::create_snapshot(warmup)
+}
+
+/// Check `snapshot` against a hex-encoded SHA-256.
+fn verify(snapshot: &[u8], expected_sha256: &str) -> Result<()> {
+    let actual = snapshot_sha256(snapshot);
+    if actual != expected_sha256.trim() {
+        return Err(anyhow!(
+            "V8 snapshot integrity check failed (expected sha256 {}, got {}); \
+             the binary and snapshot do not belong to the same build",
+            expected_sha256.trim(),
+            actual
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use deno_core::{v8, JsRuntime, RuntimeOptions};
+
+    #[test]
+    fn test_embedded_snapshot_is_valid() {
+        // The embedded bytes themselves, not a copy
+        assert!(std::ptr::eq(verified_snapshot().unwrap(), VORTEX_SNAPSHOT));
+    }
+
+    #[test]
+    fn test_mismatched_snapshot_is_rejected() {
+        let error = verify(b"not a snapshot", VORTEX_SNAPSHOT_SHA256).unwrap_err();
+        assert!(error.to_string().contains("integrity check failed"));
+    }
+
+    /// Take a snapshot with `warmup` in a child test process, since V8 can't
+    /// be initialized for taking snapshots in a process that also runs them.
+    fn create_snapshot_in_child(warmup: &str) -> std::result::Result<Vec<u8>, String> {
+        let out = std::env::temp_dir().join(format!(
+            "vortex-snapshot-test-{}-{}.bin",
+            std::process::id(),
+            snapshot_sha256(warmup.as_bytes())
+        ));
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--ignored", "--exact", "snapshot::tests::snapshot_child"])
+            .args(["--nocapture", "--test-threads=1"])
+            .env("VORTEX_SNAPSHOT_TEST_WARMUP", warmup)
+            .env("VORTEX_SNAPSHOT_TEST_OUT", &out)
+            .output()
+            .unwrap();
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+        }
+        let snapshot = std::fs::read(&out).unwrap();
+        let _ = std::fs::remove_file(&out);
+        Ok(snapshot)
+    }
+
+    #[test]
+    #[ignore = "run by create_snapshot_in_child"]
+    fn snapshot_child() {
+        let warmup = std::env::var("VORTEX_SNAPSHOT_TEST_WARMUP").unwrap();
+        let out = std::env::var("VORTEX_SNAPSHOT_TEST_OUT").unwrap();
+        match create_snapshot(Some(warmup)) {
+            Ok(snapshot) => std::fs::write(out, snapshot).unwrap(),
+            Err(e) => panic!("{:#}", e),
+        }
+    }
+
+    #[test]
+    fn test_warmup_state_is_in_the_snapshot() {
+        let warmup = format!(
+            "{}\nglobalThis.warmed = [typeof Deno.core, 6 * 7];",
+            DEFAULT_WARMUP_JS
+        );
+        let snapshot = create_snapshot_in_child(&warmup).unwrap();
+
+        crate::worker::init_v8_flags(&[]).unwrap();
+        let mut runtime = JsRuntime::new(RuntimeOptions {
+            startup_snapshot: Some(&*Box::leak(snapshot.into_boxed_slice())),
+            ..Default::default()
+        });
+        let value = runtime
+            .execute_script("[test]", "JSON.stringify(globalThis.warmed)")
+            .unwrap();
+        let scope = &mut runtime.handle_scope();
+        let value = v8::Local::new(scope, value);
+        assert_eq!(value.to_rust_string_lossy(scope), r#"["object",42]"#);
+    }
+
+    #[test]
+    fn test_failing_warmup_is_an_error() {
+        let error = create_snapshot_in_child("throw new Error('boom')").unwrap_err();
+        assert!(error.contains("Snapshot warmup script failed"));
+        assert!(error.contains("boom"));
+    }
+}
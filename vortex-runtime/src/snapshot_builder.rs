@@ -0,0 +1,61 @@
+//! Taking the V8 startup snapshot.
+//!
+//! `build.rs` takes the snapshot the binary embeds (see [`crate::snapshot`]);
+//! `vortex-runtime snapshot` takes one on demand, so a warmup script can be
+//! tried out before it is built in. Both go through [`create_snapshot`].
+//!
+//! The warmup script runs after deno_core's own JavaScript has been evaluated,
+//! just before the snapshot is taken, so the functions it calls are compiled
+//! in the snapshot and anything it leaves on the global object is there for
+//! every worker.
+//!
+//! This module is shared with `build.rs` and only depends on `deno_core` and `sha2`.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use deno_core::anyhow::{Error, Result};
+use deno_core::snapshot::{CreateSnapshotOptions, WithRuntimeCb};
+use deno_core::JsRuntimeForSnapshot;
+use sha2::{Digest, Sha256};
+
+/// Take a snapshot of deno_core's JavaScript, running `warmup` just before.
+///
+/// V8 flags are process-wide and a snapshot has to be loaded with the flags
+/// it was taken with, so the caller sets them first.
+pub fn create_snapshot(warmup: Option<String>) -> Result<Box<[u8]>> {
+    // The callback can't return an error, so it leaves it here
+    let failed: Rc<RefCell<Option<Error>>> = Rc::default();
+    let with_runtime_cb = warmup.map(|source| {
+        let failed = failed.clone();
+        Box::new(move |runtime: &mut JsRuntimeForSnapshot| {
+            if let Err(e) = runtime.execute_script("[vortex:warmup]", source.clone()) {
+                *failed.borrow_mut() = Some(e);
+            }
+        }) as Box<WithRuntimeCb>
+    });
+
+    let snapshot = deno_core::snapshot::create_snapshot(
+        CreateSnapshotOptions {
+            cargo_manifest_dir: env!("CARGO_MANIFEST_DIR"),
+            startup_snapshot: None,
+            skip_op_registration: false,
+            extensions: vec![],
+            with_runtime_cb,
+            extension_transpiler: None,
+        },
+        None,
+    )?;
+    if let Some(e) = failed.take() {
+        return Err(e.context("Snapshot warmup script failed"));
+    }
+    Ok(snapshot.output)
+}
+
+/// Hex-encoded SHA-256 of a snapshot, as recorded next to it.
+pub fn snapshot_sha256(snapshot: &[u8]) -> String {
+    Sha256::digest(snapshot)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
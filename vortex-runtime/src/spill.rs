@@ -0,0 +1,123 @@
+//! Spilling oversized outputs to object storage.
+//!
+//! [`WorkerOptions::max_output_bytes`](crate::WorkerOptions::max_output_bytes)
+//! keeps huge return values out of the result. When an [`OutputStorage`] is
+//! configured as well, an output over the limit is written to the blob
+//! backend instead of being truncated, and the result carries an
+//! [`OutputRef`] pointing at it:
+//!
+//! ```json
+//! { "output": null, "output_ref": { "url": "s3://vortex-outputs/runs/<invocation_id>.json", "size": 52428800 } }
+//! ```
+//!
+//! The object holds the output exactly as it would have appeared inline:
+//! the return value serialized as JSON.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::{ObjectStore, PutPayload};
+use serde::{Deserialize, Serialize};
+
+/// Blob store that oversized outputs are written to.
+#[derive(Debug, Clone)]
+pub struct OutputStorage {
+    store: Arc<dyn ObjectStore>,
+    /// URL of the store's root, e.g. `s3://vortex-outputs`
+    base_url: String,
+    /// Key prefix for output objects
+    prefix: Path,
+}
+
+/// Where a spilled output was written.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputRef {
+    /// Location of the JSON object, e.g. `s3://bucket/runs/<invocation_id>.json`
+    pub url: String,
+    /// Size of the object in bytes
+    pub size: usize,
+}
+
+impl OutputStorage {
+    /// Write outputs to the root of `store`, which is reachable at `base_url`.
+    pub fn new(store: Arc<dyn ObjectStore>, base_url: impl Into<String>) -> Self {
+        Self {
+            store,
+            base_url: base_url.into(),
+            prefix: Path::default(),
+        }
+    }
+
+    /// Store objects under `prefix`, e.g. `runs/`.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Path::from(prefix);
+        self
+    }
+
+    /// Connect to an S3-compatible bucket given as `s3://<bucket>[/<prefix>]`.
+    ///
+    /// Credentials, region and endpoint come from the standard `AWS_*`
+    /// environment variables; set `AWS_ENDPOINT` (and `AWS_ALLOW_HTTP=true`
+    /// for plain HTTP) to use MinIO.
+    pub fn s3(url: &str) -> Result<Self> {
+        let location = url
+            .strip_prefix("s3://")
+            .ok_or_else(|| anyhow!("Invalid output storage URL '{}', expected s3://<bucket>", url))?;
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            return Err(anyhow!("Output storage URL '{}' has no bucket", url));
+        }
+        let store = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .build()
+            .map_err(|e| anyhow!("Failed to configure output storage: {}", e))?;
+        Ok(Self::new(Arc::new(store), format!("s3://{}", bucket)).with_prefix(prefix))
+    }
+
+    /// Write the JSON-serialized output of `invocation_id`.
+    pub async fn put(&self, invocation_id: &str, json: String) -> Result<OutputRef> {
+        let size = json.len();
+        let location = self.prefix.child(format!("{}.json", invocation_id));
+        self.store
+            .put(&location, PutPayload::from(json.into_bytes()))
+            .await
+            .map_err(|e| anyhow!("Failed to write output to {}: {}", self.base_url, e))?;
+        Ok(OutputRef {
+            url: format!("{}/{}", self.base_url.trim_end_matches('/'), location),
+            size,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    #[tokio::test]
+    async fn test_put_output() {
+        let store = Arc::new(InMemory::new());
+        let storage = OutputStorage::new(store.clone(), "memory://outputs").with_prefix("runs");
+
+        let output_ref = storage.put("inv-1", "\"big\"".to_string()).await.unwrap();
+        assert_eq!(output_ref.url, "memory://outputs/runs/inv-1.json");
+        assert_eq!(output_ref.size, 5);
+
+        let stored = store
+            .get(&Path::from("runs/inv-1.json"))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(&stored[..], b"\"big\"");
+    }
+
+    #[test]
+    fn test_s3_url() {
+        assert!(OutputStorage::s3("https://bucket").is_err());
+        assert!(OutputStorage::s3("s3://").is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Pieces of `vortex.sql` that don't depend on the database behind it.
+//!
+//! `vortex.sql` works on the function's SQL database: the host's Postgres
+//! when it has a pool (see [`crate::postgres`]), the function's
+//! `vortex.sqlite` database otherwise (see [`crate::sqlite`]). Functions
+//! that own their schema keep it next to their code and apply it on
+//! startup:
+//!
+//! ```js
+//! await vortex.sql.migrate([
+//!     { version: 1, name: 'notes', sql: 'CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT)' },
+//!     { version: 2, name: 'notes.created', sql: 'ALTER TABLE notes ADD created_at INTEGER' },
+//! ]);
+//! ```
+//!
+//! Applied migrations are recorded in [`MIGRATIONS_TABLE`] with a checksum
+//! of their SQL, so running the same list again does nothing and editing a
+//! migration that was already applied is reported instead of ignored.
+//!
+//! `vortex.sql.transaction()` holds a connection for the callback it is
+//! given and rolls back if the callback throws or the transaction is still
+//! open after its timeout ([`DEFAULT_SQL_TRANSACTION_TIMEOUT`]):
+//!
+//! ```js
+//! await vortex.sql.transaction(async (tx) => {
+//!     await tx.execute('UPDATE accounts SET balance = balance - $1 WHERE id = $2', [amount, from]);
+//!     await tx.execute('UPDATE accounts SET balance = balance + $1 WHERE id = $2', [amount, to]);
+//! });
+//! ```
+//!
+//! In strict mode, statements may not contain string or blob literals
+//! ([`find_literal`]), so values can only arrive as parameters and a
+//! function that builds SQL by concatenating input fails on its first run
+//! instead of being injectable. Numbers are still allowed, for `LIMIT 10`
+//! and the like, and migrations are exempt: their SQL comes with the code.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Table that records which migrations a database has had.
+pub const MIGRATIONS_TABLE: &str = "_vortex_migrations";
+
+/// How long a `vortex.sql.transaction()` may stay open by default.
+pub const DEFAULT_SQL_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest a transaction may ask to stay open.
+pub const MAX_SQL_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One step of a function's schema.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Migration {
+    /// Position in the schema's history; migrations apply in this order
+    pub version: i64,
+    /// For people reading [`MIGRATIONS_TABLE`]
+    #[serde(default)]
+    pub name: String,
+    /// One or more statements
+    pub sql: String,
+}
+
+impl Migration {
+    /// SHA-256 of the migration's SQL, hex-encoded.
+    pub fn checksum(&self) -> String {
+        Sha256::digest(self.sql.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// What `vortex.sql.migrate()` did.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MigrationReport {
+    /// Versions applied by this call, in order
+    pub applied: Vec<i64>,
+    /// Latest version the database now has, if any
+    pub version: Option<i64>,
+}
+
+/// Check that `migrations` can be applied in the order given.
+pub fn check_migrations(migrations: &[Migration]) -> Result<(), String> {
+    let mut previous: Option<i64> = None;
+    for migration in migrations {
+        if migration.version <= 0 {
+            return Err(format!(
+                "migration versions must be positive, got {}",
+                migration.version
+            ));
+        }
+        if let Some(previous) = previous.filter(|&previous| migration.version <= previous) {
+            return Err(format!(
+                "migration versions must be increasing, but {} follows {}",
+                migration.version, previous
+            ));
+        }
+        if migration.sql.trim().is_empty() {
+            return Err(format!("migration {} has no SQL", migration.version));
+        }
+        previous = Some(migration.version);
+    }
+    Ok(())
+}
+
+/// Byte offset of the first string or blob literal in `sql`, outside
+/// comments and quoted identifiers. Understands the quoting of both SQLite
+/// and Postgres, including dollar-quoted strings.
+pub fn find_literal(sql: &str) -> Option<usize> {
+    let bytes = sql.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\'' => return Some(i),
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = find(bytes, i + 2, b"\n").map_or(bytes.len(), |end| end + 1);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = find(bytes, i + 2, b"*/").map_or(bytes.len(), |end| end + 2);
+            }
+            // Quoted identifiers; a doubled quote inside is an escaped one,
+            // which resuming the scan at the second quote handles
+            quote @ (b'"' | b'`') => {
+                i = find(bytes, i + 1, &[quote]).map_or(bytes.len(), |end| end + 1);
+            }
+            b'[' => i = find(bytes, i + 1, b"]").map_or(bytes.len(), |end| end + 1),
+            b'$' => {
+                let tag_end = bytes[i + 1..]
+                    .iter()
+                    .position(|&b| !(b.is_ascii_alphanumeric() || b == b'_'))
+                    .map(|len| i + 1 + len);
+                match tag_end {
+                    // `$tag$...$tag$` or `$$...$$`, but not `$1`
+                    Some(end)
+                        if bytes[end] == b'$'
+                            && !bytes.get(i + 1).is_some_and(u8::is_ascii_digit) =>
+                    {
+                        return Some(i)
+                    }
+                    _ => i += 1,
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Index of the first `needle` in `haystack` at or after `from`.
+fn find(haystack: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| from + position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migration(version: i64, sql: &str) -> Migration {
+        Migration {
+            version,
+            name: String::new(),
+            sql: sql.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_find_literal() {
+        for sql in [
+            "SELECT * FROM users WHERE id = ? LIMIT 10",
+            "SELECT * FROM users WHERE id = $1 AND name = $2",
+            "SELECT \"it's\" FROM `a'b` JOIN [c'd] ON 1 = 1",
+            "SELECT x -- it's fine\nFROM t",
+            "SELECT /* don't */ x FROM t WHERE y = :name",
+            "SELECT \"a\"\"'b\" FROM t",
+        ] {
+            assert_eq!(find_literal(sql), None, "{}", sql);
+        }
+        assert_eq!(
+            find_literal("SELECT * FROM users WHERE name = 'bob' OR 1=1"),
+            Some(33)
+        );
+        assert_eq!(find_literal("SELECT E'\\n', 1"), Some(8));
+        assert_eq!(find_literal("INSERT INTO t VALUES (x'00ff')"), Some(23));
+        assert_eq!(find_literal("SELECT $$it's$$"), Some(7));
+        assert_eq!(find_literal("SELECT $body$x$body$"), Some(7));
+        assert_eq!(
+            find_literal("SELECT x -- comment\nFROM t WHERE y = 'z'"),
+            Some(37)
+        );
+    }
+
+    #[test]
+    fn test_check_migrations() {
+        assert_eq!(check_migrations(&[]), Ok(()));
+        assert_eq!(
+            check_migrations(&[
+                migration(1, "CREATE TABLE a (x)"),
+                migration(5, "DROP TABLE a")
+            ]),
+            Ok(())
+        );
+        assert_eq!(
+            check_migrations(&[migration(2, "SELECT 1"), migration(2, "SELECT 2")]),
+            Err("migration versions must be increasing, but 2 follows 2".to_string())
+        );
+        assert_eq!(
+            check_migrations(&[migration(0, "SELECT 1")]),
+            Err("migration versions must be positive, got 0".to_string())
+        );
+        assert_eq!(
+            check_migrations(&[migration(3, "  ")]),
+            Err("migration 3 has no SQL".to_string())
+        );
+        assert_ne!(
+            migration(1, "SELECT 1").checksum(),
+            migration(1, "SELECT 2").checksum()
+        );
+    }
+}
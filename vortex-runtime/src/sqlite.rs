@@ -0,0 +1,928 @@
+//! Embedded SQLite databases behind `vortex.sqlite`.
+//!
+//! Single-node deployments that don't run Postgres can still give functions
+//! relational storage:
+//!
+//! ```js
+//! await vortex.sqlite.execute('CREATE TABLE IF NOT EXISTS visits (path TEXT, at INTEGER)');
+//! await vortex.sqlite.execute('INSERT INTO visits VALUES (?, ?)', [path, Date.now()]);
+//! const rows = await vortex.sqlite.query('SELECT path, count(*) AS n FROM visits GROUP BY path');
+//! ```
+//!
+//! Every function gets its own database, opened on first use and kept open
+//! for the life of the process: a file in [`SqliteOptions::dir`], named
+//! after a hash of the function ID, or an in-memory database when no
+//! directory is set. A database can't grow past [`SqliteOptions::max_bytes`]
+//! and a statement can't run for longer than
+//! [`SqliteOptions::statement_timeout`]. Statements may not attach other
+//! databases, create temporary objects, which live outside the quota, set
+//! pragmas, which could lift it, or begin transactions, which would stay
+//! open on the connection other invocations share. Transactions go through
+//! [`SqliteDatabase::begin`] instead, which holds the connection until the
+//! transaction ends and rolls back when it is dropped.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rusqlite::config::DbConfig;
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use rusqlite::limits::Limit;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params_from_iter, Connection, ErrorCode};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::OwnedMutexGuard;
+
+use crate::sql::{Migration, MigrationReport, MIGRATIONS_TABLE};
+
+/// Default limit on the size of a function's database.
+pub const DEFAULT_SQLITE_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Default limit on how long one statement may run.
+pub const DEFAULT_SQLITE_STATEMENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most rows one `vortex.sqlite.query()` returns.
+pub const MAX_SQLITE_ROWS: usize = 10_000;
+
+/// How many virtual machine instructions run between deadline checks.
+const PROGRESS_INTERVAL: i32 = 1000;
+
+/// Pragmas that only describe the schema, allowed with any argument.
+const SCHEMA_PRAGMAS: &[&str] = &[
+    "foreign_key_check",
+    "foreign_key_list",
+    "index_info",
+    "index_list",
+    "index_xinfo",
+    "integrity_check",
+    "quick_check",
+    "table_info",
+    "table_list",
+    "table_xinfo",
+];
+
+/// Pragmas that may be read but not set.
+const READ_ONLY_PRAGMAS: &[&str] = &[
+    "foreign_keys",
+    "freelist_count",
+    "max_page_count",
+    "page_count",
+    "page_size",
+    "user_version",
+];
+
+/// Where functions' databases are kept and how large they may grow.
+#[derive(Debug, Clone)]
+pub struct SqliteOptions {
+    /// Keep databases in this directory instead of in memory. File names
+    /// are hashes, so function IDs can't escape it.
+    pub dir: Option<PathBuf>,
+    /// Largest a database may grow, in bytes.
+    pub max_bytes: u64,
+    /// Longest one statement may run, including waiting for another
+    /// process that holds the database locked.
+    pub statement_timeout: Duration,
+}
+
+impl Default for SqliteOptions {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            max_bytes: DEFAULT_SQLITE_MAX_BYTES,
+            statement_timeout: DEFAULT_SQLITE_STATEMENT_TIMEOUT,
+        }
+    }
+}
+
+/// What `vortex.sqlite.execute()` changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SqliteChanges {
+    /// Rows inserted, updated or deleted
+    pub changes: usize,
+    /// Rowid of the last row inserted through this connection
+    pub last_insert_rowid: i64,
+}
+
+/// Why a statement didn't run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SqliteError {
+    /// Parameter (counting from 1) isn't null, a boolean, a number or a string
+    InvalidParameter(usize),
+    /// The query matched more than [`MAX_SQLITE_ROWS`] rows
+    TooManyRows,
+    /// The database reached [`SqliteOptions::max_bytes`]
+    Full,
+    /// The statement ran past [`SqliteOptions::statement_timeout`]
+    Timeout,
+    /// The statement attaches a database, creates a temporary object, sets a
+    /// pragma or controls transactions
+    NotAuthorized,
+    /// A migration that was already applied has different SQL now
+    ChangedMigration(i64),
+    /// A migration failed, so none of the pending ones were applied
+    Migration {
+        version: i64,
+        error: Box<SqliteError>,
+    },
+    /// Anything else SQLite reported, such as a syntax error
+    Sql(String),
+}
+
+impl fmt::Display for SqliteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidParameter(index) => write!(
+                f,
+                "parameter {} must be null, a boolean, a number or a string",
+                index
+            ),
+            Self::TooManyRows => write!(
+                f,
+                "query returned more than {} rows; use LIMIT to page through them",
+                MAX_SQLITE_ROWS
+            ),
+            Self::Full => write!(f, "database has reached its size limit"),
+            Self::Timeout => write!(f, "statement ran past its time limit"),
+            Self::NotAuthorized => write!(
+                f,
+                "statement is not allowed: attaching databases, temporary objects, transactions and setting pragmas are disabled"
+            ),
+            Self::ChangedMigration(version) => write!(
+                f,
+                "migration {} was changed after it was applied; add a new migration instead",
+                version
+            ),
+            Self::Migration { version, error } => {
+                write!(f, "migration {} failed: {}", version, error)
+            }
+            Self::Sql(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for SqliteError {}
+
+impl From<rusqlite::Error> for SqliteError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e.sqlite_error_code() {
+            Some(ErrorCode::DiskFull) => Self::Full,
+            Some(ErrorCode::OperationInterrupted) => Self::Timeout,
+            Some(ErrorCode::AuthorizationForStatementDenied) => Self::NotAuthorized,
+            _ => Self::Sql(e.to_string()),
+        }
+    }
+}
+
+/// The databases of every function in the process. Clones share them, so
+/// give every worker a clone of the same value.
+#[derive(Debug, Clone, Default)]
+pub struct SqliteDatabases {
+    options: SqliteOptions,
+    open: Arc<Mutex<HashMap<String, SqliteDatabase>>>,
+}
+
+impl SqliteDatabases {
+    pub fn new(options: SqliteOptions) -> Self {
+        Self {
+            options,
+            open: Arc::default(),
+        }
+    }
+
+    pub fn options(&self) -> &SqliteOptions {
+        &self.options
+    }
+
+    /// The database of `function_id`, created on first use.
+    pub async fn open(&self, function_id: &str) -> Result<SqliteDatabase, SqliteError> {
+        if let Some(database) = self.lock().get(function_id) {
+            return Ok(database.clone());
+        }
+        let path = self
+            .options
+            .dir
+            .as_ref()
+            .map(|dir| dir.join(file_name(function_id)));
+        let options = self.options.clone();
+        let connection = tokio::task::spawn_blocking(move || open(path.as_deref(), &options))
+            .await
+            .map_err(|e| SqliteError::Sql(e.to_string()))??;
+        // Another worker may have opened it meanwhile; theirs wins
+        Ok(self
+            .lock()
+            .entry(function_id.to_string())
+            .or_insert_with(|| SqliteDatabase {
+                connection: Arc::new(tokio::sync::Mutex::new(connection)),
+            })
+            .clone())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, SqliteDatabase>> {
+        self.open.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Database file of `function_id`, unique and safe to join to a directory.
+fn file_name(function_id: &str) -> String {
+    let hash: String = Sha256::digest(function_id.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}.sqlite", hash)
+}
+
+fn open(path: Option<&Path>, options: &SqliteOptions) -> Result<LimitedConnection, SqliteError> {
+    let connection = match path {
+        Some(path) => Connection::open(path)?,
+        None => Connection::open_in_memory()?,
+    };
+    connection.busy_timeout(options.statement_timeout)?;
+    connection.set_db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE, true)?;
+    connection.set_limit(Limit::SQLITE_LIMIT_ATTACHED, 0);
+    let page_size: u64 = connection.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+    connection.pragma_update(
+        None,
+        "max_page_count",
+        (options.max_bytes / page_size).max(1),
+    )?;
+
+    let deadline = Arc::new(Mutex::new(None::<Instant>));
+    let expires = deadline.clone();
+    connection.progress_handler(
+        PROGRESS_INTERVAL,
+        Some(move || {
+            expires
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        }),
+    );
+    let internal = Arc::new(AtomicBool::new(false));
+    let trusted = internal.clone();
+    connection.authorizer(Some(move |context: AuthContext<'_>| {
+        authorize(context, trusted.load(Ordering::Relaxed))
+    }));
+    Ok(LimitedConnection {
+        connection,
+        deadline,
+        timeout: options.statement_timeout,
+        internal,
+    })
+}
+
+/// Whether a statement may do what `context` describes. `internal`
+/// statements are the runtime's own and may control transactions.
+fn authorize(context: AuthContext<'_>, internal: bool) -> Authorization {
+    match context.action {
+        AuthAction::Transaction { .. } | AuthAction::Savepoint { .. } if internal => {
+            Authorization::Allow
+        }
+        AuthAction::Attach { .. }
+        | AuthAction::Detach { .. }
+        | AuthAction::CreateTempIndex { .. }
+        | AuthAction::CreateTempTable { .. }
+        | AuthAction::CreateTempTrigger { .. }
+        | AuthAction::CreateTempView { .. }
+        | AuthAction::Transaction { .. }
+        | AuthAction::Savepoint { .. } => Authorization::Deny,
+        AuthAction::Pragma {
+            pragma_name,
+            pragma_value,
+        } => {
+            let name = pragma_name.to_ascii_lowercase();
+            let read_only = pragma_value.is_none() && READ_ONLY_PRAGMAS.contains(&name.as_str());
+            if read_only || SCHEMA_PRAGMAS.contains(&name.as_str()) {
+                Authorization::Allow
+            } else {
+                Authorization::Deny
+            }
+        }
+        _ => Authorization::Allow,
+    }
+}
+
+/// A connection with its statement deadline.
+#[derive(Debug)]
+struct LimitedConnection {
+    connection: Connection,
+    /// Checked by the progress handler, which interrupts the statement
+    deadline: Arc<Mutex<Option<Instant>>>,
+    timeout: Duration,
+    /// Set while the runtime runs its own transaction statements
+    internal: Arc<AtomicBool>,
+}
+
+impl LimitedConnection {
+    /// Run `f` with the statement deadline set.
+    fn run<T>(
+        &mut self,
+        f: impl FnOnce(&LimitedConnection) -> Result<T, SqliteError>,
+    ) -> Result<T, SqliteError> {
+        self.restart_deadline(None);
+        let result = f(self);
+        self.set_deadline(None);
+        result
+    }
+
+    /// Give the next statement the full timeout, but no time past `limit`.
+    fn restart_deadline(&self, limit: Option<Instant>) {
+        let deadline = Instant::now() + self.timeout;
+        self.set_deadline(Some(limit.map_or(deadline, |limit| limit.min(deadline))));
+    }
+
+    fn set_deadline(&self, deadline: Option<Instant>) {
+        *self.deadline.lock().unwrap_or_else(|e| e.into_inner()) = deadline;
+    }
+
+    /// Run `sql`, a transaction statement function statements may not use.
+    fn control(&self, sql: &str) -> rusqlite::Result<()> {
+        self.internal.store(true, Ordering::Relaxed);
+        let result = self.connection.execute_batch(sql);
+        self.internal.store(false, Ordering::Relaxed);
+        result
+    }
+}
+
+impl Deref for LimitedConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.connection
+    }
+}
+
+/// One function's database. Clones share its connection, which runs one
+/// statement at a time.
+#[derive(Debug, Clone)]
+pub struct SqliteDatabase {
+    connection: Arc<tokio::sync::Mutex<LimitedConnection>>,
+}
+
+impl SqliteDatabase {
+    /// Run the statement `sql` and return the rows it produced, as objects
+    /// keyed by column name.
+    pub async fn query(
+        &self,
+        sql: &str,
+        params: Vec<Value>,
+    ) -> Result<Vec<Map<String, Value>>, SqliteError> {
+        let sql = sql.to_string();
+        let params = sql_params(params)?;
+        self.run(move |connection| query(connection, &sql, params))
+            .await
+    }
+
+    /// Run the statement `sql`, which must not return rows.
+    pub async fn execute(
+        &self,
+        sql: &str,
+        params: Vec<Value>,
+    ) -> Result<SqliteChanges, SqliteError> {
+        let sql = sql.to_string();
+        let params = sql_params(params)?;
+        self.run(move |connection| execute(connection, &sql, params))
+            .await
+    }
+
+    /// Apply the `migrations` the database doesn't have yet, in order, in
+    /// one transaction that also keeps other workers and processes from
+    /// migrating at the same time. Each migration gets the full statement
+    /// timeout.
+    pub async fn migrate(
+        &self,
+        migrations: Vec<Migration>,
+    ) -> Result<MigrationReport, SqliteError> {
+        self.run(move |connection| {
+            connection.control("BEGIN IMMEDIATE")?;
+            let result = apply_migrations(connection, &migrations).and_then(|report| {
+                connection.control("COMMIT")?;
+                Ok(report)
+            });
+            if result.is_err() {
+                // The error that made us roll back is the one worth reporting
+                let _ = connection.control("ROLLBACK");
+            }
+            result
+        })
+        .await
+    }
+
+    /// Begin a transaction, which has the connection to itself until it
+    /// ends. Its statements can't run past `deadline`.
+    pub async fn begin(&self, deadline: Instant) -> Result<SqliteTransaction, SqliteError> {
+        let connection =
+            tokio::time::timeout_at(deadline.into(), self.connection.clone().lock_owned())
+                .await
+                .map_err(|_| SqliteError::Timeout)?;
+        let mut transaction = SqliteTransaction {
+            open: Some(OpenTransaction {
+                connection,
+                active: false,
+            }),
+            deadline,
+        };
+        transaction
+            .run(|open| {
+                open.connection.control("BEGIN IMMEDIATE")?;
+                open.active = true;
+                Ok(())
+            })
+            .await?;
+        Ok(transaction)
+    }
+
+    /// Run `f` on a blocking thread once the connection is free.
+    async fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&LimitedConnection) -> Result<T, SqliteError> + Send + 'static,
+    ) -> Result<T, SqliteError> {
+        let mut connection = self.connection.clone().lock_owned().await;
+        tokio::task::spawn_blocking(move || connection.run(f))
+            .await
+            .map_err(|e| SqliteError::Sql(e.to_string()))?
+    }
+}
+
+/// A transaction on a function's database. Other statements on the
+/// database wait until it commits or rolls back; dropping it rolls back.
+#[derive(Debug)]
+pub struct SqliteTransaction {
+    /// Only `None` while a statement runs, or if one panicked
+    open: Option<OpenTransaction>,
+    deadline: Instant,
+}
+
+#[derive(Debug)]
+struct OpenTransaction {
+    connection: OwnedMutexGuard<LimitedConnection>,
+    /// Whether `BEGIN` ran and neither `COMMIT` nor `ROLLBACK` did since
+    active: bool,
+}
+
+impl Drop for OpenTransaction {
+    fn drop(&mut self) {
+        if self.active {
+            let _ = self.connection.control("ROLLBACK");
+        }
+    }
+}
+
+impl SqliteTransaction {
+    /// Like [`SqliteDatabase::query`], inside the transaction.
+    pub async fn query(
+        &mut self,
+        sql: &str,
+        params: Vec<Value>,
+    ) -> Result<Vec<Map<String, Value>>, SqliteError> {
+        let sql = sql.to_string();
+        let params = sql_params(params)?;
+        self.run(move |open| query(&open.connection, &sql, params))
+            .await
+    }
+
+    /// Like [`SqliteDatabase::execute`], inside the transaction.
+    pub async fn execute(
+        &mut self,
+        sql: &str,
+        params: Vec<Value>,
+    ) -> Result<SqliteChanges, SqliteError> {
+        let sql = sql.to_string();
+        let params = sql_params(params)?;
+        self.run(move |open| execute(&open.connection, &sql, params))
+            .await
+    }
+
+    pub async fn commit(mut self) -> Result<(), SqliteError> {
+        self.run(|open| {
+            open.connection.control("COMMIT")?;
+            open.active = false;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn rollback(mut self) -> Result<(), SqliteError> {
+        self.run(|open| {
+            open.active = false;
+            Ok(open.connection.control("ROLLBACK")?)
+        })
+        .await
+    }
+
+    /// Run `f` on a blocking thread, within the transaction's deadline.
+    async fn run<T: Send + 'static>(
+        &mut self,
+        f: impl FnOnce(&mut OpenTransaction) -> Result<T, SqliteError> + Send + 'static,
+    ) -> Result<T, SqliteError> {
+        let mut open = self
+            .open
+            .take()
+            .ok_or_else(|| SqliteError::Sql("transaction has ended".to_string()))?;
+        let deadline = self.deadline;
+        let (open, result) = tokio::task::spawn_blocking(move || {
+            open.connection.restart_deadline(Some(deadline));
+            let result = f(&mut open);
+            open.connection.set_deadline(None);
+            (open, result)
+        })
+        .await
+        .map_err(|e| SqliteError::Sql(e.to_string()))?;
+        self.open = Some(open);
+        result
+    }
+}
+
+fn query(
+    connection: &Connection,
+    sql: &str,
+    params: Vec<SqlValue>,
+) -> Result<Vec<Map<String, Value>>, SqliteError> {
+    let mut statement = connection.prepare(sql)?;
+    let columns: Vec<String> = statement
+        .column_names()
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let mut rows = statement.query(params_from_iter(params))?;
+    let mut objects = Vec::new();
+    while let Some(row) = rows.next()? {
+        if objects.len() == MAX_SQLITE_ROWS {
+            return Err(SqliteError::TooManyRows);
+        }
+        let mut object = Map::new();
+        for (index, column) in columns.iter().enumerate() {
+            object.insert(column.clone(), json_value(row.get_ref(index)?));
+        }
+        objects.push(object);
+    }
+    Ok(objects)
+}
+
+fn execute(
+    connection: &Connection,
+    sql: &str,
+    params: Vec<SqlValue>,
+) -> Result<SqliteChanges, SqliteError> {
+    let changes = connection.execute(sql, params_from_iter(params))?;
+    Ok(SqliteChanges {
+        changes,
+        last_insert_rowid: connection.last_insert_rowid(),
+    })
+}
+
+fn apply_migrations(
+    connection: &LimitedConnection,
+    migrations: &[Migration],
+) -> Result<MigrationReport, SqliteError> {
+    connection.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            checksum TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )",
+        MIGRATIONS_TABLE
+    ))?;
+    let applied: HashMap<i64, String> = connection
+        .prepare(&format!(
+            "SELECT version, checksum FROM {}",
+            MIGRATIONS_TABLE
+        ))?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut report = MigrationReport::default();
+    for migration in migrations {
+        let checksum = migration.checksum();
+        match applied.get(&migration.version) {
+            Some(existing) if *existing == checksum => continue,
+            Some(_) => return Err(SqliteError::ChangedMigration(migration.version)),
+            None => {}
+        }
+        connection.restart_deadline(None);
+        connection
+            .execute_batch(&migration.sql)
+            .map_err(|e| SqliteError::Migration {
+                version: migration.version,
+                error: Box::new(e.into()),
+            })?;
+        connection.execute(
+            &format!(
+                "INSERT INTO {} (version, name, checksum, applied_at) \
+                 VALUES (?, ?, ?, strftime('%Y-%m-%dT%H:%M:%fZ'))",
+                MIGRATIONS_TABLE
+            ),
+            (migration.version, &migration.name, &checksum),
+        )?;
+        report.applied.push(migration.version);
+    }
+    report.version = connection.query_row(
+        &format!("SELECT max(version) FROM {}", MIGRATIONS_TABLE),
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(report)
+}
+
+/// Bind JSON parameters: booleans as 0 and 1, numbers as integers where
+/// they are whole.
+fn sql_params(params: Vec<Value>) -> Result<Vec<SqlValue>, SqliteError> {
+    params
+        .into_iter()
+        .enumerate()
+        .map(|(index, param)| match param {
+            Value::Null => Ok(SqlValue::Null),
+            Value::Bool(b) => Ok(SqlValue::Integer(b.into())),
+            Value::Number(n) => Ok(match n.as_i64() {
+                Some(n) => SqlValue::Integer(n),
+                None => SqlValue::Real(n.as_f64().unwrap_or(f64::NAN)),
+            }),
+            Value::String(s) => Ok(SqlValue::Text(s)),
+            Value::Array(_) | Value::Object(_) => Err(SqliteError::InvalidParameter(index + 1)),
+        })
+        .collect()
+}
+
+/// A column value as JSON; blobs become arrays of bytes.
+fn json_value(value: ValueRef<'_>) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(n) => n.into(),
+        ValueRef::Real(n) => n.into(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).into(),
+        ValueRef::Blob(bytes) => bytes.iter().copied().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn databases(max_bytes: u64) -> SqliteDatabases {
+        SqliteDatabases::new(SqliteOptions {
+            max_bytes,
+            statement_timeout: Duration::from_millis(200),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_query_and_execute() {
+        let databases = databases(DEFAULT_SQLITE_MAX_BYTES);
+        let db = databases.open("fn-1").await.unwrap();
+        db.execute(
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT, score REAL, data BLOB)",
+            vec![],
+        )
+        .await
+        .unwrap();
+        let changes = db
+            .execute(
+                "INSERT INTO t (name, score, data) VALUES (?, ?, x'0102')",
+                vec!["ada".into(), 1.5.into()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            changes,
+            SqliteChanges {
+                changes: 1,
+                last_insert_rowid: 1
+            }
+        );
+
+        let rows = db
+            .query("SELECT * FROM t WHERE name = ?", vec!["ada".into()])
+            .await
+            .unwrap();
+        assert_eq!(
+            Value::Array(rows.into_iter().map(Value::Object).collect()),
+            serde_json::json!([{ "id": 1, "name": "ada", "score": 1.5, "data": [1, 2] }])
+        );
+
+        // Every function has its own database; a function's is reused
+        let other = databases.open("fn-2").await.unwrap();
+        assert!(other.query("SELECT * FROM t", vec![]).await.is_err());
+        let again = databases.open("fn-1").await.unwrap();
+        assert_eq!(
+            again.query("SELECT * FROM t", vec![]).await.unwrap().len(),
+            1
+        );
+
+        assert_eq!(
+            db.query("SELECT ?", vec![serde_json::json!({})]).await,
+            Err(SqliteError::InvalidParameter(1))
+        );
+        assert!(matches!(
+            db.query("SELEC 1", vec![]).await,
+            Err(SqliteError::Sql(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        let db = databases(64 * 1024).open("fn").await.unwrap();
+        db.execute("CREATE TABLE t (data BLOB)", vec![])
+            .await
+            .unwrap();
+        assert_eq!(
+            db.execute("INSERT INTO t VALUES (zeroblob(100000))", vec![])
+                .await,
+            Err(SqliteError::Full)
+        );
+
+        assert_eq!(
+            db.query(
+                "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n) SELECT count(*) FROM n",
+                vec![]
+            )
+            .await,
+            Err(SqliteError::Timeout)
+        );
+        // The deadline is per statement
+        assert!(db.query("SELECT 1", vec![]).await.is_ok());
+
+        assert_eq!(
+            db.query(
+                "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n LIMIT 10001) SELECT i FROM n",
+                vec![]
+            )
+            .await,
+            Err(SqliteError::TooManyRows)
+        );
+
+        for denied in [
+            "ATTACH DATABASE '/tmp/other.sqlite' AS other",
+            "VACUUM INTO '/tmp/copy.sqlite'",
+            "CREATE TEMP TABLE scratch (x)",
+            "PRAGMA max_page_count = 1000000",
+            "PRAGMA writable_schema = ON",
+            "BEGIN",
+            "SAVEPOINT s",
+        ] {
+            assert!(db.execute(denied, vec![]).await.is_err(), "{}", denied);
+        }
+        let columns = db.query("PRAGMA table_info(t)", vec![]).await.unwrap();
+        assert_eq!(columns[0]["name"], "data");
+    }
+
+    #[tokio::test]
+    async fn test_migrate() {
+        let db = databases(DEFAULT_SQLITE_MAX_BYTES)
+            .open("fn")
+            .await
+            .unwrap();
+        let migration = |version, sql: &str| Migration {
+            version,
+            name: format!("step {}", version),
+            sql: sql.to_string(),
+        };
+        let schema = vec![
+            migration(1, "CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT)"),
+            migration(2, "ALTER TABLE notes ADD created_at INTEGER; CREATE INDEX notes_created ON notes (created_at)"),
+        ];
+        assert_eq!(
+            db.migrate(schema.clone()).await,
+            Ok(MigrationReport {
+                applied: vec![1, 2],
+                version: Some(2)
+            })
+        );
+        // Running it again is a no-op
+        assert_eq!(
+            db.migrate(schema.clone()).await,
+            Ok(MigrationReport {
+                applied: vec![],
+                version: Some(2)
+            })
+        );
+
+        // A failing migration takes the others in the same call with it
+        let mut failing = schema.clone();
+        failing.push(migration(3, "CREATE TABLE tags (name TEXT)"));
+        failing.push(migration(4, "ALTER TABLE missing ADD x"));
+        assert!(matches!(
+            db.migrate(failing).await,
+            Err(SqliteError::Migration { version: 4, .. })
+        ));
+        assert!(db.query("SELECT * FROM tags", vec![]).await.is_err());
+
+        let mut changed = schema.clone();
+        changed[0].sql = "CREATE TABLE notes (id INTEGER PRIMARY KEY)".to_string();
+        assert_eq!(
+            db.migrate(changed).await,
+            Err(SqliteError::ChangedMigration(1))
+        );
+
+        // Migrations can't end the transaction they run in
+        let mut escaping = schema;
+        escaping.push(migration(3, "COMMIT; CREATE TABLE tags (name TEXT)"));
+        assert_eq!(
+            db.migrate(escaping).await,
+            Err(SqliteError::Migration {
+                version: 3,
+                error: Box::new(SqliteError::NotAuthorized)
+            })
+        );
+        let applied = db
+            .query("SELECT version, name FROM _vortex_migrations", vec![])
+            .await
+            .unwrap();
+        assert_eq!(applied.len(), 2);
+        assert_eq!(applied[1]["name"], "step 2");
+    }
+
+    #[tokio::test]
+    async fn test_transaction() {
+        let db = databases(DEFAULT_SQLITE_MAX_BYTES)
+            .open("fn")
+            .await
+            .unwrap();
+        db.execute("CREATE TABLE t (x INTEGER)", vec![])
+            .await
+            .unwrap();
+        let count = || async {
+            db.query("SELECT count(*) AS n FROM t", vec![])
+                .await
+                .unwrap()[0]["n"]
+                .clone()
+        };
+        let deadline = || Instant::now() + Duration::from_secs(5);
+
+        let mut tx = db.begin(deadline()).await.unwrap();
+        tx.execute("INSERT INTO t VALUES (?)", vec![1.into()])
+            .await
+            .unwrap();
+        let rows = tx.query("SELECT x FROM t", vec![]).await.unwrap();
+        assert_eq!(rows[0]["x"], 1);
+        // Other statements wait for the transaction
+        let (n, ()) = tokio::join!(count(), async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            tx.rollback().await.unwrap();
+        });
+        assert_eq!(n, 0);
+
+        let mut tx = db.begin(deadline()).await.unwrap();
+        tx.execute("INSERT INTO t VALUES (2)", vec![])
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(count().await, 1);
+
+        // Dropping a transaction rolls it back
+        let mut tx = db.begin(deadline()).await.unwrap();
+        tx.execute("INSERT INTO t VALUES (3)", vec![])
+            .await
+            .unwrap();
+        drop(tx);
+        assert_eq!(count().await, 1);
+
+        // Statements can't run past the transaction's deadline
+        let mut tx = db
+            .begin(Instant::now() + Duration::from_millis(50))
+            .await
+            .unwrap();
+        assert_eq!(
+            tx.query(
+                "WITH RECURSIVE r(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM r) SELECT count(*) FROM r",
+                vec![]
+            )
+            .await,
+            Err(SqliteError::Timeout)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_database() {
+        let dir = std::env::temp_dir().join(format!("vortex-sqlite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = SqliteOptions {
+            dir: Some(dir.clone()),
+            ..Default::default()
+        };
+
+        let db = SqliteDatabases::new(options.clone())
+            .open("../fn")
+            .await
+            .unwrap();
+        db.execute("CREATE TABLE t (x)", vec![]).await.unwrap();
+        db.execute("INSERT INTO t VALUES (42)", vec![])
+            .await
+            .unwrap();
+        drop(db);
+
+        // Another process (or restart) sees the same data
+        let db = SqliteDatabases::new(options).open("../fn").await.unwrap();
+        let rows = db.query("SELECT x FROM t", vec![]).await.unwrap();
+        assert_eq!(rows[0]["x"], 42);
+        assert!(dir.join(file_name("../fn")).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,452 @@
+//! Supervisor - pre-forked multi-process execution.
+//!
+//! The [`Scheduler`](crate::Scheduler) keeps every tenant in one address space,
+//! separated only by V8 isolates. The supervisor adds OS-level isolation: it
+//! spawns N child runtime processes (`vortex-runtime worker-process`) up front
+//! and routes invocations to them over their stdin/stdout pipes, so workers
+//! stay warm while a crash or V8 escape is contained to one process.
+//!
+//! # Wire format
+//!
+//! Newline-delimited JSON in both directions:
+//!
+//! ```text
+//! supervisor → child   {"id":1,"tenant_id":"t","function_id":"f","code":"...","input":{...}}
+//! child → supervisor   {"id":1,"result":{"output":...,"logs":[...],"execution_time_ms":3}}
+//!                      {"id":1,"error":"Script execution failed: ...","exception":{"message":...}}
+//! supervisor → child   {"evict_function":"f@1"}
+//! ```
+//!
+//! Responses may arrive out of order; `id` correlates them. A child that exits
+//! fails its pending invocations and is respawned on the next request.
+//! `evict_function` drops the child's idle workers for a function id and
+//! gets no response.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::error::{ExecutionError, ScriptError};
+use crate::logging::RuntimeLog;
+use crate::ops::{LogEntry, PendingOp};
+use crate::pool::{PoolManager, PoolOptions};
+use crate::scheduler::{run_job, ExecutionRequest};
+use crate::worker::{ExecutionMetrics, ExecutionResult, WorkerOptions};
+
+/// Subcommand that puts the binary into child worker mode.
+pub const WORKER_PROCESS_SUBCOMMAND: &str = "worker-process";
+
+/// An invocation sent to a child process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessRequest {
+    /// Correlates the response with this request
+    pub id: u64,
+    #[serde(default)]
+    pub tenant_id: String,
+    #[serde(default)]
+    pub function_id: Option<String>,
+    pub code: String,
+    #[serde(default)]
+    pub input: Value,
+    #[serde(default)]
+    pub invocation_id: Option<String>,
+}
+
+/// A line the supervisor sends to a child.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ChildMessage {
+    Evict { evict_function: String },
+    Invoke(ProcessRequest),
+}
+
+/// A child's reply to a [`ProcessRequest`]. Exactly one of `result` / `error` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessResponse {
+    pub id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<ExecutionResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Structured form of `error` when user code threw
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exception: Option<ScriptError>,
+    /// Logs captured before the failure
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub logs: Vec<LogEntry>,
+    /// Invocation ID of the failed run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invocation_id: Option<String>,
+    /// Resources the failed run used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<ExecutionMetrics>,
+    /// Async ops the failed run was still waiting for when it timed out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending_ops: Vec<PendingOp>,
+    /// Runtime warnings raised during the failed run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub runtime_logs: Vec<RuntimeLog>,
+}
+
+impl ProcessResponse {
+    pub(crate) fn new(id: u64, result: Result<ExecutionResult>) -> Self {
+        match result {
+            Ok(result) => Self {
+                id,
+                result: Some(result),
+                error: None,
+                exception: None,
+                logs: Vec::new(),
+                invocation_id: None,
+                metrics: None,
+                pending_ops: Vec::new(),
+                runtime_logs: Vec::new(),
+            },
+            Err(e) => Self {
+                id,
+                result: None,
+                error: Some(e.to_string()),
+                exception: ExecutionError::script_error(&e).cloned(),
+                logs: ExecutionError::partial_logs(&e).to_vec(),
+                invocation_id: ExecutionError::invocation_id(&e).map(String::from),
+                metrics: ExecutionError::metrics(&e).cloned(),
+                pending_ops: ExecutionError::pending_ops(&e).to_vec(),
+                runtime_logs: ExecutionError::runtime_logs(&e).to_vec(),
+            },
+        }
+    }
+
+    /// Turn the response back into the result the child produced.
+    fn into_result(self) -> Result<ExecutionResult> {
+        match self.result {
+            Some(result) => Ok(result),
+            None => Err(ExecutionError {
+                message: self.error.unwrap_or_default(),
+                error: self.exception,
+                logs: self.logs,
+                invocation_id: self.invocation_id.unwrap_or_default(),
+                metrics: self.metrics.unwrap_or_default(),
+                pending_ops: self.pending_ops,
+                runtime_logs: self.runtime_logs,
+                ..Default::default()
+            }
+            .into()),
+        }
+    }
+}
+
+/// Configuration for a [`Supervisor`].
+#[derive(Debug, Clone)]
+pub struct SupervisorOptions {
+    /// Number of child processes to keep running
+    pub processes: usize,
+    /// Runtime binary to spawn (defaults to the current executable)
+    pub program: PathBuf,
+    /// Extra arguments passed to each child after the subcommand,
+    /// e.g. `--harden` or `--max-pending-ops 100`
+    pub args: Vec<String>,
+}
+
+impl Default for SupervisorOptions {
+    fn default() -> Self {
+        Self {
+            processes: 2,
+            program: std::env::current_exe().unwrap_or_else(|_| PathBuf::from("vortex-runtime")),
+            args: Vec::new(),
+        }
+    }
+}
+
+type PendingReplies = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<ExecutionResult>>>>>;
+
+struct ChildProcess {
+    stdin: tokio::sync::Mutex<ChildStdin>,
+    pending: PendingReplies,
+    alive: Arc<AtomicBool>,
+    in_flight: AtomicUsize,
+    _child: Child,
+}
+
+impl ChildProcess {
+    fn spawn(options: &SupervisorOptions) -> Result<Self> {
+        let mut child = Command::new(&options.program)
+            .arg(WORKER_PROCESS_SUBCOMMAND)
+            .args(&options.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow!("Failed to spawn worker process: {}", e))?;
+
+        let stdin = child.stdin.take().ok_or_else(|| anyhow!("Worker process has no stdin"))?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow!("Worker process has no stdout"))?;
+        let pending: PendingReplies = Arc::new(Mutex::new(HashMap::new()));
+        let alive = Arc::new(AtomicBool::new(true));
+
+        // Route responses back to their callers until the child exits
+        let reader_pending = Arc::clone(&pending);
+        let reader_alive = Arc::clone(&alive);
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let response: ProcessResponse = match serde_json::from_str(&line) {
+                    Ok(response) => response,
+                    Err(e) => {
+                        eprintln!("Ignoring malformed worker process output: {}", e);
+                        continue;
+                    }
+                };
+                let reply = reader_pending.lock().unwrap().remove(&response.id);
+                if let Some(reply) = reply {
+                    let _ = reply.send(response.into_result());
+                }
+            }
+            reader_alive.store(false, Ordering::SeqCst);
+            for (_, reply) in reader_pending.lock().unwrap().drain() {
+                let _ = reply.send(Err(anyhow!("Worker process exited during execution")));
+            }
+        });
+
+        Ok(Self {
+            stdin: tokio::sync::Mutex::new(stdin),
+            pending,
+            alive,
+            in_flight: AtomicUsize::new(0),
+            _child: child,
+        })
+    }
+
+    fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+
+    async fn write_line(&self, line: &str) -> std::io::Result<()> {
+        let mut stdin = self.stdin.lock().await;
+        stdin.write_all(line.as_bytes()).await?;
+        stdin.write_all(b"\n").await?;
+        stdin.flush().await
+    }
+
+    async fn send(&self, request: ProcessRequest) -> Result<ExecutionResult> {
+        let id = request.id;
+        let (reply, response) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, reply);
+        // The reader may have already drained pending replies on exit
+        if !self.is_alive() {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(anyhow!("Worker process has exited"));
+        }
+
+        let line = serde_json::to_string(&request)?;
+        if let Err(e) = self.write_line(&line).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(anyhow!("Failed to send request to worker process: {}", e));
+        }
+
+        response
+            .await
+            .map_err(|_| anyhow!("Worker process dropped the execution"))?
+    }
+}
+
+/// Routes invocations across a set of pre-forked child runtime processes.
+pub struct Supervisor {
+    options: SupervisorOptions,
+    children: Vec<Mutex<Arc<ChildProcess>>>,
+    next_id: AtomicU64,
+}
+
+impl Supervisor {
+    /// Spawn the child processes. Must be called within a tokio runtime.
+    pub fn new(options: SupervisorOptions) -> Result<Self> {
+        let children = (0..options.processes.max(1))
+            .map(|_| ChildProcess::spawn(&options).map(|child| Mutex::new(Arc::new(child))))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            options,
+            children,
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Execute a request on the least-loaded child, respawning it if it has exited.
+    pub async fn submit(
+        &self,
+        tenant_id: &str,
+        function_id: Option<&str>,
+        code: &str,
+        input: Value,
+    ) -> Result<ExecutionResult> {
+        self.dispatch(ProcessRequest {
+            id: 0,
+            tenant_id: tenant_id.to_string(),
+            function_id: function_id.map(String::from),
+            code: code.to_string(),
+            input,
+            invocation_id: None,
+        })
+        .await
+    }
+
+    /// Send a fully formed request to the least-loaded child. Its `id` is
+    /// replaced with one unique to this supervisor.
+    pub(crate) async fn dispatch(&self, mut request: ProcessRequest) -> Result<ExecutionResult> {
+        let child = self.pick_child()?;
+        request.id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        child.in_flight.fetch_add(1, Ordering::Relaxed);
+        let result = child.send(request).await;
+        child.in_flight.fetch_sub(1, Ordering::Relaxed);
+        result
+    }
+
+    /// Execute anonymous code with an input payload.
+    pub async fn execute(&self, code: &str, input: Value) -> Result<ExecutionResult> {
+        self.submit("", None, code, input).await
+    }
+
+    /// Tell every running child to drop its idle workers for `function_id`,
+    /// e.g. those of a function version that has been replaced.
+    pub async fn evict(&self, function_id: &str) {
+        let children: Vec<Arc<ChildProcess>> = self
+            .children
+            .iter()
+            .map(|slot| Arc::clone(&*slot.lock().unwrap()))
+            .collect();
+        let line = serde_json::json!({ "evict_function": function_id }).to_string();
+        for child in children.iter().filter(|child| child.is_alive()) {
+            // A child that has just exited has no idle workers left anyway
+            let _ = child.write_line(&line).await;
+        }
+    }
+
+    fn pick_child(&self) -> Result<Arc<ChildProcess>> {
+        let slot = self
+            .children
+            .iter()
+            .min_by_key(|slot| {
+                let child = slot.lock().unwrap();
+                // Dead children sort first so they are respawned promptly
+                if child.is_alive() {
+                    child.in_flight.load(Ordering::Relaxed) + 1
+                } else {
+                    0
+                }
+            })
+            .ok_or_else(|| anyhow!("Supervisor has no worker processes"))?;
+
+        let mut child = slot.lock().unwrap();
+        if !child.is_alive() {
+            *child = Arc::new(ChildProcess::spawn(&self.options)?);
+        }
+        Ok(Arc::clone(&*child))
+    }
+}
+
+/// Entry point for `vortex-runtime worker-process`.
+///
+/// Reads [`ProcessRequest`]s from stdin and writes [`ProcessResponse`]s to
+/// stdout until stdin is closed. Invocations run concurrently on a warm
+/// [`PoolManager`] configured by `pool`; must be called inside a `LocalSet`.
+pub async fn serve_worker_process(options: WorkerOptions, pool: PoolOptions) -> Result<()> {
+    let pool = Rc::new(PoolManager::new(pool));
+
+    // A single writer keeps concurrent responses from interleaving on stdout
+    let (out_tx, mut out_rx) = mpsc::unbounded_channel::<String>();
+    let writer = tokio::task::spawn_local(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(line) = out_rx.recv().await {
+            if stdout.write_all(line.as_bytes()).await.is_err() || stdout.flush().await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match serde_json::from_str(&line) {
+            Ok(ChildMessage::Invoke(request)) => request,
+            Ok(ChildMessage::Evict { evict_function }) => {
+                pool.evict_function(&evict_function);
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Ignoring malformed request: {}", e);
+                continue;
+            }
+        };
+
+        let pool = Rc::clone(&pool);
+        let options = options.clone();
+        let out_tx = out_tx.clone();
+        tokio::task::spawn_local(async move {
+            let id = request.id;
+            let result = run_job(
+                &pool,
+                ExecutionRequest {
+                    tenant_id: request.tenant_id,
+                    function_id: request.function_id,
+                    code: request.code,
+                    input: request.input,
+                    invocation_id: request.invocation_id,
+                    options,
+                    body: None,
+                    output: None,
+                    websocket: None,
+                },
+            )
+            .await;
+            if let Ok(mut line) = serde_json::to_string(&ProcessResponse::new(id, result)) {
+                line.push('\n');
+                let _ = out_tx.send(line);
+            }
+        });
+    }
+
+    // The writer finishes once every in-flight invocation has replied
+    drop(out_tx);
+    writer.await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_response_wire_format() {
+        let error = ProcessResponse::new(7, Err(anyhow!("boom")));
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({ "id": 7, "error": "boom" })
+        );
+
+        let request: ProcessRequest =
+            serde_json::from_str(r#"{"id":1,"code":"return 1"}"#).unwrap();
+        assert_eq!(request.function_id, None);
+        assert_eq!(request.input, Value::Null);
+
+        match serde_json::from_str(r#"{"evict_function":"f@1"}"#).unwrap() {
+            ChildMessage::Evict { evict_function } => assert_eq!(evict_function, "f@1"),
+            other => panic!("expected an eviction, got {:?}", other),
+        }
+        match serde_json::from_str(r#"{"id":2,"function_id":"f@1","code":"return 1"}"#).unwrap() {
+            ChildMessage::Invoke(request) => assert_eq!(request.id, 2),
+            other => panic!("expected an invocation, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! TypeScript and JSX transpilation.
+//!
+//! `.ts`, `.tsx` and `.jsx` modules are stripped of types and have their JSX
+//! lowered to plain function calls before V8 sees them. The JSX factory is
+//! configurable so server-side rendering libraries other than React work,
+//! e.g. preact with `factory: "h"` / `fragment_factory: "Fragment"`.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use deno_ast::{
+    EmitOptions, MediaType, ModuleSpecifier, ParseParams, SourceMapOption, TranspileOptions,
+};
+use serde::{Deserialize, Serialize};
+
+/// How JSX is lowered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct JsxOptions {
+    /// Function called for each element (classic runtime), e.g. `h`
+    #[serde(default = "default_factory")]
+    pub factory: String,
+    /// Expression used for `<>...</>` fragments, e.g. `Fragment`
+    #[serde(default = "default_fragment_factory")]
+    pub fragment_factory: String,
+    /// Use the automatic runtime, importing `<import_source>/jsx-runtime`
+    /// instead of calling `factory`, e.g. `preact`
+    #[serde(default)]
+    pub import_source: Option<String>,
+}
+
+fn default_factory() -> String {
+    "React.createElement".to_string()
+}
+
+fn default_fragment_factory() -> String {
+    "React.Fragment".to_string()
+}
+
+impl Default for JsxOptions {
+    fn default() -> Self {
+        Self {
+            factory: default_factory(),
+            fragment_factory: default_fragment_factory(),
+            import_source: None,
+        }
+    }
+}
+
+impl JsxOptions {
+    /// deno_ast transpile options for this JSX configuration.
+    pub(crate) fn transpile_options(&self) -> TranspileOptions {
+        TranspileOptions {
+            jsx_automatic: self.import_source.is_some(),
+            jsx_factory: self.factory.clone(),
+            jsx_fragment_factory: self.fragment_factory.clone(),
+            jsx_import_source: self.import_source.clone(),
+            transform_jsx: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Whether a file with this path needs transpiling before V8 can run it.
+pub fn needs_transpile(path: &str) -> bool {
+    [".ts", ".tsx", ".jsx", ".mts", ".cts"]
+        .iter()
+        .any(|ext| path.ends_with(ext))
+}
+
+/// Transpile a TypeScript/JSX module to JavaScript.
+///
+/// `specifier` is only used for its extension and in error messages.
+pub fn transpile(specifier: &ModuleSpecifier, source: &str, jsx: &JsxOptions) -> Result<String> {
+    let parsed = deno_ast::parse_module(ParseParams {
+        specifier: specifier.clone(),
+        text: Arc::from(source),
+        media_type: MediaType::from_specifier(specifier),
+        capture_tokens: false,
+        scope_analysis: false,
+        maybe_syntax: None,
+    })
+    .map_err(|e| anyhow!("Failed to parse '{}': {}", specifier, e))?;
+
+    let emitted = parsed
+        .transpile(
+            &jsx.transpile_options(),
+            &EmitOptions {
+                source_map: SourceMapOption::None,
+                ..Default::default()
+            },
+        )
+        .map_err(|e| anyhow!("Failed to transpile '{}': {}", specifier, e))?
+        .into_source();
+    Ok(emitted.text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_jsx_factory() {
+        let specifier = ModuleSpecifier::parse("file:///page.tsx").unwrap();
+        let jsx = JsxOptions {
+            factory: "h".to_string(),
+            fragment_factory: "Fragment".to_string(),
+            import_source: None,
+        };
+        let code = transpile(
+            &specifier,
+            "const name: string = 'x'; export default () => <><p>{name}</p></>;",
+            &jsx,
+        )
+        .unwrap();
+        assert!(code.contains("h(Fragment"));
+        assert!(code.contains("h(\"p\""));
+        assert!(!code.contains(": string"));
+    }
+
+    #[test]
+    fn test_needs_transpile() {
+        assert!(needs_transpile("index.tsx"));
+        assert!(needs_transpile("lib/util.ts"));
+        assert!(!needs_transpile("index.js"));
+    }
+}
@@ -0,0 +1,21 @@
+// Vortex Runtime - Snapshot Warmup
+//
+// Executed by build.rs just before the V8 snapshot is taken, on top of
+// deno_core's own JavaScript (bootstrap.js is not part of the snapshot).
+// Calling the deno_core paths every worker goes through forces V8 to compile
+// them, so the compiled functions end up in the snapshot instead of being
+// compiled lazily on every cold start.
+//
+// Keep this free of timers and other pending work, and leave nothing behind
+// that user code could see: the snapshot is taken synchronously once this
+// script returns, and every worker starts from it.
+
+((core) => {
+  // deno_core calls this on every event loop turn to deliver op results,
+  // timers and promise rejections; with none of them to deliver it only
+  // runs the (empty) microtask queue
+  core.eventLoopTick(undefined, undefined, false);
+
+  // bootstrap.js creates and closes a cancel handle for every timer
+  core.tryClose(core.createCancelHandle());
+})(Deno.core);
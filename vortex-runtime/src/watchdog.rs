@@ -0,0 +1,247 @@
+//! CPU watchdog - budgets that hold even when user code never yields.
+//!
+//! Timeouts enforced from the event loop only fire when JavaScript gives the
+//! loop a chance to run, so `while (true) {}` would hold the isolate thread
+//! forever. While an invocation runs, the watchdog asks V8 to interrupt the
+//! isolate every [`WATCHDOG_INTERVAL`]. The interrupt runs on the isolate's
+//! own thread, between two JavaScript instructions, where it compares the
+//! thread's CPU time and the wall-clock time against the worker's budgets
+//! and terminates execution once one is exceeded.
+//!
+//! Compiling can't be interrupted that way, since V8 only services
+//! interrupts while JavaScript runs. [`CompileWatchdog`] instead terminates
+//! the isolate from a thread of its own once a deadline passes. The thread
+//! lives as long as the worker and is armed for each compile, so it isn't
+//! spawned per invocation.
+
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use deno_core::v8;
+
+use crate::resources::thread_cpu_time;
+
+/// How often the isolate is interrupted to check its budgets.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The budget an interrupt found exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BudgetExceeded {
+    /// The invocation used more CPU time than `cpu_budget`
+    Cpu(Duration),
+    /// The invocation ran longer than `timeout`
+    Timeout(Duration),
+}
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cpu(budget) => write!(
+                f,
+                "CPU budget exceeded in synchronous code (budget: {}ms)",
+                budget.as_millis()
+            ),
+            Self::Timeout(timeout) => write!(
+                f,
+                "Execution timed out after {}ms in synchronous code",
+                timeout.as_millis()
+            ),
+        }
+    }
+}
+
+/// What an interrupt checks, shared between the watchdog thread and the
+/// interrupts it requested.
+struct Budgets {
+    cpu_budget: Option<Duration>,
+    timeout: Option<Duration>,
+    /// The isolate thread's CPU time when the invocation started
+    cpu_start: Option<Duration>,
+    started_at: Instant,
+    /// Cleared when the invocation ends, so a late interrupt does nothing
+    active: AtomicBool,
+    /// Whether an interrupt was requested and hasn't run yet. While the
+    /// isolate waits on the event loop, requests queue up until JavaScript
+    /// runs again, so only one is kept in flight.
+    requested: AtomicBool,
+    exceeded: OnceLock<BudgetExceeded>,
+}
+
+impl Budgets {
+    fn check(&self) -> Option<BudgetExceeded> {
+        let elapsed = self.started_at.elapsed();
+        if let Some(budget) = self.cpu_budget {
+            // Without a CPU clock for the thread, wall-clock time stands in
+            let used = match (self.cpu_start, thread_cpu_time()) {
+                (Some(start), Some(now)) => now.saturating_sub(start),
+                _ => elapsed,
+            };
+            if used >= budget {
+                return Some(BudgetExceeded::Cpu(budget));
+            }
+        }
+        match self.timeout {
+            Some(timeout) if elapsed >= timeout => Some(BudgetExceeded::Timeout(timeout)),
+            _ => None,
+        }
+    }
+}
+
+/// Interrupts one invocation's isolate until [`CpuWatchdog::finish`].
+pub(crate) struct CpuWatchdog {
+    budgets: Arc<Budgets>,
+    stop: mpsc::Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl CpuWatchdog {
+    /// Start watching the isolate behind `handle`. Must be called on the
+    /// isolate's thread, whose CPU time the budget counts.
+    pub fn start(
+        handle: v8::IsolateHandle,
+        cpu_budget: Option<Duration>,
+        timeout: Option<Duration>,
+    ) -> Self {
+        let budgets = Arc::new(Budgets {
+            cpu_budget,
+            timeout,
+            cpu_start: thread_cpu_time(),
+            started_at: Instant::now(),
+            active: AtomicBool::new(true),
+            requested: AtomicBool::new(false),
+            exceeded: OnceLock::new(),
+        });
+        let (stop, stopped) = mpsc::channel::<()>();
+        let watched = budgets.clone();
+        let thread = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(WATCHDOG_INTERVAL)
+            {
+                if watched.requested.swap(true, Ordering::AcqRel) {
+                    continue;
+                }
+                // The interrupt owns this reference and releases it when it runs
+                let data = Arc::into_raw(watched.clone()) as *mut c_void;
+                if !handle.request_interrupt(check_budgets, data) {
+                    // The isolate is gone, so the interrupt will never run
+                    // SAFETY: `data` came from `Arc::into_raw` above and was not handed out
+                    drop(unsafe { Arc::from_raw(data as *const Budgets) });
+                    break;
+                }
+            }
+        });
+        Self {
+            budgets,
+            stop,
+            thread,
+        }
+    }
+
+    /// Stop watching and report the budget that was exceeded, if any. When
+    /// one was, execution was terminated and has to be resumed with
+    /// `cancel_terminate_execution` before the isolate runs code again.
+    pub fn finish(self) -> Option<BudgetExceeded> {
+        self.budgets.active.store(false, Ordering::Release);
+        let _ = self.stop.send(());
+        let _ = self.thread.join();
+        self.budgets.exceeded.get().copied()
+    }
+}
+
+/// Runs on the isolate's thread when V8 services an interrupt.
+extern "C" fn check_budgets(isolate: &mut v8::Isolate, data: *mut c_void) {
+    // SAFETY: `data` is the reference `CpuWatchdog::start` leaked for this interrupt
+    let budgets = unsafe { Arc::from_raw(data as *const Budgets) };
+    budgets.requested.store(false, Ordering::Release);
+    if !budgets.active.load(Ordering::Acquire) {
+        return;
+    }
+    if let Some(exceeded) = budgets.check() {
+        if budgets.exceeded.set(exceeded).is_ok() {
+            isolate.terminate_execution();
+        }
+    }
+}
+
+/// Deadline a [`CompileWatchdog`] is armed with.
+#[derive(Default)]
+struct Deadline {
+    at: Option<Instant>,
+    /// Whether the deadline passed and execution was terminated
+    fired: bool,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct DeadlineState {
+    deadline: Mutex<Deadline>,
+    changed: Condvar,
+}
+
+/// Terminates the isolate when a compile runs past its deadline, from one
+/// thread kept for the lifetime of the worker.
+pub(crate) struct CompileWatchdog {
+    state: Arc<DeadlineState>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CompileWatchdog {
+    /// Start the (idle) watchdog thread for the isolate behind `handle`.
+    pub fn new(handle: v8::IsolateHandle) -> Self {
+        let state = Arc::new(DeadlineState::default());
+        let watched = state.clone();
+        let thread = thread::spawn(move || {
+            let mut deadline = watched.deadline.lock().unwrap();
+            while !deadline.shutdown {
+                deadline = match deadline.at {
+                    None => watched.changed.wait(deadline).unwrap(),
+                    Some(at) => match at.checked_duration_since(Instant::now()) {
+                        Some(left) if !left.is_zero() => {
+                            watched.changed.wait_timeout(deadline, left).unwrap().0
+                        }
+                        _ => {
+                            // Under the lock, so `disarm` can't miss it
+                            handle.terminate_execution();
+                            deadline.at = None;
+                            deadline.fired = true;
+                            deadline
+                        }
+                    },
+                };
+            }
+        });
+        Self {
+            state,
+            thread: Some(thread),
+        }
+    }
+
+    /// Terminate execution unless [`CompileWatchdog::disarm`] is called within `timeout`.
+    pub fn arm(&self, timeout: Duration) {
+        let mut deadline = self.state.deadline.lock().unwrap();
+        deadline.at = Some(Instant::now() + timeout);
+        deadline.fired = false;
+        self.state.changed.notify_one();
+    }
+
+    /// Stop the countdown and report whether it ran out. When it did,
+    /// execution was terminated and has to be resumed with
+    /// `cancel_terminate_execution` before the isolate runs code again.
+    pub fn disarm(&self) -> bool {
+        let mut deadline = self.state.deadline.lock().unwrap();
+        deadline.at = None;
+        std::mem::take(&mut deadline.fired)
+    }
+}
+
+impl Drop for CompileWatchdog {
+    fn drop(&mut self) {
+        self.state.deadline.lock().unwrap().shutdown = true;
+        self.state.changed.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}